@@ -0,0 +1,39 @@
+//! Checking scenarios for problems without running them.
+
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use outcome::util::{find_project_root, get_scenario_paths};
+
+/// Checks the scenario at the given path, or all the scenarios in the
+/// project if the path points to a directory.
+///
+/// Returns an error if any of the checked scenarios has errors.
+pub fn check(path: PathBuf, color: bool) -> Result<()> {
+    let paths = if path.is_file() {
+        vec![path]
+    } else {
+        get_scenario_paths(find_project_root(path, 4)?)?
+    };
+    if paths.is_empty() {
+        return Err(Error::msg("no scenarios found"));
+    }
+
+    let mut errors = 0;
+    let mut warnings = 0;
+    for path in paths {
+        println!("checking scenario: {}", path.to_string_lossy());
+        let report = outcome::check::check_scenario(path);
+        for diagnostic in &report.diagnostics {
+            println!("{}\n", diagnostic.render(color));
+        }
+        errors += report.error_count();
+        warnings += report.warning_count();
+    }
+
+    println!("found {} error(s), {} warning(s)", errors, warnings);
+    if errors > 0 {
+        return Err(Error::msg(format!("check failed with {} error(s)", errors)));
+    }
+    Ok(())
+}
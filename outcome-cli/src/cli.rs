@@ -11,18 +11,23 @@ use std::{env, thread};
 use anyhow::{Error, Result};
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use outcome::util::{find_project_root, get_scenario_paths, get_snapshot_paths};
+use outcome::model::cache as mod_cache;
+use outcome::model::Scenario;
 use outcome::Sim;
 use outcome_net::{
     CompressionPolicy, Organizer, Server, ServerConfig, SimConnection, SocketEvent,
     SocketEventType, Worker,
 };
 
+#[cfg(feature = "save_img")]
+use outcome::img::{GridExport, GridImageOptions};
+
 #[cfg(feature = "watcher")]
 use notify::{RecommendedWatcher, Watcher};
 
 use crate::interactive::{OnSignal, OnSignalAction};
-use crate::util::format_elements_list;
-use crate::{interactive, test};
+use crate::util::{format_elements_list, select_scenario};
+use crate::{check, fmt, interactive, lsp, model, mods, snapshot, test};
 use std::str::FromStr;
 
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
@@ -44,6 +49,14 @@ pub fn app_matches() -> ArgMatches<'static> {
             .global(true)
             .help("Set the verbosity of the log output \
             [possible values: trace, debug, info, warn, error, none]"))
+        .arg(Arg::with_name("mod-path")
+            .long("mod-path")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .value_name("dir")
+            .global(true)
+            .help("Additional directory to search for mods, can be used multiple times"))
 
         // new
         .subcommand(SubCommand::with_name("new")
@@ -72,6 +85,83 @@ pub fn app_matches() -> ArgMatches<'static> {
                 .short("p"))
         )
 
+        // check
+        .subcommand(SubCommand::with_name("check")
+            .about("Check scenario for problems without running it")
+            .display_order(13)
+            .long_about("Check scenario for problems without running it.\n\
+                If the path points to a directory, all the scenarios in the project are\n\
+                checked. Exits with non-zero status if any errors are found.")
+            .arg(Arg::with_name("path")
+                .value_name("path")
+                .help("Path to the scenario manifest or project directory"))
+            .arg(Arg::with_name("no-color")
+                .long("no-color")
+                .help("Disable colored output")))
+
+        // model
+        .subcommand(SubCommand::with_name("model")
+            .about("Export information about the scenario model")
+            .display_order(14)
+            .long_about("Export information about the scenario model.\n\
+                Outputs components, vars, states, triggers, events and prefabs, along with\n\
+                the modules they come from, as JSON. Using the `dot` format renders\n\
+                component state machines and event triggers as a Graphviz graph.")
+            .arg(Arg::with_name("path")
+                .value_name("path")
+                .help("Path to the scenario manifest or project directory"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .short("f")
+                .takes_value(true)
+                .value_name("format")
+                .possible_values(&["json", "dot"])
+                .default_value("json"))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .help("Write to file instead of stdout")
+                .takes_value(true)
+                .value_name("path")))
+
+        // manifest
+        .subcommand(SubCommand::with_name("manifest")
+            .about("Print the effective scenario manifest")
+            .display_order(15)
+            .long_about("Print the effective scenario manifest.\n\
+                For scenarios extending other scenarios, the printed manifest includes\n\
+                all the inherited entries.")
+            .arg(Arg::with_name("path")
+                .value_name("path")
+                .help("Path to the scenario manifest or project directory")))
+
+        // fmt
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Format scripts into their canonical form")
+            .display_order(16)
+            .long_about("Format scripts into their canonical form.\n\
+                Normalizes indentation inside blocks and spacing between arguments, keeping\n\
+                comments and directives in place. If the path points to a directory, all\n\
+                the scripts found within it are formatted.")
+            .arg(Arg::with_name("path")
+                .value_name("path")
+                .help("Path to the script file or directory"))
+            .arg(Arg::with_name("check")
+                .long("check")
+                .help("Only check whether the scripts are formatted, exits with non-zero \
+                status if any of them is not")))
+
+        // lsp
+        .subcommand(SubCommand::with_name("lsp")
+            .about("Start a language server for outcome scripts")
+            .display_order(17)
+            .long_about("Start a language server for outcome scripts.\n\
+                Communicates with the editor over stdio, providing diagnostics, completion,\n\
+                go-to-definition and hover for the scripts within the project.")
+            .arg(Arg::with_name("path")
+                .value_name("path")
+                .help("Path to the project directory, uses the editor workspace by default")))
+
         // run
         .subcommand(SubCommand::with_name("run")
             .about("Run a simulation locally")
@@ -110,6 +200,71 @@ pub fn app_matches() -> ArgMatches<'static> {
                 .value_name("on-change")
                 .default_value("restart")
                 .possible_values(&["restart", "update"]))
+            .arg(Arg::with_name("set")
+                .long("set")
+                .help("Override scenario setting, can be used multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("key=value"))
+            .arg(Arg::with_name("resume")
+                .long("resume")
+                .help("Resume from an automatic checkpoint, either `latest` or checkpoint file name")
+                .takes_value(true)
+                .value_name("checkpoint"))
+            .arg(Arg::with_name("checkpoint-steps")
+                .long("checkpoint-steps")
+                .help("Create a checkpoint every N steps")
+                .takes_value(true)
+                .value_name("steps"))
+            .arg(Arg::with_name("checkpoint-minutes")
+                .long("checkpoint-minutes")
+                .help("Create a checkpoint every T minutes")
+                .takes_value(true)
+                .value_name("minutes"))
+            .arg(Arg::with_name("checkpoint-keep")
+                .long("checkpoint-keep")
+                .help("Number of most recent checkpoints to keep")
+                .takes_value(true)
+                .value_name("count"))
+            .arg(Arg::with_name("checkpoint-keep-every")
+                .long("checkpoint-keep-every")
+                .help("Keep every Mth checkpoint forever")
+                .takes_value(true)
+                .value_name("m"))
+            .arg(Arg::with_name("checkpoint-compress")
+                .long("checkpoint-compress")
+                .help("Compress checkpoints using LZ4"))
+            .arg(Arg::with_name("export-grid")
+                .long("export-grid")
+                .help("Export grid var to PNG images as the simulation is stepped, can be used multiple times")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("address"))
+            .arg(Arg::with_name("export-every")
+                .long("export-every")
+                .help("Export grids every N steps")
+                .takes_value(true)
+                .value_name("steps")
+                .default_value("1"))
+            .arg(Arg::with_name("export-dir")
+                .long("export-dir")
+                .help("Directory exported images are written to")
+                .takes_value(true)
+                .value_name("path")
+                .default_value("exports"))
+            .arg(Arg::with_name("colormap")
+                .long("colormap")
+                .help("Colormap used for exported grids")
+                .takes_value(true)
+                .value_name("name")
+                .possible_values(&["grayscale", "viridis", "heat", "diverging"]))
+            .arg(Arg::with_name("range")
+                .long("range")
+                .help("Value range mapped to the colormap, defaults to grid minimum and maximum")
+                .takes_value(true)
+                .value_name("min,max"))
 
         )
 
@@ -169,6 +324,19 @@ pub fn app_matches() -> ArgMatches<'static> {
                 .takes_value(true)
                 .value_name("compression-policy")
                 .possible_values(&["all", "bigger_than_[n_bytes]"]))
+            .arg(Arg::with_name("set")
+                .long("set")
+                .help("Override scenario setting, can be used multiple times")
+                .display_order(7)
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("key=value"))
+            .arg(Arg::with_name("watch")
+                .long("watch")
+                .help("Watch scenario manifests and mod files for changes and apply them to \
+                the running simulation (local simulations only)")
+                .display_order(8))
             .arg(Arg::with_name("organizer")
                 .long("organizer")
                 .short("o")
@@ -276,6 +444,113 @@ pub fn app_matches() -> ArgMatches<'static> {
                 .default_value("tcp"))
         )
 
+        // snapshot
+        .subcommand(SubCommand::with_name("snapshot")
+            .about("Inspect, compare and export snapshots")
+            .display_order(30)
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list")
+                .about("List snapshots available in the project")
+                .display_order(0)
+                .arg(Arg::with_name("path")
+                    .value_name("path")
+                    .help("Path within the project, defaults to current directory")))
+            .subcommand(SubCommand::with_name("info")
+                .about("Print snapshot header, clock, entity counts and model summary")
+                .display_order(1)
+                .arg(Arg::with_name("snapshot")
+                    .value_name("snapshot")
+                    .required(true)
+                    .help("Snapshot name or path")))
+            .subcommand(SubCommand::with_name("diff")
+                .about("Print per-entity and per-var differences between two snapshots")
+                .display_order(2)
+                .arg(Arg::with_name("a")
+                    .value_name("snapshot-a")
+                    .required(true))
+                .arg(Arg::with_name("b")
+                    .value_name("snapshot-b")
+                    .required(true)))
+            .subcommand(SubCommand::with_name("export")
+                .about("Export entity data selected through a query")
+                .display_order(3)
+                .arg(Arg::with_name("snapshot")
+                    .value_name("snapshot")
+                    .required(true)
+                    .help("Snapshot name or path"))
+                .arg(Arg::with_name("format")
+                    .long("format")
+                    .short("f")
+                    .takes_value(true)
+                    .value_name("format")
+                    .default_value("jsonl")
+                    .possible_values(&["jsonl", "csv"]))
+                .arg(Arg::with_name("out")
+                    .long("out")
+                    .short("o")
+                    .takes_value(true)
+                    .value_name("path")
+                    .help("Output file path, defaults to standard output"))
+                .arg(Arg::with_name("with-components")
+                    .long("with-components")
+                    .takes_value(true)
+                    .value_name("components")
+                    .help("Select entities having all of the listed components"))
+                .arg(Arg::with_name("names")
+                    .long("names")
+                    .takes_value(true)
+                    .value_name("names")
+                    .help("Select entities with the listed names"))
+                .arg(Arg::with_name("ids")
+                    .long("ids")
+                    .takes_value(true)
+                    .value_name("ids")
+                    .help("Select entities with the listed ids"))
+                .arg(Arg::with_name("components")
+                    .long("components")
+                    .short("c")
+                    .takes_value(true)
+                    .value_name("components")
+                    .help("Only export vars of the listed components"))
+                .arg(Arg::with_name("vars")
+                    .long("vars")
+                    .takes_value(true)
+                    .value_name("var-names")
+                    .help("Only export vars with the listed names")))
+        )
+
+        // mod
+        .subcommand(SubCommand::with_name("mod")
+            .about("Manage mods installed in the local mod cache")
+            .display_order(31)
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list")
+                .about("List mods available in the project and the mod search paths")
+                .display_order(0)
+                .arg(Arg::with_name("path")
+                    .value_name("path")
+                    .help("Path within the project, defaults to current directory")))
+            .subcommand(SubCommand::with_name("install")
+                .about("Install mod from a local directory or archive (.tar, .tar.gz, .tgz)")
+                .display_order(1)
+                .arg(Arg::with_name("path")
+                    .value_name("path")
+                    .required(true))
+                .arg(Arg::with_name("force")
+                    .long("force")
+                    .short("f")
+                    .help("Replace already installed copy of the same mod version")))
+            .subcommand(SubCommand::with_name("remove")
+                .about("Remove mod from the local mod cache")
+                .display_order(2)
+                .arg(Arg::with_name("name")
+                    .value_name("name")
+                    .required(true))
+                .arg(Arg::with_name("version")
+                    .value_name("version")
+                    .help("Version to remove, defaults to all installed versions")))
+        )
+
         .subcommand(SubCommand::with_name("worker")
             .about("Start a worker")
             .long_about("Start a worker. Worker is the smallest independent part\n\
@@ -394,17 +669,42 @@ pub fn app_matches() -> ArgMatches<'static> {
 /// Runs based on specified subcommand.
 pub fn start(matches: ArgMatches) -> Result<()> {
     setup_log_verbosity(&matches);
+    setup_mod_paths(&matches)?;
     match matches.subcommand() {
         ("new", Some(m)) => start_new(m),
         ("test", Some(m)) => start_test(m),
+        ("check", Some(m)) => start_check(m),
+        ("model", Some(m)) => start_model(m),
+        ("manifest", Some(m)) => start_manifest(m),
+        ("fmt", Some(m)) => start_fmt(m),
+        ("lsp", Some(m)) => start_lsp(m),
         ("run", Some(m)) => start_run(m),
         ("server", Some(m)) => start_server(m),
         ("client", Some(m)) => start_client(m),
         ("worker", Some(m)) => start_worker(m),
+        ("snapshot", Some(m)) => start_snapshot(m),
+        ("mod", Some(m)) => start_mod(m),
         _ => Ok(()),
     }
 }
 
+/// Prepends directories passed with `--mod-path` to the mod search path
+/// environment variable.
+///
+/// Passing them through the environment makes them available to scenario
+/// loading within the library, as well as to any spawned processes.
+fn setup_mod_paths(matches: &ArgMatches) -> Result<()> {
+    let mut paths = match matches.values_of("mod-path") {
+        Some(values) => values.map(|v| PathBuf::from(v)).collect::<Vec<_>>(),
+        None => return Ok(()),
+    };
+    if let Some(existing) = env::var_os(mod_cache::MOD_PATH_ENV) {
+        paths.extend(env::split_paths(&existing));
+    }
+    env::set_var(mod_cache::MOD_PATH_ENV, env::join_paths(paths)?);
+    Ok(())
+}
+
 fn start_new(matches: &ArgMatches) -> Result<()> {
     let name = matches.value_of("name").unwrap();
     unimplemented!();
@@ -426,6 +726,58 @@ fn start_test(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+fn start_check(matches: &ArgMatches) -> Result<()> {
+    let path = match matches.value_of("path") {
+        Some(p) => PathBuf::from(p),
+        None => env::current_dir()?,
+    };
+    let path = path.canonicalize().unwrap_or(path);
+    check::check(path, !matches.is_present("no-color"))
+}
+
+fn start_model(matches: &ArgMatches) -> Result<()> {
+    let path = match matches.value_of("path") {
+        Some(p) => PathBuf::from(p),
+        None => env::current_dir()?,
+    };
+    let path = path.canonicalize().unwrap_or(path);
+    model::export(
+        path,
+        matches.value_of("format").unwrap_or("json"),
+        matches.value_of("output").map(std::path::Path::new),
+    )
+}
+
+fn start_manifest(matches: &ArgMatches) -> Result<()> {
+    let path = match matches.value_of("path") {
+        Some(p) => PathBuf::from(p),
+        None => env::current_dir()?,
+    };
+    let path = select_scenario(path.canonicalize().unwrap_or(path))?;
+    let manifest = outcome::model::ScenarioManifest::from_path(path)?;
+    if !manifest.extends.is_empty() {
+        println!(
+            "# effective manifest of \"{}\", extends: {}\n",
+            manifest.name,
+            manifest.extends.join(" -> ")
+        );
+    }
+    print!("{}", manifest.to_toml_string()?);
+    Ok(())
+}
+
+fn start_fmt(matches: &ArgMatches) -> Result<()> {
+    let path = match matches.value_of("path") {
+        Some(p) => PathBuf::from(p),
+        None => env::current_dir()?,
+    };
+    fmt::format(path, matches.is_present("check"))
+}
+
+fn start_lsp(matches: &ArgMatches) -> Result<()> {
+    lsp::run(matches.value_of("path").map(PathBuf::from))
+}
+
 /// Starts a new simulation run, using a scenario or a snapshot file.
 ///
 /// # Resolving ambiguity
@@ -437,6 +789,10 @@ fn start_test(matches: &ArgMatches) -> Result<()> {
 /// If the path argument is not provided, this function will scan the project
 /// directory and print possible choices to the user.
 fn start_run(matches: &ArgMatches) -> Result<()> {
+    if let Some(checkpoint) = matches.value_of("resume") {
+        return start_run_resume(checkpoint, matches);
+    }
+
     let mut path = env::current_dir()?;
     match matches.value_of("path") {
         Some(p_str) => {
@@ -564,6 +920,10 @@ fn start_run_scenario(path: PathBuf, matches: &ArgMatches) -> Result<()> {
             .to_string();
 
         let mut on_change = None;
+        // watcher stops watching once dropped, keep it around until the
+        // end of the session
+        #[cfg(feature = "watcher")]
+        let mut _watcher = None;
 
         if matches.is_present("watch") {
             #[cfg(feature = "watcher")]
@@ -591,6 +951,7 @@ fn start_run_scenario(path: PathBuf, matches: &ArgMatches) -> Result<()> {
                         }
                     })?;
                 watcher.watch(watch_path, notify::RecursiveMode::Recursive)?;
+                _watcher = Some(watcher);
 
                 on_change = match matches.value_of("watch") {
                     Some("restart") => Some(interactive::OnChange {
@@ -619,8 +980,16 @@ fn start_run_scenario(path: PathBuf, matches: &ArgMatches) -> Result<()> {
         })
         .expect("error setting ctrlc handler");
 
+        let mut scenario = Scenario::from_path(path.clone())?;
+        for (key, value) in parse_setting_overrides(matches)? {
+            scenario.manifest.set_setting(&key, &value)?;
+        }
+        let mut sim = Sim::from_scenario(scenario)?;
+        apply_checkpoint_args(&mut sim, matches)?;
+        apply_grid_export_args(&mut sim, matches)?;
+
         interactive::start(
-            interactive::InterfaceType::Scenario(path.to_string_lossy().to_string()),
+            interactive::InterfaceType::Local(sim, Some(path.to_string_lossy().to_string())),
             &config_path,
             on_change,
             Some(OnSignal {
@@ -635,10 +1004,14 @@ fn start_run_scenario(path: PathBuf, matches: &ArgMatches) -> Result<()> {
 fn start_run_snapshot(path: PathBuf, matches: &ArgMatches) -> Result<()> {
     info!("Running interactive session using snapshot at: {:?}", path);
     if matches.is_present("interactive") {
+        let mut sim = Sim::load_snapshot(&path.file_name().unwrap().to_string_lossy(), None)?;
+        for (key, value) in parse_setting_overrides(matches)? {
+            sim.set_setting(&key, &value)?;
+        }
+        apply_checkpoint_args(&mut sim, matches)?;
+        apply_grid_export_args(&mut sim, matches)?;
         interactive::start(
-            interactive::InterfaceType::Snapshot(
-                path.file_name().unwrap().to_string_lossy().to_string(),
-            ),
+            interactive::InterfaceType::Local(sim, None),
             matches.value_of("icfg").unwrap_or(interactive::CONFIG_FILE),
             None,
             None,
@@ -647,6 +1020,173 @@ fn start_run_snapshot(path: PathBuf, matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Resumes simulation from one of the automatic checkpoints stored within
+/// the current project.
+///
+/// Using `latest` selects the newest checkpoint that can be successfully
+/// loaded, skipping any corrupted ones.
+fn start_run_resume(checkpoint: &str, matches: &ArgMatches) -> Result<()> {
+    let root = find_project_root(env::current_dir()?, 4)?;
+    let checkpoints_dir = root
+        .join(outcome::SNAPSHOTS_DIR_NAME)
+        .join(outcome::checkpoint::CHECKPOINTS_DIR_NAME);
+
+    let mut sim = match checkpoint {
+        "latest" => {
+            let (info, sim) = outcome::checkpoint::load_latest_valid(&checkpoints_dir)?;
+            println!(
+                "Resuming from checkpoint {} (clock: {})",
+                info.name, info.clock
+            );
+            sim
+        }
+        name => Sim::load_snapshot_from_dir(&checkpoints_dir, name)?,
+    };
+
+    // checkpoint settings are not part of the snapshot, read them from
+    // the original scenario manifest if it's still available
+    let mut settings = None;
+    for scenario_path in get_scenario_paths(root)? {
+        match outcome::model::ScenarioManifest::from_path(scenario_path) {
+            Ok(manifest) if manifest.name == sim.model.scenario.manifest.name => {
+                settings = manifest.checkpoint;
+                break;
+            }
+            Ok(_) => continue,
+            Err(e) => warn!("failed reading scenario manifest: {}", e),
+        }
+    }
+    sim.set_checkpointing(settings)?;
+    apply_checkpoint_args(&mut sim, matches)?;
+    apply_grid_export_args(&mut sim, matches)?;
+    for (key, value) in parse_setting_overrides(matches)? {
+        sim.set_setting(&key, &value)?;
+    }
+
+    if matches.is_present("interactive") {
+        interactive::start(
+            interactive::InterfaceType::Local(sim, None),
+            matches.value_of("icfg").unwrap_or(interactive::CONFIG_FILE),
+            None,
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Parses scenario setting overrides provided with `--set key=value`.
+fn parse_setting_overrides(matches: &ArgMatches) -> Result<Vec<(String, String)>> {
+    let mut overrides = Vec::new();
+    if let Some(values) = matches.values_of("set") {
+        for value in values {
+            let split = value.splitn(2, '=').collect::<Vec<&str>>();
+            if split.len() != 2 {
+                return Err(Error::msg(format!(
+                    "invalid setting override: {}, expected key=value",
+                    value
+                )));
+            }
+            overrides.push((split[0].trim().to_string(), split[1].trim().to_string()));
+        }
+    }
+    Ok(overrides)
+}
+
+/// Applies checkpoint settings provided as command line arguments,
+/// overriding the ones already set on the simulation.
+fn apply_checkpoint_args(sim: &mut Sim, matches: &ArgMatches) -> Result<()> {
+    let args = [
+        "checkpoint-steps",
+        "checkpoint-minutes",
+        "checkpoint-keep",
+        "checkpoint-keep-every",
+        "checkpoint-compress",
+    ];
+    if !args.iter().any(|arg| matches.is_present(arg)) {
+        return Ok(());
+    }
+
+    let mut settings = sim
+        .checkpointer
+        .as_ref()
+        .map(|c| c.settings.clone())
+        .unwrap_or_default();
+    if let Some(steps) = matches.value_of("checkpoint-steps") {
+        settings.every_steps = Some(steps.parse()?);
+    }
+    if let Some(minutes) = matches.value_of("checkpoint-minutes") {
+        settings.every_minutes = Some(minutes.parse()?);
+    }
+    if let Some(keep) = matches.value_of("checkpoint-keep") {
+        settings.keep_last = keep.parse()?;
+    }
+    if let Some(keep_every) = matches.value_of("checkpoint-keep-every") {
+        settings.keep_every = Some(keep_every.parse()?);
+    }
+    if matches.is_present("checkpoint-compress") {
+        settings.compress = true;
+    }
+    sim.set_checkpointing(Some(settings))?;
+    Ok(())
+}
+
+/// Sets up periodic grid exports provided as command line arguments.
+#[cfg(feature = "save_img")]
+fn apply_grid_export_args(sim: &mut Sim, matches: &ArgMatches) -> Result<()> {
+    let addresses = match matches.values_of("export-grid") {
+        Some(addrs) => addrs,
+        None => return Ok(()),
+    };
+    let mut options = GridImageOptions::default();
+    if let Some(colormap) = matches.value_of("colormap") {
+        options.colormap = colormap.parse()?;
+    }
+    if let Some(range) = matches.value_of("range") {
+        options.range = Some(GridImageOptions::parse_range(range)?);
+    }
+    let every_steps = matches.value_of("export-every").unwrap_or("1").parse()?;
+    let dir = PathBuf::from(matches.value_of("export-dir").unwrap_or("exports"));
+    for address in addresses {
+        sim.grid_exports.push(GridExport {
+            address: address.parse()?,
+            every_steps,
+            dir: dir.clone(),
+            options: options.clone(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "save_img"))]
+fn apply_grid_export_args(_sim: &mut Sim, matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("export-grid") {
+        return Err(Error::msg(
+            "exporting grids requires the `save_img` feature",
+        ));
+    }
+    Ok(())
+}
+
+/// Watches the scenario manifests and the files of the mods used by the
+/// scenario. Other project files, such as snapshots, are not watched.
+#[cfg(feature = "watcher")]
+fn watch_scenario(watcher: &mut RecommendedWatcher, scenario: &Scenario) -> Result<()> {
+    let scenarios_dir = scenario.path.join(outcome::SCENARIOS_DIR_NAME);
+    info!(
+        "watching changes at scenarios path: {}",
+        scenarios_dir.to_string_lossy()
+    );
+    watcher.watch(&scenarios_dir, notify::RecursiveMode::NonRecursive)?;
+    for module in &scenario.modules {
+        info!(
+            "watching changes at mod path: {}",
+            module.path.to_string_lossy()
+        );
+        watcher.watch(&module.path, notify::RecursiveMode::Recursive)?;
+    }
+    Ok(())
+}
+
 fn start_server(matches: &ArgMatches) -> Result<()> {
     let server_address = match matches.value_of("address") {
         Some(addr) => addr,
@@ -732,23 +1272,40 @@ fn start_server(matches: &ArgMatches) -> Result<()> {
         None => Vec::new(),
     };
 
+    let setting_overrides = parse_setting_overrides(matches)?;
+
     let sim_instance = match matches.value_of("organizer") {
         Some(addr) => {
             if let Some(scenario_path) = matches.value_of("scenario") {
-                SimConnection::UnionOrganizer(Organizer::new_with_path(
-                    &scenario_path,
+                let scenario_path = PathBuf::from(scenario_path);
+                let mut scenario = Scenario::from_path(scenario_path.clone())?;
+                for (key, value) in &setting_overrides {
+                    scenario.manifest.set_setting(key, value)?;
+                }
+                let starter = outcome::SimStarter::Scenario(
+                    scenario_path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                );
+                SimConnection::UnionOrganizer(Organizer::new_with_scenario(
+                    scenario,
+                    Some(starter),
                     addr,
                     worker_addrs,
                 )?)
             } else if let Some(snapshot_path) = matches.value_of("snapshot") {
                 let snapshot_path = PathBuf::from(snapshot_path);
                 let project_root = find_project_root(snapshot_path.clone(), 3)?;
-                let central = outcome::distr::central::SimCentral::new_from_project_starter(
+                let mut central = outcome::distr::central::SimCentral::new_from_project_starter(
                     project_root,
                     outcome::SimStarter::Snapshot(String::from(
                         snapshot_path.file_name().unwrap().to_str().unwrap(),
                     )),
                 )?;
+                for (key, value) in &setting_overrides {
+                    central.model.scenario.manifest.set_setting(key, value)?;
+                }
                 SimConnection::UnionOrganizer(Organizer::new(
                     central,
                     matches.value_of("organizer").unwrap_or(""),
@@ -768,9 +1325,18 @@ fn start_server(matches: &ArgMatches) -> Result<()> {
         }
         None => {
             if let Some(scenario_path) = matches.value_of("scenario") {
-                SimConnection::Local(Sim::from_scenario_at(&scenario_path)?)
+                let scenario_path = PathBuf::from(scenario_path).canonicalize()?;
+                let mut scenario = Scenario::from_path(scenario_path)?;
+                for (key, value) in &setting_overrides {
+                    scenario.manifest.set_setting(key, value)?;
+                }
+                SimConnection::Local(Sim::from_scenario(scenario)?)
             } else if let Some(snapshot_path) = matches.value_of("snapshot") {
-                SimConnection::Local(Sim::from_snapshot_at(&snapshot_path)?)
+                let mut sim = Sim::from_snapshot_at(&snapshot_path)?;
+                for (key, value) in &setting_overrides {
+                    sim.set_setting(key, value)?;
+                }
+                SimConnection::Local(sim)
             } else {
                 panic!("")
             }
@@ -780,6 +1346,37 @@ fn start_server(matches: &ArgMatches) -> Result<()> {
     let mut server = Server::new_with_config(server_address, config, sim_instance)?;
     server.initialize_services()?;
 
+    // watcher stops watching once dropped, keep it around until shutdown
+    #[cfg(feature = "watcher")]
+    let mut _watcher = None;
+    if matches.is_present("watch") {
+        #[cfg(feature = "watcher")]
+        {
+            let scenario = match &server.sim {
+                SimConnection::Local(sim) => sim.model.scenario.clone(),
+                _ => return Err(Error::msg("watch is only supported for local simulations")),
+            };
+            let trigger = Arc::new(AtomicBool::new(false));
+            let trigger_clone = trigger.clone();
+            let mut watcher: RecommendedWatcher =
+                Watcher::new_immediate(move |res: Result<notify::Event, notify::Error>| {
+                    match res {
+                        Ok(event) => debug!("change detected: {:?}", event),
+                        Err(e) => error!("watch error: {:?}", e),
+                    }
+                    trigger_clone.store(true, Ordering::SeqCst);
+                })?;
+            watch_scenario(&mut watcher, &scenario)?;
+            _watcher = Some(watcher);
+            server.reload_trigger = Some(trigger);
+        }
+
+        #[cfg(not(feature = "watcher"))]
+        {
+            warn!("tried to use watcher, but that feature is not enabled")
+        }
+    }
+
     // run a loop allowing graceful shutdown
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -881,6 +1478,55 @@ fn start_client(matches: &ArgMatches) -> Result<()> {
     Ok(())
 }
 
+fn start_snapshot(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        ("list", Some(m)) => {
+            let path = match m.value_of("path") {
+                Some(p) => PathBuf::from(p),
+                None => env::current_dir()?,
+            };
+            snapshot::list(path)
+        }
+        ("info", Some(m)) => snapshot::info(snapshot::resolve_path(
+            m.value_of("snapshot").unwrap(),
+        )?),
+        ("diff", Some(m)) => snapshot::diff(
+            snapshot::resolve_path(m.value_of("a").unwrap())?,
+            snapshot::resolve_path(m.value_of("b").unwrap())?,
+        ),
+        ("export", Some(m)) => snapshot::export(
+            snapshot::resolve_path(m.value_of("snapshot").unwrap())?,
+            m.value_of("format").unwrap_or("jsonl").parse()?,
+            snapshot::parse_filters(
+                m.value_of("with-components"),
+                m.value_of("names"),
+                m.value_of("ids"),
+            )?,
+            snapshot::parse_mappings(m.value_of("components"), m.value_of("vars")),
+            m.value_of("out").map(|p| PathBuf::from(p)),
+        ),
+        _ => Ok(()),
+    }
+}
+
+fn start_mod(matches: &ArgMatches) -> Result<()> {
+    match matches.subcommand() {
+        ("list", Some(m)) => {
+            let path = match m.value_of("path") {
+                Some(p) => PathBuf::from(p),
+                None => env::current_dir()?,
+            };
+            mods::list(path)
+        }
+        ("install", Some(m)) => mods::install(
+            PathBuf::from(m.value_of("path").unwrap()),
+            m.is_present("force"),
+        ),
+        ("remove", Some(m)) => mods::remove(m.value_of("name").unwrap(), m.value_of("version")),
+        _ => Ok(()),
+    }
+}
+
 fn start_worker(matches: &ArgMatches) -> Result<()> {
     let mut use_auth = matches.is_present("use_auth");
     let passwd_list = match matches.value_of("passwd") {
@@ -981,5 +1627,10 @@ fn setup_log_verbosity(matches: &ArgMatches) {
         //.set_location_level(LevelFilter::Trace)
         .set_time_format_str("%H:%M:%S%.6f")
         .build();
-    TermLogger::init(level_filter, logger_conf, simplelog::TerminalMode::Mixed);
+    // language server uses stdout for communicating with the client
+    let terminal_mode = match matches.subcommand_name() {
+        Some("lsp") => simplelog::TerminalMode::Stderr,
+        _ => simplelog::TerminalMode::Mixed,
+    };
+    TermLogger::init(level_filter, logger_conf, terminal_mode);
 }
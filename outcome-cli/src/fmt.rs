@@ -0,0 +1,75 @@
+//! Formatting scripts into their canonical form.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Error, Result};
+use outcome::machine::script::{formatter, SCRIPT_FILE_EXTENSION};
+use outcome::util::find_files_with_extension;
+
+/// Formats the script at the given path, or all the scripts found within
+/// the directory if the path points to one.
+///
+/// In check mode files are left untouched, and an error is returned if
+/// any of them is not formatted.
+pub fn format(path: PathBuf, check: bool) -> Result<()> {
+    let paths = if path.is_file() {
+        vec![path]
+    } else {
+        let mut paths =
+            find_files_with_extension(path, vec![&SCRIPT_FILE_EXTENSION[1..]], true, None);
+        paths.sort();
+        paths
+    };
+    if paths.is_empty() {
+        return Err(Error::msg("no scripts found"));
+    }
+
+    let mut unformatted = 0;
+    let mut errors = 0;
+    for path in paths {
+        let text = fs::read_to_string(&path)?;
+        let formatted = match formatter::format(&text) {
+            Ok(f) => f,
+            Err(e) => {
+                let line = e
+                    .location()
+                    .source_line
+                    .map(|l| l.to_string())
+                    .unwrap_or("unknown".to_string());
+                println!(
+                    "failed formatting {}, line {}: {}",
+                    path.to_string_lossy(),
+                    line,
+                    e.message()
+                );
+                errors += 1;
+                continue;
+            }
+        };
+        if formatted == text {
+            continue;
+        }
+        unformatted += 1;
+        if check {
+            println!("not formatted: {}", path.to_string_lossy());
+        } else {
+            fs::write(&path, formatted)?;
+            println!("formatted: {}", path.to_string_lossy());
+        }
+    }
+
+    if errors > 0 {
+        return Err(Error::msg(format!(
+            "failed formatting {} script(s)",
+            errors
+        )));
+    }
+    if check && unformatted > 0 {
+        return Err(Error::msg(format!(
+            "{} script(s) not formatted",
+            unformatted
+        )));
+    }
+    Ok(())
+}
@@ -39,9 +39,14 @@ website: theoutcomeproject.com
 # list of modules
 mods:
 - init_module: 0.1.0
-# settings
+# settings, each with a type, default value, description and the
+# target address the value is applied to at initialization
 #settings:
-#  /uni/const/quantum_drive_tech_possible: false
+#  quantum_drive_tech_possible:
+#    type: bool
+#    default: false
+#    desc: Whether quantum drive technology can be researched
+#    target: uni:const:bool:quantum_drive_tech_possible
 "##,
             name = name.replace(" ", "_"),
             title = name.replace("_", " "),
@@ -81,9 +86,13 @@ outcome: 0.1.1
 # list of modules
 modules:
   test_module: 0.1.0
-# settings, not working yet
+# settings, can be overridden using `--set key=value`
 settings:
-  /uni/const/quantum_drive_tech_possible: false
+  quantum_drive_tech_possible:
+    type: bool
+    default: false
+    desc: Whether quantum drive technology can be researched
+    target: uni:const:bool:quantum_drive_tech_possible
 "##,
             name = name,
             title = name.replace("_", " "),
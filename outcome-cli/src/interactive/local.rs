@@ -1,6 +1,9 @@
-use outcome::{Address, Sim};
+use outcome::machine::debug::{BreakpointLocation, DebugStatus};
+use outcome::sim::StepStatus;
+use outcome::{string, Address, EntityId, Sim};
 
 use crate::interactive::Config;
+use anyhow::Result;
 use std::str::FromStr;
 
 /// Create the prompt string. It defaults to current clock tick integer number.
@@ -120,10 +123,114 @@ pub fn print_show(sim: &Sim, config: &Config) {
 pub fn process_step(sim: &mut Sim, config: &Config) {
     let turn_ticks: i32 = config.get("turn_ticks").unwrap().parse().unwrap();
     for n in 0..turn_ticks {
-        sim.step();
+        match sim.step() {
+            Ok(StepStatus::Finished) => (),
+            Ok(StepStatus::Paused) => {
+                print_paused(sim);
+                return;
+            }
+            Err(e) => {
+                println!("{}", e);
+                return;
+            }
+        }
     }
 
     if config.show_on {
         print_show(&sim, config);
     }
 }
+
+/// Adds a new breakpoint, attaching a debugger if needed. Lists existing
+/// breakpoints if no arguments are provided.
+///
+/// Breakpoint location is either `source:line` or `comp:state`, optionally
+/// followed by name or id of the entity the breakpoint is restricted to.
+pub fn process_break(sim: &mut Sim, args: &str) -> Result<()> {
+    let split = args.split_whitespace().collect::<Vec<&str>>();
+    if split.is_empty() {
+        match sim.debugger() {
+            Some(debugger) if !debugger.breakpoints().is_empty() => {
+                for bp in debugger.breakpoints() {
+                    match bp.entity {
+                        Some(ent) => println!("{}: {} (entity {})", bp.id, bp.location, ent),
+                        None => println!("{}: {}", bp.id, bp.location),
+                    }
+                }
+            }
+            _ => println!("no breakpoints set"),
+        }
+        return Ok(());
+    }
+    let location = BreakpointLocation::from_str(split[0])?;
+    let entity = match split.get(1) {
+        Some(ent) => match sim.entity_idx.get(&string::new_truncate(ent)) {
+            Some(id) => Some(*id),
+            None => Some(
+                ent.parse::<EntityId>()
+                    .map_err(|_| anyhow::anyhow!("entity not found: {}", ent))?,
+            ),
+        },
+        None => None,
+    };
+    let id = sim.attach_debugger().add_breakpoint(location, entity);
+    println!("breakpoint {} set", id);
+    Ok(())
+}
+
+pub fn print_debug_status(status: &DebugStatus) {
+    match status {
+        DebugStatus::Paused(info) => println!("{}", info),
+        DebugStatus::Finished => println!("step finished"),
+    }
+}
+
+/// Prints information about the paused execution, returning `false` if
+/// the simulation is not paused.
+pub fn print_paused(sim: &Sim) -> bool {
+    match sim.debugger().and_then(|d| d.paused()) {
+        Some(info) => {
+            println!("{}", info);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Prints vars of the paused entity, along with the local vars, the call
+/// stack and the registry.
+pub fn print_locals(sim: &Sim) {
+    let locals = match sim.debug_locals() {
+        Some(l) => l,
+        None => {
+            println!("not paused");
+            return;
+        }
+    };
+    for ((comp, var_name), var) in &locals.vars {
+        println!(
+            "{}:{}:{} = {}",
+            comp,
+            var.get_type().to_str(),
+            var_name,
+            var.to_string()
+        );
+    }
+    if !locals.local_vars.is_empty() {
+        println!("local vars:");
+        for ((comp, var_name), var) in &locals.local_vars {
+            println!(
+                "  {}:{}:{} = {}",
+                comp,
+                var.get_type().to_str(),
+                var_name,
+                var.to_string()
+            );
+        }
+    }
+    println!("call stack:");
+    for call in locals.call_stack.iter().rev() {
+        println!("  {:?}", call);
+    }
+    println!("registry: {:?}", locals.registry);
+}
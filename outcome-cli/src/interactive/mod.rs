@@ -19,6 +19,7 @@ mod img_print;
 use std::io::Write;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{fs, io, thread};
@@ -27,6 +28,8 @@ use anyhow::Result;
 use linefeed::inputrc::parse_text;
 use linefeed::{Interface, ReadResult};
 
+use outcome::model::VarOverride;
+use outcome::sim::StepStatus;
 use outcome::Sim;
 use outcome_net::{Client, SocketEvent, SocketEventType};
 
@@ -182,6 +185,9 @@ pub enum OnSignalAction {
 pub enum InterfaceType {
     Scenario(String),
     Snapshot(String),
+    /// Already initialized local simulation, optionally along with the path
+    /// to the scenario it was created from
+    Local(Sim, Option<String>),
     Remote(Client),
 }
 
@@ -209,11 +215,13 @@ pub fn start(
     let path = match &_type {
         InterfaceType::Scenario(path) => Some(path.clone()),
         InterfaceType::Snapshot(path) => Some(path.clone()),
+        InterfaceType::Local(_, path) => path.clone(),
         _ => None,
     };
     let mut sim_driver = match _type {
         InterfaceType::Scenario(path) => SimDriver::Local(Sim::from_scenario_at(&path)?),
         InterfaceType::Snapshot(path) => SimDriver::Local(Sim::load_snapshot(&path, None)?),
+        InterfaceType::Local(sim, _) => SimDriver::Local(sim),
         InterfaceType::Remote(client) => SimDriver::Remote(client),
         _ => unimplemented!(),
     };
@@ -334,7 +342,12 @@ pub fn start(
                 if do_run {
                     match driver.deref_mut() {
                         SimDriver::Local(ref mut sim) => {
-                            sim.step()?;
+                            if sim.step()? == StepStatus::Paused {
+                                local::print_paused(sim);
+                                do_run_freq = None;
+                                do_run_loop = false;
+                                run_loop_count = 0;
+                            }
                             interface.set_prompt(create_prompt(&mut driver, &config)?.as_str())?;
                         }
                         SimDriver::Remote(client) => {
@@ -405,7 +418,10 @@ pub fn start(
                                 match driver.deref_mut() {
                                     SimDriver::Local(ref mut sim) => {
                                         while loop_count > 0 {
-                                            sim.step();
+                                            if sim.step()? == StepStatus::Paused {
+                                                local::print_paused(sim);
+                                                break;
+                                            }
                                             loop_count -= 1;
                                         }
                                     }
@@ -445,10 +461,16 @@ pub fn start(
                                     last = Instant::now();
                                     match driver.deref_mut() {
                                         SimDriver::Local(ref mut sim) => {
-                                            sim.step()?;
+                                            let paused = sim.step()? == StepStatus::Paused;
+                                            if paused {
+                                                local::print_paused(sim);
+                                            }
                                             interface.set_prompt(
                                                 create_prompt(&mut driver, &config)?.as_str(),
                                             )?;
+                                            if paused {
+                                                break;
+                                            }
                                         }
                                         SimDriver::Remote(client) => {
                                             let msg = client.server_step_request(1)?;
@@ -506,23 +528,48 @@ pub fn start(
                                 }
                             },
                             // spawn entity
+                            // additional arguments are var overrides in the
+                            // `comp:type:var=value` format
                             "spawn" => {
                                 let split = args.split(" ").collect::<Vec<&str>>();
+                                if split.len() < 2 {
+                                    println!(
+                                        "Usage: spawn <prefab> <name> [comp:type:var=value]..."
+                                    );
+                                    continue;
+                                }
+                                let vars = split[2..]
+                                    .iter()
+                                    .map(|s| s.to_string())
+                                    .collect::<Vec<_>>();
                                 match driver.deref_mut() {
                                     SimDriver::Remote(client) => {
                                         client.connection.send_payload(
                                             SpawnEntitiesRequest {
                                                 entity_prefabs: vec![split[0].to_string()],
                                                 entity_names: vec![split[1].to_string()],
+                                                entity_vars: vec![vars],
                                             },
                                             None,
                                         )?;
                                         client.connection.recv_msg()?;
                                     }
                                     SimDriver::Local(sim) => {
-                                        sim.spawn_entity(
+                                        let vars = match vars
+                                            .iter()
+                                            .map(|v| VarOverride::from_str(v))
+                                            .collect::<outcome::Result<Vec<_>>>()
+                                        {
+                                            Ok(v) => v,
+                                            Err(e) => {
+                                                println!("{}", e);
+                                                continue;
+                                            }
+                                        };
+                                        sim.spawn_entity_with(
                                             Some(&outcome::string::new_truncate(split[0])),
                                             Some(outcome::string::new_truncate(split[1])),
+                                            &vars,
                                         )?;
                                     }
                                 }
@@ -551,6 +598,7 @@ pub fn start(
                                             SpawnEntitiesRequest {
                                                 entity_prefabs: vec![split[0].to_string()],
                                                 entity_names: vec![split[1].to_string()],
+                                                entity_vars: vec![],
                                             },
                                             None,
                                         )?;
@@ -607,6 +655,62 @@ pub fn start(
                                 };
                             }
 
+                            "break" => match driver.deref_mut() {
+                                SimDriver::Local(sim) => {
+                                    if let Err(e) = local::process_break(sim, args) {
+                                        println!("{}", e);
+                                    }
+                                }
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+                            "break-rm" => match driver.deref_mut() {
+                                SimDriver::Local(sim) => {
+                                    let removed = match (sim.debugger_mut(), args.parse()) {
+                                        (Some(debugger), Ok(id)) => debugger.remove_breakpoint(id),
+                                        _ => false,
+                                    };
+                                    if !removed {
+                                        println!("no breakpoint with id: {}", args);
+                                    }
+                                }
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+                            "step-cmd" => match driver.deref_mut() {
+                                SimDriver::Local(sim) => {
+                                    sim.attach_debugger();
+                                    match sim.debug_step_cmd() {
+                                        Ok(status) => local::print_debug_status(&status),
+                                        Err(e) => println!("{}", e),
+                                    }
+                                }
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+                            "continue" => match driver.deref_mut() {
+                                SimDriver::Local(sim) => {
+                                    if sim.debugger().is_none() {
+                                        println!("debugger not attached, set a breakpoint first");
+                                    } else {
+                                        match sim.debug_continue() {
+                                            Ok(status) => local::print_debug_status(&status),
+                                            Err(e) => println!("{}", e),
+                                        }
+                                    }
+                                }
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+                            "detach" => match driver.deref_mut() {
+                                SimDriver::Local(sim) => match sim.detach_debugger() {
+                                    Ok(Some(_)) => println!("debugger detached"),
+                                    Ok(None) => println!("debugger not attached"),
+                                    Err(e) => println!("{}", e),
+                                },
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+                            "locals" => match driver.deref() {
+                                SimDriver::Local(sim) => local::print_locals(sim),
+                                SimDriver::Remote(_) => println!("{}", DEBUG_LOCAL_ONLY),
+                            },
+
                             "help" => {
                                 println!("available commands:");
                                 println!();
@@ -758,7 +862,6 @@ show_list               {show_list}
                         if do_run_freq.is_none() && !do_run_loop {
                             interface.set_prompt(create_prompt(&mut driver, &config)?.as_str())?;
                         }
-                        std::mem::drop(driver);
                     }
                     // handle quitting using signals and eof
                     ReadResult::Signal(Signal::Break)
@@ -792,15 +895,23 @@ show_list               {show_list}
                         }
                         OnChangeAction::UpdateModel => {
                             warn!("changes to project files detected: updating model...");
-                            if let SimDriver::Local(sim) = driver_arc.lock().unwrap().deref_mut() {
-                                let new_model =
-                                    Sim::from_scenario_at(&path.clone().unwrap())?.model;
-
-                                sim.model = new_model;
+                            // driver is still locked from the start of the loop
+                            let update: Result<_> = match driver.deref_mut() {
+                                SimDriver::Local(sim) => sim.reload_model().map_err(Into::into),
+                                SimDriver::Remote(client) => {
+                                    client.reload_model().map_err(Into::into)
+                                }
+                            };
+                            match update {
+                                Ok(update) => {
+                                    print!("{}", update.diff);
+                                    for issue in &update.incompatible {
+                                        println!("{}", issue);
+                                    }
+                                }
+                                Err(e) => println!("failed updating model: {}", e),
                             }
                             *oc = false;
-
-                            // let new_model;
                         }
                     }
                 }
@@ -823,6 +934,8 @@ pub fn create_prompt(driver: &mut SimDriver, cfg: &Config) -> Result<String> {
     }
 }
 
+const DEBUG_LOCAL_ONLY: &str = "debugging is only supported for local simulations";
+
 static APP_COMMANDS: &[(&str, &str)] = &[
     ("run", "Run a number of simulation ticks (hours), takes in an integer number"),
     ("runf", "Similar to `run` but doesn't listen to interupt signals, `f` stands for \"fast\" \
@@ -848,6 +961,13 @@ static APP_COMMANDS: &[(&str, &str)] = &[
         "Clear the list of simulation data to be shown",
     ),
     ("show-toggle", "Toggle automatic printing after each turn"),
+    ("break", "Set a breakpoint at `source:line` or `comp:state`, optionally followed by \
+        entity name or id. Lists breakpoints if no arguments are given"),
+    ("break-rm", "Remove breakpoint with the given id"),
+    ("step-cmd", "Execute a single command and pause before the next one"),
+    ("continue", "Continue execution until the next breakpoint or the end of the step"),
+    ("locals", "Print vars, call stack and registry of the paused execution"),
+    ("detach", "Detach the debugger, finishing the paused step and removing all breakpoints"),
     ("history", "Print input history"),
     ("help", "Show available commands"),
     (
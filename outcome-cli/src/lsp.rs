@@ -0,0 +1,710 @@
+//! Language server for outcome scripts.
+//!
+//! Server talks to the editor over stdio. It provides diagnostics for the
+//! open scripts, completion of command names and var addresses, jumping
+//! to definitions of components, procedures, states and included files,
+//! and hover information showing var types.
+//!
+//! Open scripts are parsed and their commands initialized on every change.
+//! The whole project model is loaded and checked on startup and whenever
+//! a script is saved.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{Completion, GotoDefinition, HoverRequest, Request as _};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DidSaveTextDocumentParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeParams, Location, MarkupContent, MarkupKind, OneOf, Position,
+    PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+
+use outcome::address::ShortLocalAddress;
+use outcome::check::{self, Severity};
+use outcome::error::Error as CoreError;
+use outcome::machine::cmd::Command;
+use outcome::machine::script::{parser, preprocessor, InstructionType, SCRIPT_FILE_EXTENSION};
+use outcome::machine::{CommandPrototype, LocationInfo};
+use outcome::model::{Scenario, SimModel};
+use outcome::util::{find_files_with_extension, find_project_root, get_scenario_paths};
+use outcome::VarType;
+
+/// Starts the language server, communicating over stdio.
+///
+/// Project root is searched for starting at the given path, or at the
+/// root provided by the client if no path is given.
+pub fn run(path: Option<PathBuf>) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = serde_json::to_value(&ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["$".to_string(), ":".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        ..ServerCapabilities::default()
+    })?;
+    let params: InitializeParams = serde_json::from_value(connection.initialize(capabilities)?)?;
+
+    let root = path
+        .or_else(|| params.root_uri.and_then(|uri| uri.to_file_path().ok()))
+        .and_then(|path| find_project_root(normalize(path), 4).ok());
+    match &root {
+        Some(root) => info!("language server started at {}", root.to_string_lossy()),
+        None => warn!("language server started without a project root"),
+    }
+
+    let mut server = Server::new(root);
+    server.reload_project(&connection)?;
+    for message in &connection.receiver {
+        match message {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                server.handle_request(&connection, req)?;
+            }
+            Message::Notification(not) => server.handle_notification(&connection, not)?,
+            Message::Response(_) => (),
+        }
+    }
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Kind of a named block declared within a script.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Component,
+    Procedure,
+    State,
+}
+
+/// Named block declared within a script.
+#[derive(Debug, Clone)]
+struct Symbol {
+    kind: SymbolKind,
+    name: String,
+    path: PathBuf,
+    line: u32,
+}
+
+/// Var declared either within the model or within a script.
+#[derive(Debug, Clone)]
+struct VarInfo {
+    comp: String,
+    type_: VarType,
+    name: String,
+    default: Option<String>,
+}
+
+impl VarInfo {
+    fn address(&self) -> String {
+        format!("{}:{}:{}", self.comp, self.type_.to_str(), self.name)
+    }
+}
+
+/// Information gathered from a single script.
+#[derive(Debug, Default)]
+struct Script {
+    diagnostics: Vec<Diagnostic>,
+    symbols: Vec<Symbol>,
+    /// Included files along with lines of the include directives
+    includes: Vec<(u32, PathBuf)>,
+    vars: Vec<VarInfo>,
+}
+
+impl Script {
+    /// Parses the script and initializes its commands, collecting
+    /// problems along with declared symbols.
+    ///
+    /// Commands are initialized in the context of the script alone, the
+    /// same way as they would be after including it.
+    fn analyze(text: &str, path: &Path, root: &Path) -> Script {
+        let mut script = Script::default();
+
+        let mut location = LocationInfo::empty();
+        location.source = path
+            .strip_prefix(root)
+            .unwrap_or(path)
+            .to_str()
+            .and_then(|s| s.parse().ok());
+        let instructions = match parser::parse_lines(text, location) {
+            Ok(i) => i,
+            Err(e) => {
+                script.push_error(text, e.location(), e.message());
+                return script;
+            }
+        };
+
+        for (location, include) in preprocessor::list_includes(&instructions) {
+            script
+                .includes
+                .push((line_of(&location), normalize(root.join(include))));
+        }
+
+        let mut prototypes: Vec<CommandPrototype> = Vec::new();
+        let mut locations: Vec<LocationInfo> = Vec::new();
+        for instruction in instructions {
+            if let InstructionType::Command(proto) = instruction.type_ {
+                prototypes.push(proto);
+                locations.push(instruction.location);
+            }
+        }
+
+        let mut comp = None;
+        for (n, proto) in prototypes.iter().enumerate() {
+            locations[n].line = Some(n);
+            let location = &locations[n];
+            let name = proto.name.as_deref().unwrap_or("");
+            let args = proto.arguments.clone().unwrap_or_default();
+
+            let kind = match name {
+                "component" | "comp" => Some(SymbolKind::Component),
+                "proc" | "procedure" => Some(SymbolKind::Procedure),
+                "state" => Some(SymbolKind::State),
+                _ => None,
+            };
+            if let (Some(kind), Some(arg)) = (kind, args.first()) {
+                if kind == SymbolKind::Component {
+                    comp = Some(arg.to_string());
+                }
+                script.symbols.push(Symbol {
+                    kind,
+                    name: arg.to_string(),
+                    path: path.to_path_buf(),
+                    line: line_of(location),
+                });
+            }
+            if name == "var" {
+                if let Some(var) = args.first().and_then(|a| parse_address(a)) {
+                    script.vars.push(VarInfo {
+                        comp: var
+                            .comp
+                            .map(|c| c.to_string())
+                            .or(comp.clone())
+                            .unwrap_or_default(),
+                        type_: var.var_type,
+                        name: var.var_name.to_string(),
+                        default: args.iter().skip(1).find(|a| *a != "=").cloned(),
+                    });
+                }
+            }
+
+            if let Err(e) = Command::from_prototype(proto, location, &prototypes) {
+                script.push_error(text, location, e.message());
+            }
+        }
+        script
+    }
+
+    fn push_error(&mut self, text: &str, location: &LocationInfo, message: String) {
+        let line = line_of(location);
+        if self
+            .diagnostics
+            .iter()
+            .any(|d| d.range.start.line == line && d.message == message)
+        {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            severity: Some(DiagnosticSeverity::Error),
+            source: Some("outcome".to_string()),
+            ..Diagnostic::new_simple(line_range(text, line), message)
+        });
+    }
+}
+
+struct Server {
+    root: Option<PathBuf>,
+    /// Contents of the open documents
+    documents: HashMap<Url, String>,
+    /// Diagnostics from analyzing the open documents
+    doc_diagnostics: HashMap<Url, Vec<Diagnostic>>,
+    /// Diagnostics from loading and checking the model, by file
+    project_diagnostics: HashMap<PathBuf, Vec<Diagnostic>>,
+    /// Model as of the last successful load
+    model: Option<SimModel>,
+}
+
+impl Server {
+    fn new(root: Option<PathBuf>) -> Self {
+        Server {
+            root,
+            documents: HashMap::new(),
+            doc_diagnostics: HashMap::new(),
+            project_diagnostics: HashMap::new(),
+            model: None,
+        }
+    }
+
+    fn handle_request(&mut self, conn: &Connection, req: Request) -> Result<()> {
+        let result = match req.method.as_str() {
+            Completion::METHOD => match serde_json::from_value::<CompletionParams>(req.params) {
+                Ok(params) => serde_json::to_value(self.completion(params))?,
+                Err(e) => return reply_invalid_params(conn, req.id, e),
+            },
+            GotoDefinition::METHOD => {
+                match serde_json::from_value::<GotoDefinitionParams>(req.params) {
+                    Ok(params) => serde_json::to_value(self.definition(params))?,
+                    Err(e) => return reply_invalid_params(conn, req.id, e),
+                }
+            }
+            HoverRequest::METHOD => match serde_json::from_value::<HoverParams>(req.params) {
+                Ok(params) => serde_json::to_value(self.hover(params))?,
+                Err(e) => return reply_invalid_params(conn, req.id, e),
+            },
+            method => {
+                let response = Response::new_err(
+                    req.id,
+                    ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request: {}", method),
+                );
+                conn.sender.send(Message::Response(response))?;
+                return Ok(());
+            }
+        };
+        conn.sender
+            .send(Message::Response(Response::new_ok(req.id, result)))?;
+        Ok(())
+    }
+
+    fn handle_notification(&mut self, conn: &Connection, not: Notification) -> Result<()> {
+        match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let doc = params.text_document;
+                self.update_document(conn, doc.uri, doc.text)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                // using full document sync, last change holds the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.update_document(conn, params.text_document.uri, change.text)?;
+                }
+            }
+            DidSaveTextDocument::METHOD => {
+                let _: DidSaveTextDocumentParams = serde_json::from_value(not.params)?;
+                self.reload_project(conn)?;
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.doc_diagnostics.remove(&uri);
+                self.publish(conn, &uri)?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn update_document(&mut self, conn: &Connection, uri: Url, text: String) -> Result<()> {
+        if let Ok(path) = uri.to_file_path() {
+            let path = normalize(path);
+            let script = Script::analyze(&text, &path, &self.root_for(&path));
+            self.doc_diagnostics.insert(uri.clone(), script.diagnostics);
+        }
+        self.documents.insert(uri.clone(), text);
+        self.publish(conn, &uri)
+    }
+
+    /// Loads and checks the project model, publishing the diagnostics.
+    fn reload_project(&mut self, conn: &Connection) -> Result<()> {
+        let root = match &self.root {
+            Some(r) => r.clone(),
+            None => return Ok(()),
+        };
+        let previous = self
+            .project_diagnostics
+            .drain()
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+
+        let mut scenarios = get_scenario_paths(root.clone())?;
+        scenarios.sort();
+        if let Some(scenario_path) = scenarios.first() {
+            let (model, diagnostics) = load_model(scenario_path);
+            for diagnostic in diagnostics {
+                let (path, line) = match &diagnostic.location {
+                    Some(location) => {
+                        let base = location
+                            .root
+                            .as_ref()
+                            .map(|r| PathBuf::from(r.as_str()))
+                            .unwrap_or_else(|| root.clone());
+                        let source = location.source.as_ref().map(|s| s.as_str()).unwrap_or("");
+                        (normalize(base.join(source)), line_of(location))
+                    }
+                    None => (normalize(scenario_path.clone()), 0),
+                };
+                let range = line_range(&self.text_of(&path), line);
+                let severity = match diagnostic.severity {
+                    Severity::Error => DiagnosticSeverity::Error,
+                    Severity::Warning => DiagnosticSeverity::Warning,
+                };
+                self.project_diagnostics
+                    .entry(path)
+                    .or_default()
+                    .push(Diagnostic {
+                        severity: Some(severity),
+                        source: Some("outcome".to_string()),
+                        ..Diagnostic::new_simple(range, diagnostic.message)
+                    });
+            }
+            if model.is_some() {
+                self.model = model;
+            }
+        }
+
+        let mut uris = self.documents.keys().cloned().collect::<HashSet<_>>();
+        for path in previous.iter().chain(self.project_diagnostics.keys()) {
+            if let Ok(uri) = Url::from_file_path(path) {
+                uris.insert(uri);
+            }
+        }
+        for uri in uris {
+            self.publish(conn, &uri)?;
+        }
+        Ok(())
+    }
+
+    /// Publishes diagnostics for the document, merging the ones coming
+    /// from the document itself with the ones from the model check.
+    fn publish(&self, conn: &Connection, uri: &Url) -> Result<()> {
+        let mut diagnostics = self.doc_diagnostics.get(uri).cloned().unwrap_or_default();
+        let project = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| self.project_diagnostics.get(&normalize(path)));
+        for diagnostic in project.into_iter().flatten() {
+            if !diagnostics.iter().any(|d| {
+                d.range.start.line == diagnostic.range.start.line && d.message == diagnostic.message
+            }) {
+                diagnostics.push(diagnostic.clone());
+            }
+        }
+        let params = PublishDiagnosticsParams {
+            uri: uri.clone(),
+            diagnostics,
+            version: None,
+        };
+        conn.sender.send(Message::Notification(Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            params,
+        )))?;
+        Ok(())
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position.position;
+        let uri = params.text_document_position.text_document.uri;
+        let line = self
+            .documents
+            .get(&uri)?
+            .lines()
+            .nth(position.line as usize)?;
+        let before = line
+            .chars()
+            .take(position.character as usize)
+            .collect::<String>();
+
+        // first word on the line is the command name
+        let before = before.trim_start();
+        if !before.contains(char::is_whitespace) && !before.starts_with('!') {
+            let items = Command::names()
+                .into_iter()
+                .map(|name| CompletionItem {
+                    label: name.to_string(),
+                    kind: Some(CompletionItemKind::Keyword),
+                    ..CompletionItem::default()
+                })
+                .collect();
+            return Some(CompletionResponse::Array(items));
+        }
+
+        let prefix = if before.rsplit(char::is_whitespace).next()?.starts_with('$') {
+            "$"
+        } else {
+            ""
+        };
+        let items = self
+            .known_vars(&uri)
+            .into_iter()
+            .map(|var| CompletionItem {
+                label: format!("{}{}", prefix, var.address()),
+                kind: Some(CompletionItemKind::Variable),
+                detail: Some(var.type_.to_str().to_string()),
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        let path = normalize(uri.to_file_path().ok()?);
+        let text = self.documents.get(&uri)?;
+        let root = self.root_for(&path);
+
+        let script = Script::analyze(text, &path, &root);
+        if let Some((_, include)) = script.includes.iter().find(|(l, _)| *l == position.line) {
+            let uri = Url::from_file_path(include).ok()?;
+            return Some(GotoDefinitionResponse::Scalar(Location::new(
+                uri,
+                Range::default(),
+            )));
+        }
+
+        let word = word_at(text, position)?;
+        // calls and state transitions only point to blocks of given kind
+        let line = text.lines().nth(position.line as usize).unwrap_or("");
+        let kind = match line.split_whitespace().next() {
+            Some("call") => Some(SymbolKind::Procedure),
+            Some("goto") => Some(SymbolKind::State),
+            _ => None,
+        };
+        let matches = |s: &Symbol| s.name == word && kind.map(|k| k == s.kind).unwrap_or(true);
+
+        // symbols from the current script come first
+        let mut symbols = script
+            .symbols
+            .into_iter()
+            .filter(|s| matches(s))
+            .collect::<Vec<_>>();
+        for other in self.project_scripts(&root) {
+            if other == path {
+                continue;
+            }
+            let script = Script::analyze(&self.text_of(&other), &other, &root);
+            symbols.extend(script.symbols.into_iter().filter(|s| matches(s)));
+        }
+
+        let locations = symbols
+            .into_iter()
+            .filter_map(|s| {
+                let range = Range::new(Position::new(s.line, 0), Position::new(s.line, 0));
+                Some(Location::new(Url::from_file_path(&s.path).ok()?, range))
+            })
+            .collect::<Vec<_>>();
+        match locations.len() {
+            0 => None,
+            1 => Some(GotoDefinitionResponse::Scalar(locations[0].clone())),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        }
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params.position;
+        let uri = params.text_document_position_params.text_document.uri;
+        let word = word_at(self.documents.get(&uri)?, position)?;
+        let vars = self.known_vars(&uri);
+
+        // component name shows the list of its vars
+        if vars.iter().any(|v| v.comp == word) {
+            let mut value = format!("component `{}`\n", word);
+            for var in vars.iter().filter(|v| v.comp == word) {
+                value.push_str(&format!("\n- `{}:{}`", var.type_.to_str(), var.name));
+                if let Some(default) = &var.default {
+                    value.push_str(&format!(" = `{}`", default));
+                }
+            }
+            return Some(markdown_hover(value));
+        }
+
+        let addr = parse_address(&word)?;
+        let var = vars.iter().find(|v| {
+            v.name == addr.var_name.as_str()
+                && v.type_ == addr.var_type
+                && addr
+                    .comp
+                    .as_ref()
+                    .map(|c| c.as_str() == v.comp)
+                    .unwrap_or(true)
+        });
+        let value = match var {
+            Some(var) => {
+                let mut value = format!(
+                    "var `{}` of type `{}`, component `{}`",
+                    var.name,
+                    var.type_.to_str(),
+                    var.comp
+                );
+                if let Some(default) = &var.default {
+                    value.push_str(&format!(", default `{}`", default));
+                }
+                value
+            }
+            None => format!(
+                "var `{}` of type `{}`, not declared",
+                addr.var_name,
+                addr.var_type.to_str()
+            ),
+        };
+        Some(markdown_hover(value))
+    }
+
+    /// Collects vars declared in the model along with the ones declared
+    /// within the given document.
+    fn known_vars(&self, uri: &Url) -> Vec<VarInfo> {
+        let mut vars = Vec::new();
+        if let Some(model) = &self.model {
+            for comp in &model.components {
+                for var in &comp.vars {
+                    vars.push(VarInfo {
+                        comp: comp.name.to_string(),
+                        type_: var.type_,
+                        name: var.name.to_string(),
+                        default: var.default.as_ref().map(|v| v.to_string()),
+                    });
+                }
+            }
+        }
+        if let (Some(text), Ok(path)) = (self.documents.get(uri), uri.to_file_path()) {
+            let path = normalize(path);
+            let script = Script::analyze(text, &path, &self.root_for(&path));
+            for var in script.vars {
+                if !vars.iter().any(|v| v.address() == var.address()) {
+                    vars.push(var);
+                }
+            }
+        }
+        vars
+    }
+
+    /// Lists all the script files within the project.
+    fn project_scripts(&self, root: &Path) -> Vec<PathBuf> {
+        find_files_with_extension(
+            root.join(outcome::MODULES_DIR_NAME),
+            vec![&SCRIPT_FILE_EXTENSION[1..]],
+            true,
+            None,
+        )
+        .into_iter()
+        .map(normalize)
+        .collect()
+    }
+
+    fn root_for(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => root.clone(),
+            None => path.parent().map(|p| p.to_path_buf()).unwrap_or_default(),
+        }
+    }
+
+    /// Gets the text of the file, preferring the contents of the open
+    /// document over the file on disk.
+    fn text_of(&self, path: &Path) -> String {
+        Url::from_file_path(path)
+            .ok()
+            .and_then(|uri| self.documents.get(&uri).cloned())
+            .or_else(|| fs::read_to_string(path).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Loads the model for the scenario at the given path and checks it.
+///
+/// Errors coming from the scripts are reported along with their locations.
+fn load_model(path: &Path) -> (Option<SimModel>, Vec<check::Diagnostic>) {
+    let error = |message, location| check::Diagnostic {
+        severity: Severity::Error,
+        message,
+        location,
+    };
+    match Scenario::from_path(path.to_path_buf()).and_then(SimModel::from_scenario) {
+        Ok(model) => {
+            let report = check::check_model(&model);
+            (Some(model), report.diagnostics)
+        }
+        Err(CoreError::MachinePanic(e)) => {
+            (None, vec![error(e.message(), Some(e.location().clone()))])
+        }
+        Err(e) => (
+            None,
+            vec![error(format!("failed loading scenario: {}", e), None)],
+        ),
+    }
+}
+
+/// Replies to the request with an error about its malformed params.
+fn reply_invalid_params(conn: &Connection, id: RequestId, e: serde_json::Error) -> Result<()> {
+    let response = Response::new_err(id, ErrorCode::InvalidParams as i32, e.to_string());
+    conn.sender.send(Message::Response(response))?;
+    Ok(())
+}
+
+/// Parses a var address, accepting an optional `$` prefix and addresses
+/// pointing to other entities.
+fn parse_address(s: &str) -> Option<ShortLocalAddress> {
+    let s = s.trim_start_matches('$');
+    let split = s.split(':').collect::<Vec<_>>();
+    let start = split.len().saturating_sub(3);
+    ShortLocalAddress::from_str(&split[start..].join(":")).ok()
+}
+
+/// Gets the word at the given position, including the address separators.
+fn word_at(text: &str, position: Position) -> Option<String> {
+    let line = text.lines().nth(position.line as usize)?;
+    let chars = line.chars().collect::<Vec<_>>();
+    let is_word = |c: &char| c.is_alphanumeric() || ['_', ':', '$'].contains(c);
+    let at = (position.character as usize).min(chars.len());
+    let start = chars[..at]
+        .iter()
+        .rposition(|c| !is_word(c))
+        .map(|n| n + 1)
+        .unwrap_or(0);
+    let end = chars[at..]
+        .iter()
+        .position(|c| !is_word(c))
+        .map(|n| at + n)
+        .unwrap_or(chars.len());
+    if start >= end {
+        return None;
+    }
+    Some(chars[start..end].iter().collect())
+}
+
+/// Gets the range spanning the non-whitespace part of the line.
+fn line_range(text: &str, line: u32) -> Range {
+    let content = text.lines().nth(line as usize).unwrap_or("");
+    let start = content.chars().take_while(|c| c.is_whitespace()).count();
+    let end = content.trim_end().chars().count().max(start);
+    Range::new(
+        Position::new(line, start as u32),
+        Position::new(line, end as u32),
+    )
+}
+
+/// Gets the zero-based line of the location.
+fn line_of(location: &LocationInfo) -> u32 {
+    location.source_line.unwrap_or(1).saturating_sub(1) as u32
+}
+
+fn markdown_hover(value: String) -> Hover {
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: None,
+    }
+}
+
+fn normalize(path: PathBuf) -> PathBuf {
+    path.canonicalize().unwrap_or(path)
+}
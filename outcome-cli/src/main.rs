@@ -14,9 +14,15 @@ extern crate linefeed;
 
 extern crate outcome_core as outcome;
 
+pub mod check;
 pub mod cli;
+pub mod fmt;
 pub mod init;
 pub mod interactive;
+pub mod lsp;
+pub mod model;
+pub mod mods;
+pub mod snapshot;
 pub mod test;
 mod util;
 
@@ -31,6 +37,7 @@ fn main() {
             if e.root_cause().to_string() != e.to_string() {
                 println!("Caused by:\n{}", e.root_cause())
             }
+            std::process::exit(1);
         }
     }
 }
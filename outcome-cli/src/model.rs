@@ -0,0 +1,32 @@
+//! Exporting the resolved simulation model.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Error, Result};
+use outcome::model::introspect::ModelInfo;
+use outcome::model::{Scenario, SimModel};
+
+use crate::util::select_scenario;
+
+/// Loads the scenario at the given path and exports information about
+/// the resulting model in the selected format, either `json` or `dot`.
+///
+/// If the path points to a directory, the project is expected to contain
+/// exactly one scenario. Output is written to stdout if no output path is
+/// provided.
+pub fn export(path: PathBuf, format: &str, output: Option<&Path>) -> Result<()> {
+    let path = select_scenario(path)?;
+    let model = SimModel::from_scenario(Scenario::from_path(path)?)?;
+    let info = ModelInfo::new(&model);
+    let out = match format {
+        "json" => serde_json::to_string_pretty(&info)?,
+        "dot" => info.to_dot(),
+        _ => return Err(Error::msg(format!("unknown format: {}", format))),
+    };
+
+    match output {
+        Some(output) => std::fs::write(output, out)?,
+        None => println!("{}", out),
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! Managing modules installed in the local module cache.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Error, Result};
+use outcome::model::cache;
+use outcome::model::resolver::{self, Candidate};
+use outcome::util::find_project_root;
+use outcome::{MODULES_DIR_NAME, MODULE_MANIFEST_FILE};
+
+/// Lists modules available in the current project and in all of the
+/// module search paths.
+pub fn list(path: PathBuf) -> Result<()> {
+    let mut dirs = Vec::new();
+    if let Ok(root) = find_project_root(path, 4) {
+        dirs.push(root.join(MODULES_DIR_NAME));
+    }
+    dirs.extend(cache::search_dirs());
+
+    let mut found_any = false;
+    for dir in dirs {
+        let mut candidates = resolver::find_candidates(&[dir.clone()])
+            .into_iter()
+            .flat_map(|(_, versions)| versions)
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            continue;
+        }
+        found_any = true;
+        candidates.sort_by(|a, b| {
+            a.manifest
+                .name
+                .cmp(&b.manifest.name)
+                .then(a.version.cmp(&b.version))
+        });
+        println!("{}:", dir.to_string_lossy());
+        for candidate in candidates {
+            print_candidate(&candidate);
+        }
+    }
+    if !found_any {
+        println!("no mods available");
+    }
+    Ok(())
+}
+
+/// Installs module from a local directory or archive into the module cache.
+///
+/// Supported archive formats are `.tar`, `.tar.gz` and `.tgz`.
+pub fn install(path: PathBuf, force: bool) -> Result<()> {
+    let installed = if path.is_dir() {
+        cache::install(&path, force)?
+    } else if path.is_file() {
+        // removed when dropped
+        let temp = tempfile::tempdir()?;
+        unpack_archive(&path, temp.path())?;
+        let root = find_module_root(temp.path())?;
+        cache::install(&root, force)?
+    } else {
+        return Err(Error::msg(format!(
+            "mod not found at: {}",
+            path.to_string_lossy()
+        )));
+    };
+    println!(
+        "installed mod \"{}\" version \"{}\" at: {}",
+        installed.manifest.name,
+        installed.version,
+        installed.path.to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Removes module from the module cache.
+pub fn remove(name: &str, version: Option<&str>) -> Result<()> {
+    for removed in cache::remove(name, version)? {
+        println!(
+            "removed mod \"{}\" version \"{}\"",
+            removed.manifest.name, removed.version
+        );
+    }
+    Ok(())
+}
+
+fn print_candidate(candidate: &Candidate) {
+    println!(
+        "   {:<24} {:>10}   {}",
+        candidate.manifest.name,
+        candidate.version.to_string(),
+        candidate.manifest.title.as_deref().unwrap_or("")
+    );
+}
+
+fn unpack_archive(path: &Path, target: &Path) -> Result<()> {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let file = File::open(path)?;
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        tar::Archive::new(flate2::read::GzDecoder::new(file)).unpack(target)?;
+    } else if name.ends_with(".tar") {
+        tar::Archive::new(file).unpack(target)?;
+    } else {
+        return Err(Error::msg(format!(
+            "unsupported archive format: {}, supported formats: .tar, .tar.gz, .tgz",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Finds the module root within an unpacked archive, which is either the
+/// top directory itself or its single subdirectory.
+fn find_module_root(dir: &Path) -> Result<PathBuf> {
+    if dir.join(MODULE_MANIFEST_FILE).is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let subdirs = outcome::util::get_top_dirs_at(dir.to_path_buf());
+    match subdirs.as_slice() {
+        [single] if single.join(MODULE_MANIFEST_FILE).is_file() => Ok(single.clone()),
+        _ => Err(Error::msg(format!(
+            "archive doesn't contain a mod, \"{}\" file not found",
+            MODULE_MANIFEST_FILE
+        ))),
+    }
+}
+
+#[test]
+fn archive_unpacks_into_temp_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let archive_path = dir.path().join("weather.tar.gz");
+    {
+        let file = File::create(&archive_path).unwrap();
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        let manifest = b"[mod]\nname = \"weather\"\nversion = \"0.1.0\"\nengine = \"*\"\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "weather/mod.toml", &manifest[..])
+            .unwrap();
+        // entry trying to escape the unpack directory, builder itself
+        // refuses such paths so the header is filled in directly
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..11].copy_from_slice(b"../evil.txt");
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    let temp = dir.path().join("unpacked");
+    std::fs::create_dir_all(&temp).unwrap();
+    unpack_archive(&archive_path, &temp).unwrap();
+    assert!(!dir.path().join("evil.txt").exists());
+    let root = find_module_root(&temp).unwrap();
+    assert_eq!(root, temp.join("weather"));
+
+    let cache = dir.path().join("cache");
+    let installed = cache::install_into(&cache, &root, false).unwrap();
+    assert_eq!(installed.manifest.name, "weather");
+    assert!(cache
+        .join("weather-0.1.0")
+        .join(MODULE_MANIFEST_FILE)
+        .is_file());
+}
+
+#[test]
+fn archive_without_mod_is_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(find_module_root(dir.path()).is_err());
+
+    let zip_path = dir.path().join("weather.zip");
+    std::fs::write(&zip_path, "").unwrap();
+    assert!(unpack_archive(&zip_path, dir.path()).is_err());
+}
@@ -0,0 +1,653 @@
+//! Inspecting, comparing and exporting snapshots.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Error, Result};
+use outcome::query::{Description, Filter, Layout, Map, Trigger};
+use outcome::snapshot;
+use outcome::util::{find_project_root, get_snapshot_paths};
+use outcome::{EntityId, EntityName, Query, Sim, Var};
+
+use crate::util::format_elements_list;
+
+/// Supported export formats.
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" | "json" | "jsonlines" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(Error::msg(format!("unknown export format: {}", s))),
+        }
+    }
+}
+
+/// Resolves snapshot path from either a path or a snapshot name within the
+/// current project.
+pub fn resolve_path(arg: &str) -> Result<PathBuf> {
+    let path = PathBuf::from(arg);
+    if path.is_file() {
+        return Ok(path.canonicalize().unwrap_or(path));
+    }
+    let root = find_project_root(std::env::current_dir()?, 4)?;
+    let available = get_snapshot_paths(root)?;
+    for snap_path in &available {
+        if snap_path.file_stem().map(|s| s == arg).unwrap_or(false)
+            || snap_path.file_name().map(|s| s == arg).unwrap_or(false)
+        {
+            return Ok(snap_path.clone());
+        }
+    }
+    Err(Error::msg(format!(
+        "snapshot not found: {}, available snapshots: {}",
+        arg,
+        format_elements_list(&available)
+    )))
+}
+
+/// Lists snapshots available in the project.
+pub fn list(path: PathBuf) -> Result<()> {
+    let root = find_project_root(path, 4)?;
+    let available = get_snapshot_paths(root)?;
+    if available.is_empty() {
+        println!("no snapshots available in project");
+        return Ok(());
+    }
+    for snap_path in &available {
+        let size = std::fs::metadata(snap_path).map(|m| m.len()).unwrap_or(0);
+        let kind = match snapshot::read_snapshot_file(snap_path)
+            .and_then(|bytes| snapshot::decompress_if_needed(bytes))
+        {
+            Ok(bytes) if snapshot::is_delta(&bytes) => "delta",
+            Ok(bytes) if snapshot::is_versioned(&bytes) => "full",
+            Ok(_) => "legacy",
+            Err(_) => "unreadable",
+        };
+        println!(
+            "{:<30} {:>8} {:>12}",
+            snap_path
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            kind,
+            format_size(size),
+        );
+    }
+    Ok(())
+}
+
+/// Prints summary information about the snapshot.
+pub fn info(path: PathBuf) -> Result<()> {
+    write_info(&path, &mut std::io::stdout())
+}
+
+/// Writes summary information about the snapshot into the given output.
+fn write_info(path: &Path, out: &mut impl Write) -> Result<()> {
+    let raw = snapshot::read_snapshot_file(path)?;
+    let raw_len = raw.len();
+    let compressed = snapshot::is_compressed(&raw)?;
+    let mut bytes = snapshot::decompress_if_needed(raw)?;
+    let preamble = snapshot::read_preamble(&mut bytes)?;
+    let header = if preamble.is_delta() {
+        snapshot::extract_delta(&mut bytes)?.header
+    } else {
+        snapshot::extract_header(&mut bytes)?
+    };
+
+    writeln!(out, "snapshot: {}", path.to_string_lossy())?;
+    writeln!(out, "size: {}", format_size(raw_len as u64))?;
+    writeln!(out, "compressed: {}", compressed)?;
+    writeln!(out, "format version: {}", preamble.format_version)?;
+    writeln!(out, "engine version: {}", preamble.engine_version)?;
+    writeln!(out, "engine features: [{}]", preamble.features.join(", "))?;
+    writeln!(out, "created: {}", header.metadata.created)?;
+    writeln!(out, "starter: {:?}", header.metadata.starter)?;
+    writeln!(out, "clock: {}", header.clock)?;
+
+    let (snapshots_dir, name) = split_path(path)?;
+    if preamble.is_delta() {
+        let chain = snapshot::snapshot_chain(&snapshots_dir, &name)?;
+        writeln!(out, "delta chain: {}", chain.join(" -> "))?;
+    }
+
+    let sim = Sim::load_snapshot_from_dir(&snapshots_dir, &name)?;
+    let mut comp_counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut var_count = 0;
+    for entity in sim.entities.values() {
+        for comp in &entity.components {
+            *comp_counts.entry(comp.to_string()).or_insert(0) += 1;
+        }
+        var_count += entity.storage.map.len();
+    }
+    writeln!(out, "entities: {}", sim.entities.len())?;
+    writeln!(out, "named entities: {}", sim.entity_idx.len())?;
+    writeln!(out, "vars: {}", var_count)?;
+    writeln!(out, "components:")?;
+    for (comp, count) in &comp_counts {
+        writeln!(out, "   {:<30} {}", comp, count)?;
+    }
+
+    let model = &sim.model;
+    writeln!(out, "model:")?;
+    writeln!(
+        out,
+        "   scenario: {} ({})",
+        model.scenario.manifest.name, model.scenario.manifest.version
+    )?;
+    writeln!(out, "   modules: {}", model.scenario.modules.len())?;
+    writeln!(out, "   events: {}", model.events.len())?;
+    writeln!(out, "   prefabs: {}", model.entities.len())?;
+    writeln!(out, "   components: {}", model.components.len())?;
+    writeln!(out, "   scripts: {}", model.scripts.len())?;
+    writeln!(out, "   event queue: {:?}", sim.event_queue)?;
+    Ok(())
+}
+
+/// Prints per-entity and per-var differences between two snapshots.
+pub fn diff(path_a: PathBuf, path_b: PathBuf) -> Result<()> {
+    write_diff(&path_a, &path_b, &mut std::io::stdout())
+}
+
+/// Writes per-entity and per-var differences between two snapshots into
+/// the given output.
+fn write_diff(path_a: &Path, path_b: &Path, out: &mut impl Write) -> Result<()> {
+    let sim_a = load(path_a)?;
+    let sim_b = load(path_b)?;
+
+    if sim_a.get_clock() != sim_b.get_clock() {
+        writeln!(
+            out,
+            "~ clock: {} -> {}",
+            sim_a.get_clock(),
+            sim_b.get_clock()
+        )?;
+    }
+    if sim_a.event_queue != sim_b.event_queue {
+        writeln!(
+            out,
+            "~ event queue: {:?} -> {:?}",
+            sim_a.event_queue, sim_b.event_queue
+        )?;
+    }
+
+    let names_a = entity_names(&sim_a.entity_idx);
+    let names_b = entity_names(&sim_b.entity_idx);
+    let ids = sim_a
+        .entities
+        .keys()
+        .chain(sim_b.entities.keys())
+        .cloned()
+        .collect::<BTreeSet<EntityId>>();
+
+    let mut changed_count = 0;
+    for id in ids {
+        match (sim_a.entities.get(&id), sim_b.entities.get(&id)) {
+            (Some(_), None) => {
+                changed_count += 1;
+                writeln!(out, "- entity {}", display_entity(id, &names_a))?;
+            }
+            (None, Some(_)) => {
+                changed_count += 1;
+                writeln!(out, "+ entity {}", display_entity(id, &names_b))?;
+            }
+            (Some(a), Some(b)) => {
+                let indexes = a
+                    .storage
+                    .map
+                    .keys()
+                    .chain(b.storage.map.keys())
+                    .map(|(comp, var)| (comp.to_string(), var.to_string()))
+                    .collect::<BTreeSet<_>>();
+                let mut lines = Vec::new();
+                for (comp, var) in indexes {
+                    let idx = (
+                        outcome::string::new_truncate(&comp),
+                        outcome::string::new_truncate(&var),
+                    );
+                    match (a.storage.map.get(&idx), b.storage.map.get(&idx)) {
+                        (Some(va), Some(vb)) if va != vb => lines.push(format!(
+                            "    ~ {}:{}:{}: {} -> {}",
+                            comp,
+                            vb.get_type(),
+                            var,
+                            va.to_string(),
+                            vb.to_string()
+                        )),
+                        (Some(va), None) => lines.push(format!(
+                            "    - {}:{}:{}: {}",
+                            comp,
+                            va.get_type(),
+                            var,
+                            va.to_string()
+                        )),
+                        (None, Some(vb)) => lines.push(format!(
+                            "    + {}:{}:{}: {}",
+                            comp,
+                            vb.get_type(),
+                            var,
+                            vb.to_string()
+                        )),
+                        _ => (),
+                    }
+                }
+                if !lines.is_empty() {
+                    changed_count += 1;
+                    writeln!(out, "~ entity {}", display_entity(id, &names_b))?;
+                    for line in lines {
+                        writeln!(out, "{}", line)?;
+                    }
+                }
+            }
+            (None, None) => (),
+        }
+    }
+    writeln!(out, "{} entities differ", changed_count)?;
+    Ok(())
+}
+
+/// Exports entity data selected with a query into a file, or to standard
+/// output if no output path is given.
+pub fn export(
+    path: PathBuf,
+    format: ExportFormat,
+    filters: Vec<Filter>,
+    mappings: Vec<Map>,
+    out: Option<PathBuf>,
+) -> Result<()> {
+    let sim = load(&path)?;
+    let query = Query {
+        trigger: Trigger::Immediate,
+        description: Description::NativeDescribed,
+        layout: Layout::Var,
+        filters,
+        mappings: if mappings.is_empty() {
+            vec![Map::All]
+        } else {
+            mappings
+        },
+    };
+    let product = query.process(&sim.entities, &sim.entity_idx)?;
+    let data = match product {
+        outcome::QueryProduct::NativeAddressedVar(data) => data.into_iter().collect(),
+        outcome::QueryProduct::Empty => Vec::new(),
+        _ => return Err(Error::msg("unexpected query product")),
+    };
+
+    // group the vars by entity, keeping a stable ordering
+    let mut grouped: BTreeMap<EntityId, BTreeMap<String, Var>> = BTreeMap::new();
+    for ((id, comp, var_name), var) in data {
+        grouped
+            .entry(id)
+            .or_default()
+            .insert(format!("{}:{}:{}", comp, var.get_type(), var_name), var);
+    }
+    let names = entity_names(&sim.entity_idx);
+
+    let mut writer: Box<dyn Write> = match &out {
+        Some(out_path) => Box::new(BufWriter::new(File::create(out_path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout())),
+    };
+    match format {
+        ExportFormat::JsonLines => {
+            for (id, vars) in &grouped {
+                let mut object = serde_json::Map::new();
+                object.insert("id".to_string(), serde_json::Value::from(*id));
+                if let Some(name) = names.get(id) {
+                    object.insert("name".to_string(), serde_json::Value::from(name.clone()));
+                }
+                let vars = vars
+                    .iter()
+                    .map(|(addr, var)| (addr.clone(), var_to_json(var)))
+                    .collect::<serde_json::Map<_, _>>();
+                object.insert("vars".to_string(), serde_json::Value::Object(vars));
+                writeln!(writer, "{}", serde_json::Value::Object(object))?;
+            }
+        }
+        ExportFormat::Csv => {
+            writeln!(writer, "entity,name,component,type,var,value")?;
+            for (id, vars) in &grouped {
+                let name = names.get(id).cloned().unwrap_or_default();
+                for (addr, var) in vars {
+                    let mut split = addr.splitn(3, ':');
+                    let comp = split.next().unwrap_or_default();
+                    let type_ = split.next().unwrap_or_default();
+                    let var_name = split.next().unwrap_or_default();
+                    writeln!(
+                        writer,
+                        "{},{},{},{},{},{}",
+                        id,
+                        csv_escape(&name),
+                        csv_escape(comp),
+                        type_,
+                        csv_escape(var_name),
+                        csv_escape(&var.to_string())
+                    )?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    if let Some(out_path) = out {
+        info!(
+            "exported {} entities to {}",
+            grouped.len(),
+            out_path.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Parses query filter arguments into native query filters.
+pub fn parse_filters(
+    components: Option<&str>,
+    names: Option<&str>,
+    ids: Option<&str>,
+) -> Result<Vec<Filter>> {
+    let mut filters = Vec::new();
+    if let Some(components) = components {
+        filters.push(Filter::AllComponents(
+            split_list(components)
+                .map(|c| outcome::string::new_truncate(c))
+                .collect(),
+        ));
+    }
+    if let Some(names) = names {
+        filters.push(Filter::Name(
+            split_list(names)
+                .map(|n| outcome::string::new_truncate(n))
+                .collect(),
+        ));
+    }
+    if let Some(ids) = ids {
+        let mut parsed = Vec::new();
+        for id in split_list(ids) {
+            parsed.push(id.parse()?);
+        }
+        filters.push(Filter::Id(parsed));
+    }
+    Ok(filters)
+}
+
+/// Parses query mapping arguments into native query mappings.
+pub fn parse_mappings(components: Option<&str>, vars: Option<&str>) -> Vec<Map> {
+    let mut mappings = Vec::new();
+    if let Some(components) = components {
+        mappings.push(Map::Components(
+            split_list(components)
+                .map(|c| outcome::string::new_truncate(c))
+                .collect(),
+        ));
+    }
+    if let Some(vars) = vars {
+        for var in split_list(vars) {
+            mappings.push(Map::VarName(outcome::string::new_truncate(var)));
+        }
+    }
+    mappings
+}
+
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+    s.split(',').map(|s| s.trim()).filter(|s| !s.is_empty())
+}
+
+/// Loads the snapshot, including any delta chain it's part of.
+fn load(path: &Path) -> Result<Sim> {
+    let (snapshots_dir, name) = split_path(path)?;
+    Ok(Sim::load_snapshot_from_dir(&snapshots_dir, &name)?)
+}
+
+fn split_path(path: &Path) -> Result<(PathBuf, String)> {
+    let name = path
+        .file_name()
+        .ok_or(Error::msg(format!(
+            "invalid snapshot path: {}",
+            path.to_string_lossy()
+        )))?
+        .to_string_lossy()
+        .to_string();
+    let dir = path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or(PathBuf::from("."));
+    Ok((dir, name))
+}
+
+fn entity_names<'a>(
+    idx: impl IntoIterator<Item = (&'a EntityName, &'a EntityId)>,
+) -> HashMap<EntityId, String> {
+    idx.into_iter()
+        .map(|(name, id)| (*id, name.to_string()))
+        .collect()
+}
+
+fn display_entity(id: EntityId, names: &HashMap<EntityId, String>) -> String {
+    match names.get(&id) {
+        Some(name) => format!("{} ({})", id, name),
+        None => id.to_string(),
+    }
+}
+
+fn var_to_json(var: &Var) -> serde_json::Value {
+    use serde_json::Value;
+    match var {
+        Var::String(s) => Value::from(s.clone()),
+        Var::Int(i) => Value::from(*i),
+        Var::Float(f) => Value::from(*f),
+        Var::Bool(b) => Value::from(*b),
+        Var::Byte(b) => Value::from(*b),
+        Var::Vec2(x, y) => Value::from(vec![*x, *y]),
+        Var::Vec3(x, y, z) => Value::from(vec![*x, *y, *z]),
+        Var::List(list) => Value::Array(list.iter().map(var_to_json).collect()),
+        Var::Grid(grid) => Value::Array(
+            grid.iter()
+                .map(|row| Value::Array(row.iter().map(var_to_json).collect()))
+                .collect(),
+        ),
+        Var::Map(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.to_string(), var_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn csv_escape(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 * 1024 {
+        format!("{:.2} GiB", bytes as f64 / (1024. * 1024. * 1024.))
+    } else if bytes >= 1024 * 1024 {
+        format!("{:.2} MiB", bytes as f64 / (1024. * 1024.))
+    } else if bytes >= 1024 {
+        format!("{:.2} KiB", bytes as f64 / 1024.)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+fn test_sim(entities: &[(EntityId, Option<&str>, Vec<(&str, Var)>)]) -> Sim {
+    let mut sim = Sim::new();
+    for (id, name, vars) in entities {
+        let mut entity = outcome::entity::Entity::empty();
+        for (var_name, var) in vars {
+            entity.storage.insert(
+                (
+                    outcome::string::new_truncate("comp"),
+                    outcome::string::new_truncate(var_name),
+                ),
+                var.clone(),
+            );
+        }
+        sim.entities.insert(*id, entity);
+        if let Some(name) = name {
+            sim.entity_idx
+                .insert(outcome::string::new_truncate(name), *id);
+        }
+    }
+    sim
+}
+
+#[cfg(test)]
+fn write_snapshot(dir: &Path, name: &str, sim: &Sim) -> PathBuf {
+    use outcome::snapshot::Snap;
+    let path = dir.join(name);
+    std::fs::write(&path, sim.to_snapshot().unwrap()).unwrap();
+    path
+}
+
+#[test]
+fn diff_lists_changed_entities_and_vars() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = write_snapshot(
+        dir.path(),
+        "a",
+        &test_sim(&[
+            (
+                0,
+                Some("alice"),
+                vec![("x", Var::Int(1)), ("y", Var::Int(2))],
+            ),
+            (1, Some("bob"), vec![("x", Var::Int(1))]),
+            (3, None, vec![("x", Var::Int(1))]),
+        ]),
+    );
+    let b = write_snapshot(
+        dir.path(),
+        "b",
+        &test_sim(&[
+            (
+                0,
+                Some("alice"),
+                vec![("x", Var::Int(3)), ("z", Var::Bool(true))],
+            ),
+            (2, None, vec![("x", Var::Int(1))]),
+            (3, None, vec![("x", Var::Int(1))]),
+        ]),
+    );
+
+    let mut out = Vec::new();
+    write_diff(&a, &b, &mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "~ entity 0 (alice)\n\
+         \x20   ~ comp:int:x: 1 -> 3\n\
+         \x20   - comp:int:y: 2\n\
+         \x20   + comp:bool:z: true\n\
+         - entity 1 (bob)\n\
+         + entity 2\n\
+         3 entities differ\n"
+    );
+
+    let mut out = Vec::new();
+    write_diff(&a, &a, &mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "0 entities differ\n");
+}
+
+#[test]
+fn info_reads_versioned_preamble() {
+    let dir = tempfile::tempdir().unwrap();
+    let sim = test_sim(&[(0, Some("alice"), vec![("x", Var::Int(1))])]);
+    let path = write_snapshot(dir.path(), "full", &sim);
+
+    let mut out = Vec::new();
+    write_info(&path, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(&format!(
+        "format version: {}\n",
+        snapshot::SNAPSHOT_FORMAT_VERSION
+    )));
+    assert!(out.contains("entities: 1\n"));
+    assert!(out.contains("named entities: 1\n"));
+    assert!(!out.contains("delta chain"));
+}
+
+#[test]
+fn info_reads_delta_preamble() {
+    let dir = tempfile::tempdir().unwrap();
+    let base = test_sim(&[(0, None, vec![("x", Var::Int(1))])]);
+    write_snapshot(dir.path(), "base", &base);
+    let current = test_sim(&[
+        (0, None, vec![("x", Var::Int(2))]),
+        (1, None, vec![("x", Var::Int(1))]),
+    ]);
+    let delta =
+        snapshot::SnapshotDelta::between(&snapshot::SnapshotBase::new("base", &base), &current);
+    let path = dir.path().join("delta");
+    std::fs::write(&path, delta.to_bytes().unwrap()).unwrap();
+
+    let mut out = Vec::new();
+    write_info(&path, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains(&format!(
+        "format version: {}\n",
+        snapshot::SNAPSHOT_FORMAT_VERSION
+    )));
+    assert!(out.contains("delta chain: base -> delta\n"));
+    assert!(out.contains("entities: 2\n"));
+    assert!(out.contains("vars: 2\n"));
+}
+
+#[test]
+fn info_reads_legacy_snapshot() {
+    use outcome::snapshot::Snap;
+
+    fn push<T: serde::Serialize + ?Sized>(bytes: &mut Vec<u8>, value: &T) {
+        bytes.extend(bincode::serialize(value).unwrap());
+    }
+
+    // legacy snapshots have no preamble and use the layout of format
+    // version 0, here with an empty model and no entities
+    let mut current = Sim::new().to_snapshot().unwrap();
+    snapshot::read_preamble(&mut current).unwrap();
+    let header = snapshot::extract_header(&mut current).unwrap();
+    let empty: Vec<()> = Vec::new();
+    let mut bytes = Vec::new();
+    push(&mut bytes, &header.metadata);
+    push(&mut bytes, &header.clock);
+    // scenario path and manifest
+    push(&mut bytes, &PathBuf::new());
+    for field in &["legacy", "0.1.0", "0.1.0"] {
+        push(&mut bytes, field);
+    }
+    push(&mut bytes, &empty);
+    push(&mut bytes, &HashMap::<String, String>::new());
+    for _ in 0..5 {
+        push(&mut bytes, &None::<String>);
+    }
+    // scenario modules and the rest of the model, without entity tables
+    for _ in 0..9 {
+        push(&mut bytes, &empty);
+    }
+    push(&mut bytes, &header.entities_idx);
+    push(&mut bytes, &header.event_queue);
+    push(&mut bytes, &header.entity_pool);
+    // single part without entities
+    push(&mut bytes, &empty);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("legacy");
+    std::fs::write(&path, bytes).unwrap();
+
+    let mut out = Vec::new();
+    write_info(&path, &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("format version: 0\n"));
+    assert!(out.contains("engine version: unknown\n"));
+    assert!(out.contains("   scenario: legacy (0.1.0)\n"));
+    assert!(out.contains("entities: 0\n"));
+}
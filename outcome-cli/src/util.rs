@@ -1,4 +1,5 @@
 use anyhow::{Error, Result};
+use outcome::util::{find_project_root, get_scenario_paths};
 use std::fs;
 use std::path::PathBuf;
 
@@ -13,3 +14,20 @@ pub(crate) fn format_elements_list(paths: &Vec<PathBuf>) -> String {
     }
     list
 }
+
+/// Selects a scenario manifest using the given path. If the path points to
+/// a directory, the project is expected to contain exactly one scenario.
+pub(crate) fn select_scenario(path: PathBuf) -> Result<PathBuf> {
+    if path.is_file() {
+        return Ok(path);
+    }
+    let mut paths = get_scenario_paths(find_project_root(path, 4)?)?;
+    match paths.len() {
+        0 => Err(Error::msg("no scenarios found")),
+        1 => Ok(paths.remove(0)),
+        _ => Err(Error::msg(format!(
+            "found multiple scenarios, select one by passing path to its manifest:{}",
+            format_elements_list(&paths)
+        ))),
+    }
+}
@@ -12,6 +12,7 @@ fn add_entity(c: &mut Criterion) {
     sim.model.entities.push(EntityPrefab {
         name: string::new_truncate("bench_ent"),
         components: vec![],
+        ..EntityPrefab::default()
     });
 
     c.bench_function("add_entity_100", |b| {
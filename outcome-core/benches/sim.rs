@@ -22,6 +22,7 @@ fn spawn_entities(c: &mut Criterion) {
     sim.model.entities.push(EntityPrefab {
         name: string::new_truncate("bench_ent"),
         components: vec![string::new_truncate("bench_comp")],
+        ..EntityPrefab::default()
     });
 
     println!("once");
@@ -0,0 +1,616 @@
+//! Static checks performed on the simulation model.
+//!
+//! Checks are run against the model as it stands after the initial script
+//! processing, and are meant to catch problems that would otherwise only
+//! surface at runtime, if at all.
+//!
+//! Problems that will certainly result in broken behavior, e.g. prefabs
+//! referencing unknown components, are reported as errors. Things that are
+//! likely a mistake, e.g. procedures that are never called, are reported
+//! as warnings.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+
+use annotate_snippets::display_list::{DisplayList, FormatOptions};
+use annotate_snippets::snippet::{Annotation, AnnotationType, Slice, Snippet, SourceAnnotation};
+
+use crate::address::ShortLocalAddress;
+use crate::machine::cmd::flow::forin::ForInTarget;
+use crate::machine::cmd::set::{Source, Target};
+use crate::machine::cmd::Command;
+use crate::machine::LocationInfo;
+use crate::model::introspect::find_location;
+use crate::model::{ComponentModel, SimModel};
+use crate::{CompName, ShortString, Sim, StringId, VarName, VarType};
+
+/// Severity of the reported problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Single problem found during the check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Location of the offending script line, if known
+    pub location: Option<LocationInfo>,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic, including the offending source line if it
+    /// can be read from disk.
+    pub fn render(&self, color: bool) -> String {
+        let annotation_type = match self.severity {
+            Severity::Error => AnnotationType::Error,
+            Severity::Warning => AnnotationType::Warning,
+        };
+        let source = self.location.as_ref().and_then(|l| read_source_line(l));
+
+        let mut slices = Vec::new();
+        if let (Some((line, line_num)), Some(location)) = (&source, &self.location) {
+            let range_start = line.chars().take_while(|c| c.is_whitespace()).count();
+            let range_end = line.trim_end().chars().count();
+            slices.push(Slice {
+                source: line,
+                line_start: *line_num,
+                origin: location.source.as_ref().map(|s| s.as_str()),
+                fold: false,
+                annotations: if range_start < range_end {
+                    vec![SourceAnnotation {
+                        label: "",
+                        annotation_type,
+                        range: (range_start, range_end),
+                    }]
+                } else {
+                    vec![]
+                },
+            });
+        }
+
+        let snippet = Snippet {
+            title: Some(Annotation {
+                label: Some(&self.message),
+                id: None,
+                annotation_type,
+            }),
+            footer: vec![],
+            slices,
+            opt: FormatOptions {
+                color,
+                ..Default::default()
+            },
+        };
+        DisplayList::from(snippet).to_string()
+    }
+}
+
+/// Reads the source line pointed to by the location.
+fn read_source_line(location: &LocationInfo) -> Option<(String, usize)> {
+    let path = PathBuf::from(location.root.as_ref()?.as_str())
+        .join(location.source.as_ref()?.as_str());
+    let line_num = location.source_line?;
+    let file = File::open(path).ok()?;
+    let line = BufReader::new(file)
+        .lines()
+        .nth(line_num.checked_sub(1)?)?
+        .ok()?;
+    Some((line, line_num))
+}
+
+/// Collection of problems found during the check.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CheckReport {
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+            .count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    fn error(&mut self, message: String, location: Option<&LocationInfo>) {
+        self.push(Severity::Error, message, location)
+    }
+
+    fn warning(&mut self, message: String, location: Option<&LocationInfo>) {
+        self.push(Severity::Warning, message, location)
+    }
+
+    fn push(&mut self, severity: Severity, message: String, location: Option<&LocationInfo>) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message,
+            location: location.cloned(),
+        })
+    }
+}
+
+/// Loads the scenario at the given path and checks the resulting model.
+///
+/// Failure to load the scenario is itself reported as an error.
+pub fn check_scenario(path: PathBuf) -> CheckReport {
+    match Sim::from_scenario_at_path(path) {
+        Ok(sim) => check_model(&sim.model),
+        Err(e) => {
+            let mut report = CheckReport::default();
+            report.error(format!("failed loading scenario: {}", e), None);
+            report
+        }
+    }
+}
+
+/// Checks the model for problems.
+pub fn check_model(model: &SimModel) -> CheckReport {
+    let mut report = CheckReport::default();
+    check_prefabs(model, &mut report);
+
+    // module init components hold the module-level script code, which
+    // is already covered by the components registered from it
+    let init_comps = model
+        .get_entity(&crate::string::new_truncate("_mod_init"))
+        .map(|prefab| prefab.components.clone())
+        .unwrap_or_default();
+    for comp in &model.components {
+        if comp.name.starts_with('_') || init_comps.contains(&comp.name) {
+            continue;
+        }
+        check_component(model, comp, &mut report);
+    }
+    check_events(model, &mut report);
+    report
+}
+
+fn check_prefabs(model: &SimModel, report: &mut CheckReport) {
+    for prefab in &model.entities {
+        let location = || {
+            find_location(model, |cmd| match cmd {
+                Command::RegisterEntityPrefab(reg) => reg.name == prefab.name,
+                _ => false,
+            })
+        };
+        for comp in &prefab.components {
+            if model.get_component(comp).is_ok() {
+                continue;
+            }
+            report.error(
+                format!(
+                    "prefab \"{}\" references unknown component \"{}\"",
+                    prefab.name, comp
+                ),
+                location(),
+            );
+        }
+        if prefab.extends.is_some() {
+            if let Err(e) = model.resolve_prefab(&prefab.name) {
+                report.error(e.to_string(), location());
+            }
+        }
+    }
+}
+
+fn check_component(model: &SimModel, comp: &ComponentModel, report: &mut CheckReport) {
+    let logic = &comp.logic;
+    let mut states: Vec<(StringId, Option<&LocationInfo>)> = Vec::new();
+    let mut goto_targets = Vec::new();
+    let mut procedures: Vec<(ShortString, Option<&LocationInfo>)> = Vec::new();
+    let mut calls = Vec::new();
+
+    // procedure parameters and local vars, along with the procedure's lines
+    let mut local_scopes: Vec<(usize, usize, Vec<VarName>)> = Vec::new();
+    for cmd in &logic.commands {
+        if let Command::Procedure(procedure) = cmd {
+            let mut names = procedure
+                .params
+                .iter()
+                .map(|param| param.var_name.clone())
+                .collect::<Vec<_>>();
+            for cmd in logic
+                .commands
+                .get(procedure.start_line..procedure.end_line)
+                .unwrap_or(&[])
+            {
+                if let Command::Local(local) = cmd {
+                    names.push(local.var.var_name.clone());
+                }
+            }
+            local_scopes.push((procedure.start_line, procedure.end_line, names));
+        }
+    }
+    let is_local = |n: usize, comp: &Option<CompName>, name: &VarName| {
+        comp.is_none()
+            && local_scopes
+                .iter()
+                .any(|(start, end, names)| n > *start && n < *end && names.contains(name))
+    };
+
+    for (n, cmd) in logic.commands.iter().enumerate() {
+        let location = logic.cmd_location_map.get(n);
+
+        // local addresses read or written by the command
+        let mut accessed: Vec<(&ShortLocalAddress, &str)> = Vec::new();
+        match cmd {
+            Command::Eval(eval) => {
+                accessed.extend(eval.args.iter().map(|(_, arg)| (arg, "read")));
+                accessed.extend(eval.out.iter().map(|out| (out, "written")));
+            }
+            Command::List(list) => {
+                accessed.push((&list.list, "accessed"));
+                accessed.extend(list.out.iter().map(|out| (out, "written")));
+            }
+            Command::Map(map) => {
+                accessed.push((&map.map, "accessed"));
+                accessed.extend(map.out.iter().map(|out| (out, "written")));
+            }
+            Command::Grid(grid) => {
+                accessed.push((&grid.grid, "accessed"));
+                accessed.extend(grid.out.iter().map(|out| (out, "written")));
+            }
+            Command::ForIn(forin) => {
+                if let ForInTarget::Var(target) = &forin.target {
+                    accessed.push((target, "read"));
+                }
+            }
+            _ => (),
+        }
+        for (addr, access) in accessed {
+            if !is_local(n, &addr.comp, &addr.var_name) {
+                check_local_address(model, comp, addr, access, location, report);
+            }
+        }
+
+        match cmd {
+            Command::Set(set) => {
+                let target_local = match &set.target {
+                    Target::LocalAddress(addr) => is_local(n, &addr.comp, &addr.var_name),
+                    Target::Address(_) => false,
+                };
+                let (target_comp, target_type, target_name) = match &set.target {
+                    Target::LocalAddress(addr) => (
+                        addr.comp.clone().unwrap_or(comp.name.clone()),
+                        addr.var_type,
+                        addr.var_name.clone(),
+                    ),
+                    Target::Address(addr) => (
+                        addr.component.clone(),
+                        addr.var_type,
+                        addr.var_name.clone(),
+                    ),
+                };
+                let declared = if target_local {
+                    None
+                } else {
+                    check_address(
+                        model,
+                        &target_comp,
+                        target_type,
+                        &target_name,
+                        location,
+                        report,
+                    )
+                };
+                if let Some(declared) = declared {
+                    if declared != target_type {
+                        report.error(
+                            format!(
+                                "var \"{}:{}\" declared as {} is written as {}",
+                                target_comp,
+                                target_name,
+                                declared.to_str(),
+                                target_type.to_str()
+                            ),
+                            location,
+                        );
+                    }
+                }
+
+                let (source_comp, source_type, source_name) = match &set.source {
+                    Source::LocalAddress(addr) => (
+                        addr.comp.clone().unwrap_or(comp.name.clone()),
+                        addr.var_type,
+                        addr.var_name.clone(),
+                    ),
+                    Source::Address(addr) => (
+                        addr.component.clone(),
+                        addr.var_type,
+                        addr.var_name.clone(),
+                    ),
+                    Source::Value(_) => continue,
+                };
+                let source_local = match &set.source {
+                    Source::LocalAddress(addr) => is_local(n, &addr.comp, &addr.var_name),
+                    _ => false,
+                };
+                if !source_local {
+                    check_address(
+                        model,
+                        &source_comp,
+                        source_type,
+                        &source_name,
+                        location,
+                        report,
+                    );
+                }
+                if source_type != target_type {
+                    report.error(
+                        format!(
+                            "var \"{}:{}\" of type {} is written with a value of type {}",
+                            target_comp,
+                            target_name,
+                            target_type.to_str(),
+                            source_type.to_str()
+                        ),
+                        location,
+                    );
+                }
+            }
+            Command::Eval(eval) => {
+                for name in &eval.vars {
+                    if !eval.args.iter().any(|(arg, _)| arg == name) {
+                        report.warning(
+                            format!(
+                                "variable \"{}\" used in the expression isn't passed to eval",
+                                name
+                            ),
+                            location,
+                        );
+                    }
+                }
+            }
+            Command::State(state) => states.push((state.name.clone(), location)),
+            Command::Goto(goto) => goto_targets.push(goto.target_state.clone()),
+            Command::Procedure(procedure) => procedures.push((procedure.name, location)),
+            Command::Call(call) => {
+                let procedure = logic
+                    .procedures
+                    .get(&call.proc_name)
+                    .and_then(|(start, _)| logic.commands.get(*start));
+                if let Some(Command::Procedure(procedure)) = procedure {
+                    if procedure.params.len() != call.args.len() {
+                        report.error(
+                            format!(
+                                "procedure \"{}\" of component \"{}\" takes {} argument(s), {} given",
+                                call.proc_name,
+                                comp.name,
+                                procedure.params.len(),
+                                call.args.len()
+                            ),
+                            location,
+                        );
+                    }
+                    if call.output.is_some() && procedure.returns.is_none() {
+                        report.error(
+                            format!(
+                                "procedure \"{}\" of component \"{}\" doesn't return a value",
+                                call.proc_name, comp.name
+                            ),
+                            location,
+                        );
+                    }
+                }
+                calls.push(call.proc_name)
+            }
+            _ => (),
+        }
+    }
+
+    for state in logic.states.keys() {
+        if !states.iter().any(|(s, _)| s == state) {
+            states.push((state.clone(), None));
+        }
+    }
+    for (state, location) in states {
+        if state == logic.start_state || goto_targets.contains(&state) {
+            continue;
+        }
+        report.warning(
+            format!(
+                "state \"{}\" of component \"{}\" is never reached by any goto",
+                state, comp.name
+            ),
+            location,
+        );
+    }
+
+    for procedure in logic.procedures.keys() {
+        if !procedures.iter().any(|(p, _)| p == procedure) {
+            procedures.push((*procedure, None));
+        }
+    }
+    for (procedure, location) in procedures {
+        if calls.contains(&procedure) {
+            continue;
+        }
+        report.warning(
+            format!(
+                "procedure \"{}\" of component \"{}\" is never called",
+                procedure, comp.name
+            ),
+            location,
+        );
+    }
+}
+
+/// Checks whether the local address points at a declared var of the same
+/// type. Access describes what the command does with the var.
+fn check_local_address(
+    model: &SimModel,
+    comp: &ComponentModel,
+    addr: &ShortLocalAddress,
+    access: &str,
+    location: Option<&LocationInfo>,
+    report: &mut CheckReport,
+) {
+    let comp_name = addr.comp.clone().unwrap_or(comp.name.clone());
+    let declared = check_address(
+        model,
+        &comp_name,
+        addr.var_type,
+        &addr.var_name,
+        location,
+        report,
+    );
+    if let Some(declared) = declared {
+        if declared != addr.var_type {
+            report.error(
+                format!(
+                    "var \"{}:{}\" declared as {} is {} as {}",
+                    comp_name,
+                    addr.var_name,
+                    declared.to_str(),
+                    access,
+                    addr.var_type.to_str()
+                ),
+                location,
+            );
+        }
+    }
+}
+
+/// Checks whether the address points at a declared var, returning the
+/// declared var type.
+fn check_address(
+    model: &SimModel,
+    comp_name: &CompName,
+    var_type: VarType,
+    var_name: &VarName,
+    location: Option<&LocationInfo>,
+    report: &mut CheckReport,
+) -> Option<VarType> {
+    let address = format!("{}:{}:{}", comp_name, var_type.to_str(), var_name);
+    let comp = match model.get_component(comp_name) {
+        Ok(c) => c,
+        Err(_) => {
+            report.error(
+                format!(
+                    "address \"{}\" points at unknown component \"{}\"",
+                    address, comp_name
+                ),
+                location,
+            );
+            return None;
+        }
+    };
+    match comp.vars.iter().find(|v| &v.name == var_name) {
+        Some(var) => Some(var.type_),
+        None => {
+            report.error(
+                format!("address \"{}\" points at undeclared var", address),
+                location,
+            );
+            None
+        }
+    }
+}
+
+fn check_events(model: &SimModel, report: &mut CheckReport) {
+    for event in &model.events {
+        let name = &event.id;
+        // built-in events are triggered by the engine itself
+        if name.as_str() == crate::DEFAULT_STEP_EVENT
+            || name.as_str() == crate::DEFAULT_INIT_EVENT
+            || name.starts_with('_')
+        {
+            continue;
+        }
+        let location = find_location(model, |cmd| match cmd {
+            Command::RegisterEvent(reg) => &reg.name == name,
+            _ => false,
+        });
+
+        if !model.components.iter().any(|c| c.triggers.contains(name)) {
+            report.warning(
+                format!("event \"{}\" doesn't trigger any component", name),
+                location,
+            );
+        }
+        let invoked = model
+            .components
+            .iter()
+            .flat_map(|c| c.logic.commands.iter())
+            .any(|cmd| match cmd {
+                Command::Invoke(invoke) => invoke.events.contains(name),
+                _ => false,
+            });
+        if !invoked {
+            report.warning(format!("event \"{}\" is never invoked", name), location);
+        }
+    }
+}
+
+#[test]
+fn check_model_reports_problems() {
+    use std::str::FromStr;
+    use crate::model::{EntityPrefab, EventModel, LogicModel, VarModel};
+
+    let id = |s: &str| -> StringId { crate::string::new_truncate(s) };
+    let mut logic = LogicModel::empty();
+    logic.commands.push(
+        crate::machine::cmd::set::Set::new(
+            vec!["float:current".to_string(), "1.0".to_string()],
+            &LocationInfo::default(),
+        )
+        .unwrap(),
+    );
+    logic.cmd_location_map.push(LocationInfo::default());
+    logic
+        .commands
+        .push(Command::List(crate::machine::cmd::collection::ListCommand {
+            list: ShortLocalAddress::from_str("list_int:queue").unwrap(),
+            op: crate::machine::cmd::collection::ListOp::Pop,
+            out: Some(ShortLocalAddress::from_str("float:current").unwrap()),
+        }));
+    logic.cmd_location_map.push(LocationInfo::default());
+    logic.commands.push(
+        crate::machine::cmd::eval::Eval::new(
+            vec!["x + y".to_string(), "x=int:current".to_string()],
+            &LocationInfo::default(),
+        )
+        .unwrap(),
+    );
+    logic.cmd_location_map.push(LocationInfo::default());
+
+    let mut model = SimModel::default();
+    model.components.push(ComponentModel {
+        name: id("health"),
+        vars: vec![VarModel {
+            name: id("current"),
+            type_: VarType::Int,
+            default: None,
+        }],
+        triggers: vec![id("step")],
+        logic,
+    });
+    model.entities.push(EntityPrefab {
+        name: id("player"),
+        components: vec![id("health"), id("inventory")],
+        ..EntityPrefab::default()
+    });
+    model.events.push(EventModel { id: id("hit") });
+
+    let report = check_model(&model);
+    // unknown component, var written with the wrong type by both set and
+    // pop, and pop from an undeclared list
+    assert_eq!(report.error_count(), 4);
+    // event neither triggered nor invoked, and eval missing an argument
+    assert_eq!(report.warning_count(), 3);
+    assert!(report.has_errors());
+}
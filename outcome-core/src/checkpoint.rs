@@ -0,0 +1,288 @@
+//! Automatic checkpointing of running simulations.
+//!
+//! Checkpoints are regular snapshots written periodically to a dedicated
+//! directory within the project's snapshots directory. Writing happens on
+//! a background thread so that the simulation can keep on processing steps
+//! while the data is compressed and saved to disk.
+//!
+//! # Retention
+//!
+//! Only a limited number of the most recent checkpoints is kept around.
+//! Additionally every Mth checkpoint can be marked as permanent, which
+//! prevents it from ever being removed.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::{Result, Sim};
+
+/// Name of the checkpoints directory, located within the snapshots
+/// directory.
+pub const CHECKPOINTS_DIR_NAME: &str = "checkpoints";
+
+const CHECKPOINT_FILE_PREFIX: &str = "checkpoint";
+const CHECKPOINT_FILE_EXTENSION: &str = "snapshot";
+const CHECKPOINT_TEMP_EXTENSION: &str = "tmp";
+
+/// Checkpointing configuration.
+///
+/// Can be defined in the `[checkpoint]` section of the scenario manifest.
+/// If neither `every_steps` nor `every_minutes` is set, no checkpoints are
+/// ever created.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckpointSettings {
+    /// Create a checkpoint every N steps
+    pub every_steps: Option<usize>,
+    /// Create a checkpoint every T minutes
+    pub every_minutes: Option<u64>,
+    /// Number of most recent checkpoints to keep
+    pub keep_last: usize,
+    /// Keep every Mth checkpoint forever
+    pub keep_every: Option<usize>,
+    /// Compress checkpoints using LZ4
+    pub compress: bool,
+}
+
+impl Default for CheckpointSettings {
+    fn default() -> Self {
+        Self {
+            every_steps: None,
+            every_minutes: None,
+            keep_last: 3,
+            keep_every: None,
+            compress: false,
+        }
+    }
+}
+
+impl CheckpointSettings {
+    /// Checks whether the settings call for any checkpoints to be created.
+    pub fn is_enabled(&self) -> bool {
+        self.every_steps.map_or(false, |n| n > 0) || self.every_minutes.is_some()
+    }
+}
+
+/// Information about a single checkpoint file.
+#[derive(Debug, Clone)]
+pub struct CheckpointInfo {
+    /// Sequence number of the checkpoint
+    pub seq: usize,
+    /// Simulation clock at the time the checkpoint was created
+    pub clock: usize,
+    /// File name of the checkpoint
+    pub name: String,
+    /// Full path to the checkpoint file
+    pub path: PathBuf,
+}
+
+impl CheckpointInfo {
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()? != CHECKPOINT_FILE_EXTENSION {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        let mut split = stem.split('_');
+        if split.next()? != CHECKPOINT_FILE_PREFIX {
+            return None;
+        }
+        let seq = split.next()?.parse().ok()?;
+        let clock = split.next()?.parse().ok()?;
+        Some(Self {
+            seq,
+            clock,
+            name: path.file_name()?.to_string_lossy().to_string(),
+            path,
+        })
+    }
+}
+
+fn checkpoint_file_name(seq: usize, clock: usize) -> String {
+    format!(
+        "{}_{:06}_{}.{}",
+        CHECKPOINT_FILE_PREFIX, seq, clock, CHECKPOINT_FILE_EXTENSION
+    )
+}
+
+/// Lists checkpoints found in the given directory, ordered from oldest
+/// to newest.
+///
+/// Non-existent directory results in an empty list.
+pub fn list_checkpoints(dir: &Path) -> Result<Vec<CheckpointInfo>> {
+    let mut checkpoints = Vec::new();
+    if !dir.is_dir() {
+        return Ok(checkpoints);
+    }
+    for entry in fs::read_dir(dir)? {
+        if let Some(info) = CheckpointInfo::from_path(entry?.path()) {
+            checkpoints.push(info);
+        }
+    }
+    checkpoints.sort_by_key(|c| c.seq);
+    Ok(checkpoints)
+}
+
+/// Loads the newest checkpoint from the given directory that can be
+/// successfully restored.
+///
+/// Checkpoints that fail to load, for example because they were only
+/// partially written, are skipped.
+pub fn load_latest_valid(dir: &Path) -> Result<(CheckpointInfo, Sim)> {
+    for checkpoint in list_checkpoints(dir)?.into_iter().rev() {
+        match Sim::load_snapshot_from_dir(dir, &checkpoint.name) {
+            Ok(sim) => return Ok((checkpoint, sim)),
+            Err(e) => warn!("skipping invalid checkpoint {}: {}", checkpoint.name, e),
+        }
+    }
+    Err(Error::FailedReadingSnapshot(format!(
+        "no valid checkpoints found at: {}",
+        dir.to_string_lossy()
+    )))
+}
+
+/// Removes checkpoints that are not covered by the retention policy.
+pub fn prune_checkpoints(dir: &Path, settings: &CheckpointSettings) -> Result<()> {
+    let checkpoints = list_checkpoints(dir)?;
+    let recent_from = checkpoints.len().saturating_sub(settings.keep_last);
+    for (n, checkpoint) in checkpoints.iter().enumerate() {
+        if n >= recent_from {
+            break;
+        }
+        if let Some(keep_every) = settings.keep_every {
+            if keep_every > 0 && checkpoint.seq % keep_every == 0 {
+                continue;
+            }
+        }
+        debug!("removing checkpoint: {}", checkpoint.name);
+        fs::remove_file(&checkpoint.path)?;
+    }
+    Ok(())
+}
+
+/// Writes checkpoints based on the provided settings.
+///
+/// Only a single write is in progress at any given time, starting a new
+/// one waits for the previous one to finish. Pending write is also
+/// finished when the checkpointer is dropped.
+pub struct Checkpointer {
+    pub settings: CheckpointSettings,
+    dir: PathBuf,
+    next_seq: usize,
+    last_clock: usize,
+    last_time: Instant,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Checkpointer {
+    /// Creates a new checkpointer writing to the given directory.
+    ///
+    /// Sequence numbering continues from checkpoints already present in
+    /// the directory. Step intervals are counted from the provided clock.
+    pub fn new(settings: CheckpointSettings, dir: PathBuf, clock: usize) -> Result<Self> {
+        let next_seq = list_checkpoints(&dir)?
+            .last()
+            .map(|c| c.seq + 1)
+            .unwrap_or(1);
+        Ok(Self {
+            settings,
+            dir,
+            next_seq,
+            last_clock: clock,
+            last_time: Instant::now(),
+            handle: None,
+        })
+    }
+
+    /// Directory checkpoints are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Checks whether a checkpoint should be created at the given clock.
+    pub fn is_due(&self, clock: usize) -> bool {
+        if let Some(every_steps) = self.settings.every_steps {
+            if every_steps > 0 && clock >= self.last_clock + every_steps {
+                return true;
+            }
+        }
+        if let Some(every_minutes) = self.settings.every_minutes {
+            if self.last_time.elapsed() >= Duration::from_secs(every_minutes * 60) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Writes the serialized simulation state as a new checkpoint.
+    ///
+    /// Compression, writing and pruning of old checkpoints is performed on
+    /// a background thread.
+    pub fn write(&mut self, clock: usize, mut data: Vec<u8>) -> Result<()> {
+        self.finish()?;
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.last_clock = clock;
+        self.last_time = Instant::now();
+
+        let dir = self.dir.clone();
+        let settings = self.settings.clone();
+        self.handle = Some(std::thread::spawn(move || {
+            if settings.compress {
+                #[cfg(feature = "lz4")]
+                {
+                    data = crate::snapshot::compress_snapshot(data)?;
+                }
+                #[cfg(not(feature = "lz4"))]
+                warn!("checkpoint compression requires the lz4 feature");
+            }
+            fs::create_dir_all(&dir)?;
+            // write to a temporary file first so that an interrupted write
+            // doesn't leave a corrupted checkpoint behind
+            let path = dir.join(checkpoint_file_name(seq, clock));
+            let temp_path = path.with_extension(CHECKPOINT_TEMP_EXTENSION);
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &path)?;
+            debug!("written checkpoint: {}", path.to_string_lossy());
+
+            prune_checkpoints(&dir, &settings)
+        }));
+        Ok(())
+    }
+
+    /// Waits for the pending write to finish.
+    pub fn finish(&mut self) -> Result<()> {
+        if let Some(handle) = self.handle.take() {
+            handle
+                .join()
+                .map_err(|_| Error::FailedCreatingSnapshot("checkpoint thread panicked".into()))??;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Checkpointer {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            error!("failed writing checkpoint: {}", e);
+        }
+    }
+}
+
+#[test]
+fn checkpoint_file_name_roundtrip() {
+    let name = checkpoint_file_name(12, 3400);
+    let info = CheckpointInfo::from_path(PathBuf::from("checkpoints").join(&name)).unwrap();
+    assert_eq!(info.seq, 12);
+    assert_eq!(info.clock, 3400);
+    assert_eq!(info.name, name);
+
+    let temp = PathBuf::from(name).with_extension(CHECKPOINT_TEMP_EXTENSION);
+    assert!(CheckpointInfo::from_path(temp).is_none());
+}
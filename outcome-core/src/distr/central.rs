@@ -13,7 +13,9 @@ use id_pool::IdPool;
 use rand::prelude::SliceRandom;
 
 #[cfg(feature = "machine")]
-use crate::machine::{cmd::CentralRemoteCommand, cmd::Command, cmd::ExtCommand, ExecutionContext};
+use crate::machine::{
+    cmd::CentralRemoteCommand, cmd::Command, cmd::ExtCommand, exec, ExecutionContext,
+};
 
 use crate::distr::{
     CentralCommunication, DistributionPolicy, NodeCommunication, NodeId, Signal, TaskId,
@@ -21,7 +23,7 @@ use crate::distr::{
 use crate::entity::Entity;
 use crate::error::{Error, Result};
 use crate::model::Scenario;
-use crate::snapshot::Snapshot;
+use crate::snapshot::{Snapshot, SnapshotHeader, SnapshotMetadata};
 use crate::{
     string, Address, EntityId, EntityName, EventName, PrefabName, ShortString, Sim, SimModel,
     SimStarter, StringId, Var, SCENARIOS_DIR_NAME, SNAPSHOTS_DIR_NAME,
@@ -56,6 +58,11 @@ pub struct SimCentral {
 
     ent_spawn_queue: FnvHashMap<NodeId, Vec<(EntityId, Option<PrefabName>, Option<EntityName>)>>,
     pub model_changes_queue: SimModel,
+
+    /// Entities restored from a snapshot, waiting to be distributed among
+    /// the nodes
+    #[serde(skip)]
+    pub restore_queue: FnvHashMap<EntityId, Entity>,
 }
 
 impl SimCentral {
@@ -64,6 +71,14 @@ impl SimCentral {
         self.clock
     }
 
+    /// Sets the default distribution policy, rejecting policies that are
+    /// not supported yet.
+    pub fn set_distribution_policy(&mut self, policy: DistributionPolicy) -> Result<()> {
+        policy.check_supported()?;
+        self.distribution_policy = policy;
+        Ok(())
+    }
+
     /// Flushes the communication queue, lumping requests of the same type
     /// together if possible.
     pub fn flush_queue<C: CentralCommunication>(&mut self, comms: &mut C) -> Result<()> {
@@ -99,23 +114,31 @@ impl SimCentral {
                         .to_str()
                         .unwrap(),
                 )?;
-                Ok(Self {
-                    starter: Some(starter.clone()),
-                    model: sim.model,
-                    clock: sim.clock,
-                    event_queue: sim.event_queue,
-                    distribution_policy: DistributionPolicy::Random,
-                    node_entities: Default::default(),
-                    entities_idx: sim.entity_idx,
-                    entity_idpool: sim.entity_pool,
-                    ent_spawn_queue: Default::default(),
-                    model_changes_queue: Default::default(),
-                })
+                Ok(Self::from_sim(sim, Some(starter.clone())))
             }
             SimStarter::Experiment(_) => unimplemented!(),
         }
     }
 
+    /// Creates a new `SimCentral` out of a simulation restored from
+    /// a snapshot. Entities are put into the restore queue, waiting to be
+    /// distributed among the nodes.
+    pub fn from_sim(sim: Sim, starter: Option<SimStarter>) -> SimCentral {
+        SimCentral {
+            starter,
+            model: sim.model,
+            clock: sim.clock,
+            event_queue: sim.event_queue,
+            distribution_policy: DistributionPolicy::Random,
+            node_entities: Default::default(),
+            entities_idx: sim.entity_idx,
+            entity_idpool: sim.entity_pool,
+            ent_spawn_queue: Default::default(),
+            model_changes_queue: Default::default(),
+            restore_queue: sim.entities,
+        }
+    }
+
     /// Creates a new `SimCentral` using a model object.
     pub fn from_model(model: SimModel, starter: Option<SimStarter>) -> Result<SimCentral> {
         let mut event_queue = vec![string::new_truncate("step")];
@@ -130,6 +153,7 @@ impl SimCentral {
             entity_idpool: IdPool::new(),
             ent_spawn_queue: Default::default(),
             model_changes_queue: SimModel::default(),
+            restore_queue: Default::default(),
         };
         // module script init
         // #[cfg(feature = "machine_script")]
@@ -144,6 +168,13 @@ impl SimCentral {
         //         .push(StringId::from("_scr_init").unwrap());
         // }
 
+        // TODO spawn signal doesn't carry initial var values
+        if !sim_central.model.entity_tables.is_empty() {
+            return Err(Error::Other(
+                "entity tables are not supported in distributed simulations".to_string(),
+            ));
+        }
+
         Ok(sim_central)
     }
 
@@ -174,6 +205,7 @@ impl SimCentral {
         policy: DistributionPolicy,
     ) -> Result<()> {
         trace!("spawning entity from central");
+        policy.check_supported()?;
 
         let new_id = self.entity_idpool.request_id().unwrap();
 
@@ -222,7 +254,25 @@ impl SimCentral {
                     name.clone(),
                 ));
             }
-            _ => unimplemented!(),
+            DistributionPolicy::Balanced => {
+                // pick the node currently holding the least entities
+                let node_id = self
+                    .node_entities
+                    .iter()
+                    .map(|(node_id, entities)| {
+                        let queued = self.ent_spawn_queue.get(node_id).map_or(0, |q| q.len());
+                        (entities.len() + queued, *node_id)
+                    })
+                    .min()
+                    .map(|(_, node_id)| node_id)
+                    .ok_or(Error::Other("no nodes available".to_string()))?;
+                self.ent_spawn_queue.entry(node_id).or_default().push((
+                    new_id,
+                    prefab,
+                    name.clone(),
+                ));
+            }
+            _ => unreachable!(),
         }
 
         // self.ent_spawn_queue.push((new_uid, prefab, name));
@@ -234,39 +284,106 @@ impl SimCentral {
         Ok(())
     }
 
+    /// Assigns entities to nodes based on the distribution policy.
+    ///
+    /// Returns an error for policies that are not supported yet, see
+    /// [`DistributionPolicy::is_supported`].
     pub fn assign_entities(
         &self,
-        node_count: usize,
-        policy: DistributionPolicy,
-    ) -> Vec<Vec<EntityId>> {
+        entities: &[EntityId],
+        node_ids: &[NodeId],
+        policy: &DistributionPolicy,
+    ) -> Result<FnvHashMap<NodeId, Vec<EntityId>>> {
+        policy.check_supported()?;
+        if node_ids.is_empty() {
+            return Err(Error::Other("no nodes available".to_string()));
+        }
+        let mut assignment: FnvHashMap<NodeId, Vec<EntityId>> =
+            node_ids.iter().map(|id| (*id, Vec::new())).collect();
         match policy {
-            // EntityAssignMethod::Random => {
-            //     let mut ent_models = self.model.entities.clone();
-            //     let mut thread_rng = rand::thread_rng();
-            //     ent_models.shuffle(&mut thread_rng);
-            //
-            //     let mut out_vec = Vec::new();
-            //     let chunk_size = ent_models.len() / node_count;
-            //     for n in 0..node_count {
-            //         let mut ent_vec = Vec::new();
-            //         if ent_models.len() >= chunk_size {
-            //             for cn in 0..chunk_size {
-            //                 let em = ent_models.pop().unwrap();
-            //                 ent_vec.push(StringId::from(&em.name).unwrap());
-            //             }
-            //         } else {
-            //             for em in &ent_models {
-            //                 ent_vec.push(StringId::from(&em.name).unwrap());
-            //             }
-            //             ent_models.clear();
-            //         }
-            //         out_vec.push(ent_vec);
-            //         //                    let div =
-            //         // ent_models
-            //     }
-            //     return out_vec;
-            // }
-            _ => unimplemented!(),
+            DistributionPolicy::BindToNode(node_id) => {
+                assignment
+                    .get_mut(node_id)
+                    .ok_or(Error::Other(format!("node not available: {}", node_id)))?
+                    .extend_from_slice(entities);
+            }
+            DistributionPolicy::Random => {
+                let mut entities = entities.to_vec();
+                entities.shuffle(&mut rand::thread_rng());
+                for (n, entity_id) in entities.into_iter().enumerate() {
+                    let node_id = node_ids[n % node_ids.len()];
+                    assignment.get_mut(&node_id).unwrap().push(entity_id);
+                }
+            }
+            DistributionPolicy::Balanced => {
+                let mut entities = entities.to_vec();
+                entities.sort();
+                for (n, entity_id) in entities.into_iter().enumerate() {
+                    let node_id = node_ids[n % node_ids.len()];
+                    assignment.get_mut(&node_id).unwrap().push(entity_id);
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(assignment)
+    }
+
+    /// Distributes entities waiting in the restore queue among all the
+    /// currently available nodes, based on the current distribution policy.
+    ///
+    /// Returns the resulting assignment of entities to nodes.
+    pub fn flush_restore_queue<C: CentralCommunication>(
+        &mut self,
+        comms: &mut C,
+    ) -> Result<FnvHashMap<NodeId, Vec<EntityId>>> {
+        if self.restore_queue.is_empty() {
+            return Ok(Default::default());
+        }
+        let mut node_ids = comms.get_node_ids()?;
+        node_ids.sort();
+        let entity_ids = self.restore_queue.keys().cloned().collect::<Vec<_>>();
+        let assignment = self.assign_entities(&entity_ids, &node_ids, &self.distribution_policy)?;
+
+        let names: FnvHashMap<EntityId, EntityName> = self
+            .entities_idx
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect();
+        for (node_id, entity_ids) in &assignment {
+            let mut entities = Vec::new();
+            for entity_id in entity_ids {
+                if let Some(entity) = self.restore_queue.remove(entity_id) {
+                    entities.push((*entity_id, names.get(entity_id).cloned(), entity));
+                }
+            }
+            debug!("restoring {} entities on node {}", entities.len(), node_id);
+            comms.send_sig_to_node(*node_id, 0, Signal::RestoreEntities(self.clock, entities))?;
+            self.node_entities
+                .entry(*node_id)
+                .or_insert(Vec::new())
+                .extend(entity_ids);
+        }
+        Ok(assignment)
+    }
+
+    /// Creates a snapshot header using the current central state.
+    ///
+    /// Header is combined with parts collected from the nodes to create
+    /// a full union snapshot.
+    pub fn snapshot_header(&self) -> SnapshotHeader {
+        SnapshotHeader {
+            metadata: SnapshotMetadata {
+                created: chrono::Utc::now(),
+                starter: self
+                    .starter
+                    .clone()
+                    .unwrap_or(SimStarter::Scenario("".to_string())),
+            },
+            clock: self.clock,
+            model: self.model.clone(),
+            entities_idx: self.entities_idx.clone(),
+            event_queue: self.event_queue.clone(),
+            entity_pool: self.entity_idpool.clone(),
         }
     }
 
@@ -287,10 +404,11 @@ impl SimCentral {
     /// # Protocol overview
     ///
     /// 1. All nodes are signalled to start processing next step.
-    /// 2. Nodes send back central remote commands that came up during their
-    /// local processing, if any.
+    /// 2. Nodes send back central remote commands and writes to other
+    /// entities that came up during their local processing, if any.
     /// 3. Incoming central remote commands are executed and results are sent
-    /// back. Any model changes are also sent to the nodes.
+    /// back. Any model changes are also sent to the nodes. Writes are sorted
+    /// and routed to the nodes holding the target entities.
     /// 4. Nodes signal their readiness to move on to the next step.
     pub fn step_network<N: CentralCommunication>(
         &mut self,
@@ -307,6 +425,8 @@ impl SimCentral {
         #[cfg(feature = "machine")]
         let mut cext_cmds: Arc<Mutex<Vec<(ExecutionContext, CentralRemoteCommand)>>> =
             Arc::new(Mutex::new(Vec::new()));
+        #[cfg(feature = "machine")]
+        let mut ext_cmds: Vec<(ExecutionContext, ExtCommand)> = Vec::new();
 
         let mut do_nodes = network.get_node_ids()?;
         let mut node_counter = 0;
@@ -318,6 +438,13 @@ impl SimCentral {
                     Signal::ExecuteCentralExtCmd(cmd) => cext_cmds.lock().unwrap().push(cmd),
                     #[cfg(feature = "machine")]
                     Signal::ExecuteCentralExtCmds(cmds) => cext_cmds.lock().unwrap().extend(cmds),
+                    #[cfg(feature = "machine")]
+                    Signal::ExecuteExtCmds(cmds) => ext_cmds.extend(cmds),
+                    Signal::RouteToEntity(entity, signal) => {
+                        if let Err(e) = network.send_sig_to_entity(entity, task_id, *signal) {
+                            error!("failed routing signal to entity {}: {}", entity, e);
+                        }
+                    }
                     Signal::EndOfMessages | Signal::ProcessStepFinished => {
                         do_nodes.remove(node_counter);
                     }
@@ -344,16 +471,25 @@ impl SimCentral {
         }
         network.broadcast_sig(0, Signal::UpdateModel(self.model.clone()));
         self.flush_queue(network)?;
+        #[cfg(feature = "machine")]
+        self.route_ext_cmds(network, ext_cmds)?;
 
         network.broadcast_sig(0, Signal::EndOfMessages)?;
         // network.sig_broadcast(Signal::EndOfMessages)?;
-        loop {
-            std::thread::sleep(std::time::Duration::from_millis(8));
-            if let Ok((_, _, s)) = network.try_recv_sig() {
-                match s {
-                    Signal::ProcessStepFinished => break,
-                    _ => (),
+
+        // wait until all the nodes have finished the step, so that the
+        // clock only advances once the whole union is at the same step
+        let mut unfinished = network.get_node_ids()?;
+        while !unfinished.is_empty() {
+            match network.try_recv_sig() {
+                Ok((node_id, _, Signal::ProcessStepFinished)) => {
+                    unfinished.retain(|id| *id != node_id)
+                }
+                Ok((node_id, _, signal)) => {
+                    debug!("unexpected signal from node {}: {:?}", node_id, signal)
                 }
+                Err(Error::WouldBlock) => std::thread::sleep(std::time::Duration::from_millis(1)),
+                Err(e) => return Err(e),
             }
         }
         debug!("finished executing cext commands");
@@ -362,6 +498,58 @@ impl SimCentral {
         Ok(())
     }
 
+    /// Routes external commands collected from the nodes to the nodes
+    /// holding the target entities.
+    ///
+    /// Commands are sorted before routing, so that nodes apply them in
+    /// the same order as a single `Sim` would. Only writes and messages
+    /// are currently routed, other external commands are dropped.
+    #[cfg(feature = "machine")]
+    fn route_ext_cmds<N: CentralCommunication>(
+        &self,
+        network: &mut N,
+        mut ext_cmds: Vec<(ExecutionContext, ExtCommand)>,
+    ) -> Result<()> {
+        exec::sort_ext(&mut ext_cmds);
+        let mut routed: FnvHashMap<EntityId, Vec<(ExecutionContext, ExtCommand)>> =
+            FnvHashMap::default();
+        for (context, mut cmd) in ext_cmds {
+            let target = match &mut cmd {
+                ExtCommand::Write(write) => {
+                    let id = match self.entities_idx.get(&write.target.entity) {
+                        Some(id) => *id,
+                        None => match write.target.entity.parse::<EntityId>() {
+                            Ok(id) => id,
+                            Err(_) => {
+                                warn!("write target entity not found: {}", write.target);
+                                continue;
+                            }
+                        },
+                    };
+                    // nodes only know the names of their own entities
+                    write.target.entity = string::new_truncate(&id.to_string());
+                    id
+                }
+                ExtCommand::Send(send) => match send.target_id(&self.entities_idx) {
+                    Some(id) => {
+                        send.target = string::new_truncate(&id.to_string());
+                        id
+                    }
+                    None => {
+                        warn!("message target entity not found: {}", send.target);
+                        continue;
+                    }
+                },
+                _ => continue,
+            };
+            routed.entry(target).or_default().push((context, cmd));
+        }
+        for (entity, cmds) in routed {
+            network.send_sig_to_entity(entity, 0, Signal::ExecuteExtCmds(cmds))?;
+        }
+        Ok(())
+    }
+
     pub fn init_snapshot_download<N: CentralCommunication>(
         &mut self,
         network: &mut N,
@@ -376,3 +564,144 @@ impl SimCentral {
         Ok(task_id)
     }
 }
+
+#[cfg(test)]
+#[derive(Default)]
+struct TestComms {
+    node_ids: Vec<NodeId>,
+    sent: Vec<(NodeId, Signal)>,
+}
+
+#[cfg(test)]
+impl CentralCommunication for TestComms {
+    fn request_task_id(&mut self) -> Result<TaskId> {
+        Ok(0)
+    }
+    fn return_task_id(&mut self, _task_id: TaskId) -> Result<()> {
+        Ok(())
+    }
+    fn get_node_ids(&self) -> Result<Vec<NodeId>> {
+        Ok(self.node_ids.clone())
+    }
+    fn try_recv_sig(&mut self) -> Result<(NodeId, TaskId, Signal)> {
+        Err(Error::Other("no signals".to_string()))
+    }
+    fn try_recv_sig_from(&mut self, _node_id: NodeId) -> Result<(TaskId, Signal)> {
+        Err(Error::Other("no signals".to_string()))
+    }
+    fn send_sig_to_node(
+        &mut self,
+        node_id: NodeId,
+        _task_id: TaskId,
+        signal: Signal,
+    ) -> Result<()> {
+        self.sent.push((node_id, signal));
+        Ok(())
+    }
+    fn send_sig_to_entity(
+        &mut self,
+        _entity_uid: EntityId,
+        _task_id: TaskId,
+        _signal: Signal,
+    ) -> Result<()> {
+        unimplemented!()
+    }
+    fn broadcast_sig(&mut self, _task_id: TaskId, _signal: Signal) -> Result<()> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn union_snapshot_restores_on_any_node_count() {
+    use crate::distr::SimNode;
+    use crate::snapshot::{assemble_union_snapshot, Snap, SnapPart};
+
+    let var = |name: &str| (string::new_truncate("comp"), string::new_truncate(name));
+
+    // three nodes, each holding a few entities
+    let mut central = SimCentral::from_model(SimModel::default(), None).unwrap();
+    let mut parts = Vec::new();
+    let mut expected = FnvHashMap::default();
+    for node in 0..3u32 {
+        let mut sim_node = SimNode::from_model(&central.model).unwrap();
+        for n in 0..4u32 {
+            let id = node * 4 + n;
+            let mut entity = Entity::empty();
+            entity.storage.insert(var("id"), Var::Int(id as crate::Int));
+            entity
+                .storage
+                .insert(var("node"), Var::Int(node as crate::Int));
+            expected.insert(id, entity.storage.map.clone());
+            sim_node.entities.insert(id, entity);
+            let name = string::new_truncate(&format!("ent_{}", id));
+            sim_node.entities_idx.insert(name.clone(), id);
+            central.entities_idx.insert(name, id);
+        }
+        parts.push(sim_node.to_snapshot_part().unwrap());
+    }
+    let bytes = assemble_union_snapshot(&central.snapshot_header(), &parts).unwrap();
+
+    // restore onto a single local sim
+    let sim = Sim::from_snapshot(&mut bytes.clone()).unwrap();
+    assert_eq!(sim.entities.len(), expected.len());
+    for (id, map) in &expected {
+        assert_eq!(&sim.entities[id].storage.map, map);
+    }
+    assert_eq!(sim.entity_idx, central.entities_idx);
+
+    // re-shard onto two nodes
+    let mut central = SimCentral::from_sim(sim, None);
+    central
+        .set_distribution_policy(DistributionPolicy::Balanced)
+        .unwrap();
+    let mut comms = TestComms {
+        node_ids: vec![1, 0],
+        ..Default::default()
+    };
+    let assignment = central.flush_restore_queue(&mut comms).unwrap();
+    assert_eq!(assignment.len(), 2);
+    assert!(central.restore_queue.is_empty());
+
+    let mut nodes: FnvHashMap<NodeId, SimNode> = FnvHashMap::default();
+    for (node_id, signal) in comms.sent {
+        match signal {
+            Signal::RestoreEntities(clock, entities) => {
+                let sim_node = nodes
+                    .entry(node_id)
+                    .or_insert_with(|| SimNode::from_model(&central.model).unwrap());
+                sim_node.restore_entities(clock, entities);
+            }
+            _ => panic!("unexpected signal"),
+        }
+    }
+    assert_eq!(nodes.len(), 2);
+    let mut restored = 0;
+    for (node_id, sim_node) in &nodes {
+        assert_eq!(
+            sim_node.entities.len(),
+            central.node_entities[node_id].len()
+        );
+        for (id, entity) in &sim_node.entities {
+            assert_eq!(&entity.storage.map, &expected[id]);
+            restored += 1;
+        }
+        for (name, id) in &sim_node.entities_idx {
+            assert_eq!(central.entities_idx[name], *id);
+        }
+    }
+    assert_eq!(restored, expected.len());
+}
+
+#[test]
+fn unsupported_distribution_policy_is_rejected() {
+    let mut central = SimCentral::from_model(SimModel::default(), None).unwrap();
+    assert!(central
+        .set_distribution_policy(DistributionPolicy::MaxSpeed)
+        .is_err());
+    assert!(central
+        .assign_entities(&[0, 1], &[0], &DistributionPolicy::Spatial)
+        .is_err());
+    assert!(central
+        .assign_entities(&[0, 1], &[0], &DistributionPolicy::Balanced)
+        .is_ok());
+}
@@ -50,7 +50,13 @@ pub enum Signal {
     /// Request node to start processing step, includes event_queue vec
     StartProcessStep(Vec<StringId>),
 
+    /// Request node to send back its part of the union snapshot
     SnapshotRequest,
+    /// Node's serialized snapshot part, along with the node's clock
+    SnapshotResponse(usize, Vec<u8>),
+    /// Request node to take over entities restored from a snapshot,
+    /// includes the clock of the restored simulation
+    RestoreEntities(usize, Vec<(EntityId, Option<EntityName>, Entity)>),
 
     WorkerConnected,
 
@@ -86,9 +92,16 @@ pub enum Signal {
     /// Request pulling the provided data
     DataPullRequest(Vec<(Address, Var)>),
 
+    /// Signal to be passed on by central to the node holding the entity
+    RouteToEntity(EntityId, Box<Signal>),
+
     /// External command to be executed on a node
     #[cfg(feature = "machine")]
     ExecuteExtCmd((ExecutionContext, ExtCommand)),
+    /// External commands sent by a node to central for routing, or by
+    /// central to the node holding the target entity
+    #[cfg(feature = "machine")]
+    ExecuteExtCmds(Vec<(ExecutionContext, ExtCommand)>),
     /// Central-external command to be executed on central
     #[cfg(feature = "machine")]
     ExecuteCentralExtCmd((ExecutionContext, CentralRemoteCommand)),
@@ -167,7 +180,7 @@ pub trait NodeCommunication {
 /// Some policies define a more rigid distribution, while others work by
 /// actively monitoring the situation across different nodes and transferring
 /// entities around as needed.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum DistributionPolicy {
     /// Set binding to a specific node
     BindToNode(u32),
@@ -197,3 +210,31 @@ pub enum DistributionPolicy {
     /// distributed based on which box they are currently in.
     Spatial,
 }
+
+impl DistributionPolicy {
+    /// Checks whether the policy can currently be used for distributing
+    /// entities.
+    ///
+    /// Policies relying on node capability information, traffic monitoring
+    /// or entity positions are not supported yet.
+    pub fn is_supported(&self) -> bool {
+        match self {
+            DistributionPolicy::BindToNode(_)
+            | DistributionPolicy::Random
+            | DistributionPolicy::Balanced => true,
+            _ => false,
+        }
+    }
+
+    /// Returns an error if the policy is not supported.
+    pub fn check_supported(&self) -> Result<()> {
+        if self.is_supported() {
+            Ok(())
+        } else {
+            Err(Error::Other(format!(
+                "distribution policy not supported: {:?}",
+                self
+            )))
+        }
+    }
+}
@@ -132,6 +132,24 @@ impl SimNode {
         Ok(())
     }
 
+    /// Takes over entities restored from a snapshot, setting the node clock
+    /// to match the restored simulation.
+    pub fn restore_entities(
+        &mut self,
+        clock: usize,
+        entities: Vec<(EntityId, Option<EntityName>, Entity)>,
+    ) {
+        self.clock = clock;
+        // restored simulation is already past initialization
+        self.event_queue.clear();
+        for (id, name, entity) in entities {
+            self.entities.insert(id, entity);
+            if let Some(name) = name {
+                self.entities_idx.insert(name, id);
+            }
+        }
+    }
+
     /// Apply registered model entities by instantiating them.
     /// None of the existing entities are removed. Only entities
     /// registered with the `spawn` flag are instantiated.
@@ -268,6 +286,41 @@ impl SimNode {
         //     });
         // println!("sim_node finished read ext cmd responses");
 
+        // messages received during the previous step were already read
+        self.entities
+            .par_iter_mut()
+            .for_each(|(_, entity)| entity.storage.inbox.clear());
+
+        // messages for entities stored on this node are collected together
+        // with the ones routed here from other nodes, other messages are
+        // sent to the nodes holding the target entities. Writes and
+        // messages for entities this node doesn't know about go to central,
+        // which routes them further.
+        let mut ext_cmds = ext_cmds.lock().unwrap().clone();
+        crate::machine::exec::sort_ext(&mut ext_cmds);
+        let mut messages = Vec::new();
+        let mut to_central = Vec::new();
+        let mut to_entities: FnvHashMap<EntityId, Vec<(ExecutionContext, ExtCommand)>> =
+            FnvHashMap::default();
+        for (context, cmd) in ext_cmds {
+            let target = match &cmd {
+                ExtCommand::Send(send) => send.target_id(&self.entities_idx),
+                ExtCommand::Write(_) => None,
+                _ => continue,
+            };
+            match target {
+                Some(id) if self.entities.contains_key(&id) => messages.push((context, cmd)),
+                Some(id) => to_entities.entry(id).or_default().push((context, cmd)),
+                None => to_central.push((context, cmd)),
+            }
+        }
+        for part in to_central.chunks(1000) {
+            network.sig_send_central(0, Signal::ExecuteExtCmds(part.to_vec()))?;
+        }
+        for (entity, cmds) in to_entities {
+            network.sig_send_to_entity(entity, 0, Signal::ExecuteExtCmds(cmds))?;
+        }
+
         let mut cexts = central_ext_cmds.lock().unwrap().clone();
         cexts.reverse();
         let mut counter = 0;
@@ -306,6 +359,16 @@ impl SimNode {
                     }
                     info!("spawn entities finished");
                 }
+                Signal::ExecuteExtCmds(cmds) => {
+                    debug!("signal: execute ext cmds ({})", cmds.len());
+                    let (sends, others): (Vec<_>, Vec<_>) =
+                        cmds.into_iter().partition(|(_, cmd)| match cmd {
+                            ExtCommand::Send(_) => true,
+                            _ => false,
+                        });
+                    self.execute_ext_cmds(&others);
+                    messages.extend(sends);
+                }
                 // TODO currently rewrites the whole model with the received data
                 Signal::UpdateModel(model) => {
                     debug!("signal: update model");
@@ -319,6 +382,10 @@ impl SimNode {
                 _ => (),
             }
         }
+        // messages coming from other nodes arrive in no particular order,
+        // deliver all of them ordered by the sender
+        crate::machine::exec::sort_ext(&mut messages);
+        self.execute_ext_cmds(&messages);
         self.clock += 1;
 
         debug!("sending signal process step finished");
@@ -329,6 +396,42 @@ impl SimNode {
         Ok(())
     }
 
+    /// Applies writes to vars of entities stored on this node and delivers
+    /// messages to their inboxes.
+    ///
+    /// Commands are executed in the order they were received in, which is
+    /// expected to be already sorted by the sender.
+    #[cfg(feature = "machine")]
+    pub fn execute_ext_cmds(
+        &mut self,
+        ext_cmds: &[(
+            crate::machine::ExecutionContext,
+            crate::machine::cmd::ExtCommand,
+        )],
+    ) {
+        use crate::machine::cmd::ExtCommand;
+        for (_, cmd) in ext_cmds {
+            match cmd {
+                ExtCommand::Write(write) => {
+                    let result = self
+                        .get_var_mut(&write.target)
+                        .and_then(|target| write.policy.merge(target, write.value.clone()));
+                    if let Err(e) = result {
+                        error!("failed executing write to {}: {}", write.target, e);
+                    }
+                }
+                ExtCommand::Send(send) => match send
+                    .target_id(&self.entities_idx)
+                    .and_then(|id| self.entities.get_mut(&id))
+                {
+                    Some(entity) => entity.storage.inbox.push(send.message.clone()),
+                    None => error!("message target entity not found: {}", send.target),
+                },
+                _ => (),
+            }
+        }
+    }
+
     //fn exec_ext_get(&self, get: cmd::get_set::Get) {}
 
     /// Serialize, send over and locally remove selected
@@ -2,7 +2,7 @@
 
 mod storage;
 
-pub use self::storage::Storage;
+pub use self::storage::{Message, Storage};
 
 use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
@@ -10,7 +10,7 @@ use std::sync::{Arc, Mutex};
 use fnv::FnvHashMap;
 
 use crate::error::{Error, Result};
-use crate::model::{ComponentModel, EntityPrefab};
+use crate::model::{ComponentModel, EntityPrefab, VarOverride};
 use crate::{model, CompName, StringId};
 use crate::{string, EntityName, EventName, SimModel};
 
@@ -72,19 +72,19 @@ impl Entity {
         for comp in &prefab.components {
             ent.attach(comp.clone(), model)?;
         }
+        ent.set_vars(&prefab.vars)?;
 
         // TODO setup dyn libs
 
         Ok(ent)
     }
 
-    /// Creates a new entity from model.
+    /// Creates a new entity from model, applying the prefab's inheritance
+    /// chain.
     pub fn from_prefab_name(prefab: &EntityName, sim_model: &model::SimModel) -> Result<Entity> {
         trace!("creating entity from prefab name: {}", prefab);
-        let ent_model = sim_model
-            .get_entity(prefab)
-            .ok_or(Error::NoEntityPrefab(prefab.clone()))?;
-        Entity::from_prefab(ent_model, sim_model)
+        let ent_model = sim_model.resolve_prefab(prefab)?;
+        Entity::from_prefab(&ent_model, sim_model)
     }
 
     /// Creates a new empty entity.
@@ -100,6 +100,25 @@ impl Entity {
         }
     }
 
+    /// Sets values of existing vars, making sure the value types match the
+    /// declared var types.
+    pub fn set_vars(&mut self, vars: &[VarOverride]) -> Result<()> {
+        for var in vars {
+            let current = self.storage.get_var_mut(&var.address.storage_index())?;
+            if current.get_type() != var.value.get_type() {
+                return Err(Error::InvalidVarType(format!(
+                    "{}:{}: declared as {}, got {}",
+                    var.address.comp,
+                    var.address.var_name,
+                    current.get_type().to_str(),
+                    var.value.get_type().to_str()
+                )));
+            }
+            *current = var.value.clone();
+        }
+        Ok(())
+    }
+
     pub fn attach(&mut self, component: CompName, model: &SimModel) -> Result<()> {
         let comp_model = model.get_component(&component)?;
         debug!("attaching component: {:?}", comp_model);
@@ -178,4 +197,111 @@ impl Entity {
 
         Ok(())
     }
+
+    /// Brings the entity up to date with a changed model.
+    ///
+    /// Existing vars keep their values as long as their type didn't
+    /// change, otherwise they're reset to the default value. Components
+    /// no longer present in the model are detached.
+    ///
+    /// Returns descriptions of changes that resulted in loss of data.
+    pub fn update_to_model(&mut self, model: &SimModel) -> Vec<String> {
+        let mut incompatible = Vec::new();
+
+        let removed = self
+            .components
+            .iter()
+            .filter(|c| model.get_component(c).is_err())
+            .cloned()
+            .collect::<Vec<_>>();
+        for comp_name in &removed {
+            self.components.retain(|c| c != comp_name);
+            self.storage.map.retain(|(c, _), _| c != comp_name);
+            #[cfg(feature = "machine")]
+            {
+                self.comp_state.remove(comp_name);
+                for queue in self.comp_queue.values_mut() {
+                    queue.retain(|c| c != comp_name);
+                }
+            }
+        }
+
+        #[cfg(feature = "machine")]
+        for event in &model.events {
+            self.comp_queue
+                .entry(string::new_truncate(&event.id))
+                .or_insert_with(Vec::new);
+        }
+
+        for comp_name in &self.components {
+            let comp_model = match model.get_component(comp_name) {
+                Ok(c) => c,
+                Err(_) => continue,
+            };
+
+            // remove vars that are no longer declared
+            self.storage.map.retain(|(c, v), _| {
+                c != comp_name || comp_model.vars.iter().any(|var| &var.name == v)
+            });
+            for var_model in &comp_model.vars {
+                let default = var_model
+                    .default
+                    .to_owned()
+                    .unwrap_or(var_model.type_.default_value());
+                let idx = (comp_name.clone(), var_model.name.clone());
+                match self.storage.map.get_mut(&idx) {
+                    Some(var) if var.get_type() == var_model.type_ => (),
+                    Some(var) => {
+                        incompatible.push(format!(
+                            "{}:{}: type changed from {} to {}, value reset to default",
+                            comp_name,
+                            var_model.name,
+                            var.get_type().to_str(),
+                            var_model.type_.to_str()
+                        ));
+                        *var = default;
+                    }
+                    None => self.storage.insert(idx, default),
+                }
+            }
+
+            #[cfg(feature = "machine")]
+            {
+                for (event, queue) in &mut self.comp_queue {
+                    let triggered = comp_model
+                        .triggers
+                        .iter()
+                        .any(|t| t.as_str() == event.as_str());
+                    let queued = queue.contains(comp_name);
+                    if triggered && !queued {
+                        queue.push(comp_name.clone());
+                    } else if !triggered && queued {
+                        queue.retain(|c| c != comp_name);
+                    }
+                }
+
+                let start_state = &comp_model.logic.start_state;
+                let current = self.comp_state.get(comp_name).cloned();
+                match current {
+                    Some(state)
+                        if &state == start_state
+                            || comp_model.logic.states.contains_key(&state) => {}
+                    Some(state) => {
+                        incompatible.push(format!(
+                            "{}: state \"{}\" no longer exists, reset to \"{}\"",
+                            comp_name, state, start_state
+                        ));
+                        self.comp_state
+                            .insert(comp_name.clone(), start_state.clone());
+                    }
+                    None => {
+                        self.comp_state
+                            .insert(comp_name.clone(), start_state.clone());
+                    }
+                }
+            }
+        }
+
+        incompatible
+    }
 }
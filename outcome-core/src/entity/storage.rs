@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use fnv::FnvHashMap;
 
 use crate::address::{Address, LocalAddress};
 use crate::error::{Error, Result};
 use crate::model::ComponentModel;
-use crate::{string, CompName, StringId, Var, VarName, VarType};
+use crate::{string, CompName, EntityId, StringId, Var, VarName, VarType};
 
 pub type StorageIndex = (CompName, VarName);
 // type TypedStorageIndex = (StorageIndex, VarType);
@@ -14,10 +14,43 @@ pub type StorageIndex = (CompName, VarName);
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct Storage {
     pub map: FnvHashMap<StorageIndex, Var>,
+    /// Messages sent to the entity during the previous step
+    pub inbox: Vec<Message>,
     // TODO benchmark performance of the alternative storage layout
     // _map: FnvHashMap<CompId, FnvHashMap<VarId, Var>>,
 }
 
+/// Message sent from one entity to another.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Message {
+    /// Id of the sending entity
+    pub sender: EntityId,
+    /// Type of the message, used by the receiver to tell messages apart
+    pub kind: StringId,
+    pub args: Vec<Var>,
+}
+
+impl Message {
+    /// Converts the message into a map var with `sender`, `type` and `args`
+    /// keys.
+    pub fn to_var(&self) -> Var {
+        let mut map = BTreeMap::new();
+        map.insert(
+            Var::String("sender".to_string()),
+            Var::Int(self.sender as crate::Int),
+        );
+        map.insert(
+            Var::String("type".to_string()),
+            Var::String(self.kind.to_string()),
+        );
+        map.insert(
+            Var::String("args".to_string()),
+            Var::List(self.args.clone()),
+        );
+        Var::Map(map)
+    }
+}
+
 impl Storage {
     pub fn get_var(&self, idx: &StorageIndex) -> Result<&Var> {
         self.map
@@ -72,10 +72,38 @@ pub enum Error {
     FailedReadingSnapshot(String),
     #[error("failed creating snapshot: {0}")]
     FailedCreatingSnapshot(String),
+    #[error("unsupported snapshot format version: {0}, newest supported version: {1}")]
+    SnapshotUnsupportedFormatVersion(u16, u16),
+    #[error("snapshot not compatible with current engine features: {0}")]
+    SnapshotFeatureMismatch(String),
+    #[error("failed migrating snapshot from format version {0}: {1}")]
+    SnapshotMigrationFailed(u16, String),
 
     #[error("failed reading scenario: missing modules")]
     ScenarioMissingModules,
-
+    #[error("module not found: {0}, required by: {1}")]
+    ModuleNotFound(String, String),
+    #[error("module version conflict: {0} ({1})")]
+    ModuleVersionConflict(String, String),
+    #[error("module dependency cycle: {0}")]
+    ModuleDependencyCycle(String),
+    #[error("module engine version mismatch: {0} ({1})")]
+    ModuleEngineVersionMismatch(String, String),
+    #[error("base scenario not found: {0}, extended by: {1}")]
+    BaseScenarioNotFound(String, String),
+    #[error("scenario inheritance cycle: {0}")]
+    ScenarioInheritanceCycle(String),
+    #[error("invalid scenario setting: {0} ({1})")]
+    InvalidScenarioSetting(String, String),
+    #[error("invalid entity table: {0} ({1})")]
+    InvalidEntityTable(String, String),
+    #[error("invalid data entry: {0} ({1})")]
+    InvalidDataEntry(String, String),
+    #[error("failed exporting image: {0}")]
+    FailedExportingImage(String),
+
+    #[error("invalid entity prefab: {0} ({1})")]
+    InvalidEntityPrefab(String, String),
     #[error("model: no entity prefab named: {0}")]
     NoEntityPrefab(EntityName),
     #[error("model: no component named: {0}")]
@@ -104,6 +132,11 @@ pub enum Error {
 
     #[error("required engine feature not available: {0}, required by module: {1}")]
     RequiredEngineFeatureNotAvailable(String, String),
+    #[error("unknown engine feature: {0}, required by module: {1}")]
+    UnknownEngineFeature(String, String),
+    #[cfg(feature = "machine_dynlib")]
+    #[error("failed loading library: {0} ({1})")]
+    FailedLoadingLibrary(String, String),
 
     #[error("other error: {0}")]
     Other(String),
@@ -0,0 +1,231 @@
+//! Exporting grid vars as images.
+//!
+//! Grids of `int`, `float` and `byte` values can be written out as PNG
+//! images. Each value is first normalized using a value range, which is
+//! either provided explicitly or computed from the grid itself, and then
+//! mapped to a color using one of the available colormaps.
+//!
+//! Exports can be performed on demand with [`Sim::export_grid_png`], from
+//! scripts using the `export_png` command, or periodically while the
+//! simulation is running, see [`GridExport`].
+//!
+//! [`Sim::export_grid_png`]: ../sim/struct.Sim.html#method.export_grid_png
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use image::{Rgb, RgbImage};
+
+use crate::address::Address;
+use crate::error::Error;
+use crate::{Float, Result, Var};
+
+/// Mapping of normalized values to colors.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Colormap {
+    /// Black to white
+    Grayscale,
+    /// Perceptually uniform dark blue to yellow
+    Viridis,
+    /// Black through red and yellow to white
+    Heat,
+    /// Blue through white to red, useful for values around a midpoint
+    Diverging,
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Colormap::Grayscale
+    }
+}
+
+impl FromStr for Colormap {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "grayscale" | "greyscale" | "gray" | "grey" => Ok(Colormap::Grayscale),
+            "viridis" => Ok(Colormap::Viridis),
+            "heat" => Ok(Colormap::Heat),
+            "diverging" => Ok(Colormap::Diverging),
+            _ => Err(Error::Other(format!(
+                "unknown colormap: {}, available colormaps: grayscale, viridis, heat, diverging",
+                s
+            ))),
+        }
+    }
+}
+
+impl Colormap {
+    /// Gets the color for a value normalized to the `0..1` range.
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        let t = if t.is_nan() { 0. } else { t.max(0.).min(1.) };
+        match self {
+            Colormap::Grayscale => {
+                let v = (t * 255.).round() as u8;
+                [v, v, v]
+            }
+            Colormap::Viridis => interpolate(
+                &[
+                    [68, 1, 84],
+                    [59, 82, 139],
+                    [33, 145, 140],
+                    [94, 201, 98],
+                    [253, 231, 37],
+                ],
+                t,
+            ),
+            Colormap::Heat => {
+                interpolate(&[[0, 0, 0], [230, 0, 0], [255, 210, 0], [255, 255, 255]], t)
+            }
+            Colormap::Diverging => interpolate(&[[59, 76, 192], [240, 240, 240], [180, 4, 38]], t),
+        }
+    }
+}
+
+/// Linearly interpolates between evenly spaced color stops.
+fn interpolate(stops: &[[u8; 3]], t: f64) -> [u8; 3] {
+    let scaled = t * (stops.len() - 1) as f64;
+    let idx = (scaled.floor() as usize).min(stops.len() - 2);
+    let frac = scaled - idx as f64;
+    let (a, b) = (stops[idx], stops[idx + 1]);
+    let mut out = [0; 3];
+    for i in 0..3 {
+        out[i] = (a[i] as f64 + (b[i] as f64 - a[i] as f64) * frac).round() as u8;
+    }
+    out
+}
+
+/// Options for turning grids into images.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GridImageOptions {
+    pub colormap: Colormap,
+    /// Values mapped to the start and the end of the colormap, values
+    /// outside the range are clamped. If not provided, minimum and maximum
+    /// values found in the grid are used.
+    pub range: Option<(Float, Float)>,
+}
+
+impl GridImageOptions {
+    /// Parses value range in the `min,max` format.
+    pub fn parse_range(s: &str) -> Result<(Float, Float)> {
+        let split = s.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+        if split.len() != 2 {
+            return Err(Error::Other(format!(
+                "invalid value range: {}, expected format: min,max",
+                s
+            )));
+        }
+        Ok((split[0].parse()?, split[1].parse()?))
+    }
+}
+
+/// Renders the grid var as an image, with grid rows becoming image rows.
+///
+/// Only `int`, `float` and `byte` grids are supported. Rows shorter than
+/// the longest row are padded with black pixels.
+pub fn grid_to_image(grid: &Var, options: &GridImageOptions) -> Result<RgbImage> {
+    let grid = grid.as_grid()?;
+    let values = grid
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|var| match var {
+                    Var::Int(v) => Ok(*v as f64),
+                    Var::Float(v) => Ok(*v as f64),
+                    Var::Byte(v) => Ok(*v as f64),
+                    _ => Err(Error::FailedExportingImage(format!(
+                        "unsupported grid value type: {}, expected int, float or byte",
+                        var.get_type()
+                    ))),
+                })
+                .collect::<Result<Vec<f64>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (min, max) = match options.range {
+        Some((min, max)) => (min as f64, max as f64),
+        None => values
+            .iter()
+            .flatten()
+            .fold((f64::MAX, f64::MIN), |(min, max), v| {
+                (min.min(*v), max.max(*v))
+            }),
+    };
+    let span = max - min;
+
+    let width = values.iter().map(|row| row.len()).max().unwrap_or(0);
+    let height = values.len();
+    if width == 0 || height == 0 {
+        return Err(Error::FailedExportingImage("grid is empty".to_string()));
+    }
+    let mut img = RgbImage::new(width as u32, height as u32);
+    for (y, row) in values.iter().enumerate() {
+        for (x, v) in row.iter().enumerate() {
+            let t = if span > 0. { (v - min) / span } else { 0. };
+            img.put_pixel(x as u32, y as u32, Rgb(options.colormap.color(t)));
+        }
+    }
+    Ok(img)
+}
+
+/// Writes the grid var to a PNG file, creating parent directories as
+/// needed.
+pub fn save_grid_png(grid: &Var, path: &Path, options: &GridImageOptions) -> Result<()> {
+    let img = grid_to_image(grid, options)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    img.save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| Error::FailedExportingImage(e.to_string()))
+}
+
+/// Periodic export of a grid var, performed as the simulation is stepped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridExport {
+    /// Address of the exported grid var
+    pub address: Address,
+    /// Export the grid every N steps
+    pub every_steps: usize,
+    /// Directory the images are written to
+    pub dir: PathBuf,
+    pub options: GridImageOptions,
+}
+
+impl GridExport {
+    pub fn is_due(&self, clock: usize) -> bool {
+        self.every_steps > 0 && clock % self.every_steps == 0
+    }
+
+    /// Gets the path of the image file for the given clock value.
+    pub fn file_path(&self, clock: usize) -> PathBuf {
+        self.dir.join(format!(
+            "{}_{}_{}_{:06}.png",
+            self.address.entity, self.address.component, self.address.var_name, clock
+        ))
+    }
+}
+
+#[test]
+fn grid_to_image_auto_range() {
+    let grid = Var::Grid(vec![
+        vec![Var::Int(0), Var::Int(5)],
+        vec![Var::Int(10), Var::Int(5)],
+    ]);
+    let img = grid_to_image(&grid, &GridImageOptions::default()).unwrap();
+    assert_eq!(img.dimensions(), (2, 2));
+    assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0]);
+    assert_eq!(img.get_pixel(0, 1).0, [255, 255, 255]);
+    assert_eq!(img.get_pixel(1, 0).0, [128, 128, 128]);
+
+    let clamped = grid_to_image(
+        &grid,
+        &GridImageOptions {
+            colormap: Colormap::Grayscale,
+            range: Some((0., 5.)),
+        },
+    )
+    .unwrap();
+    assert_eq!(clamped.get_pixel(0, 1).0, [255, 255, 255]);
+    assert!(grid_to_image(&Var::Grid(vec![vec![Var::Bool(true)]]), &Default::default()).is_err());
+}
@@ -84,9 +84,14 @@ pub use sim::Sim;
 pub use var::{Var, VarType};
 
 pub mod address;
+#[cfg(feature = "machine_script")]
+pub mod check;
+pub mod checkpoint;
 pub mod distr;
 pub mod entity;
 pub mod error;
+#[cfg(feature = "save_img")]
+pub mod img;
 pub mod model;
 pub mod sim;
 pub mod snapshot;
@@ -146,6 +151,12 @@ pub const FEATURE_MACHINE_LUA: bool = false;
 #[cfg(feature = "machine_lua")]
 pub const FEATURE_MACHINE_LUA: bool = true;
 
+pub const FEATURE_NAME_SAVE_IMG: &str = "save_img";
+#[cfg(not(feature = "save_img"))]
+pub const FEATURE_SAVE_IMG: bool = false;
+#[cfg(feature = "save_img")]
+pub const FEATURE_SAVE_IMG: bool = true;
+
 // TODO are these necessary?
 // aggregate features
 pub const FEATURE_NAME_MACHINE_SANDBOX: &str = "machine_sandbox";
@@ -163,7 +174,8 @@ pub const FEATURE_MACHINE_COMPLETE: bool = true;
 pub const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
 const SCENARIO_MANIFEST_FILE: &str = "scenario.toml";
-const MODULE_MANIFEST_FILE: &str = "mod.toml";
+/// Name of the module manifest file.
+pub const MODULE_MANIFEST_FILE: &str = "mod.toml";
 
 /// Name of the module directory within the scenario file tree.
 pub const SCENARIOS_DIR_NAME: &str = "scenarios";
@@ -0,0 +1,946 @@
+//! Commands operating on collection vars: lists, maps and grids.
+//!
+//! Arguments can be either local var addresses or literal values. Literal
+//! list and grid elements are parsed as the element type of the collection,
+//! literal map keys are always strings.
+//!
+//! Commands producing a result write it to the var provided with the
+//! `--out` option, same as `eval`. Results written to string, int, float
+//! and bool vars are coerced to the type of the output var.
+//!
+//! ```text
+//! push list_int:queue 3
+//! pop list_int:queue --out int:next
+//! contains list_int:visited int:cell --out bool:seen
+//! shuffle list_int:order --seed int:rng
+//! map_set map:prices wood 2.5
+//! map_get map:prices wood --out float:price
+//! grid_neighbours grid_int:cells int:x int:y --von-neumann --out list_int:around
+//! ```
+
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::address::{self, ShortLocalAddress};
+use crate::entity::StorageIndex;
+use crate::{CompName, EntityId, Float, Int, Var, VarType};
+
+use super::super::{error::Error, error::ErrorKind, error::Result, LocationInfo, ScopedStorage};
+use super::eval::seeded_rng;
+use super::set::Source;
+use super::{Command, CommandResult};
+
+pub const LIST_COMMAND_NAMES: [&'static str; 9] = [
+    "push", "pop", "insert", "remove", "len", "contains", "sort", "shuffle", "clear",
+];
+pub const MAP_COMMAND_NAMES: [&'static str; 3] = ["map_get", "map_set", "map_del"];
+pub const GRID_COMMAND_NAMES: [&'static str; 4] =
+    ["grid_fill", "grid_neighbours", "grid_row", "grid_col"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ListOp {
+    /// Appends the value to the end of the list
+    Push(Source),
+    /// Removes the last element, outputting it
+    Pop,
+    /// Inserts the value at the index
+    Insert(Source, Source),
+    /// Removes the element at the index, outputting it
+    Remove(Source),
+    /// Outputs the number of elements
+    Len,
+    /// Outputs whether the list contains the value
+    Contains(Source),
+    /// Sorts the list in ascending or descending order
+    Sort { descending: bool },
+    /// Shuffles the list, optionally using the generator state stored in
+    /// the seed var
+    Shuffle { seed: Option<ShortLocalAddress> },
+    /// Removes all the elements
+    Clear,
+}
+
+/// Modifies or queries a list var.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListCommand {
+    pub list: ShortLocalAddress,
+    pub op: ListOp,
+    pub out: Option<ShortLocalAddress>,
+}
+
+impl ListCommand {
+    pub fn new(name: &str, args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        let matches = getopts::Options::new()
+            .optopt("o", "out", "", "")
+            .optopt("s", "seed", "", "")
+            .optflag("d", "desc", "")
+            .parse(&args)?;
+        let free = &matches.free;
+
+        let arg_count = match name {
+            "push" | "remove" | "contains" => 2,
+            "insert" => 3,
+            _ => 1,
+        };
+        check_arg_count(name, free, arg_count, location)?;
+
+        let list = ShortLocalAddress::from_str(&free[0])?;
+        if !is_list(list.var_type) {
+            return Err(invalid(
+                format!("expected list var, got: {}", list.var_type),
+                location,
+            ));
+        }
+        let element_type = list.var_type.element_type();
+
+        let op = match name {
+            "push" => ListOp::Push(parse_operand(&free[1], element_type, location)?),
+            "pop" => ListOp::Pop,
+            "insert" => ListOp::Insert(
+                parse_operand(&free[1], Some(VarType::Int), location)?,
+                parse_operand(&free[2], element_type, location)?,
+            ),
+            "remove" => ListOp::Remove(parse_operand(&free[1], Some(VarType::Int), location)?),
+            "len" => ListOp::Len,
+            "contains" => ListOp::Contains(parse_operand(&free[1], element_type, location)?),
+            "sort" => ListOp::Sort {
+                descending: matches.opt_present("desc"),
+            },
+            "shuffle" => ListOp::Shuffle {
+                seed: parse_seed(matches.opt_str("seed"), location)?,
+            },
+            "clear" => ListOp::Clear,
+            _ => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::UnknownCommand(name.to_string()),
+                ))
+            }
+        };
+
+        Ok(Command::List(ListCommand {
+            list,
+            op,
+            out: parse_out(matches.opt_str("out"))?,
+        }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &mut ScopedStorage,
+        ent_id: &EntityId,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let result = self.run(storage, ent_id, comp_name);
+        finish(result, &self.out, storage, comp_name, location)
+    }
+
+    fn run(
+        &self,
+        storage: &mut ScopedStorage,
+        ent_id: &EntityId,
+        comp_name: &CompName,
+    ) -> std::result::Result<Option<Var>, String> {
+        let index = storage_index(&self.list, comp_name);
+        let out = match &self.op {
+            ListOp::Push(value) => {
+                let value = resolve(value, storage, comp_name)?;
+                list_mut(storage, &index)?.push(value);
+                None
+            }
+            ListOp::Pop => match list_mut(storage, &index)?.pop() {
+                Some(last) => Some(last),
+                None => return Err("can't pop from an empty list".to_string()),
+            },
+            ListOp::Insert(at, value) => {
+                let at = resolve_index(at, storage, comp_name)?;
+                let value = resolve(value, storage, comp_name)?;
+                let list = list_mut(storage, &index)?;
+                if at > list.len() {
+                    return Err(format!(
+                        "insertion index {} out of bounds for list of length {}",
+                        at,
+                        list.len()
+                    ));
+                }
+                list.insert(at, value);
+                None
+            }
+            ListOp::Remove(at) => {
+                let at = resolve_index(at, storage, comp_name)?;
+                let list = list_mut(storage, &index)?;
+                if at >= list.len() {
+                    return Err(format!(
+                        "index {} out of bounds for list of length {}",
+                        at,
+                        list.len()
+                    ));
+                }
+                Some(list.remove(at))
+            }
+            ListOp::Len => Some(Var::Int(list_ref(storage, &index)?.len() as Int)),
+            ListOp::Contains(value) => {
+                let value = resolve(value, storage, comp_name)?;
+                Some(Var::Bool(list_ref(storage, &index)?.contains(&value)))
+            }
+            ListOp::Sort { descending } => {
+                let list = list_mut(storage, &index)?;
+                list.sort();
+                if *descending {
+                    list.reverse();
+                }
+                None
+            }
+            ListOp::Shuffle { seed: Some(seed) } => {
+                let seed_index = storage_index(seed, comp_name);
+                let state = storage
+                    .get_var(&seed_index)
+                    .map_err(|e| e.to_string())?
+                    .to_int();
+                let mut rng = seeded_rng(state, ent_id);
+                list_mut(storage, &index)?.shuffle(&mut rng);
+                if let Ok(target) = storage.get_var_mut(&seed_index) {
+                    *target = Var::Int(rng.gen());
+                }
+                None
+            }
+            ListOp::Shuffle { seed: None } => {
+                list_mut(storage, &index)?.shuffle(&mut rand::thread_rng());
+                None
+            }
+            ListOp::Clear => {
+                list_mut(storage, &index)?.clear();
+                None
+            }
+        };
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MapOp {
+    /// Outputs the value stored at the key
+    Get(Source),
+    /// Stores the value at the key, outputting the previous value if any
+    Set(Source, Source),
+    /// Removes the key, outputting whether it was present
+    Delete(Source),
+}
+
+/// Modifies or queries a map var.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapCommand {
+    pub map: ShortLocalAddress,
+    pub op: MapOp,
+    pub out: Option<ShortLocalAddress>,
+}
+
+impl MapCommand {
+    pub fn new(name: &str, args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        let matches = getopts::Options::new()
+            .optopt("o", "out", "", "")
+            .parse(&args)?;
+        let free = &matches.free;
+
+        let arg_count = if name == "map_set" { 3 } else { 2 };
+        check_arg_count(name, free, arg_count, location)?;
+
+        let map = ShortLocalAddress::from_str(&free[0])?;
+        if map.var_type != VarType::Map {
+            return Err(invalid(
+                format!("expected map var, got: {}", map.var_type),
+                location,
+            ));
+        }
+        let key = parse_operand(&free[1], Some(VarType::String), location)?;
+
+        let op = match name {
+            "map_get" => MapOp::Get(key),
+            "map_set" => MapOp::Set(key, parse_operand(&free[2], None, location)?),
+            "map_del" => MapOp::Delete(key),
+            _ => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::UnknownCommand(name.to_string()),
+                ))
+            }
+        };
+
+        Ok(Command::Map(MapCommand {
+            map,
+            op,
+            out: parse_out(matches.opt_str("out"))?,
+        }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &mut ScopedStorage,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let result = self.run(storage, comp_name);
+        finish(result, &self.out, storage, comp_name, location)
+    }
+
+    fn run(
+        &self,
+        storage: &mut ScopedStorage,
+        comp_name: &CompName,
+    ) -> std::result::Result<Option<Var>, String> {
+        let index = storage_index(&self.map, comp_name);
+        let out = match &self.op {
+            MapOp::Get(key) => {
+                let key = resolve(key, storage, comp_name)?;
+                match map_mut(storage, &index)?.get(&key) {
+                    Some(value) => Some(value.clone()),
+                    None => return Err(format!("key not found: {}", key.to_string())),
+                }
+            }
+            MapOp::Set(key, value) => {
+                let key = resolve(key, storage, comp_name)?;
+                let value = resolve(value, storage, comp_name)?;
+                map_mut(storage, &index)?.insert(key, value)
+            }
+            MapOp::Delete(key) => {
+                let key = resolve(key, storage, comp_name)?;
+                let removed = map_mut(storage, &index)?.remove(&key);
+                Some(Var::Bool(removed.is_some()))
+            }
+        };
+        Ok(out)
+    }
+}
+
+/// Set of cells surrounding a grid cell.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Neighbourhood {
+    /// Cells within the radius along both axes, including diagonals
+    Moore,
+    /// Cells within the radius in Manhattan distance
+    VonNeumann,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GridOp {
+    /// Sets all the cells to the value, optionally resizing the grid to
+    /// the given width and height first
+    Fill {
+        value: Source,
+        size: Option<(usize, usize)>,
+    },
+    /// Outputs the list of values of the cells surrounding the cell at
+    /// the given coordinates, in row-major order
+    Neighbours {
+        x: Source,
+        y: Source,
+        neighbourhood: Neighbourhood,
+        radius: usize,
+        /// Wrap around the grid edges instead of skipping cells outside
+        /// the grid
+        wrap: bool,
+    },
+    /// Outputs the row at the index as a list
+    Row(Source),
+    /// Outputs the column at the index as a list
+    Column(Source),
+}
+
+/// Modifies or queries a grid var.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridCommand {
+    pub grid: ShortLocalAddress,
+    pub op: GridOp,
+    pub out: Option<ShortLocalAddress>,
+}
+
+impl GridCommand {
+    pub fn new(name: &str, args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        let matches = getopts::Options::new()
+            .optopt("o", "out", "", "")
+            .optopt("", "size", "Resize the grid before filling", "WIDTH,HEIGHT")
+            .optopt("r", "radius", "Neighbourhood radius", "RADIUS")
+            .optflag("", "moore", "Use the Moore neighbourhood (default)")
+            .optflag("", "von-neumann", "Use the von Neumann neighbourhood")
+            .optflag("w", "wrap", "Wrap around the grid edges")
+            .parse(&args)?;
+        let free = &matches.free;
+
+        let arg_count = if name == "grid_neighbours" { 3 } else { 2 };
+        check_arg_count(name, free, arg_count, location)?;
+
+        let grid = ShortLocalAddress::from_str(&free[0])?;
+        if !is_grid(grid.var_type) {
+            return Err(invalid(
+                format!("expected grid var, got: {}", grid.var_type),
+                location,
+            ));
+        }
+
+        let op = match name {
+            "grid_fill" => {
+                let size = match matches.opt_str("size") {
+                    Some(s) => {
+                        let split = s.split(',').collect::<Vec<&str>>();
+                        match (
+                            split.get(0).and_then(|w| w.trim().parse::<usize>().ok()),
+                            split.get(1).and_then(|h| h.trim().parse::<usize>().ok()),
+                        ) {
+                            (Some(w), Some(h)) if split.len() == 2 => Some((w, h)),
+                            _ => {
+                                return Err(invalid(format!("invalid grid size: {}", s), location))
+                            }
+                        }
+                    }
+                    None => None,
+                };
+                GridOp::Fill {
+                    value: parse_operand(&free[1], grid.var_type.element_type(), location)?,
+                    size,
+                }
+            }
+            "grid_neighbours" => {
+                if matches.opt_present("moore") && matches.opt_present("von-neumann") {
+                    return Err(invalid(
+                        "can't use both the Moore and von Neumann neighbourhoods".to_string(),
+                        location,
+                    ));
+                }
+                let radius = match matches.opt_str("radius") {
+                    Some(r) => r
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid radius: {}", r), location))?,
+                    None => 1,
+                };
+                GridOp::Neighbours {
+                    x: parse_operand(&free[1], Some(VarType::Int), location)?,
+                    y: parse_operand(&free[2], Some(VarType::Int), location)?,
+                    neighbourhood: if matches.opt_present("von-neumann") {
+                        Neighbourhood::VonNeumann
+                    } else {
+                        Neighbourhood::Moore
+                    },
+                    radius,
+                    wrap: matches.opt_present("wrap"),
+                }
+            }
+            "grid_row" => GridOp::Row(parse_operand(&free[1], Some(VarType::Int), location)?),
+            "grid_col" => GridOp::Column(parse_operand(&free[1], Some(VarType::Int), location)?),
+            _ => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::UnknownCommand(name.to_string()),
+                ))
+            }
+        };
+
+        Ok(Command::Grid(GridCommand {
+            grid,
+            op,
+            out: parse_out(matches.opt_str("out"))?,
+        }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &mut ScopedStorage,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let result = self.run(storage, comp_name);
+        finish(result, &self.out, storage, comp_name, location)
+    }
+
+    fn run(
+        &self,
+        storage: &mut ScopedStorage,
+        comp_name: &CompName,
+    ) -> std::result::Result<Option<Var>, String> {
+        let index = storage_index(&self.grid, comp_name);
+        let out = match &self.op {
+            GridOp::Fill { value, size } => {
+                let value = resolve(value, storage, comp_name)?;
+                let var = storage.get_var_mut(&index).map_err(|e| e.to_string())?;
+                match size {
+                    Some((width, height)) => *var = Var::Grid(vec![vec![value; *width]; *height]),
+                    None => {
+                        for cell in var
+                            .as_grid_mut()
+                            .map_err(|e| e.to_string())?
+                            .iter_mut()
+                            .flatten()
+                        {
+                            *cell = value.clone();
+                        }
+                    }
+                }
+                None
+            }
+            GridOp::Neighbours {
+                x,
+                y,
+                neighbourhood,
+                radius,
+                wrap,
+            } => {
+                let x = resolve(x, storage, comp_name)?.to_int();
+                let y = resolve(y, storage, comp_name)?.to_int();
+                let grid = grid_ref(storage, &index)?;
+                Some(Var::List(neighbours(
+                    grid,
+                    x as i64,
+                    y as i64,
+                    *neighbourhood,
+                    *radius,
+                    *wrap,
+                )))
+            }
+            GridOp::Row(row) => {
+                let row = resolve_index(row, storage, comp_name)?;
+                match grid_ref(storage, &index)?.get(row) {
+                    Some(cells) => Some(Var::List(cells.clone())),
+                    None => return Err(format!("row index out of bounds: {}", row)),
+                }
+            }
+            GridOp::Column(column) => {
+                let column = resolve_index(column, storage, comp_name)?;
+                let cells = grid_ref(storage, &index)?
+                    .iter()
+                    .map(|row| row.get(column).cloned())
+                    .collect::<Option<Vec<Var>>>();
+                match cells {
+                    Some(cells) => Some(Var::List(cells)),
+                    None => return Err(format!("column index out of bounds: {}", column)),
+                }
+            }
+        };
+        Ok(out)
+    }
+}
+
+/// Collects values of the cells surrounding the given cell, in row-major
+/// order.
+///
+/// Rows can differ in length, in which case wrapping around the horizontal
+/// edge uses the length of the given row.
+fn neighbours(
+    grid: &[Vec<Var>],
+    x: i64,
+    y: i64,
+    neighbourhood: Neighbourhood,
+    radius: usize,
+    wrap: bool,
+) -> Vec<Var> {
+    let radius = radius as i64;
+    let height = grid.len() as i64;
+    let mut out = Vec::new();
+    if height == 0 {
+        return out;
+    }
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            if neighbourhood == Neighbourhood::VonNeumann && dx.abs() + dy.abs() > radius {
+                continue;
+            }
+            let (mut nx, mut ny) = (x + dx, y + dy);
+            if wrap {
+                ny = ny.rem_euclid(height);
+            } else if ny < 0 || ny >= height {
+                continue;
+            }
+            let row = &grid[ny as usize];
+            let width = row.len() as i64;
+            if wrap && width > 0 {
+                nx = nx.rem_euclid(width);
+            } else if nx < 0 || nx >= width {
+                continue;
+            }
+            out.push(row[nx as usize].clone());
+        }
+    }
+    out
+}
+
+fn invalid(message: String, location: &LocationInfo) -> Error {
+    Error::new(location.clone(), ErrorKind::InvalidCommandBody(message))
+}
+
+fn check_arg_count(
+    name: &str,
+    args: &[String],
+    count: usize,
+    location: &LocationInfo,
+) -> Result<()> {
+    if args.len() != count {
+        return Err(invalid(
+            format!(
+                "`{}` expects {} argument(s), got {}",
+                name,
+                count,
+                args.len()
+            ),
+            location,
+        ));
+    }
+    Ok(())
+}
+
+fn is_list(var_type: VarType) -> bool {
+    match var_type {
+        VarType::StringList
+        | VarType::IntList
+        | VarType::FloatList
+        | VarType::BoolList
+        | VarType::ByteList
+        | VarType::Vec2List
+        | VarType::Vec3List
+        | VarType::VarList => true,
+        _ => false,
+    }
+}
+
+fn is_grid(var_type: VarType) -> bool {
+    match var_type {
+        VarType::StringGrid
+        | VarType::IntGrid
+        | VarType::FloatGrid
+        | VarType::BoolGrid
+        | VarType::ByteGrid
+        | VarType::Vec2Grid
+        | VarType::Vec3Grid
+        | VarType::VarGrid => true,
+        _ => false,
+    }
+}
+
+fn parse_out(out: Option<String>) -> Result<Option<ShortLocalAddress>> {
+    Ok(out.map(|s| ShortLocalAddress::from_str(&s)).transpose()?)
+}
+
+fn parse_seed(seed: Option<String>, location: &LocationInfo) -> Result<Option<ShortLocalAddress>> {
+    let seed = parse_out(seed)?;
+    if let Some(seed) = &seed {
+        if seed.var_type != VarType::Int {
+            return Err(invalid(
+                format!("seed must be an int var, got: {}", seed.var_type),
+                location,
+            ));
+        }
+    }
+    Ok(seed)
+}
+
+/// Parses an argument that's either a local var address or a literal value.
+///
+/// Literals are parsed as the given type. Without a type the literal is
+/// read as an int, float or bool, falling back to a string.
+pub(super) fn parse_operand(
+    s: &str,
+    var_type: Option<VarType>,
+    location: &LocationInfo,
+) -> Result<Source> {
+    let source = match var_type {
+        Some(var_type) => Source::from_str(s, var_type, location)?,
+        None if s.contains(address::SEPARATOR_SYMBOL) => {
+            Source::from_str(s, VarType::String, location)?
+        }
+        None => Source::Value(parse_literal(s)),
+    };
+    if let Source::Address(_) = source {
+        return Err(invalid(
+            format!("remote addresses are not supported: {}", s),
+            location,
+        ));
+    }
+    Ok(source)
+}
+
+fn parse_literal(s: &str) -> Var {
+    if let Ok(v) = s.parse::<Int>() {
+        Var::Int(v)
+    } else if let Ok(v) = s.parse::<Float>() {
+        Var::Float(v)
+    } else if let Ok(v) = s.parse::<bool>() {
+        Var::Bool(v)
+    } else {
+        Var::String(s.to_string())
+    }
+}
+
+fn storage_index(address: &ShortLocalAddress, comp_name: &CompName) -> StorageIndex {
+    let comp = address.comp.clone().unwrap_or_else(|| comp_name.clone());
+    address.storage_index_using(comp)
+}
+
+pub(super) fn resolve(
+    source: &Source,
+    storage: &ScopedStorage,
+    comp_name: &CompName,
+) -> std::result::Result<Var, String> {
+    match source {
+        Source::Value(var) => Ok(var.clone()),
+        Source::LocalAddress(address) => storage
+            .get_var(&storage_index(address, comp_name))
+            .map(|var| var.clone())
+            .map_err(|e| e.to_string()),
+        Source::Address(_) => Err("remote addresses are not supported".to_string()),
+    }
+}
+
+fn resolve_index(
+    source: &Source,
+    storage: &ScopedStorage,
+    comp_name: &CompName,
+) -> std::result::Result<usize, String> {
+    let index = resolve(source, storage, comp_name)?.to_int();
+    if index < 0 {
+        return Err(format!("negative index: {}", index));
+    }
+    Ok(index as usize)
+}
+
+fn list_ref<'a>(
+    storage: &'a ScopedStorage,
+    index: &StorageIndex,
+) -> std::result::Result<&'a Vec<Var>, String> {
+    storage
+        .get_var(index)
+        .and_then(|var| var.as_list())
+        .map_err(|e| e.to_string())
+}
+
+fn list_mut<'a>(
+    storage: &'a mut ScopedStorage,
+    index: &StorageIndex,
+) -> std::result::Result<&'a mut Vec<Var>, String> {
+    storage
+        .get_var_mut(index)
+        .and_then(|var| var.as_list_mut())
+        .map_err(|e| e.to_string())
+}
+
+fn grid_ref<'a>(
+    storage: &'a ScopedStorage,
+    index: &StorageIndex,
+) -> std::result::Result<&'a Vec<Vec<Var>>, String> {
+    storage
+        .get_var(index)
+        .and_then(|var| var.as_grid())
+        .map_err(|e| e.to_string())
+}
+
+fn map_mut<'a>(
+    storage: &'a mut ScopedStorage,
+    index: &StorageIndex,
+) -> std::result::Result<&'a mut std::collections::BTreeMap<Var, Var>, String> {
+    match storage.get_var_mut(index).map_err(|e| e.to_string())? {
+        Var::Map(map) => Ok(map),
+        var => Err(format!("expected map, got: {}", var.get_type())),
+    }
+}
+
+/// Writes the result to the output var, if both are present, and turns
+/// errors into command results.
+fn finish(
+    result: std::result::Result<Option<Var>, String>,
+    out: &Option<ShortLocalAddress>,
+    storage: &mut ScopedStorage,
+    comp_name: &CompName,
+    location: &LocationInfo,
+) -> CommandResult {
+    let (out, var) = match (out, result) {
+        (Some(out), Ok(Some(var))) => (out, var),
+        (_, Ok(_)) => return CommandResult::Continue,
+        (_, Err(e)) => {
+            return CommandResult::Err(Error::new(location.clone(), ErrorKind::Other(e)))
+        }
+    };
+    let var = match out.var_type {
+        VarType::String | VarType::Int | VarType::Float | VarType::Bool => {
+            match var.coerce(out.var_type) {
+                Ok(v) => v,
+                Err(e) => {
+                    return CommandResult::Err(Error::new(
+                        location.clone(),
+                        ErrorKind::CoreError(e.to_string()),
+                    ))
+                }
+            }
+        }
+        _ => var,
+    };
+    match storage.get_var_mut(&storage_index(out, comp_name)) {
+        Ok(target) => {
+            *target = var;
+            CommandResult::Continue
+        }
+        Err(e) => CommandResult::Err(Error::new(
+            location.clone(),
+            ErrorKind::CoreError(e.to_string()),
+        )),
+    }
+}
+
+#[test]
+fn grid_neighbourhoods() {
+    let grid = (0..3)
+        .map(|y| (0..3).map(|x| Var::Int(y * 3 + x)).collect())
+        .collect::<Vec<Vec<Var>>>();
+    let ints = |vars: Vec<Var>| vars.iter().map(|v| v.to_int()).collect::<Vec<Int>>();
+
+    assert_eq!(
+        ints(neighbours(&grid, 1, 1, Neighbourhood::Moore, 1, false)),
+        vec![0, 1, 2, 3, 5, 6, 7, 8]
+    );
+    assert_eq!(
+        ints(neighbours(&grid, 1, 1, Neighbourhood::VonNeumann, 1, false)),
+        vec![1, 3, 5, 7]
+    );
+    assert_eq!(
+        ints(neighbours(&grid, 0, 0, Neighbourhood::Moore, 1, false)),
+        vec![1, 3, 4]
+    );
+    assert_eq!(
+        ints(neighbours(&grid, 0, 0, Neighbourhood::VonNeumann, 1, true)),
+        vec![6, 2, 1, 3]
+    );
+}
+
+#[cfg(test)]
+fn split_args(args: &str) -> Vec<String> {
+    args.split_whitespace().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn map_commands_parse() {
+    let location = LocationInfo::empty();
+    match MapCommand::new("map_set", split_args("map:prices wood 2.5"), &location).unwrap() {
+        Command::Map(MapCommand {
+            map,
+            op: MapOp::Set(Source::Value(Var::String(key)), Source::Value(value)),
+            out: None,
+        }) => {
+            assert_eq!(map.var_type, VarType::Map);
+            assert_eq!(key, "wood");
+            assert_eq!(value, Var::Float(2.5));
+        }
+        cmd => panic!("unexpected command: {:?}", cmd),
+    }
+    match MapCommand::new(
+        "map_get",
+        split_args("map:prices wood --out float:price"),
+        &location,
+    )
+    .unwrap()
+    {
+        Command::Map(MapCommand {
+            op: MapOp::Get(Source::Value(Var::String(key))),
+            out: Some(out),
+            ..
+        }) => {
+            assert_eq!(key, "wood");
+            assert_eq!(out.var_type, VarType::Float);
+        }
+        cmd => panic!("unexpected command: {:?}", cmd),
+    }
+    assert!(MapCommand::new("map_get", split_args("list_int:prices wood"), &location).is_err());
+    assert!(MapCommand::new("map_set", split_args("map:prices wood"), &location).is_err());
+}
+
+#[test]
+fn list_and_map_commands() {
+    let location = LocationInfo::empty();
+    let comp = crate::string::new_truncate("stock");
+    let mut storage = crate::entity::Storage::default();
+    let mut scopes = crate::machine::ScopeStackVec::new();
+    storage.insert(
+        (comp.clone(), crate::string::new_truncate("queue")),
+        Var::List(Vec::new()),
+    );
+    storage.insert(
+        (comp.clone(), crate::string::new_truncate("prices")),
+        Var::Map(Default::default()),
+    );
+    let mut storage = ScopedStorage::new(&mut storage, &mut scopes);
+    let mut run = |cmd: &str| {
+        let args = split_args(cmd);
+        match Command::from_prototype(
+            &crate::machine::CommandPrototype {
+                name: Some(args[0].clone()),
+                arguments: Some(args[1..].to_vec()),
+                output: None,
+            },
+            &location,
+            &Vec::new(),
+        )
+        .unwrap()
+        {
+            Command::List(list) => list.run(&mut storage, &0, &comp).unwrap(),
+            Command::Map(map) => map.run(&mut storage, &comp).unwrap(),
+            cmd => panic!("unexpected command: {:?}", cmd),
+        }
+    };
+
+    assert_eq!(run("push list_int:queue 3"), None);
+    assert_eq!(run("push list_int:queue 1"), None);
+    assert_eq!(run("insert list_int:queue 0 2"), None);
+    assert_eq!(run("len list_int:queue"), Some(Var::Int(3)));
+    assert_eq!(run("contains list_int:queue 1"), Some(Var::Bool(true)));
+    assert_eq!(run("sort list_int:queue --desc"), None);
+    assert_eq!(run("pop list_int:queue"), Some(Var::Int(1)));
+    assert_eq!(run("remove list_int:queue 0"), Some(Var::Int(3)));
+
+    assert_eq!(run("map_set map:prices wood 2"), None);
+    assert_eq!(run("map_set map:prices wood 3"), Some(Var::Int(2)));
+    assert_eq!(run("map_get map:prices wood"), Some(Var::Int(3)));
+    assert_eq!(run("map_del map:prices wood"), Some(Var::Bool(true)));
+    assert_eq!(run("map_del map:prices wood"), Some(Var::Bool(false)));
+}
+
+#[test]
+fn seeded_shuffle_is_reproducible() {
+    let location = LocationInfo::empty();
+    let comp = crate::string::new_truncate("deck");
+    let shuffle = match ListCommand::new(
+        "shuffle",
+        split_args("list_int:cards --seed int:rng"),
+        &location,
+    )
+    .unwrap()
+    {
+        Command::List(list) => list,
+        cmd => panic!("unexpected command: {:?}", cmd),
+    };
+    let shuffled = |ent_id: EntityId| {
+        let mut storage = crate::entity::Storage::default();
+        let mut scopes = crate::machine::ScopeStackVec::new();
+        let mut storage = ScopedStorage::new(&mut storage, &mut scopes);
+        storage.insert(
+            (comp.clone(), crate::string::new_truncate("cards")),
+            Var::List((0..32).map(Var::Int).collect()),
+        );
+        storage.insert(
+            (comp.clone(), crate::string::new_truncate("rng")),
+            Var::Int(7),
+        );
+        shuffle.run(&mut storage, &ent_id, &comp).unwrap();
+        let index = (comp.clone(), crate::string::new_truncate("cards"));
+        let seed = (comp.clone(), crate::string::new_truncate("rng"));
+        (
+            storage.get_var(&index).unwrap().clone(),
+            storage.get_var(&seed).unwrap().clone(),
+        )
+    };
+
+    let (cards, seed) = shuffled(1);
+    assert_eq!((cards.clone(), seed.clone()), shuffled(1));
+    assert_ne!(cards, Var::List((0..32).map(Var::Int).collect()));
+    // generator state is advanced for the next draw
+    assert_ne!(seed, Var::Int(7));
+}
@@ -0,0 +1,797 @@
+//! Expressions operating on vectors and lists.
+//!
+//! Complements the scalar expressions compiled with `fasteval`, which only
+//! deal with numbers. Values here can be numbers, 2D and 3D vectors, or
+//! lists of any of these.
+//!
+//! Arithmetic and comparison operators work component-wise, with numbers
+//! broadcast to match the other operand, e.g. `pos + vel * 0.5`. Applied
+//! to lists they work element-wise. Vector components are accessed with
+//! `.x`, `.y` and `.z`.
+//!
+//! Besides the basic math functions known from `fasteval` the following
+//! functions are available:
+//!
+//! - `len(v)` length of a vector, or number of elements of a list
+//! - `dot(a, b)`, `cross(a, b)`, `normalize(v)` and `dist(a, b)`
+//! - `clamp(x, min, max)` and `lerp(a, b, t)`
+//! - `min(..)`, `max(..)` and `sum(list)`, where `min` and `max` accept
+//!   either multiple arguments or a single list
+//! - `vec2(x, y)` and `vec3(x, y, z)` creating vectors
+//! - `rand()`, `rand(max)` and `rand(min, max)` returning a random number
+//!   from the given range, `[0, 1)` by default
+
+use std::collections::HashMap;
+use std::f64::consts;
+
+use rand::{Rng, RngCore};
+
+use crate::{Float, Int, Var, VarType};
+
+pub type EvalResult<T> = std::result::Result<T, String>;
+
+/// Functions along with the minimum and maximum number of arguments.
+const FUNCTIONS: [(&str, usize, usize); 31] = [
+    ("abs", 1, 1),
+    ("sign", 1, 1),
+    ("int", 1, 1),
+    ("floor", 1, 1),
+    ("ceil", 1, 1),
+    ("round", 1, 2),
+    ("log", 1, 2),
+    ("sin", 1, 1),
+    ("cos", 1, 1),
+    ("tan", 1, 1),
+    ("asin", 1, 1),
+    ("acos", 1, 1),
+    ("atan", 1, 1),
+    ("pi", 0, 0),
+    ("e", 0, 0),
+    ("min", 1, usize::MAX),
+    ("max", 1, usize::MAX),
+    ("sqrt", 1, 1),
+    ("exp", 1, 1),
+    ("ln", 1, 1),
+    ("len", 1, 1),
+    ("dot", 2, 2),
+    ("cross", 2, 2),
+    ("normalize", 1, 1),
+    ("dist", 2, 2),
+    ("clamp", 3, 3),
+    ("lerp", 3, 3),
+    ("sum", 1, 1),
+    ("vec2", 2, 2),
+    ("vec3", 3, 3),
+    ("rand", 0, 2),
+];
+
+/// Functions also available in scalar `fasteval` expressions.
+const SCALAR_FUNCTIONS: [&str; 17] = [
+    "abs", "sign", "int", "floor", "ceil", "round", "log", "sin", "cos", "tan", "asin", "acos",
+    "atan", "pi", "e", "min", "max",
+];
+
+/// Operators ordered so that the longer ones are matched first.
+const OPERATORS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "^", "<", ">", "!", "(", ")", ",",
+    ".",
+];
+
+/// Binary operators grouped by precedence, from the lowest.
+const PRECEDENCE: [&[(&str, BinaryOp)]; 6] = [
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[("==", BinaryOp::Eq), ("!=", BinaryOp::Ne)],
+    &[
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+    &[
+        ("*", BinaryOp::Mul),
+        ("/", BinaryOp::Div),
+        ("%", BinaryOp::Rem),
+    ],
+];
+
+/// Value resulting from evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Num(f64),
+    Vec2([f64; 2]),
+    Vec3([f64; 3]),
+    List(Vec<Value>),
+}
+
+impl Value {
+    pub fn from_var(var: &Var) -> EvalResult<Value> {
+        let value = match var {
+            Var::Int(v) => Value::Num(*v as f64),
+            Var::Float(v) => Value::Num(*v as f64),
+            Var::Byte(v) => Value::Num(*v as f64),
+            Var::Bool(v) => Value::Num(from_bool(*v)),
+            Var::Vec2(x, y) => Value::Vec2([*x as f64, *y as f64]),
+            Var::Vec3(x, y, z) => Value::Vec3([*x as f64, *y as f64, *z as f64]),
+            Var::List(list) => Value::List(
+                list.iter()
+                    .map(Value::from_var)
+                    .collect::<EvalResult<_>>()?,
+            ),
+            _ => return Err(format!("unsupported value type: {}", var.get_type())),
+        };
+        Ok(value)
+    }
+
+    /// Converts the value into a var of the target type.
+    pub fn into_var(self, target: VarType) -> EvalResult<Var> {
+        let element_type = match target {
+            VarType::IntList => Some(VarType::Int),
+            VarType::FloatList => Some(VarType::Float),
+            VarType::BoolList => Some(VarType::Bool),
+            VarType::ByteList => Some(VarType::Byte),
+            VarType::Vec2List => Some(VarType::Vec2),
+            VarType::Vec3List => Some(VarType::Vec3),
+            _ => None,
+        };
+        let var = match (self, target) {
+            (Value::Num(v), VarType::Int) => Var::Int(v as Int),
+            (Value::Num(v), VarType::Float) => Var::Float(v as Float),
+            (Value::Num(v), VarType::Bool) => Var::Bool(v != 0.),
+            (Value::Num(v), VarType::Byte) => Var::Byte(v as u8),
+            (Value::Num(v), VarType::String) => Var::String(v.to_string()),
+            (Value::Vec2([x, y]), VarType::Vec2) => Var::Vec2(x as Float, y as Float),
+            (Value::Vec3([x, y, z]), VarType::Vec3) => {
+                Var::Vec3(x as Float, y as Float, z as Float)
+            }
+            (Value::List(list), VarType::VarList) => Var::List(
+                list.into_iter()
+                    .map(|v| {
+                        let var_type = v.natural_type();
+                        v.into_var(var_type)
+                    })
+                    .collect::<EvalResult<_>>()?,
+            ),
+            (Value::List(list), _) if element_type.is_some() => Var::List(
+                list.into_iter()
+                    .map(|v| v.into_var(element_type.unwrap()))
+                    .collect::<EvalResult<_>>()?,
+            ),
+            (value, _) => {
+                return Err(format!(
+                    "can't output {} to a var of type {}",
+                    value.type_name(),
+                    target
+                ))
+            }
+        };
+        Ok(var)
+    }
+
+    fn natural_type(&self) -> VarType {
+        match self {
+            Value::Num(_) => VarType::Float,
+            Value::Vec2(_) => VarType::Vec2,
+            Value::Vec3(_) => VarType::Vec3,
+            Value::List(_) => VarType::VarList,
+        }
+    }
+
+    fn type_name(&self) -> &str {
+        match self {
+            Value::Num(_) => "number",
+            Value::Vec2(_) => "vec2",
+            Value::Vec3(_) => "vec3",
+            Value::List(_) => "list",
+        }
+    }
+
+    fn num(&self) -> EvalResult<f64> {
+        match self {
+            Value::Num(v) => Ok(*v),
+            v => Err(format!("expected number, got {}", v.type_name())),
+        }
+    }
+
+    fn components(&self) -> Option<&[f64]> {
+        match self {
+            Value::Vec2(v) => Some(v),
+            Value::Vec3(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn length(&self) -> EvalResult<f64> {
+        match self.components() {
+            Some(c) => Ok(c.iter().map(|x| x * x).sum::<f64>().sqrt()),
+            None => Err(format!("expected vector, got {}", self.type_name())),
+        }
+    }
+
+    /// Applies the function to each of the numbers making up the value.
+    fn map(self, f: &dyn Fn(f64) -> f64) -> Value {
+        match self {
+            Value::Num(x) => Value::Num(f(x)),
+            Value::Vec2(v) => Value::Vec2([f(v[0]), f(v[1])]),
+            Value::Vec3(v) => Value::Vec3([f(v[0]), f(v[1]), f(v[2])]),
+            Value::List(list) => Value::List(list.into_iter().map(|v| v.map(f)).collect()),
+        }
+    }
+}
+
+/// Combines the values component-wise, broadcasting numbers to vectors and
+/// both numbers and vectors to lists.
+fn zip(a: Value, b: Value, f: &dyn Fn(f64, f64) -> f64) -> EvalResult<Value> {
+    let value = match (a, b) {
+        (Value::Num(a), Value::Num(b)) => Value::Num(f(a, b)),
+        (Value::List(a), Value::List(b)) => {
+            if a.len() != b.len() {
+                return Err(format!(
+                    "list lengths don't match: {} and {}",
+                    a.len(),
+                    b.len()
+                ));
+            }
+            Value::List(
+                a.into_iter()
+                    .zip(b)
+                    .map(|(a, b)| zip(a, b, f))
+                    .collect::<EvalResult<_>>()?,
+            )
+        }
+        (Value::List(a), b) => Value::List(
+            a.into_iter()
+                .map(|a| zip(a, b.clone(), f))
+                .collect::<EvalResult<_>>()?,
+        ),
+        (a, Value::List(b)) => Value::List(
+            b.into_iter()
+                .map(|b| zip(a.clone(), b, f))
+                .collect::<EvalResult<_>>()?,
+        ),
+        (Value::Vec2(a), Value::Vec2(b)) => Value::Vec2([f(a[0], b[0]), f(a[1], b[1])]),
+        (Value::Vec3(a), Value::Vec3(b)) => {
+            Value::Vec3([f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2])])
+        }
+        (Value::Vec2(a), Value::Num(b)) => Value::Vec2([f(a[0], b), f(a[1], b)]),
+        (Value::Num(a), Value::Vec2(b)) => Value::Vec2([f(a, b[0]), f(a, b[1])]),
+        (Value::Vec3(a), Value::Num(b)) => Value::Vec3([f(a[0], b), f(a[1], b), f(a[2], b)]),
+        (Value::Num(a), Value::Vec3(b)) => Value::Vec3([f(a, b[0]), f(a, b[1]), f(a, b[2])]),
+        (a, b) => {
+            return Err(format!(
+                "mismatched operands: {} and {}",
+                a.type_name(),
+                b.type_name()
+            ))
+        }
+    };
+    Ok(value)
+}
+
+fn from_bool(b: bool) -> f64 {
+    if b {
+        1.
+    } else {
+        0.
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            BinaryOp::Add => a + b,
+            BinaryOp::Sub => a - b,
+            BinaryOp::Mul => a * b,
+            BinaryOp::Div => a / b,
+            BinaryOp::Rem => a % b,
+            BinaryOp::Pow => a.powf(b),
+            BinaryOp::Eq => from_bool(a == b),
+            BinaryOp::Ne => from_bool(a != b),
+            BinaryOp::Lt => from_bool(a < b),
+            BinaryOp::Le => from_bool(a <= b),
+            BinaryOp::Gt => from_bool(a > b),
+            BinaryOp::Ge => from_bool(a >= b),
+            BinaryOp::And => from_bool(a != 0. && b != 0.),
+            BinaryOp::Or => from_bool(a != 0. || b != 0.),
+        }
+    }
+}
+
+/// Parsed expression.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    /// Component of a vector, selected by index
+    Component(Box<Expr>, usize),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> EvalResult<Expr> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+        let expr = parser.binary(0)?;
+        match parser.next() {
+            Some(token) => Err(format!("unexpected token: {}", token)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Checks whether the expression requires vector support, either by
+    /// calling functions unknown to `fasteval` or by accessing vector
+    /// components.
+    pub fn is_extended(&self) -> bool {
+        match self {
+            Expr::Num(_) | Expr::Var(_) => false,
+            Expr::Component(_, _) => true,
+            Expr::Neg(e) | Expr::Not(e) => e.is_extended(),
+            Expr::Binary(_, a, b) => a.is_extended() || b.is_extended(),
+            Expr::Call(name, args) => {
+                !SCALAR_FUNCTIONS.contains(&name.as_str()) || args.iter().any(|a| a.is_extended())
+            }
+        }
+    }
+
+    /// Lists names of the variables used within the expression.
+    pub fn vars(&self) -> Vec<&str> {
+        match self {
+            Expr::Num(_) => Vec::new(),
+            Expr::Var(name) => vec![name.as_str()],
+            Expr::Component(e, _) | Expr::Neg(e) | Expr::Not(e) => e.vars(),
+            Expr::Binary(_, a, b) => {
+                let mut vars = a.vars();
+                vars.extend(b.vars());
+                vars
+            }
+            Expr::Call(_, args) => args.iter().flat_map(|a| a.vars()).collect(),
+        }
+    }
+
+    pub fn eval(&self, vars: &HashMap<String, Value>, rng: &mut dyn RngCore) -> EvalResult<Value> {
+        match self {
+            Expr::Num(n) => Ok(Value::Num(*n)),
+            Expr::Var(name) => vars
+                .get(name)
+                .cloned()
+                .ok_or_else(|| format!("unknown variable: {}", name)),
+            Expr::Component(e, index) => match e.eval(vars, rng)? {
+                Value::Vec2(v) if *index < 2 => Ok(Value::Num(v[*index])),
+                Value::Vec3(v) if *index < 3 => Ok(Value::Num(v[*index])),
+                v => Err(format!(
+                    "can't access component {} of {}",
+                    index,
+                    v.type_name()
+                )),
+            },
+            Expr::Neg(e) => Ok(e.eval(vars, rng)?.map(&|x| -x)),
+            Expr::Not(e) => Ok(e.eval(vars, rng)?.map(&|x| from_bool(x == 0.))),
+            Expr::Binary(op, a, b) => {
+                let op = *op;
+                zip(a.eval(vars, rng)?, b.eval(vars, rng)?, &|a, b| {
+                    op.apply(a, b)
+                })
+            }
+            Expr::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.eval(vars, rng))
+                    .collect::<EvalResult<Vec<_>>>()?;
+                call(name, args, rng)
+            }
+        }
+    }
+}
+
+fn call(name: &str, mut args: Vec<Value>, rng: &mut dyn RngCore) -> EvalResult<Value> {
+    let value = match name {
+        "abs" => args.remove(0).map(&f64::abs),
+        "sign" => args
+            .remove(0)
+            .map(&|x| if x == 0. { 0. } else { x.signum() }),
+        "int" => args.remove(0).map(&f64::trunc),
+        "floor" => args.remove(0).map(&f64::floor),
+        "ceil" => args.remove(0).map(&f64::ceil),
+        "round" if args.len() == 2 => {
+            // rounds to the nearest multiple of the modulus
+            let x = args.remove(1);
+            zip(args.remove(0), x, &|m, x| (x / m).round() * m)?
+        }
+        "round" => args.remove(0).map(&f64::round),
+        "log" if args.len() == 2 => {
+            let x = args.remove(1);
+            zip(args.remove(0), x, &|base, x| x.log(base))?
+        }
+        "log" => args.remove(0).map(&f64::log10),
+        "sin" => args.remove(0).map(&f64::sin),
+        "cos" => args.remove(0).map(&f64::cos),
+        "tan" => args.remove(0).map(&f64::tan),
+        "asin" => args.remove(0).map(&f64::asin),
+        "acos" => args.remove(0).map(&f64::acos),
+        "atan" => args.remove(0).map(&f64::atan),
+        "sqrt" => args.remove(0).map(&f64::sqrt),
+        "exp" => args.remove(0).map(&f64::exp),
+        "ln" => args.remove(0).map(&f64::ln),
+        "pi" => Value::Num(consts::PI),
+        "e" => Value::Num(consts::E),
+        "min" | "max" => {
+            let f = if name == "min" { f64::min } else { f64::max };
+            let single_list = match args.as_slice() {
+                [Value::List(_)] => true,
+                _ => false,
+            };
+            let items = match args.pop() {
+                Some(Value::List(list)) if single_list => list,
+                Some(last) => {
+                    args.push(last);
+                    args
+                }
+                None => args,
+            };
+            let mut items = items.into_iter();
+            let first = items
+                .next()
+                .ok_or_else(|| format!("{} of an empty list", name))?;
+            items.try_fold(first, |acc, v| zip(acc, v, &f))?
+        }
+        "sum" => match args.remove(0) {
+            Value::List(list) => {
+                let mut items = list.into_iter();
+                match items.next() {
+                    Some(first) => items.try_fold(first, |acc, v| zip(acc, v, &|a, b| a + b))?,
+                    None => Value::Num(0.),
+                }
+            }
+            v => v,
+        },
+        "len" => match args.remove(0) {
+            Value::List(list) => Value::Num(list.len() as f64),
+            v => Value::Num(v.length()?),
+        },
+        "dot" => {
+            let (a, b) = (args.remove(0), args.remove(0));
+            if a.components().is_none() || b.components().is_none() {
+                return Err(format!(
+                    "dot product of {} and {}",
+                    a.type_name(),
+                    b.type_name()
+                ));
+            }
+            let product = zip(a, b, &|a, b| a * b)?;
+            Value::Num(product.components().unwrap().iter().sum())
+        }
+        "cross" => match (args.remove(0), args.remove(0)) {
+            (Value::Vec3(a), Value::Vec3(b)) => Value::Vec3([
+                a[1] * b[2] - a[2] * b[1],
+                a[2] * b[0] - a[0] * b[2],
+                a[0] * b[1] - a[1] * b[0],
+            ]),
+            (Value::Vec2(a), Value::Vec2(b)) => Value::Num(a[0] * b[1] - a[1] * b[0]),
+            (a, b) => {
+                return Err(format!(
+                    "cross product of {} and {}",
+                    a.type_name(),
+                    b.type_name()
+                ))
+            }
+        },
+        "normalize" => {
+            let v = args.remove(0);
+            let length = v.length()?;
+            if length == 0. {
+                v
+            } else {
+                v.map(&|x| x / length)
+            }
+        }
+        "dist" => {
+            let b = args.remove(1);
+            match zip(args.remove(0), b, &|a, b| a - b)? {
+                Value::Num(x) => Value::Num(x.abs()),
+                v => Value::Num(v.length()?),
+            }
+        }
+        "clamp" => {
+            let (x, min, max) = (args.remove(0), args.remove(0), args.remove(0));
+            zip(zip(x, min, &f64::max)?, max, &f64::min)?
+        }
+        "lerp" => {
+            let (a, b, t) = (args.remove(0), args.remove(0), args.remove(0));
+            let delta = zip(zip(b, a.clone(), &|b, a| b - a)?, t, &|d, t| d * t)?;
+            zip(a, delta, &|a, d| a + d)?
+        }
+        "vec2" => Value::Vec2([args[0].num()?, args[1].num()?]),
+        "vec3" => Value::Vec3([args[0].num()?, args[1].num()?, args[2].num()?]),
+        "rand" => {
+            let (min, max) = match args.len() {
+                0 => (0., 1.),
+                1 => (0., args[0].num()?),
+                _ => (args[0].num()?, args[1].num()?),
+            };
+            Value::Num(min + (max - min) * rng.gen::<f64>())
+        }
+        _ => return Err(format!("unknown function: {}", name)),
+    };
+    Ok(value)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Ident(s) => write!(f, "{}", s),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> EvalResult<Vec<Token>> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next_is_digit = chars.get(i + 1).map_or(false, |c| c.is_ascii_digit());
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || (c == '.' && next_is_digit) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // optional exponent
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text = chars[start..i].iter().collect::<String>();
+            let num = text
+                .parse()
+                .map_err(|_| format!("invalid number: {}", text))?;
+            tokens.push(Token::Num(num));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(*op));
+                    i += op.len();
+                }
+                None => return Err(format!("unexpected character: {}", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(o)) if *o == op => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> EvalResult<()> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            match self.tokens.get(self.pos) {
+                Some(token) => Err(format!("expected `{}`, got `{}`", op, token)),
+                None => Err(format!("expected `{}` at the end of expression", op)),
+            }
+        }
+    }
+
+    /// Parses binary operations with precedence of at least the given level.
+    fn binary(&mut self, level: usize) -> EvalResult<Expr> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for (symbol, op) in PRECEDENCE[level] {
+                if self.eat(symbol) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> EvalResult<Expr> {
+        if self.eat("-") {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat("!") {
+            Ok(Expr::Not(Box::new(self.unary()?)))
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    /// Parses exponentiation, which is right associative and binds tighter
+    /// than the unary operators on its left.
+    fn power(&mut self) -> EvalResult<Expr> {
+        let base = self.postfix()?;
+        if self.eat("^") {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(
+                BinaryOp::Pow,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> EvalResult<Expr> {
+        let mut expr = self.primary()?;
+        while self.eat(".") {
+            let index = match self.next() {
+                Some(Token::Ident(c)) if c == "x" => 0,
+                Some(Token::Ident(c)) if c == "y" => 1,
+                Some(Token::Ident(c)) if c == "z" => 2,
+                _ => return Err("expected vector component after `.`".to_string()),
+            };
+            expr = Expr::Component(Box::new(expr), index);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> EvalResult<Expr> {
+        match self.next() {
+            Some(Token::Num(n)) => Ok(Expr::Num(n)),
+            Some(Token::Ident(name)) => {
+                if !self.eat("(") {
+                    return Ok(Expr::Var(name));
+                }
+                let mut args = Vec::new();
+                if !self.eat(")") {
+                    loop {
+                        args.push(self.binary(0)?);
+                        if self.eat(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                match FUNCTIONS.iter().find(|(n, _, _)| *n == name) {
+                    Some((_, min, max)) if args.len() >= *min && args.len() <= *max => {
+                        Ok(Expr::Call(name, args))
+                    }
+                    Some(_) => Err(format!(
+                        "invalid number of arguments for `{}`: {}",
+                        name,
+                        args.len()
+                    )),
+                    None => Err(format!("unknown function: {}", name)),
+                }
+            }
+            Some(Token::Op("(")) => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => Err(format!("unexpected token: {}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[test]
+fn eval_vectors() {
+    let mut vars = HashMap::new();
+    vars.insert("pos".to_string(), Value::Vec2([1., 2.]));
+    vars.insert("target".to_string(), Value::Vec2([4., 6.]));
+    vars.insert("speed".to_string(), Value::Num(10.));
+    let mut rng = rand::thread_rng();
+    let eval = |s: &str, rng: &mut dyn RngCore| Expr::parse(s).unwrap().eval(&vars, rng).unwrap();
+
+    assert_eq!(eval("dist(pos, target)", &mut rng), Value::Num(5.));
+    assert_eq!(
+        eval("normalize(target - pos) * speed", &mut rng),
+        Value::Vec2([6., 8.])
+    );
+    assert_eq!(eval("dot(pos, target) + pos.y", &mut rng), Value::Num(18.));
+    assert_eq!(
+        eval("cross(vec3(1, 0, 0), vec3(0, 1, 0))", &mut rng),
+        Value::Vec3([0., 0., 1.])
+    );
+    assert_eq!(
+        eval("lerp(pos, target, 0.5)", &mut rng),
+        Value::Vec2([2.5, 4.])
+    );
+    assert_eq!(eval("clamp(target, 0, 5)", &mut rng), Value::Vec2([4., 5.]));
+    assert_eq!(eval("-2^2 + 10 % 4", &mut rng), Value::Num(-2.));
+    assert!(Expr::parse("normalize(pos").is_err());
+    assert!(Expr::parse("unknown(pos)").is_err());
+}
+
+#[test]
+fn eval_lists() {
+    let mut vars = HashMap::new();
+    vars.insert(
+        "l".to_string(),
+        Value::List(vec![Value::Num(3.), Value::Num(-1.), Value::Num(7.)]),
+    );
+    let mut rng = rand::thread_rng();
+    let eval = |s: &str, rng: &mut dyn RngCore| Expr::parse(s).unwrap().eval(&vars, rng).unwrap();
+
+    assert_eq!(eval("min(l)", &mut rng), Value::Num(-1.));
+    assert_eq!(eval("max(l) + len(l)", &mut rng), Value::Num(10.));
+    assert_eq!(eval("sum(l * 2)", &mut rng), Value::Num(18.));
+    assert!(Expr::parse("len(2)")
+        .unwrap()
+        .eval(&vars, &mut rng)
+        .is_err());
+    match eval("rand(2, 4)", &mut rng) {
+        Value::Num(x) => assert!(x >= 2. && x < 4.),
+        v => panic!("unexpected value: {:?}", v),
+    }
+    assert_eq!(
+        Value::List(vec![Value::Num(1.5)]).into_var(VarType::IntList),
+        Ok(Var::List(vec![Var::Int(1)]))
+    );
+}
@@ -0,0 +1,281 @@
+//! Evaluation of math expressions.
+//!
+//! Expressions working only with numbers are compiled with `fasteval`.
+//! Those using vector or list arguments, vector outputs, or any of the
+//! functions operating on them are handled by the `expr` module.
+
+extern crate fasteval;
+extern crate getopts;
+
+pub mod expr;
+
+use std::collections::{BTreeMap, HashMap};
+use std::process::Command as ProcessCommand;
+
+// use evalexpr::eval;
+use fasteval::Compiler;
+use fasteval::Evaler;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// use serde_yaml::Value;
+// use shlex::split;
+//
+use self::getopts::Options;
+
+use crate::address::{Address, LocalAddress, ShortLocalAddress};
+// use crate::component::Component;
+use crate::entity::Entity;
+// use crate::error::Error;
+use crate::model::{ComponentModel, SimModel};
+use crate::{string, CompName, EntityId, Int, Sim, StringId, Var, VarType};
+
+use self::expr::{Expr, Value};
+use super::super::{
+    CommandPrototype, Error, LocationInfo, Registry, RegistryTarget, Result, ScopedStorage,
+};
+use super::{Command, CommandResult};
+use crate::machine::ErrorKind;
+use std::str::FromStr;
+
+/// Compiled expression
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Expression {
+    /// Scalar expression compiled with `fasteval`
+    Scalar {
+        instr: fasteval::Instruction,
+        slab: fasteval::Slab,
+    },
+    /// Expression supporting vectors and lists
+    Extended(Expr),
+}
+
+/// Precompiles an evaluation and stores it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Eval {
+    pub expr: Expression,
+    pub args: Vec<(StringId, ShortLocalAddress)>,
+    // pub arg0: Option<(ShortString, RegistryTarget)>,
+    pub out: Option<ShortLocalAddress>,
+    /// Names of the variables used within the expression, left empty if
+    /// the expression could only be parsed by `fasteval`
+    pub vars: Vec<StringId>,
+    /// Int var holding the state of the random number generator, making
+    /// `rand` calls reproducible
+    pub seed: Option<ShortLocalAddress>,
+}
+
+impl Eval {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        let matches = getopts::Options::new()
+            .optopt("o", "out", "", "")
+            .optopt("s", "seed", "", "")
+            .parse(&args)?;
+
+        let invalid =
+            |msg: String| Error::new(location.clone(), ErrorKind::InvalidCommandBody(msg));
+
+        if matches.free.is_empty() {
+            return Err(invalid("missing expression".to_string()));
+        }
+        let text = &matches.free[0];
+
+        // let mut out = None;
+        let out = matches
+            .opt_str("out")
+            .map(|s| ShortLocalAddress::from_str(&s))
+            .transpose()?;
+        let seed = matches
+            .opt_str("seed")
+            .map(|s| ShortLocalAddress::from_str(&s))
+            .transpose()?;
+        if let Some(seed) = &seed {
+            if seed.var_type != VarType::Int {
+                return Err(invalid(format!(
+                    "seed must be an int var, got: {}",
+                    seed.var_type
+                )));
+            }
+        }
+
+        let mut eval_args = Vec::new();
+        for free_arg in matches.free.iter().skip(1) {
+            let split = free_arg.split('=').collect::<Vec<&str>>();
+            if split.len() == 2 {
+                eval_args.push((
+                    string::new_truncate(split[0]),
+                    ShortLocalAddress::from_str(&split[1])?,
+                ));
+            }
+        }
+
+        // vectors, lists and seeded randomness are not supported by fasteval
+        let is_scalar = |t: VarType| match t {
+            VarType::Int | VarType::Float | VarType::Bool | VarType::Byte => true,
+            _ => false,
+        };
+        let needs_extended = seed.is_some()
+            || out.as_ref().map_or(false, |o| !is_scalar(o.var_type))
+            || eval_args.iter().any(|(_, addr)| !is_scalar(addr.var_type));
+
+        let mut vars = Vec::new();
+        let expr = match Expr::parse(text) {
+            Ok(expr) => {
+                for name in expr.vars() {
+                    let name = string::new_truncate(name);
+                    if !vars.contains(&name) {
+                        vars.push(name);
+                    }
+                }
+                if needs_extended || expr.is_extended() {
+                    Expression::Extended(expr)
+                } else {
+                    compile_scalar(text).map_err(invalid)?
+                }
+            }
+            Err(e) if needs_extended => return Err(invalid(e)),
+            Err(e) => compile_scalar(text).map_err(|_| invalid(e))?,
+        };
+
+        Ok(Command::Eval(Eval {
+            expr,
+            args: eval_args,
+            out,
+            vars,
+            seed,
+        }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &mut ScopedStorage,
+        ent_id: &EntityId,
+        comp_name: &CompName,
+        registry: &mut Registry,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let val = match &self.expr {
+            Expression::Scalar { instr, slab } => {
+                let mut ns = fasteval::StringToF64Namespace::new();
+                for (arg_name, arg_addr) in &self.args {
+                    let val =
+                        match storage.get_var(&arg_addr.storage_index_using(comp_name.clone())) {
+                            Ok(v) => v.to_float(),
+                            Err(e) => {
+                                return CommandResult::Err(Error::new(
+                                    location.clone(),
+                                    ErrorKind::CoreError(e.to_string()),
+                                ));
+                            }
+                        };
+                    ns.insert(arg_name.to_string(), val as f64);
+                }
+                instr
+                    .eval(slab, &mut ns)
+                    .map(Value::Num)
+                    .map_err(|e| format!("{:?}", e))
+            }
+            Expression::Extended(expr) => {
+                let mut vars = HashMap::new();
+                for (arg_name, arg_addr) in &self.args {
+                    let val =
+                        match storage.get_var(&arg_addr.storage_index_using(comp_name.clone())) {
+                            Ok(v) => Value::from_var(v),
+                            Err(e) => {
+                                return CommandResult::Err(Error::new(
+                                    location.clone(),
+                                    ErrorKind::CoreError(e.to_string()),
+                                ));
+                            }
+                        };
+                    match val {
+                        Ok(v) => vars.insert(arg_name.to_string(), v),
+                        Err(e) => {
+                            return CommandResult::Err(Error::new(
+                                location.clone(),
+                                ErrorKind::Other(format!("invalid argument `{}`: {}", arg_name, e)),
+                            ))
+                        }
+                    };
+                }
+                match &self.seed {
+                    Some(seed) => {
+                        let index = seed.storage_index_using(comp_name.clone());
+                        let state = match storage.get_var(&index) {
+                            Ok(v) => v.to_int(),
+                            Err(e) => {
+                                return CommandResult::Err(Error::new(
+                                    location.clone(),
+                                    ErrorKind::CoreError(e.to_string()),
+                                ));
+                            }
+                        };
+                        let mut rng = seeded_rng(state, ent_id);
+                        let val = expr.eval(&vars, &mut rng);
+                        if let Ok(target) = storage.get_var_mut(&index) {
+                            *target = Var::Int(rng.gen());
+                        }
+                        val
+                    }
+                    None => expr.eval(&vars, &mut rand::thread_rng()),
+                }
+            }
+        };
+
+        let val = match val {
+            Ok(v) => v,
+            Err(e) => {
+                return CommandResult::Err(Error::new(
+                    location.clone(),
+                    ErrorKind::Other(format!("eval failed: {}", e)),
+                ))
+            }
+        };
+
+        if let Some(out) = &self.out {
+            let var = match val.into_var(out.var_type) {
+                Ok(v) => v,
+                Err(e) => {
+                    return CommandResult::Err(Error::new(location.clone(), ErrorKind::Other(e)))
+                }
+            };
+            match storage.get_var_mut(&out.storage_index_using(comp_name.clone())) {
+                Ok(target) => *target = var,
+                Err(e) => {
+                    return CommandResult::Err(Error::new(
+                        location.clone(),
+                        ErrorKind::CoreError(e.to_string()),
+                    ))
+                }
+            }
+        }
+
+        // match self.out {
+        //     RegistryTarget::Str0 => registry.str0 = ShortString::from_str_truncate(format!("{}", val)),
+        //     _ => (),
+        // }
+
+        CommandResult::Continue
+    }
+}
+
+/// Creates a random number generator from the state stored in a seed var.
+///
+/// Entity id is mixed in so that entities sharing the same seed value don't
+/// draw the same numbers. Callers are expected to store a new state drawn
+/// from the generator back in the seed var.
+pub(crate) fn seeded_rng(state: Int, ent_id: &EntityId) -> StdRng {
+    StdRng::seed_from_u64((state as u64) ^ ((*ent_id as u64) << 32))
+}
+
+/// Compiles a scalar expression using `fasteval`.
+fn compile_scalar(text: &str) -> std::result::Result<Expression, String> {
+    let mut slab = fasteval::Slab::new();
+    let parser = fasteval::Parser::new();
+    let instr = parser
+        .parse(text, &mut slab.ps)
+        .map_err(|e| format!("failed parsing expression: {:?}", e))?
+        .from(&slab.ps)
+        .compile(&slab.ps, &mut slab.cs);
+    Ok(Expression::Scalar { instr, slab })
+}
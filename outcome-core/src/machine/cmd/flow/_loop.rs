@@ -84,7 +84,12 @@ impl Loop {
             match args[0].as_str() {
                 "true" => Condition::BoolValue(true),
                 "false" => Condition::BoolValue(false),
-                _ => unimplemented!(),
+                c => {
+                    return Err(Error::new(
+                        location.clone(),
+                        ErrorKind::InvalidCommandBody(format!("invalid loop condition: {}", c)),
+                    ))
+                }
             }
         } else {
             Condition::None
@@ -1,20 +1,29 @@
 //!
 
-use crate::entity::{Entity, Storage};
+use crate::address::ShortLocalAddress;
+use crate::entity::Entity;
 use crate::model::{ComponentModel, SimModel};
 use crate::{string, CompName, ShortString, StringId};
 
-use crate::machine::cmd::{CommandPrototype, CommandResult, LocationInfo};
+use super::super::collection::{parse_operand, resolve};
+use super::super::set::Source;
+use super::procedure::{self, RETURN_SYMBOL};
+use crate::machine::cmd::{Command, CommandPrototype, CommandResult, LocationInfo};
 use crate::machine::error::{Error, ErrorKind};
 use crate::machine::{
-    CallInfo, CallStackVec, IfElseCallInfo, IfElseMetaData, ProcedureCallInfo, Registry,
+    CallInfo, CallStackVec, IfElseCallInfo, IfElseMetaData, LocalScope, ProcedureCallInfo,
+    Registry, ScopedStorage, MAX_PROCEDURE_DEPTH,
 };
 
 /// Call a procedure by name.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Call {
     /// Name of the procedure to call
     pub proc_name: ShortString,
+    /// Arguments passed to the procedure's parameters
+    pub args: Vec<Source>,
+    /// Var the returned value is written to
+    pub output: Option<ShortLocalAddress>,
 }
 
 impl Call {
@@ -23,19 +32,85 @@ impl Call {
         location: &LocationInfo,
         commands: &Vec<CommandPrototype>,
     ) -> Result<Call, Error> {
+        let invalid =
+            |msg: String| Error::new(location.clone(), ErrorKind::InvalidCommandBody(msg));
+
+        let proc_name = match args.first() {
+            Some(name) => string::new_truncate(name),
+            None => return Err(invalid("missing procedure name".to_string())),
+        };
+        let (call_args, output) = match args[1..].iter().position(|a| a == RETURN_SYMBOL) {
+            Some(n) => match &args[n + 2..] {
+                [output] => (
+                    &args[1..n + 1],
+                    Some(ShortLocalAddress::from_str(output).map_err(|e| {
+                        Error::new(location.clone(), ErrorKind::InvalidAddress(e.to_string()))
+                    })?),
+                ),
+                _ => {
+                    return Err(invalid(format!(
+                        "expected a single output var after `{}`",
+                        RETURN_SYMBOL
+                    )))
+                }
+            },
+            None => (&args[1..], None),
+        };
+
+        // if the procedure is declared within the same file make sure the
+        // call matches its signature
+        if let Some(proc_args) = commands
+            .iter()
+            .filter(|proto| match &proto.name {
+                Some(name) => procedure::COMMAND_NAMES.contains(&name.as_str()),
+                None => false,
+            })
+            .filter_map(|proto| proto.arguments.as_ref())
+            .find(|proc_args| proc_args.first() == args.first())
+        {
+            let (arity, returns) = procedure::signature_shape(proc_args);
+            if arity != call_args.len() {
+                return Err(invalid(format!(
+                    "procedure `{}` takes {} argument(s), {} given",
+                    proc_name,
+                    arity,
+                    call_args.len()
+                )));
+            }
+            if output.is_some() && !returns {
+                return Err(invalid(format!(
+                    "procedure `{}` doesn't return a value",
+                    proc_name
+                )));
+            }
+        }
+
         Ok(Call {
-            proc_name: args[0].parse().unwrap(),
+            proc_name,
+            args: call_args
+                .iter()
+                .map(|arg| parse_operand(arg, None, location))
+                .collect::<Result<Vec<_>, _>>()?,
+            output,
         })
     }
 
     pub fn execute_loc(
         &self,
         call_stack: &mut CallStackVec,
+        ent_storage: &mut ScopedStorage,
         line: usize,
         sim_model: &SimModel,
         comp_uid: &CompName,
         location: &LocationInfo,
     ) -> CommandResult {
+        let err = |msg: String| {
+            CommandResult::Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(msg),
+            ))
+        };
+
         // get the model of the currently executed component
         let comp_model = sim_model.get_component(comp_uid).unwrap();
 
@@ -52,15 +127,101 @@ impl Call {
                 ))
             }
         };
+        let procedure = match comp_model.logic.commands.get(*start_line) {
+            Some(Command::Procedure(procedure)) => procedure,
+            _ => {
+                return err(format!(
+                    "call failed: procedure `{}` not found at line {}",
+                    &self.proc_name, start_line
+                ))
+            }
+        };
+
+        // procedures declared in other files can only be checked here
+        if self.args.len() != procedure.params.len() {
+            return err(format!(
+                "call failed: procedure `{}` takes {} argument(s), {} given",
+                &self.proc_name,
+                procedure.params.len(),
+                self.args.len()
+            ));
+        }
+        if self.output.is_some() && procedure.returns.is_none() {
+            return err(format!(
+                "call failed: procedure `{}` doesn't return a value",
+                &self.proc_name
+            ));
+        }
+
+        // each procedure call in progress holds a single local scope
+        if ent_storage.scopes.len() >= MAX_PROCEDURE_DEPTH || call_stack.is_full() {
+            return err(format!(
+                "call failed: maximum procedure call depth of {} exceeded",
+                MAX_PROCEDURE_DEPTH
+            ));
+        }
+
+        // arguments are evaluated within the caller's scope
+        let mut locals = LocalScope::default();
+        for (param, arg) in procedure.params.iter().zip(&self.args) {
+            let var = match resolve(arg, ent_storage, comp_uid)
+                .and_then(|var| procedure::coerce(var, param.var_type))
+            {
+                Ok(var) => var,
+                Err(e) => {
+                    return err(format!(
+                        "call failed: parameter `{}`: {}",
+                        param.var_name, e
+                    ))
+                }
+            };
+            locals.insert(param.storage_index_using(comp_uid.clone()), var);
+        }
 
-        // push the call to the call stack
+        // push the call to the call stack, along with its scope
         call_stack.push(CallInfo::Procedure(ProcedureCallInfo {
             call_line: line,
             start_line: *start_line,
             end_line: *end_line,
+            output: self.output.as_ref().map(|output| {
+                output.storage_index_using(output.comp.clone().unwrap_or(comp_uid.clone()))
+            }),
+            returns: procedure.returns,
         }));
+        ent_storage.scopes.push(locals);
 
         // continue execution at the beginning of the called procedure
         CommandResult::JumpToLine(start_line + 1)
     }
 }
+
+#[test]
+fn call_arity_is_checked_at_parse_time() {
+    let strings = |s: &[&str]| s.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    let proto = |name: &str, args: &[&str]| CommandPrototype {
+        name: Some(name.to_string()),
+        arguments: Some(strings(args)),
+        output: None,
+    };
+    let commands = vec![
+        proto("proc", &["dist", "float:ax", "float:ay", "->", "float"]),
+        proto("end", &[]),
+        proto("proc", &["reset"]),
+        proto("end", &[]),
+    ];
+    let location = LocationInfo::empty();
+
+    let call = Call::new(
+        strings(&["dist", "1", "float:y", "->", "float:d"]),
+        &location,
+        &commands,
+    )
+    .unwrap();
+    assert_eq!(call.args.len(), 2);
+    assert!(call.output.is_some());
+
+    assert!(Call::new(strings(&["dist", "1"]), &location, &commands).is_err());
+    assert!(Call::new(strings(&["reset", "->", "int:x"]), &location, &commands).is_err());
+    // procedures declared in other files are checked at runtime
+    assert!(Call::new(strings(&["elsewhere", "1"]), &location, &commands).is_ok());
+}
@@ -38,6 +38,18 @@ impl ComponentBlock {
         commands: &Vec<CommandPrototype>,
     ) -> Result<Command> {
         trace!("making new comp block");
+        super::super::expect_args(&args, 1, location)?;
+        let (source_comp, source_file) = match (&location.comp_name, &location.source) {
+            (Some(comp_name), Some(source)) => (comp_name.clone(), source.clone()),
+            _ => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody(
+                        "component block requires a source location".to_string(),
+                    ),
+                ))
+            }
+        };
 
         let line = location.line.unwrap();
 
@@ -80,8 +92,8 @@ impl ComponentBlock {
         match positions_options {
             Some(positions) => Ok(Command::Component(ComponentBlock {
                 name: string::new_truncate(&args[0]),
-                source_comp: location.comp_name.clone().unwrap(),
-                source_file: location.source.unwrap(),
+                source_comp,
+                source_file,
                 start_line: line + 1,
                 end_line: positions.0,
                 output_variable: None,
@@ -1,13 +1,16 @@
 use arrayvec::ArrayVec;
 
-use crate::entity::{Entity, Storage};
+use crate::entity::Entity;
 use crate::model::{ComponentModel, SimModel};
 use crate::var::Var;
 use crate::{CompName, VarType};
 
-use super::super::super::{error::Error, CallInfo, CallStackVec, LocationInfo, ProcedureCallInfo};
+use super::super::super::{
+    error::Error, CallInfo, CallStackVec, LocationInfo, ProcedureCallInfo, ScopedStorage,
+};
 use super::super::CommandResult;
 use super::forin::ForIn;
+use super::procedure;
 
 pub const COMMAND_NAMES: [&'static str; 1] = ["end"];
 
@@ -22,7 +25,24 @@ impl End {
         &self,
         call_stack: &mut CallStackVec,
         comp_uid: &CompName,
-        ent_storage: &mut Storage,
+        ent_storage: &mut ScopedStorage,
+        location: &LocationInfo,
+    ) -> Vec<CommandResult> {
+        // reaching the end of a called procedure finishes the call without
+        // returning a value
+        if let Some(CallInfo::Procedure(pci)) = call_stack.last() {
+            if Some(pci.end_line) == location.line {
+                return procedure::finish_call(None, call_stack, ent_storage, location);
+            }
+        }
+        vec![self.end_block(call_stack, comp_uid, ent_storage, location)]
+    }
+
+    fn end_block(
+        &self,
+        call_stack: &mut CallStackVec,
+        comp_uid: &CompName,
+        ent_storage: &mut ScopedStorage,
         location: &LocationInfo,
     ) -> CommandResult {
         let mut do_pop = false;
@@ -37,7 +57,11 @@ impl End {
             CallInfo::ForIn(ref mut fici) => {
                 // forin that's still not finished iterating should not be popped off
                 if fici.iteration < fici.target_len {
-                    if let Some(target) = &fici.target {
+                    if fici.inbox {
+                        if let Some(variable) = &fici.variable {
+                            ForIn::update_inbox_variable(variable, fici.iteration, ent_storage);
+                        }
+                    } else if let Some(target) = &fici.target {
                         if let Some(source_variable) = &fici.variable {
                             // update the iterator variable
                             ForIn::update_variable(
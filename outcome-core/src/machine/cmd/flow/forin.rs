@@ -1,7 +1,7 @@
 use smallvec::SmallVec;
 
 use crate::address::{Address, LocalAddress, ShortLocalAddress};
-use crate::entity::{Entity, Storage, StorageIndex};
+use crate::entity::{Entity, StorageIndex};
 use crate::model::{ComponentModel, SimModel};
 use crate::var::Var;
 use crate::{CompName, StringId, VarType};
@@ -11,16 +11,28 @@ use crate::machine::cmd::flow::{end, ifelse};
 use crate::machine::error::{Error, ErrorKind};
 use crate::machine::{
     command_search, CallInfo, CallStackVec, ForInCallInfo, IfElseCallInfo, IfElseMetaData,
-    ProcedureCallInfo, Registry, Result,
+    ProcedureCallInfo, Registry, Result, ScopedStorage,
 };
 
 pub const COMMAND_NAMES: [&'static str; 1] = ["for"];
 
+/// Name of the target used to iterate over entity's inbox.
+pub const INBOX_TARGET: &'static str = "inbox";
+
+/// What the `for` loop iterates over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ForInTarget {
+    Var(ShortLocalAddress),
+    /// Messages received by the entity, each presented as a map var,
+    /// see [`crate::machine::cmd::send`]
+    Inbox,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ForIn {
     pub start: usize,
     pub end: usize,
-    pub target: ShortLocalAddress,
+    pub target: ForInTarget,
     pub variable: ShortLocalAddress,
 }
 impl ForIn {
@@ -31,29 +43,55 @@ impl ForIn {
     ) -> Result<ForIn> {
         let line = location.line.unwrap();
 
-        let variable = match &args.get(0) {
-            Some(arg) => ShortLocalAddress::from_str(arg).map_err(|e| {
+        let target = match args.get(2) {
+            Some(arg) if arg == INBOX_TARGET => ForInTarget::Inbox,
+            Some(arg) => ForInTarget::Var(ShortLocalAddress::from_str(arg).map_err(|e| {
                 Error::new(location.clone(), ErrorKind::InvalidAddress(e.to_string()))
-            })?,
-            // Some(arg) => StringId::from(arg).unwrap(),
+            })?),
             None => {
                 return Err(Error::new(
                     location.clone(),
-                    ErrorKind::InvalidCommandBody(format_err_no_arguments(location)),
+                    ErrorKind::InvalidCommandBody("too few arguments?".to_string()),
                 ))
             }
         };
-        let target = match args.get(2) {
+        let variable = match &args.get(0) {
+            // messages are maps, so the variable type can be left out
+            Some(arg) if !arg.contains(crate::address::SEPARATOR_SYMBOL) => match target {
+                ForInTarget::Inbox => ShortLocalAddress {
+                    comp: None,
+                    var_type: VarType::Map,
+                    var_name: crate::string::new_truncate(arg),
+                },
+                _ => {
+                    return Err(Error::new(
+                        location.clone(),
+                        ErrorKind::InvalidAddress(arg.to_string()),
+                    ))
+                }
+            },
             Some(arg) => ShortLocalAddress::from_str(arg).map_err(|e| {
                 Error::new(location.clone(), ErrorKind::InvalidAddress(e.to_string()))
             })?,
+            // Some(arg) => StringId::from(arg).unwrap(),
             None => {
                 return Err(Error::new(
                     location.clone(),
-                    ErrorKind::InvalidCommandBody("too few arguments?".to_string()),
+                    ErrorKind::InvalidCommandBody(format_err_no_arguments(location)),
                 ))
             }
         };
+        if let ForInTarget::Inbox = target {
+            if variable.var_type != VarType::Map {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody(format!(
+                        "inbox messages can only be assigned to map vars, got: {}",
+                        variable.var_type
+                    )),
+                ));
+            }
+        }
 
         // start names
         // TODO all these names should probably be declared in a better place
@@ -114,22 +152,25 @@ impl ForIn {
         call_stack: &mut CallStackVec,
         registry: &mut Registry,
         comp_id: &CompName,
-        ent_storage: &mut Storage,
+        ent_storage: &mut ScopedStorage,
         location: &LocationInfo,
     ) -> CommandResult {
+        let target = match &self.target {
+            ForInTarget::Var(target) => target,
+            ForInTarget::Inbox => return self.execute_inbox(call_stack, comp_id, ent_storage),
+        };
         // get target len
         // let iter_target = match ent_storage.get_var_from_addr(&self.target, Some(comp_uid)) {
-        let iter_target =
-            match ent_storage.get_var(&self.target.storage_index_using(comp_id.clone())) {
-                Ok(var) => var,
-                Err(_) => {
-                    return CommandResult::Err(Error::new(
-                        location.clone(),
-                        //todo
-                        ErrorKind::FailedGettingFromStorage(self.target.to_string()),
-                    ));
-                }
-            };
+        let iter_target = match ent_storage.get_var(&target.storage_index_using(comp_id.clone())) {
+            Ok(var) => var,
+            Err(_) => {
+                return CommandResult::Err(Error::new(
+                    location.clone(),
+                    //todo
+                    ErrorKind::FailedGettingFromStorage(target.to_string()),
+                ));
+            }
+        };
 
         let len = match iter_target {
             Var::Int(num) => *num as usize,
@@ -153,9 +194,9 @@ impl ForIn {
         };
         let target = LocalAddress {
             // comp: self.target.comp.unwrap_or(*comp_id),
-            comp: self.target.comp.clone().unwrap_or(comp_id.clone()),
-            var_type: self.target.var_type,
-            var_name: self.target.var_name.clone(),
+            comp: target.comp.clone().unwrap_or(comp_id.clone()),
+            var_type: target.var_type,
+            var_name: target.var_name.clone(),
         };
         // let target = (*comp_id, self.target.var_id);
         // let variable_type = self.variable.var_type;
@@ -168,6 +209,7 @@ impl ForIn {
             target_len: len,
             variable: Some(variable),
             // variable_type: Some(variable_type),
+            inbox: false,
             iteration: 1,
             start: self.start,
             end: self.end,
@@ -176,12 +218,55 @@ impl ForIn {
         CommandResult::Continue
     }
 
+    /// Starts iterating over the messages in the entity's inbox. Skips the
+    /// loop body if the inbox is empty.
+    fn execute_inbox(
+        &self,
+        call_stack: &mut CallStackVec,
+        comp_id: &CompName,
+        ent_storage: &mut ScopedStorage,
+    ) -> CommandResult {
+        if ent_storage.inbox.is_empty() {
+            return CommandResult::JumpToLine(self.end + 1);
+        }
+        let variable = LocalAddress {
+            comp: self.variable.comp.clone().unwrap_or(comp_id.clone()),
+            var_type: self.variable.var_type,
+            var_name: self.variable.var_name.clone(),
+        };
+        ForIn::update_inbox_variable(&variable, 0, ent_storage);
+
+        call_stack.push(CallInfo::ForIn(ForInCallInfo {
+            target: None,
+            target_len: ent_storage.inbox.len(),
+            variable: Some(variable),
+            inbox: true,
+            iteration: 1,
+            start: self.start,
+            end: self.end,
+        }));
+        CommandResult::Continue
+    }
+
+    /// Sets the variable to the message at the given position in the
+    /// entity's inbox.
+    pub fn update_inbox_variable(
+        variable: &LocalAddress,
+        iteration: usize,
+        ent_storage: &mut ScopedStorage,
+    ) {
+        if let Some(message) = ent_storage.inbox.get(iteration) {
+            let var = message.to_var();
+            ent_storage.insert(variable.storage_index(), var);
+        }
+    }
+
     pub fn update_variable(
         variable: &LocalAddress,
         // variable_type: &Option<VarType>,
         target: &LocalAddress,
         iteration: usize,
-        ent_storage: &mut Storage,
+        ent_storage: &mut ScopedStorage,
     ) {
         match variable.var_type {
             VarType::Int => {
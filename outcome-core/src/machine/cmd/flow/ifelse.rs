@@ -6,7 +6,7 @@ use crate::CompName;
 
 use super::super::super::{
     error::Error, CallInfo, CallStackVec, ForInCallInfo, IfElseCallInfo, IfElseMetaData,
-    ProcedureCallInfo, Registry,
+    ProcedureCallInfo, Registry, ScopedStorage,
 };
 use super::super::{CentralRemoteCommand, Command, CommandPrototype, CommandResult, LocationInfo};
 use crate::address::ShortLocalAddress;
@@ -24,7 +24,7 @@ pub enum Condition {
     BoolValue(bool),
 }
 impl Condition {
-    pub fn evaluate(&self, storage: &Storage, comp_name: &CompName) -> Result<bool> {
+    pub fn evaluate(&self, storage: &ScopedStorage, comp_name: &CompName) -> Result<bool> {
         match self {
             Condition::VarAddress(addr) => Ok(storage
                 .get_var(&addr.storage_index(Some(comp_name.clone()))?)?
@@ -95,7 +95,12 @@ impl If {
         };
 
         let condition = if args[0].contains(crate::address::SEPARATOR_SYMBOL) {
-            Condition::VarAddress(args[0].parse().unwrap())
+            Condition::VarAddress(args[0].parse().map_err(|e| {
+                Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody(format!("invalid condition: {}", e)),
+                )
+            })?)
         } else {
             match args[0].as_str() {
                 "true" => Condition::BoolValue(true),
@@ -119,7 +124,7 @@ impl If {
     pub fn execute_loc(
         &self,
         call_stack: &mut CallStackVec,
-        ent_storage: &mut Storage,
+        ent_storage: &mut ScopedStorage,
         comp_name: &CompName,
         line: usize,
     ) -> CommandResult {
@@ -1,27 +1,55 @@
+//! Procedures are blocks of commands that can be called by name from
+//! anywhere within the component, optionally taking typed parameters and
+//! returning a value.
+//!
+//! ```text
+//! proc dist float:ax float:ay -> float
+//!     local float:sq
+//!     eval "(x*x + y*y)^0.5" x=float:ax y=float:ay --out float:sq
+//!     return float:sq
+//! end
+//!
+//! call dist float:x 2.5 -> float:distance
+//! ```
+//!
+//! Parameters and vars declared with `local` only exist for the duration
+//! of a single call, they are not stored in the entity's storage.
+
 use std::convert::From;
 use std::iter::FromIterator;
 
+use crate::address::ShortLocalAddress;
 use crate::entity::{Entity, Storage};
 use crate::model::{ComponentModel, SimModel};
-use crate::{string, Address, ShortString};
+use crate::{string, CompName, ShortString, Var, VarType};
 
 use super::super::super::{
     error::Error, CallInfo, CallStackVec, IfElseCallInfo, IfElseMetaData, ProcedureCallInfo,
-    Registry,
+    Registry, ScopedStorage,
 };
+use super::super::collection::{parse_operand, resolve};
+use super::super::set::Source;
 use super::super::{CommandPrototype, CommandResult, LocationInfo};
 use crate::machine::error::ErrorKind;
 use crate::machine::Result;
 
 pub const COMMAND_NAMES: [&'static str; 2] = ["proc", "procedure"];
+pub const RETURN_COMMAND_NAMES: [&'static str; 1] = ["return"];
+pub const LOCAL_COMMAND_NAMES: [&'static str; 1] = ["local"];
+
+/// Separates procedure parameters from the type of the returned value, and
+/// call arguments from the var the returned value is written to.
+pub const RETURN_SYMBOL: &'static str = "->";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "stack_stringid", derive(Copy))]
 pub struct Procedure {
     pub name: ShortString,
     pub start_line: usize,
     pub end_line: usize,
-    pub output_variable: Option<Address>,
+    /// Parameters in the order the arguments are passed in
+    pub params: Vec<ShortLocalAddress>,
+    /// Type of the returned value, if the procedure returns one
+    pub returns: Option<VarType>,
 }
 impl Procedure {
     pub fn new(
@@ -30,6 +58,16 @@ impl Procedure {
         commands: &Vec<CommandPrototype>,
     ) -> Result<Procedure> {
         let line = location.line.unwrap();
+        let name = match args.first() {
+            Some(name) => string::new_truncate(name),
+            None => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody("missing procedure name".to_string()),
+                ))
+            }
+        };
+        let (params, returns) = parse_signature(&args[1..], location)?;
 
         // TODO all these names should probably be declared in a
         // better place start names
@@ -69,10 +107,11 @@ impl Procedure {
 
         match positions_options {
             Some(positions) => Ok(Procedure {
-                name: args[0].parse().unwrap(),
+                name,
                 start_line: line,
                 end_line: positions.0,
-                output_variable: None,
+                params,
+                returns,
             }),
             None => Err(Error::new(
                 location.clone(),
@@ -94,3 +133,391 @@ impl Procedure {
         CommandResult::JumpToLine(self.end_line + 1)
     }
 }
+
+/// Returns from the innermost procedure call, optionally with a value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Return {
+    pub value: Option<Source>,
+}
+
+impl Return {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Return> {
+        match args.as_slice() {
+            [] => Ok(Return { value: None }),
+            [value] => Ok(Return {
+                value: Some(parse_operand(value, None, location)?),
+            }),
+            _ => Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody("expected at most a single value".to_string()),
+            )),
+        }
+    }
+
+    pub fn execute_loc(
+        &self,
+        call_stack: &mut CallStackVec,
+        ent_storage: &mut ScopedStorage,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> Vec<CommandResult> {
+        let value = match &self.value {
+            Some(source) => match resolve(source, ent_storage, comp_name) {
+                Ok(var) => Some(var),
+                Err(e) => {
+                    return vec![CommandResult::Err(Error::new(
+                        location.clone(),
+                        ErrorKind::InvalidCommandBody(e),
+                    ))]
+                }
+            },
+            None => None,
+        };
+        finish_call(value, call_stack, ent_storage, location)
+    }
+}
+
+/// Declares a var local to the current procedure call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Local {
+    pub var: ShortLocalAddress,
+    /// Initial value, the type's default value is used if not provided
+    pub value: Option<Source>,
+}
+
+impl Local {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Local> {
+        let var = match args.first() {
+            Some(arg) => ShortLocalAddress::from_str(arg).map_err(|e| {
+                Error::new(location.clone(), ErrorKind::InvalidAddress(e.to_string()))
+            })?,
+            None => {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody("missing local var".to_string()),
+                ))
+            }
+        };
+        if var.comp.is_some() || args.len() > 2 {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "expected local var without component, optionally followed by a value"
+                        .to_string(),
+                ),
+            ));
+        }
+        let value = match args.get(1) {
+            Some(arg) => Some(parse_operand(arg, Some(var.var_type), location)?),
+            None => None,
+        };
+        Ok(Local { var, value })
+    }
+
+    pub fn execute_loc(
+        &self,
+        ent_storage: &mut ScopedStorage,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let var = match &self.value {
+            Some(source) => resolve(source, ent_storage, comp_name)
+                .and_then(|var| coerce(var, self.var.var_type)),
+            None => Ok(self.var.var_type.default_value()),
+        };
+        let result = match (var, ent_storage.scopes.last_mut()) {
+            (Ok(var), Some(locals)) => {
+                locals.insert(self.var.storage_index_using(comp_name.clone()), var);
+                return CommandResult::Continue;
+            }
+            (Err(e), _) => e,
+            (_, None) => "local vars can only be declared within procedures".to_string(),
+        };
+        CommandResult::Err(Error::new(
+            location.clone(),
+            ErrorKind::InvalidCommandBody(result),
+        ))
+    }
+}
+
+/// Finishes the innermost procedure call, leaving any blocks entered within
+/// it, and continues execution after the call.
+///
+/// Returned value is written to the caller's output var, if there is one.
+pub fn finish_call(
+    value: Option<Var>,
+    call_stack: &mut CallStackVec,
+    ent_storage: &mut ScopedStorage,
+    location: &LocationInfo,
+) -> Vec<CommandResult> {
+    let err = |msg: String| {
+        CommandResult::Err(Error::new(
+            location.clone(),
+            ErrorKind::InvalidCommandBody(msg),
+        ))
+    };
+
+    let n = match call_stack.iter().rposition(|ci| match ci {
+        CallInfo::Procedure(_) => true,
+        _ => false,
+    }) {
+        Some(n) => n,
+        None => return vec![err("return used outside of a procedure".to_string())],
+    };
+    call_stack.truncate(n + 1);
+    let pci = match call_stack.pop() {
+        Some(CallInfo::Procedure(pci)) => pci,
+        _ => unreachable!(),
+    };
+    ent_storage.scopes.pop();
+
+    let mut results = Vec::new();
+    let value = match (value, pci.returns) {
+        (Some(var), Some(var_type)) => match coerce(var, var_type) {
+            Ok(var) => Some(var),
+            Err(e) => {
+                results.push(err(format!("invalid returned value: {}", e)));
+                None
+            }
+        },
+        (Some(_), None) => {
+            results.push(err("procedure doesn't declare a return type".to_string()));
+            None
+        }
+        (None, _) => None,
+    };
+    if let Some(output) = &pci.output {
+        // with the finished call's scope gone the output is looked up
+        // within the caller's scope
+        match (ent_storage.get_var_mut(output).ok(), value) {
+            (Some(target), Some(var)) => match coerce(var, target.get_type()) {
+                Ok(var) => *target = var,
+                Err(e) => results.push(err(format!("invalid returned value: {}", e))),
+            },
+            (None, _) => results.push(err(format!(
+                "failed getting output var: {}:{}",
+                output.0, output.1
+            ))),
+            (_, None) => {
+                if results.is_empty() {
+                    results.push(err("procedure didn't return a value".to_string()))
+                }
+            }
+        }
+    }
+
+    results.push(CommandResult::JumpToLine(pci.call_line + 1));
+    results
+}
+
+/// Parses procedure parameters along with the optional return type.
+fn parse_signature(
+    args: &[String],
+    location: &LocationInfo,
+) -> Result<(Vec<ShortLocalAddress>, Option<VarType>)> {
+    let invalid = |msg: String| Error::new(location.clone(), ErrorKind::InvalidCommandBody(msg));
+
+    let (params_args, returns) = match args.iter().position(|a| a == RETURN_SYMBOL) {
+        Some(n) => match &args[n + 1..] {
+            [var_type] => (
+                &args[..n],
+                Some(VarType::from_str(var_type).map_err(|e| invalid(e.to_string()))?),
+            ),
+            _ => {
+                return Err(invalid(format!(
+                    "expected a single return type after `{}`",
+                    RETURN_SYMBOL
+                )))
+            }
+        },
+        None => (args, None),
+    };
+
+    let mut params: Vec<ShortLocalAddress> = Vec::new();
+    for arg in params_args {
+        let param = ShortLocalAddress::from_str(arg)
+            .map_err(|e| Error::new(location.clone(), ErrorKind::InvalidAddress(e.to_string())))?;
+        if param.comp.is_some() {
+            return Err(invalid(format!(
+                "procedure parameter can't point to a component: {}",
+                arg
+            )));
+        }
+        if params.iter().any(|p| p.var_name == param.var_name) {
+            return Err(invalid(format!("duplicate procedure parameter: {}", arg)));
+        }
+        params.push(param);
+    }
+    Ok((params, returns))
+}
+
+/// Gets the number of parameters and whether a value is returned, based on
+/// the arguments of a procedure prototype.
+pub(crate) fn signature_shape(args: &[String]) -> (usize, bool) {
+    match args.iter().skip(1).position(|a| a == RETURN_SYMBOL) {
+        Some(n) => (n, true),
+        None => (args.len().saturating_sub(1), false),
+    }
+}
+
+/// Coerces the var to the given type. Only the simple types can be coerced
+/// into one another, other types have to match exactly.
+pub(crate) fn coerce(var: Var, var_type: VarType) -> std::result::Result<Var, String> {
+    let is_simple = |t: VarType| match t {
+        VarType::String | VarType::Int | VarType::Float | VarType::Bool => true,
+        _ => false,
+    };
+    if var.get_type() == var_type {
+        Ok(var)
+    } else if is_simple(var.get_type()) && is_simple(var_type) {
+        var.coerce(var_type).map_err(|e| e.to_string())
+    } else {
+        Err(format!(
+            "expected value of type {}, got {}",
+            var_type.to_str(),
+            var.get_type().to_str()
+        ))
+    }
+}
+
+/// Builds a single component out of the given script lines and executes
+/// it on the storage, returning the errors produced along the way.
+#[cfg(test)]
+fn run_script(lines: &[&str], storage: &mut Storage) -> Vec<Error> {
+    use crate::entity::EntityNonSer;
+    use crate::machine::cmd::Command;
+    use crate::machine::ScopeStackVec;
+    use crate::model::LogicModel;
+
+    let comp = string::new_truncate("calc");
+    let prototypes = lines
+        .iter()
+        .map(|line| {
+            let mut words = line.split_whitespace().map(|w| w.to_string());
+            CommandPrototype {
+                name: words.next(),
+                arguments: Some(words.collect()),
+                output: None,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut logic = LogicModel::empty();
+    for (n, prototype) in prototypes.iter().enumerate() {
+        let mut location = LocationInfo::empty();
+        location.line = Some(n);
+        location.comp_name = Some(comp.clone());
+        let command = Command::from_prototype(prototype, &location, &prototypes).unwrap();
+        if let Command::Procedure(procedure) = &command {
+            logic.procedures.insert(
+                procedure.name.clone(),
+                (procedure.start_line, procedure.end_line),
+            );
+        }
+        logic.commands.push(command);
+        logic.cmd_location_map.push(location);
+    }
+    let mut model = SimModel::default();
+    model.components.push(ComponentModel {
+        name: comp.clone(),
+        vars: Vec::new(),
+        triggers: Vec::new(),
+        logic,
+    });
+
+    let logic = &model.get_component(&comp).unwrap().logic;
+    let mut call_stack = CallStackVec::new();
+    let mut scopes = ScopeStackVec::new();
+    let mut registry = Registry::new();
+    let mut state = string::new_truncate("start");
+    let mut errors = Vec::new();
+    let mut cmd_n = 0;
+    while cmd_n < logic.commands.len() {
+        let results = logic.commands[cmd_n].execute(
+            storage,
+            &mut EntityNonSer::default(),
+            &mut state,
+            &mut call_stack,
+            &mut scopes,
+            &mut registry,
+            &comp,
+            &0,
+            &model,
+            &logic.cmd_location_map[cmd_n],
+            #[cfg(feature = "machine_dynlib")]
+            &Default::default(),
+        );
+        cmd_n += 1;
+        for result in results {
+            match result {
+                CommandResult::JumpToLine(line) => cmd_n = line,
+                CommandResult::Err(e) => errors.push(e),
+                _ => (),
+            }
+        }
+    }
+    assert!(call_stack.is_empty() && scopes.is_empty());
+    errors
+}
+
+#[test]
+fn return_writes_into_caller_local() {
+    let var = |name: &str| (string::new_truncate("calc"), string::new_truncate(name));
+    let mut storage = Storage::default();
+    storage.insert(var("result"), Var::Int(0));
+    let errors = run_script(
+        &[
+            "proc double int:x -> int",
+            "    add int:x int:x",
+            "    return int:x",
+            "end",
+            "proc outer -> int",
+            "    local int:acc 3",
+            "    call double int:acc -> int:acc",
+            "    return int:acc",
+            "end",
+            "call outer -> int:result",
+        ],
+        &mut storage,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(storage.get_var(&var("result")).unwrap(), &Var::Int(6));
+    // neither parameters nor local vars end up in the entity's storage
+    assert!(storage.get_var(&var("acc")).is_err());
+    assert!(storage.get_var(&var("x")).is_err());
+}
+
+#[test]
+fn local_shadows_entity_var() {
+    let var = |name: &str| (string::new_truncate("calc"), string::new_truncate(name));
+    let mut storage = Storage::default();
+    storage.insert(var("count"), Var::Int(5));
+    storage.insert(var("out"), Var::Int(0));
+    let errors = run_script(
+        &[
+            "proc shadow -> int",
+            "    local int:count 1",
+            "    add int:count 2",
+            "    return int:count",
+            "end",
+            "call shadow -> int:out",
+        ],
+        &mut storage,
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(storage.get_var(&var("out")).unwrap(), &Var::Int(3));
+    assert_eq!(storage.get_var(&var("count")).unwrap(), &Var::Int(5));
+}
+
+#[test]
+fn call_depth_is_limited() {
+    let mut storage = Storage::default();
+    let errors = run_script(
+        &["proc recurse", "    call recurse", "end", "call recurse"],
+        &mut storage,
+    );
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message().contains(&format!(
+        "maximum procedure call depth of {} exceeded",
+        crate::machine::MAX_PROCEDURE_DEPTH
+    )));
+}
@@ -33,6 +33,7 @@ impl State {
         // commands: &Vec<(CommandPrototype, LocationInfo)>,
         commands: &Vec<CommandPrototype>,
     ) -> Result<Command, Error> {
+        super::super::expect_args(&args, 1, location)?;
         let line = location.line.unwrap();
 
         // TODO all these names should probably be declared in a
@@ -235,3 +235,194 @@ impl ExtSet {
         return Ok(());
     }
 }
+
+/// Policy for merging a value written to a var of another entity.
+///
+/// Writes issued during a single step are applied one after another,
+/// ordered by the id of the writing entity, with writes coming from the
+/// same entity kept in the order they were issued. This makes the end
+/// result independent of the order in which entities were processed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MergePolicy {
+    /// Value replaces the current one, the writer with the highest entity
+    /// id wins
+    LastWriterWins,
+    /// Value is added to the current one
+    Sum,
+    /// Lower of the current value and the written one is kept
+    Min,
+    /// Higher of the current value and the written one is kept
+    Max,
+    /// Value is appended to the target list
+    Append,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::LastWriterWins
+    }
+}
+
+impl FromStr for MergePolicy {
+    type Err = crate::error::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let policy = match s {
+            "last" | "lww" => MergePolicy::LastWriterWins,
+            "sum" => MergePolicy::Sum,
+            "min" => MergePolicy::Min,
+            "max" => MergePolicy::Max,
+            "append" => MergePolicy::Append,
+            _ => {
+                return Err(crate::error::Error::Other(format!(
+                    "unknown merge policy: {} (expected one of: last, sum, min, max, append)",
+                    s
+                )))
+            }
+        };
+        Ok(policy)
+    }
+}
+
+impl MergePolicy {
+    /// Merges the value into the target var.
+    pub fn merge(&self, target: &mut Var, value: Var) -> crate::Result<()> {
+        match self {
+            MergePolicy::LastWriterWins => *target = match_type(value, target)?,
+            MergePolicy::Sum => add_var(target, &value)?,
+            MergePolicy::Min => {
+                let value = match_type(value, target)?;
+                if value < *target {
+                    *target = value;
+                }
+            }
+            MergePolicy::Max => {
+                let value = match_type(value, target)?;
+                if value > *target {
+                    *target = value;
+                }
+            }
+            MergePolicy::Append => target.as_list_mut()?.push(value),
+        }
+        Ok(())
+    }
+}
+
+/// Converts the value to the type of the target var. Only scalar values
+/// are converted, other values need to match the target type.
+fn match_type(value: Var, target: &Var) -> crate::Result<Var> {
+    if std::mem::discriminant(&value) == std::mem::discriminant(target) {
+        return Ok(value);
+    }
+    match target {
+        Var::String(_) | Var::Int(_) | Var::Float(_) | Var::Bool(_) => {
+            value.coerce(target.get_type())
+        }
+        _ => Err(crate::error::Error::Other(format!(
+            "can't write {} to {}",
+            value.get_type(),
+            target.get_type()
+        ))),
+    }
+}
+
+/// Adds the value to the target var.
+///
+/// Numbers are added to numbers and vectors to vectors of the same size,
+/// strings are concatenated and lists are extended.
+pub fn add_var(target: &mut Var, value: &Var) -> crate::Result<()> {
+    match (target, value) {
+        (Var::Int(t), v) => *t += v.to_int(),
+        (Var::Float(t), v) => *t += v.to_float(),
+        (Var::Byte(t), v) => *t = t.wrapping_add(v.to_int() as u8),
+        (Var::String(t), v) => t.push_str(&v.to_string()),
+        (Var::Vec2(tx, ty), Var::Vec2(x, y)) => {
+            *tx += x;
+            *ty += y;
+        }
+        (Var::Vec3(tx, ty, tz), Var::Vec3(x, y, z)) => {
+            *tx += x;
+            *ty += y;
+            *tz += z;
+        }
+        (Var::List(t), Var::List(v)) => t.extend(v.iter().cloned()),
+        (t, v) => {
+            return Err(crate::error::Error::Other(format!(
+                "can't add {} to {}",
+                v.get_type(),
+                t.get_type()
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Writes a value to a var of another entity, merging it with the current
+/// value using the given policy.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExtWrite {
+    pub target: Address,
+    pub value: Var,
+    pub policy: MergePolicy,
+}
+impl ExtWrite {
+    pub fn execute_ext(&self, sim: &mut Sim, location: &LocationInfo) -> Result<()> {
+        sim.get_var_mut(&self.target)
+            .and_then(|target| self.policy.merge(target, self.value.clone()))
+            .map_err(|e| Error::new(location.clone(), ErrorKind::CoreError(e.to_string())))
+    }
+}
+
+#[test]
+fn merge_policies() {
+    let merged = |policy: MergePolicy, mut target: Var, value: Var| {
+        policy.merge(&mut target, value).map(|_| target)
+    };
+    assert_eq!(
+        merged(MergePolicy::LastWriterWins, Var::Float(1.), Var::Int(2)).unwrap(),
+        Var::Float(2.)
+    );
+    assert_eq!(
+        merged(MergePolicy::Sum, Var::Int(1), Var::Int(2)).unwrap(),
+        Var::Int(3)
+    );
+    assert_eq!(
+        merged(MergePolicy::Min, Var::Int(1), Var::Int(2)).unwrap(),
+        Var::Int(1)
+    );
+    assert_eq!(
+        merged(MergePolicy::Max, Var::Int(1), Var::Int(2)).unwrap(),
+        Var::Int(2)
+    );
+    assert_eq!(
+        merged(
+            MergePolicy::Append,
+            Var::List(vec![Var::Int(1)]),
+            Var::Int(2)
+        )
+        .unwrap(),
+        Var::List(vec![Var::Int(1), Var::Int(2)])
+    );
+    assert!(merged(MergePolicy::Append, Var::Int(1), Var::Int(2)).is_err());
+    assert!(merged(MergePolicy::LastWriterWins, Var::List(vec![]), Var::Int(2)).is_err());
+}
+
+#[test]
+fn add_var_by_type() {
+    let added = |mut target: Var, value: Var| add_var(&mut target, &value).map(|_| target);
+    assert_eq!(added(Var::Int(1), Var::Float(2.)).unwrap(), Var::Int(3));
+    assert_eq!(added(Var::Float(1.), Var::Int(2)).unwrap(), Var::Float(3.));
+    assert_eq!(
+        added(Var::String("a".to_string()), Var::Int(1)).unwrap(),
+        Var::String("a1".to_string())
+    );
+    assert_eq!(
+        added(Var::Vec2(1., 2.), Var::Vec2(3., 4.)).unwrap(),
+        Var::Vec2(4., 6.)
+    );
+    assert_eq!(
+        added(Var::List(vec![Var::Int(1)]), Var::List(vec![Var::Int(2)])).unwrap(),
+        Var::List(vec![Var::Int(1), Var::Int(2)])
+    );
+    assert!(added(Var::Vec2(1., 2.), Var::Int(1)).is_err());
+}
@@ -0,0 +1,83 @@
+use std::str::FromStr;
+
+use crate::address::ShortLocalAddress;
+use crate::img::{self, Colormap, GridImageOptions};
+use crate::model::SimModel;
+use crate::CompName;
+
+use super::super::{error::Error, error::ErrorKind, error::Result, LocationInfo, ScopedStorage};
+use super::CommandResult;
+
+/// Writes a grid var to a PNG image.
+///
+/// Path is relative to the project root. Colormap and value range can be
+/// provided with the `--colormap` and `--range` options.
+///
+/// ```text
+/// export_png grid_int:elevation maps/elevation.png --colormap heat --range 0,100
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportPng {
+    pub address: ShortLocalAddress,
+    pub path: String,
+    pub options: GridImageOptions,
+}
+
+impl ExportPng {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        let matches = getopts::Options::new()
+            .optopt("c", "colormap", "Colormap used for the image", "NAME")
+            .optopt(
+                "r",
+                "range",
+                "Value range mapped to the colormap",
+                "MIN,MAX",
+            )
+            .parse(args)?;
+        if matches.free.len() != 2 {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "expected two arguments: grid var address and output path".to_string(),
+                ),
+            ));
+        }
+        let mut options = GridImageOptions::default();
+        if let Some(colormap) = matches.opt_str("colormap") {
+            options.colormap = Colormap::from_str(&colormap)?;
+        }
+        if let Some(range) = matches.opt_str("range") {
+            options.range = Some(GridImageOptions::parse_range(&range)?);
+        }
+        Ok(ExportPng {
+            address: ShortLocalAddress::from_str(&matches.free[0])?,
+            path: matches.free[1].clone(),
+            options,
+        })
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &ScopedStorage,
+        comp_name: &CompName,
+        sim_model: &SimModel,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let comp = self
+            .address
+            .comp
+            .clone()
+            .unwrap_or_else(|| comp_name.clone());
+        let path = sim_model.scenario.path.join(&self.path);
+        let result = storage
+            .get_var(&self.address.storage_index_using(comp))
+            .and_then(|grid| img::save_grid_png(grid, &path, &self.options));
+        match result {
+            Ok(()) => CommandResult::Continue,
+            Err(e) => CommandResult::Err(Error::new(
+                location.clone(),
+                ErrorKind::CoreError(e.to_string()),
+            )),
+        }
+    }
+}
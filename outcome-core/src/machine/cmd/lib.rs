@@ -38,6 +38,12 @@ pub struct LibCall {
 }
 impl LibCall {
     pub fn new(args: Vec<String>) -> Result<Command> {
+        if args.len() < 3 {
+            return Err(Error::Other(format!(
+                "lib_call expects library, signature and function name, got: {:?}",
+                args
+            )));
+        }
         // first separate the pipe_out ending, if there is a pipe
         // present
         let mut pipe_out = None;
@@ -37,15 +37,18 @@ use crate::{EntityName, Sim, StringId, VarType};
 use crate::address::{Address, ShortLocalAddress};
 use crate::entity::{Entity, EntityNonSer, Storage};
 // use crate::error::Error;
-use crate::model::SimModel;
+use crate::model::{SimModel, VarOverride};
 // use crate::Result;
 use crate::Var;
 
 pub mod register;
 // pub mod equal;
+pub mod collection;
 pub mod eval;
 pub mod flow;
 pub mod get_set;
+#[cfg(feature = "save_img")]
+pub mod img;
 
 #[cfg(feature = "machine_dynlib")]
 pub mod lib;
@@ -57,6 +60,7 @@ pub mod lua;
 
 pub mod print;
 pub mod range;
+pub mod send;
 pub mod set;
 pub mod sim;
 
@@ -70,7 +74,9 @@ use crate::distr::DistributionPolicy;
 use crate::distr::{CentralCommunication, SimCentral};
 use crate::machine::cmd::CommandResult::JumpToLine;
 use crate::machine::error::{Error, ErrorKind, Result};
-use crate::machine::{CommandPrototype, CommandResultVec, ExecutionContext, LocationInfo};
+use crate::machine::{
+    CommandPrototype, CommandResultVec, ExecutionContext, LocationInfo, ScopedStorage,
+};
 
 // pub type CommandResult = std::result::Result<CommandOutcome, Error>;
 
@@ -170,9 +176,198 @@ pub enum Command {
     Procedure(flow::procedure::Procedure),
 
     Range(range::Range),
+
+    // variants below are appended in order of introduction, keeping the
+    // serialized layout of the preceding ones intact
+    SpawnFrom(SpawnFrom),
+    #[cfg(feature = "save_img")]
+    ExportPng(img::ExportPng),
+
+    // collections
+    List(collection::ListCommand),
+    Map(collection::MapCommand),
+    Grid(collection::GridCommand),
+
+    Add(set::Add),
+    Send(send::SendMessage),
+
+    // procedure returns and local vars
+    Return(flow::procedure::Return),
+    Local(flow::procedure::Local),
+}
+
+/// Constructor creating a command from its name, arguments, location and
+/// the list of all command prototypes in the script.
+type Constructor = fn(&str, Vec<String>, &LocationInfo, &Vec<CommandPrototype>) -> Result<Command>;
+
+/// Returns the names of all recognized commands, including aliases, together
+/// with their constructors.
+fn constructors() -> Vec<(&'static str, Constructor)> {
+    // constructors shared by aliased names
+    let prefab: Constructor = |_, args, location, _| {
+        Ok(Command::RegisterEntityPrefab(
+            register::RegisterEntityPrefab::new(args, location)?,
+        ))
+    };
+    let trigger: Constructor = |_, args, location, _| {
+        Ok(Command::RegisterTrigger(register::RegisterTrigger::new(
+            args, location,
+        )?))
+    };
+    let component: Constructor =
+        |_, args, location, commands| register::RegisterComponent::new(args, location, commands);
+    let procedure: Constructor = |_, args, location, commands| {
+        Ok(Command::Procedure(flow::procedure::Procedure::new(
+            args, location, commands,
+        )?))
+    };
+    let loop_: Constructor = |_, args, location, commands| {
+        Ok(Command::Loop(flow::_loop::Loop::new(
+            args, location, commands,
+        )?))
+    };
+
+    let mut table: Vec<(&'static str, Constructor)> = vec![
+        ("print", |_, args, location, _| {
+            Ok(Command::PrintFmt(print::PrintFmt::new(args, location)?))
+        }),
+        ("set", |_, args, location, _| set::Set::new(args, location)),
+        ("add", |_, args, location, _| set::Add::new(args, location)),
+        ("spawn", |_, args, location, _| {
+            Ok(Command::Spawn(Spawn::new(args, location)?))
+        }),
+        ("spawn_from", |_, args, location, _| {
+            Ok(Command::SpawnFrom(SpawnFrom::new(args, location)?))
+        }),
+        ("invoke", |_, args, _, _| {
+            Ok(Command::Invoke(Invoke::new(args)?))
+        }),
+        ("sim", |_, args, location, _| {
+            sim::SimControl::new(args, location)
+        }),
+        ("extend", |_, args, location, _| {
+            Ok(Command::Extend(register::Extend::new(args, location)?))
+        }),
+        // register one-liners
+        ("event", |_, args, location, _| {
+            Ok(Command::RegisterEvent(register::RegisterEvent::new(
+                args, location,
+            )?))
+        }),
+        ("entity", prefab),
+        ("prefab", prefab),
+        ("trigger", trigger),
+        ("triggered_by", trigger),
+        ("var", |_, args, location, _| {
+            Ok(Command::RegisterVar(register::RegisterVar::new(
+                args, location,
+            )?))
+        }),
+        // register blocks
+        ("component", component),
+        ("comp", component),
+        ("state", |_, args, location, commands| {
+            flow::state::State::new(args, location, commands)
+        }),
+        ("goto", |_, args, location, _| {
+            Ok(Command::Goto(Goto::new(args, location)?))
+        }),
+        // flow control
+        ("jump", |_, args, location, _| {
+            Ok(Command::Jump(Jump::new(args, location)?))
+        }),
+        ("if", |_, args, location, commands| {
+            Ok(Command::If(flow::ifelse::If::new(
+                args, location, commands,
+            )?))
+        }),
+        ("else", |_, args, _, _| {
+            Ok(Command::Else(flow::ifelse::Else::new(args)?))
+        }),
+        ("proc", procedure),
+        ("procedure", procedure),
+        ("call", |_, args, location, commands| {
+            Ok(Command::Call(flow::call::Call::new(
+                args, location, commands,
+            )?))
+        }),
+        ("return", |_, args, location, _| {
+            Ok(Command::Return(flow::procedure::Return::new(
+                args, location,
+            )?))
+        }),
+        ("local", |_, args, location, _| {
+            Ok(Command::Local(flow::procedure::Local::new(args, location)?))
+        }),
+        ("end", |_, args, _, _| {
+            Ok(Command::End(flow::end::End::new(args)?))
+        }),
+        ("for", |_, args, location, commands| {
+            Ok(Command::ForIn(flow::forin::ForIn::new(
+                args, location, commands,
+            )?))
+        }),
+        ("loop", loop_),
+        ("while", loop_),
+        ("break", |_, _, _, _| {
+            Ok(Command::Break(flow::_loop::Break {}))
+        }),
+        ("range", |_, args, location, _| {
+            Ok(Command::Range(range::Range::new(args, location)?))
+        }),
+        ("eval", |_, args, location, _| {
+            eval::Eval::new(args, location)
+        }),
+        ("send", |_, args, location, _| {
+            send::SendMessage::new(args, location)
+        }),
+    ];
+    for name in collection::LIST_COMMAND_NAMES.iter() {
+        table.push((name, |name, args, location, _| {
+            collection::ListCommand::new(name, args, location)
+        }));
+    }
+    for name in collection::MAP_COMMAND_NAMES.iter() {
+        table.push((name, |name, args, location, _| {
+            collection::MapCommand::new(name, args, location)
+        }));
+    }
+    for name in collection::GRID_COMMAND_NAMES.iter() {
+        table.push((name, |name, args, location, _| {
+            collection::GridCommand::new(name, args, location)
+        }));
+    }
+    #[cfg(feature = "save_img")]
+    table.push(("export_png", |_, args, location, _| {
+        Ok(Command::ExportPng(img::ExportPng::new(args, location)?))
+    }));
+    #[cfg(feature = "machine_dynlib")]
+    table.push(("lib_call", |_, args, _, _| Ok(LibCall::new(args)?)));
+    table
+}
+
+/// Returns an error if fewer than `count` arguments were provided.
+pub(crate) fn expect_args(args: &[String], count: usize, location: &LocationInfo) -> Result<()> {
+    if args.len() < count {
+        return Err(Error::new(
+            location.clone(),
+            ErrorKind::InvalidCommandBody(format!(
+                "expected at least {} argument(s), got {}",
+                count,
+                args.len()
+            )),
+        ));
+    }
+    Ok(())
 }
 
 impl Command {
+    /// Lists names of all the commands recognized by `from_prototype`,
+    /// including aliases.
+    pub fn names() -> Vec<&'static str> {
+        constructors().into_iter().map(|(name, _)| name).collect()
+    }
+
     /// Creates new command struct from a prototype.
     pub fn from_prototype(
         proto: &CommandPrototype,
@@ -187,70 +382,12 @@ impl Command {
             Some(a) => a.clone(),
             None => Vec::new(),
         };
-        match cmd_name.as_str() {
-            "print" => Ok(Command::PrintFmt(print::PrintFmt::new(args)?)),
-            "set" => Ok(set::Set::new(args, location)?),
-            // "set" => Ok(get::Get::new(args, location)?),
-            "spawn" => Ok(Command::Spawn(Spawn::new(args, location)?)),
-            "invoke" => Ok(Command::Invoke(Invoke::new(args)?)),
-            "sim" => Ok(sim::SimControl::new(args)?),
-
-            "extend" => Ok(Command::Extend(register::Extend::new(args, location)?)),
-
-            // register one-liners
-            "event" => Ok(Command::RegisterEvent(register::RegisterEvent::new(
-                args, location,
-            )?)),
-            "entity" | "prefab" => Ok(Command::RegisterEntityPrefab(
-                register::RegisterEntityPrefab::new(args, location)?,
-            )),
-            "trigger" | "triggered_by" => Ok(Command::RegisterTrigger(
-                register::RegisterTrigger::new(args, location)?,
-            )),
-            "var" => Ok(Command::RegisterVar(register::RegisterVar::new(
-                args, location,
-            )?)),
-
-            "component" | "comp" => {
-                Ok(register::RegisterComponent::new(args, location, &commands)?)
-            }
-
-            // register blocks
-            // "component" | "comp" => Ok(flow::component::ComponentBlock::new(
-            //     args, location, &commands,
-            // )?),
-            "state" => Ok(flow::state::State::new(args, location, &commands)?),
-            "goto" => Ok(Command::Goto(Goto::new(args)?)),
-
-            // flow control
-            "jump" => Ok(Command::Jump(Jump::new(args)?)),
-            "if" => Ok(Command::If(flow::ifelse::If::new(
-                args, location, &commands,
-            )?)),
-            "else" => Ok(Command::Else(flow::ifelse::Else::new(args)?)),
-            "proc" | "procedure" => Ok(Command::Procedure(flow::procedure::Procedure::new(
-                args, location, &commands,
-            )?)),
-            "call" => Ok(Command::Call(flow::call::Call::new(
-                args, location, &commands,
-            )?)),
-            "end" => Ok(Command::End(flow::end::End::new(args)?)),
-            "for" => Ok(Command::ForIn(flow::forin::ForIn::new(
-                args, location, commands,
-            )?)),
-            "loop" | "while" => Ok(Command::Loop(flow::_loop::Loop::new(
-                args, location, commands,
-            )?)),
-            "break" => Ok(Command::Break(flow::_loop::Break {})),
-
-            "range" => Ok(Command::Range(range::Range::new(args)?)),
-
-            "eval" => Ok(eval::Eval::new(args)?),
-
-            #[cfg(feature = "machine_dynlib")]
-            "lib_call" => Ok(LibCall::new(args)?),
-
-            _ => Err(Error::new(
+        match constructors()
+            .into_iter()
+            .find(|(name, _)| name == cmd_name)
+        {
+            Some((name, constructor)) => constructor(name, args, location, commands),
+            None => Err(Error::new(
                 location.clone(),
                 ErrorKind::UnknownCommand(cmd_name.to_string()),
             )),
@@ -264,6 +401,7 @@ impl Command {
         ent_insta: &mut EntityNonSer,
         comp_state: &mut StringId,
         call_stack: &mut super::CallStackVec,
+        scopes: &mut super::ScopeStackVec,
         registry: &mut super::Registry,
         comp_name: &CompName,
         ent_id: &EntityId,
@@ -273,6 +411,9 @@ impl Command {
     ) -> CommandResultVec {
         let line = location.line.unwrap();
         let mut out_res = CommandResultVec::new();
+        // commands see the entity's vars through the local scope of the
+        // innermost procedure call
+        let ent_storage = &mut ScopedStorage::new(ent_storage, scopes);
         match self {
             Command::Sim(cmd) => {
                 out_res.push(cmd.execute_loc(ent_storage, comp_state, comp_name, location))
@@ -284,12 +425,13 @@ impl Command {
             Command::Set(cmd) => {
                 out_res.push(cmd.execute_loc(ent_storage, ent_id, comp_state, comp_name, location))
             }
+            Command::Add(cmd) => out_res.push(cmd.execute_loc(ent_storage, comp_name, location)),
             Command::SetIntIntAddr(cmd) => {
                 out_res.push(cmd.execute_loc(ent_storage, comp_name, location))
             }
 
             Command::Eval(cmd) => {
-                out_res.push(cmd.execute_loc(ent_storage, comp_name, registry, location))
+                out_res.push(cmd.execute_loc(ent_storage, ent_id, comp_name, registry, location))
             }
             // Command::EvalReg(cmd) => out_res.push(cmd.execute_loc(registry)),
 
@@ -316,9 +458,15 @@ impl Command {
 
             Command::Invoke(cmd) => out_res.push(cmd.execute_loc()),
             Command::Spawn(cmd) => out_res.push(cmd.execute_loc()),
-            Command::Call(cmd) => {
-                out_res.push(cmd.execute_loc(call_stack, line, sim_model, comp_name, location))
-            }
+            Command::SpawnFrom(cmd) => out_res.push(cmd.execute_loc()),
+            Command::Call(cmd) => out_res.push(cmd.execute_loc(
+                call_stack,
+                ent_storage,
+                line,
+                sim_model,
+                comp_name,
+                location,
+            )),
 
             Command::Jump(cmd) => out_res.push(cmd.execute_loc()),
             Command::If(cmd) => {
@@ -336,9 +484,13 @@ impl Command {
             Command::Break(cmd) => out_res.push(cmd.execute_loc(call_stack, ent_storage, location)),
 
             Command::End(cmd) => {
-                out_res.push(cmd.execute_loc(call_stack, comp_name, ent_storage, location))
+                out_res.extend(cmd.execute_loc(call_stack, comp_name, ent_storage, location))
             }
             Command::Procedure(cmd) => out_res.push(cmd.execute_loc(call_stack, ent_storage, line)),
+            Command::Return(cmd) => {
+                out_res.extend(cmd.execute_loc(call_stack, ent_storage, comp_name, location))
+            }
+            Command::Local(cmd) => out_res.push(cmd.execute_loc(ent_storage, comp_name, location)),
 
             Command::State(cmd) => {
                 out_res.extend(cmd.execute_loc(call_stack, ent_id, comp_name, line))
@@ -350,6 +502,18 @@ impl Command {
             Command::Extend(cmd) => out_res.push(cmd.execute_loc()),
             // Command::Register(cmd) => out_res.extend(cmd.execute_loc(call_stack)),
             Command::Range(cmd) => out_res.push(cmd.execute_loc(ent_storage, comp_name, location)),
+            Command::List(cmd) => {
+                out_res.push(cmd.execute_loc(ent_storage, ent_id, comp_name, location))
+            }
+            Command::Map(cmd) => out_res.push(cmd.execute_loc(ent_storage, comp_name, location)),
+            Command::Grid(cmd) => out_res.push(cmd.execute_loc(ent_storage, comp_name, location)),
+            Command::Send(cmd) => {
+                out_res.push(cmd.execute_loc(ent_storage, ent_id, comp_name, location))
+            }
+            #[cfg(feature = "save_img")]
+            Command::ExportPng(cmd) => {
+                out_res.push(cmd.execute_loc(ent_storage, comp_name, sim_model, location))
+            }
 
             _ => out_res.push(CommandResult::Continue),
         };
@@ -385,6 +549,7 @@ pub enum CentralRemoteCommand {
     Extend(register::Extend),
     Invoke(Invoke),
     Spawn(Spawn),
+    SpawnFrom(SpawnFrom),
 
     State(flow::state::State),
     Component(flow::component::ComponentBlock),
@@ -411,6 +576,7 @@ impl CentralRemoteCommand {
             CentralRemoteCommand::Extend(cmd) => cmd.execute_ext(sim, ent_uid),
             CentralRemoteCommand::Invoke(cmd) => cmd.execute_ext(sim),
             CentralRemoteCommand::Spawn(cmd) => cmd.execute_ext(sim, ent_uid),
+            CentralRemoteCommand::SpawnFrom(cmd) => cmd.execute_ext(sim),
             // CentralRemoteCommand::Prefab(cmd) => return cmd.execute_ext(sim),
             CentralRemoteCommand::State(cmd) => cmd.execute_ext(sim),
             CentralRemoteCommand::Component(cmd) => cmd.execute_ext(sim),
@@ -426,6 +592,7 @@ impl CentralRemoteCommand {
     ) -> Result<()> {
         match self {
             CentralRemoteCommand::Spawn(cmd) => cmd.execute_ext_distr(central)?,
+            CentralRemoteCommand::SpawnFrom(cmd) => cmd.execute_ext_distr(central)?,
             CentralRemoteCommand::RegisterEntityPrefab(cmd) => cmd.execute_ext_distr(central)?,
             CentralRemoteCommand::RegisterComponent(cmd) => cmd.execute_ext_distr(central)?,
             CentralRemoteCommand::RegisterVar(cmd) => cmd.execute_ext_distr(central, comp_name)?,
@@ -451,6 +618,8 @@ pub enum ExtCommand {
     Get(Get),
     Set(ExtSet),
     SetVar(ExtSetVar),
+    Write(ExtWrite),
+    Send(send::ExtSend),
     // RemoteExec(Command),
     // CentralizedExec(CentralExtCommand),
 }
@@ -465,6 +634,8 @@ impl ExtCommand {
         match self {
             // ExtCommand::Get(cmd) => return cmd.execute_ext(sim, ent_uid, comp_uid, location),
             ExtCommand::Set(cmd) => return cmd.execute_ext(sim, ent_id, comp_name, location),
+            ExtCommand::Write(cmd) => return cmd.execute_ext(sim, location),
+            ExtCommand::Send(cmd) => return cmd.execute_ext(sim, location),
             // ExtCommand::SetVar(cmd) => return cmd.execute_ext(sim, exec_ctx),
             _ => return Ok(()),
         }
@@ -578,7 +749,8 @@ pub struct Goto {
     pub target_state: StringId,
 }
 impl Goto {
-    fn new(args: Vec<String>) -> Result<Self> {
+    fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        expect_args(&args, 1, location)?;
         Ok(Goto {
             target_state: string::new_truncate(&args[0]),
         })
@@ -604,7 +776,8 @@ pub enum JumpTarget {
 }
 
 impl Jump {
-    fn new(args: Vec<String>) -> Result<Self> {
+    fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        expect_args(&args, 1, location)?;
         if let Ok(num) = args[0].parse::<u16>() {
             Ok(Jump {
                 target: JumpTarget::Line(num),
@@ -664,18 +837,27 @@ impl Invoke {
     }
 }
 
-/// Spawn
+/// Spawns a new entity, optionally using a prefab and a name.
+///
+/// Initial var values can be overridden using the `--set` option, which
+/// can be provided multiple times.
+///
+/// ```text
+/// spawn guard guard_1 --set health:float:max=200
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "stack_stringid", derive(Copy))]
 pub struct Spawn {
     pub prefab: Option<StringId>,
     pub spawn_id: Option<StringId>,
     pub out: Option<ShortLocalAddress>,
+    /// Initial var values overriding the prefab values
+    pub vars: Vec<VarOverride>,
 }
 impl Spawn {
     fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
         let matches = getopts::Options::new()
             .optopt("o", "out", "", "")
+            .optmulti("s", "set", "", "")
             .parse(&args)
             .map_err(|e| Error::new(location.clone(), ErrorKind::ParseError(e.to_string())))?;
 
@@ -683,31 +865,32 @@ impl Spawn {
             .opt_str("out")
             .map(|s| ShortLocalAddress::from_str(&s))
             .transpose()?;
+        let vars = matches
+            .opt_strs("set")
+            .iter()
+            .map(|s| VarOverride::from_str(s))
+            .collect::<crate::Result<Vec<_>>>()?;
 
-        if matches.free.len() == 0 {
-            Ok(Self {
-                prefab: None,
-                spawn_id: None,
-                out,
-            })
-        } else if matches.free.len() == 1 {
-            Ok(Self {
-                prefab: Some(string::new_truncate(&args[0])),
-                spawn_id: None,
-                out,
-            })
-        } else if matches.free.len() == 2 {
-            Ok(Self {
-                prefab: Some(string::new_truncate(&args[0])),
-                spawn_id: Some(string::new_truncate(&args[1])),
-                out,
-            })
-        } else {
+        if matches.free.len() > 2 {
             return Err(Error::new(
                 location.clone(),
                 ErrorKind::InvalidCommandBody("can't accept more than 2 arguments".to_string()),
             ));
         }
+        if matches.free.is_empty() && !vars.is_empty() {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "var values can only be set when spawning from a prefab".to_string(),
+                ),
+            ));
+        }
+        Ok(Self {
+            prefab: matches.free.get(0).map(|s| string::new_truncate(s)),
+            spawn_id: matches.free.get(1).map(|s| string::new_truncate(s)),
+            out,
+            vars,
+        })
     }
 
     pub fn execute_loc(&self) -> CommandResult {
@@ -715,12 +898,21 @@ impl Spawn {
     }
 
     pub fn execute_ext(&self, sim: &mut Sim, ent_uid: &EntityId) -> Result<()> {
-        sim.spawn_entity(self.prefab.as_ref(), self.spawn_id.clone())?;
+        sim.spawn_entity_with(self.prefab.as_ref(), self.spawn_id.clone(), &self.vars)?;
         // #[cfg(feature = "machine_lua")]
         // sim.setup_lua_state_ent();
         Ok(())
     }
     pub fn execute_ext_distr(&self, central: &mut SimCentral) -> Result<()> {
+        if !self.vars.is_empty() {
+            return Err(Error::new(
+                LocationInfo::default(),
+                ErrorKind::Other(
+                    "setting var values on spawn is not supported in distributed simulations"
+                        .to_string(),
+                ),
+            ));
+        }
         central.spawn_entity(
             self.prefab.clone(),
             self.spawn_id.clone(),
@@ -729,3 +921,47 @@ impl Spawn {
         Ok(())
     }
 }
+
+/// Spawns entities defined in an entity table file.
+///
+/// Path to the table is relative to the project root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnFrom {
+    pub path: String,
+}
+impl SpawnFrom {
+    fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        if args.len() != 1 {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "expected a single argument: path to entity table".to_string(),
+                ),
+            ));
+        }
+        Ok(Self {
+            path: args[0].clone(),
+        })
+    }
+
+    pub fn execute_loc(&self) -> CommandResult {
+        CommandResult::ExecCentralExt(CentralRemoteCommand::SpawnFrom(self.clone()))
+    }
+
+    pub fn execute_ext(&self, sim: &mut Sim) -> Result<()> {
+        let path = sim.model.scenario.path.join(&self.path);
+        sim.spawn_from_table(&path)?;
+        Ok(())
+    }
+
+    pub fn execute_ext_distr(&self, central: &mut SimCentral) -> Result<()> {
+        // TODO spawn signal doesn't carry initial var values
+        Err(Error::new(
+            LocationInfo::default(),
+            ErrorKind::Other(
+                "spawning from entity tables is not supported in distributed simulations"
+                    .to_string(),
+            ),
+        ))
+    }
+}
@@ -5,7 +5,7 @@ use shlex;
 use crate::{CompName, StringId, VarType};
 
 use crate::address::{Address, PartialAddress, ShortLocalAddress};
-use crate::entity::Storage;
+use crate::machine::ScopedStorage;
 use crate::model::ComponentModel;
 
 use super::super::{error::Error, LocationInfo};
@@ -24,8 +24,9 @@ impl PrintFmt {
     pub fn get_type() -> String {
         return "printfmt".to_string();
     }
-    pub fn new(args: Vec<String>) -> Result<Self> {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
         let matches = getopts::Options::new().parse(args)?;
+        super::expect_args(&matches.free, 1, location)?;
         let mut fmt = matches.free[0].clone();
         let mut inserts = Vec::new();
         let mut count = 1;
@@ -75,7 +76,7 @@ impl PrintFmt {
 impl PrintFmt {
     pub fn execute_loc(
         &self,
-        entity_db: &mut Storage,
+        entity_db: &mut ScopedStorage,
         comp_state: &StringId,
         comp_uid: &CompName,
         location: &LocationInfo,
@@ -133,7 +134,7 @@ impl Print {
     }
 }
 impl Print {
-    pub fn execute_loc(&self, entity_db: &mut Storage) -> CommandResult {
+    pub fn execute_loc(&self, entity_db: &mut ScopedStorage) -> CommandResult {
         //        let evuid =
         // comp.loc_vars.get(self.source).unwrap();
         let print_string = match &self.source.var_type {
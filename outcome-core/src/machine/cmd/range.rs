@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use crate::entity::Storage;
+use crate::machine::ScopedStorage;
 use crate::{Address, CompName, StringId, Var};
 
 use super::super::{error::Error, error::ErrorKind, error::Result, LocationInfo};
@@ -17,7 +17,8 @@ impl Range {
     pub fn get_type() -> String {
         return "range".to_string();
     }
-    pub fn new(args: Vec<String>) -> Result<Self> {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        super::expect_args(&args, 3, location)?;
         Ok(Range {
             start: args[0].to_string(),
             end: args[1].to_string(),
@@ -28,7 +29,7 @@ impl Range {
 impl Range {
     pub fn execute_loc(
         &self,
-        storage: &mut Storage,
+        storage: &mut ScopedStorage,
         comp_name: &CompName,
         location: &LocationInfo,
     ) -> CommandResult {
@@ -8,7 +8,7 @@ use shlex::Shlex;
 
 use crate::address::{Address, LocalAddress, ShortLocalAddress};
 use crate::entity::Storage;
-use crate::model::{ComponentModel, EntityPrefab, EventModel, LogicModel, SimModel};
+use crate::model::{ComponentModel, EntityPrefab, EventModel, LogicModel, SimModel, VarOverride};
 use crate::sim::Sim;
 use crate::var::Var;
 use crate::{string, CompName, ShortString, StringId};
@@ -33,6 +33,7 @@ pub struct RegisterVar {
 }
 impl RegisterVar {
     pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        super::expect_args(&args, 1, location)?;
         let addr = match ShortLocalAddress::from_str(&args[0]) {
             Ok(a) => a,
             Err(e) => {
@@ -194,26 +195,30 @@ impl Extend {
     }
 }
 
-/// Register an entity prefab, specifying a name and a set of components.
+/// Register an event, making it available for triggering components.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterEvent {
     /// Name of the event
-    name: StringId,
+    pub name: StringId,
 }
 
 impl RegisterEvent {
     pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
-        Ok(Self {
-            name: string::new_truncate(&args[0]),
-        })
+        match args.first() {
+            Some(name) => Ok(Self {
+                name: string::new_truncate(name),
+            }),
+            None => Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody("missing event name".to_string()),
+            )),
+        }
     }
 
     pub fn execute_loc(&self) -> Vec<CommandResult> {
         debug!("registering event");
         vec![
-            CommandResult::ExecCentralExt(CentralRemoteCommand::RegisterEvent(Self {
-                name: self.name.clone(),
-            })),
+            CommandResult::ExecCentralExt(CentralRemoteCommand::RegisterEvent(self.clone())),
             CommandResult::Continue,
         ]
     }
@@ -233,51 +238,81 @@ impl RegisterEvent {
 }
 
 /// Register an entity prefab, specifying a name and a set of components.
+///
+/// Prefab can extend another prefab using the `--extends` option, and set
+/// initial var values using the `--set` option, which can be provided
+/// multiple times.
+///
+/// ```text
+/// prefab guard --extends person --set health:float:max=150 armor
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RegisterEntityPrefab {
     /// Name of the entity prefab
-    name: StringId,
+    pub name: StringId,
     /// List of components defining the prefab
-    components: Vec<StringId>,
+    pub components: Vec<StringId>,
+    /// Name of the prefab this one extends
+    pub extends: Option<StringId>,
+    /// Initial var values set on entities spawned from the prefab
+    pub vars: Vec<VarOverride>,
 }
 
 impl RegisterEntityPrefab {
     pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
+        let matches = getopts::Options::new()
+            .optopt("e", "extends", "Name of the prefab to extend", "PREFAB")
+            .optmulti("s", "set", "Initial var value", "COMP:TYPE:VAR=VALUE")
+            .parse(args)?;
+        if matches.free.is_empty() {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody("missing prefab name".to_string()),
+            ));
+        }
+        let vars = matches
+            .opt_strs("set")
+            .iter()
+            .map(|s| VarOverride::from_str(s))
+            .collect::<crate::Result<Vec<_>>>()?;
         Ok(Self {
-            name: string::new_truncate(&args[0]),
-            components: args
+            name: string::new_truncate(&matches.free[0]),
+            components: matches
+                .free
                 .iter()
                 .skip(1)
                 .map(|a| string::new_truncate(a))
                 .collect(),
+            extends: matches.opt_str("extends").map(|e| string::new_truncate(&e)),
+            vars,
         })
     }
 
     pub fn execute_loc(&self) -> Vec<CommandResult> {
         debug!("registering entity prefab (loc)");
         vec![
-            CommandResult::ExecCentralExt(CentralRemoteCommand::RegisterEntityPrefab(Self {
-                name: self.name.clone(),
-                components: self.components.clone(),
-            })),
+            CommandResult::ExecCentralExt(CentralRemoteCommand::RegisterEntityPrefab(self.clone())),
             CommandResult::Continue,
         ]
     }
 
     pub fn execute_ext(&self, sim: &mut Sim) -> Result<()> {
-        sim.model.entities.push(EntityPrefab {
-            name: self.name.clone(),
-            components: self.components.clone(),
-        });
+        sim.model.entities.push(self.to_prefab());
         Ok(())
     }
 
     pub fn execute_ext_distr(&self, central: &mut SimCentral) -> Result<()> {
-        central.model.entities.push(EntityPrefab {
+        central.model.entities.push(self.to_prefab());
+        Ok(())
+    }
+
+    fn to_prefab(&self) -> EntityPrefab {
+        EntityPrefab {
             name: self.name.clone(),
             components: self.components.clone(),
-        });
-        Ok(())
+            extends: self.extends.clone(),
+            vars: self.vars.clone(),
+        }
     }
 }
 
@@ -300,7 +335,7 @@ impl RegisterComponent {
         let mut options = getopts::Options::new();
         options.optflag("", "marker", "");
 
-        let matches = options.parse(&args).unwrap();
+        let matches = options.parse(&args)?;
         if matches.opt_present("marker") && !matches.free.is_empty() {
             Ok(Command::RegisterComponent(Self {
                 name: string::new_truncate(&matches.free[0]),
@@ -428,8 +463,7 @@ pub struct RegisterTrigger {
 
 impl RegisterTrigger {
     pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Self> {
-        // TODO handle cases with wrong number of args
-
+        super::expect_args(&args, 1, location)?;
         Ok(RegisterTrigger {
             name: string::new_truncate(&args[0]),
             comp: Default::default(),
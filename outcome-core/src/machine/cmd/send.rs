@@ -0,0 +1,137 @@
+//! Messaging between entities.
+//!
+//! Messages sent during a step are delivered into target entities' inboxes
+//! at the end of that step, and can be read during the next step by
+//! iterating over the inbox. Each message is presented as a map var with
+//! `sender`, `type` and `args` keys.
+//!
+//! Messages from different senders are ordered by sender id, messages from
+//! a single sender are kept in the order they were sent. Inbox is cleared
+//! at the end of each step, whether its messages were read or not.
+//!
+//! ```text
+//! send trader_2 offer 10 float:price
+//! send int:partner accept
+//!
+//! for msg in inbox
+//!     map_get map:msg type --out str:kind
+//! end
+//! ```
+
+use fnv::FnvHashMap;
+
+use crate::entity::Message;
+use crate::{string, CompName, EntityId, EntityName, Sim, StringId, Var};
+
+use super::super::{error::Error, error::ErrorKind, error::Result, LocationInfo, ScopedStorage};
+use super::collection::{parse_operand, resolve};
+use super::set::Source;
+use super::{Command, CommandResult, ExtCommand};
+
+pub const COMMAND_NAMES: [&'static str; 1] = ["send"];
+
+/// Sends a message to another entity.
+///
+/// Target entity can be provided either by name or by id, directly or
+/// using a local var.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendMessage {
+    pub target: Source,
+    pub kind: StringId,
+    pub args: Vec<Source>,
+}
+
+impl SendMessage {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        if args.len() < 2 {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "expected at least 2 arguments: target entity and message type".to_string(),
+                ),
+            ));
+        }
+        Ok(Command::Send(SendMessage {
+            target: parse_operand(&args[0], None, location)?,
+            kind: string::new_truncate(&args[1]),
+            args: args[2..]
+                .iter()
+                .map(|arg| parse_operand(arg, None, location))
+                .collect::<Result<Vec<_>>>()?,
+        }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &ScopedStorage,
+        ent_id: &EntityId,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        match self.message(storage, ent_id, comp_name) {
+            Ok((target, message)) => {
+                CommandResult::ExecExt(ExtCommand::Send(ExtSend { target, message }))
+            }
+            Err(e) => CommandResult::Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(e),
+            )),
+        }
+    }
+
+    fn message(
+        &self,
+        storage: &ScopedStorage,
+        ent_id: &EntityId,
+        comp_name: &CompName,
+    ) -> std::result::Result<(EntityName, Message), String> {
+        let target = match resolve(&self.target, storage, comp_name)? {
+            Var::String(name) => string::new_truncate(&name),
+            Var::Int(id) => string::new_truncate(&id.to_string()),
+            var => return Err(format!("invalid target entity: {}", var.to_string())),
+        };
+        let args = self
+            .args
+            .iter()
+            .map(|arg| resolve(arg, storage, comp_name))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let message = Message {
+            sender: *ent_id,
+            kind: self.kind.clone(),
+            args,
+        };
+        Ok((target, message))
+    }
+}
+
+/// Delivers a message into the inbox of the target entity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtSend {
+    /// Name or id of the target entity
+    pub target: EntityName,
+    pub message: Message,
+}
+
+impl ExtSend {
+    /// Gets the id of the target entity, using the provided name index.
+    pub fn target_id(&self, entities_idx: &FnvHashMap<EntityName, EntityId>) -> Option<EntityId> {
+        match entities_idx.get(&self.target) {
+            Some(id) => Some(*id),
+            None => self.target.parse().ok(),
+        }
+    }
+
+    pub fn execute_ext(&self, sim: &mut Sim, location: &LocationInfo) -> Result<()> {
+        let entity = self
+            .target_id(&sim.entity_idx)
+            .and_then(|id| sim.entities.get_mut(&id))
+            .ok_or_else(|| {
+                Error::new(
+                    location.clone(),
+                    ErrorKind::CoreError(format!("target entity not found: {}", self.target)),
+                )
+            })?;
+        entity.storage.inbox.push(self.message.clone());
+        Ok(())
+    }
+}
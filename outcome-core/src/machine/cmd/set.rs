@@ -1,14 +1,14 @@
 use super::{Command, CommandResult};
 use crate::address::{Address, LocalAddress, ShortLocalAddress};
-use crate::entity::{Entity, Storage};
+use crate::entity::Entity;
 use crate::var::{Var, VarType};
 use crate::{address, string};
 use crate::{CompName, EntityId, EntityName, StringId};
 
 use super::super::LocationInfo;
-use crate::machine::cmd::get_set::ExtSet;
+use crate::machine::cmd::get_set::{add_var, ExtSet, ExtWrite, MergePolicy};
 use crate::machine::cmd::ExtCommand;
-use crate::machine::{Error, ErrorKind, Result};
+use crate::machine::{Error, ErrorKind, Result, ScopedStorage};
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,7 +19,7 @@ pub struct SetIntIntAddr {
 impl SetIntIntAddr {
     pub fn execute_loc(
         &self,
-        storage: &mut Storage,
+        storage: &mut ScopedStorage,
         comp_uid: &CompName,
         location: &LocationInfo,
     ) -> CommandResult {
@@ -34,9 +34,11 @@ impl SetIntIntAddr {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Set {
-    target: Target,
-    source: Source,
-    out: Option<ShortLocalAddress>,
+    pub target: Target,
+    pub source: Source,
+    pub out: Option<ShortLocalAddress>,
+    /// Policy used when writing to a var of another entity
+    pub policy: MergePolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -138,13 +140,16 @@ impl Source {
 }
 
 impl Set {
-    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+    pub fn new(mut args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        let policy = take_policy(&mut args, location)?;
+        super::expect_args(&args, 1, location)?;
         let target = Target::from_str(&args[0], location)?;
 
         let mut source_str = "";
         // is '=' present?
         if args.len() > 1 {
             if args[1] == "=" {
+                super::expect_args(&args, 3, location)?;
                 source_str = &args[2];
             } else {
                 source_str = &args[1];
@@ -153,6 +158,25 @@ impl Set {
 
         let source = Source::from_str(source_str, target.var_type(), location)?;
 
+        // merge policies only apply to writes to other entities, values
+        // read from other entities are set using a separate mechanism
+        if policy.is_some() {
+            let reason = match (&target, &source) {
+                (Target::LocalAddress(_), _) => Some("local targets"),
+                (_, Source::Address(_)) => Some("remote sources"),
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                return Err(Error::new(
+                    location.clone(),
+                    ErrorKind::InvalidCommandBody(format!(
+                        "`--policy` can't be used with {}",
+                        reason
+                    )),
+                ));
+            }
+        }
+
         let mut out = None;
         if let Some((out_sign_pos, _)) = args.iter().enumerate().find(|(_, s)| s.as_str() == "=>") {
             if let Some(out_addr) = args.get(out_sign_pos + 1) {
@@ -164,11 +188,12 @@ impl Set {
             target,
             source,
             out,
+            policy: policy.unwrap_or_default(),
         }))
     }
     pub fn execute_loc(
         &self,
-        entity_db: &mut Storage,
+        entity_db: &mut ScopedStorage,
         ent_uid: &EntityId,
         comp_state: &mut StringId,
         comp_name: &CompName,
@@ -187,10 +212,28 @@ impl Set {
 
         match &self.source {
             Source::LocalAddress(loc_addr) => {
+                let source_comp = loc_addr.comp.clone().unwrap_or_else(|| comp_name.clone());
+                if let Target::Address(addr) = &self.target {
+                    let value = match entity_db.get_var(&loc_addr.storage_index_using(source_comp))
+                    {
+                        Ok(v) => v.clone(),
+                        Err(e) => {
+                            return CommandResult::Err(Error::new(
+                                location.clone(),
+                                ErrorKind::CoreError(e.to_string()),
+                            ))
+                        }
+                    };
+                    return CommandResult::ExecExt(ExtCommand::Write(ExtWrite {
+                        target: addr.clone(),
+                        value,
+                        policy: self.policy,
+                    }));
+                }
                 // entity_db.set_from_addr(&self.target, &addr)
 
                 *entity_db.get_var_mut(&target_addr.storage_index()).unwrap() = entity_db
-                    .get_var(&loc_addr.storage_index_using(comp_name.clone()))
+                    .get_var(&loc_addr.storage_index_using(source_comp))
                     .unwrap()
                     .clone();
             }
@@ -213,6 +256,13 @@ impl Set {
                 }))
             }
             Source::Value(val) => {
+                if let Target::Address(addr) = &self.target {
+                    return CommandResult::ExecExt(ExtCommand::Write(ExtWrite {
+                        target: addr.clone(),
+                        value: val.clone(),
+                        policy: self.policy,
+                    }));
+                }
                 if let Ok(target_var) = entity_db.get_var_mut(&target_addr.storage_index()) {
                     *target_var = val.clone();
                 } else {
@@ -223,3 +273,141 @@ impl Set {
         CommandResult::Continue
     }
 }
+
+/// Adds a value to the target var.
+///
+/// Targets on other entities are written to using the `sum` merge policy,
+/// so that additions made by multiple entities within the same step all
+/// count.
+///
+/// ```text
+/// add float:energy 2.5
+/// add world:stats:int:harvested int:carried
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Add {
+    pub target: Target,
+    pub source: Source,
+}
+
+impl Add {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Command> {
+        if args.len() != 2 {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "expected two arguments: target address and value".to_string(),
+                ),
+            ));
+        }
+        let target = Target::from_str(&args[0], location)?;
+        let source = Source::from_str(&args[1], target.var_type(), location)?;
+        if let Source::Address(_) = source {
+            return Err(Error::new(
+                location.clone(),
+                ErrorKind::InvalidCommandBody(
+                    "value can't be read from another entity".to_string(),
+                ),
+            ));
+        }
+        Ok(Command::Add(Add { target, source }))
+    }
+
+    pub fn execute_loc(
+        &self,
+        storage: &mut ScopedStorage,
+        comp_name: &CompName,
+        location: &LocationInfo,
+    ) -> CommandResult {
+        let value = match &self.source {
+            Source::Value(v) => v.clone(),
+            Source::LocalAddress(addr) => {
+                let comp = addr.comp.clone().unwrap_or_else(|| comp_name.clone());
+                match storage.get_var(&addr.storage_index_using(comp)) {
+                    Ok(v) => v.clone(),
+                    Err(e) => {
+                        return CommandResult::Err(Error::new(
+                            location.clone(),
+                            ErrorKind::CoreError(e.to_string()),
+                        ))
+                    }
+                }
+            }
+            Source::Address(_) => return CommandResult::Continue,
+        };
+        match &self.target {
+            Target::Address(addr) => CommandResult::ExecExt(ExtCommand::Write(ExtWrite {
+                target: addr.clone(),
+                value,
+                policy: MergePolicy::Sum,
+            })),
+            Target::LocalAddress(addr) => {
+                let comp = addr.comp.clone().unwrap_or_else(|| comp_name.clone());
+                match storage
+                    .get_var_mut(&addr.storage_index_using(comp))
+                    .and_then(|target| add_var(target, &value))
+                {
+                    Ok(()) => CommandResult::Continue,
+                    Err(e) => CommandResult::Err(Error::new(
+                        location.clone(),
+                        ErrorKind::CoreError(e.to_string()),
+                    )),
+                }
+            }
+        }
+    }
+}
+
+/// Removes the `--policy` option along with its value from the arguments,
+/// returning the parsed policy if the option is present.
+fn take_policy(args: &mut Vec<String>, location: &LocationInfo) -> Result<Option<MergePolicy>> {
+    let pos = match args.iter().position(|a| a == "--policy" || a == "-p") {
+        Some(pos) => pos,
+        None => return Ok(None),
+    };
+    args.remove(pos);
+    if pos >= args.len() {
+        return Err(Error::new(
+            location.clone(),
+            ErrorKind::InvalidCommandBody("missing value for `--policy`".to_string()),
+        ));
+    }
+    Ok(Some(MergePolicy::from_str(&args.remove(pos))?))
+}
+
+#[cfg(test)]
+fn run_set(args: &str, storage: &mut ScopedStorage) -> CommandResult {
+    let location = LocationInfo::empty();
+    let args = args.split_whitespace().map(|a| a.to_string()).collect();
+    match Set::new(args, &location).unwrap() {
+        Command::Set(set) => set.execute_loc(
+            storage,
+            &0,
+            &mut string::new_truncate("idle"),
+            &string::new_truncate("comp"),
+            &location,
+        ),
+        cmd => panic!("unexpected command: {:?}", cmd),
+    }
+}
+
+#[test]
+fn set_reads_qualified_local_source() {
+    let var = |comp: &str, name: &str| (string::new_truncate(comp), string::new_truncate(name));
+    let mut storage = crate::entity::Storage::default();
+    let mut scopes = crate::machine::ScopeStackVec::new();
+    storage.insert(var("comp", "x"), Var::Int(0));
+    storage.insert(var("comp", "y"), Var::Int(1));
+    storage.insert(var("other", "y"), Var::Int(5));
+    let mut storage = ScopedStorage::new(&mut storage, &mut scopes);
+
+    // writing to another entity
+    match run_set("e:c:int:x other:int:y", &mut storage) {
+        CommandResult::ExecExt(ExtCommand::Write(write)) => assert_eq!(write.value, Var::Int(5)),
+        result => panic!("unexpected result: {:?}", result),
+    }
+
+    // writing locally
+    run_set("int:x other:int:y", &mut storage);
+    assert_eq!(storage.get_var(&var("comp", "x")).unwrap(), &Var::Int(5));
+}
@@ -12,7 +12,8 @@ impl SimControl {
     pub fn get_type() -> String {
         return "sim".to_string();
     }
-    pub fn new(args: Vec<String>) -> Result<Command, Error> {
+    pub fn new(args: Vec<String>, location: &LocationInfo) -> Result<Command, Error> {
+        super::expect_args(&args, 1, location)?;
         Ok(Command::Sim(SimControl { args }))
     }
 }
@@ -29,7 +30,12 @@ impl SimControl {
 
     pub fn execute_ext(&self, sim: &mut Sim) -> Result<(), Error> {
         match self.args[0].as_str() {
-            "apply_model" => sim.apply_model().unwrap(),
+            "apply_model" => {
+                // bring existing entities up to date with runtime changes
+                // made to the model
+                let model = sim.model.clone();
+                sim.apply_model(model).unwrap();
+            }
             _ => (),
         }
         Ok(())
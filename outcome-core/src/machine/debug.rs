@@ -0,0 +1,311 @@
+//! Source-level debugging of component logic.
+//!
+//! [`Debugger`] holds a set of breakpoints, each pointing either at a line
+//! within a source file or at a selected state of a component, optionally
+//! restricted to a single entity.
+//!
+//! With a debugger attached, simulation step executes entities one at a
+//! time instead of in parallel, and can be paused before any of the
+//! commands. While paused, the entity's storage, the local vars, the call
+//! stack and the registry of the paused execution can be inspected, and
+//! execution can be continued either command by command or until the next
+//! breakpoint.
+//! See the debugging functions of [`Sim`] for the API.
+//!
+//! [`Sim`]: ../../sim/struct.Sim.html
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use fnv::FnvHashMap;
+
+use crate::entity::{Entity, StorageIndex};
+use crate::error::Error;
+use crate::{string, CompName, EntityId, EntityName, Result, StringId, Var};
+
+use super::cmd::{CentralRemoteCommand, ExtCommand};
+use super::{CallInfo, CallStackVec, ExecutionContext, LocationInfo, Registry, ScopeStackVec};
+
+pub type BreakpointId = u32;
+
+/// Place where the execution is paused.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum BreakpointLocation {
+    /// Line within a source file. Source path is matched against the end
+    /// of the path relative to the project root, so it's enough to
+    /// provide just the file name as long as it's unique.
+    Line { source: String, line: usize },
+    /// Start of the component state execution.
+    State { comp: CompName, state: StringId },
+}
+
+impl FromStr for BreakpointLocation {
+    type Err = Error;
+
+    /// Parses the location from either the `source:line` or the
+    /// `comp:state` format.
+    fn from_str(s: &str) -> Result<Self> {
+        let split = s.rsplitn(2, ':').collect::<Vec<&str>>();
+        if split.len() != 2 || split[0].is_empty() || split[1].is_empty() {
+            return Err(Error::Other(format!(
+                "invalid breakpoint location: {}, expected source:line or comp:state",
+                s
+            )));
+        }
+        match split[0].parse::<usize>() {
+            Ok(line) => Ok(BreakpointLocation::Line {
+                source: split[1].to_string(),
+                line,
+            }),
+            Err(_) => Ok(BreakpointLocation::State {
+                comp: string::new_truncate(split[1]),
+                state: string::new_truncate(split[0]),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for BreakpointLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakpointLocation::Line { source, line } => write!(f, "{}:{}", source, line),
+            BreakpointLocation::State { comp, state } => write!(f, "{}:{}", comp, state),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Breakpoint {
+    pub id: BreakpointId,
+    pub location: BreakpointLocation,
+    /// Only break when executing logic of this entity
+    pub entity: Option<EntityId>,
+}
+
+impl Breakpoint {
+    fn matches(&self, frame: &Frame, location: &LocationInfo) -> bool {
+        if let Some(entity) = self.entity {
+            if entity != frame.ent {
+                return false;
+            }
+        }
+        match &self.location {
+            BreakpointLocation::Line { source, line } => {
+                location.source_line == Some(*line)
+                    && location
+                        .source
+                        .as_ref()
+                        .map(|s| Path::new(s.as_str()).ends_with(source))
+                        .unwrap_or(false)
+            }
+            BreakpointLocation::State { comp, state } => {
+                frame.executed == 0 && &frame.comp == comp && &frame.state == state
+            }
+        }
+    }
+}
+
+/// Reason for pausing the execution.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PauseReason {
+    Breakpoint(BreakpointId),
+    /// Paused after stepping a single command
+    Step,
+}
+
+/// Information about the paused execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseInfo {
+    pub reason: PauseReason,
+    pub entity: EntityId,
+    pub entity_name: Option<EntityName>,
+    pub comp: CompName,
+    /// Current state of the component
+    pub state: StringId,
+    /// Index of the command to be executed next
+    pub cmd_index: usize,
+    /// Location of the command to be executed next
+    pub location: LocationInfo,
+}
+
+impl fmt::Display for PauseInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            PauseReason::Breakpoint(id) => write!(f, "paused at breakpoint {}", id)?,
+            PauseReason::Step => write!(f, "paused")?,
+        }
+        match &self.entity_name {
+            Some(name) => write!(f, ": entity {} ({})", name, self.entity)?,
+            None => write!(f, ": entity {}", self.entity)?,
+        }
+        write!(
+            f,
+            ", component {}, state {} ({})",
+            self.comp,
+            self.state,
+            self.location.to_string()
+        )
+    }
+}
+
+/// Values available for inspection while paused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Locals {
+    /// All vars of the paused entity, sorted by component and var name
+    pub vars: Vec<(StorageIndex, Var)>,
+    /// Local vars of the innermost procedure call, sorted the same way
+    pub local_vars: Vec<(StorageIndex, Var)>,
+    pub call_stack: Vec<CallInfo>,
+    pub registry: Registry,
+}
+
+/// Result of running the simulation with a debugger attached.
+#[derive(Debug, Clone)]
+pub enum DebugStatus {
+    /// Execution was paused before finishing the step
+    Paused(PauseInfo),
+    /// Step was finished
+    Finished,
+}
+
+/// Describes how far the execution should proceed before pausing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RunMode {
+    /// Run until a breakpoint is hit or the step is finished
+    Continue,
+    /// Execute a single command
+    StepCmd,
+    /// Finish the step ignoring breakpoints
+    Finish,
+}
+
+/// Component logic execution that can be paused and resumed.
+pub(crate) struct Frame {
+    pub ent: EntityId,
+    pub comp: CompName,
+    /// State the execution was started in
+    pub state: StringId,
+    /// Index of the command to be executed next
+    pub cmd_n: usize,
+    pub end: Option<usize>,
+    /// Number of commands executed so far
+    pub executed: usize,
+    pub call_stack: CallStackVec,
+    pub scopes: ScopeStackVec,
+    pub registry: Registry,
+}
+
+/// Progress of a step that's being processed with the debugger attached.
+pub(crate) struct StepProgress {
+    /// Components left to execute, along with their entities
+    pub tasks: VecDeque<(EntityId, CompName)>,
+    pub frame: Option<Frame>,
+    pub ext_cmds: Arc<Mutex<Vec<(ExecutionContext, ExtCommand)>>>,
+    pub central_ext_cmds: Arc<Mutex<Vec<(ExecutionContext, CentralRemoteCommand)>>>,
+}
+
+impl StepProgress {
+    /// Collects components to be executed for the given events, following
+    /// the same order as regular step processing for each of the entities.
+    pub fn new(event_queue: &[StringId], entities: &FnvHashMap<EntityId, Entity>) -> Self {
+        let mut ids = entities.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        let mut tasks = VecDeque::new();
+        for id in ids {
+            let entity = &entities[&id];
+            for event in event_queue {
+                match entity.comp_queue.get(event) {
+                    Some(comps) => tasks.extend(comps.iter().map(|c| (id, c.clone()))),
+                    None => break,
+                }
+            }
+        }
+        StepProgress {
+            tasks,
+            frame: None,
+            ext_cmds: Arc::new(Mutex::new(Vec::new())),
+            central_ext_cmds: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+/// Debugger for the logic executed within a local simulation.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_id: BreakpointId,
+    /// Step currently being processed, if any
+    pub(crate) progress: Option<StepProgress>,
+    pub(crate) paused: Option<PauseInfo>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    /// Adds a new breakpoint, optionally restricted to a single entity.
+    pub fn add_breakpoint(
+        &mut self,
+        location: BreakpointLocation,
+        entity: Option<EntityId>,
+    ) -> BreakpointId {
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint {
+            id: self.next_id,
+            location,
+            entity,
+        });
+        self.next_id
+    }
+
+    /// Removes the breakpoint, returning `false` if it doesn't exist.
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.breakpoints.len() != len
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Gets information about the paused execution, if paused.
+    pub fn paused(&self) -> Option<&PauseInfo> {
+        self.paused.as_ref()
+    }
+
+    /// Finds the breakpoint hit when about to execute the command at the
+    /// given location.
+    pub(crate) fn hit(&self, frame: &Frame, location: &LocationInfo) -> Option<BreakpointId> {
+        self.breakpoints
+            .iter()
+            .find(|b| b.matches(frame, location))
+            .map(|b| b.id)
+    }
+}
+
+#[test]
+fn breakpoint_location_from_str() {
+    match "mods/core/guard.outcome:12"
+        .parse::<BreakpointLocation>()
+        .unwrap()
+    {
+        BreakpointLocation::Line { source, line } => {
+            assert_eq!(source, "mods/core/guard.outcome");
+            assert_eq!(line, 12);
+        }
+        l => panic!("unexpected location: {:?}", l),
+    }
+    match "guard:patrol".parse::<BreakpointLocation>().unwrap() {
+        BreakpointLocation::State { comp, state } => {
+            assert_eq!(comp.as_str(), "guard");
+            assert_eq!(state.as_str(), "patrol");
+        }
+        l => panic!("unexpected location: {:?}", l),
+    }
+    assert!("guard".parse::<BreakpointLocation>().is_err());
+}
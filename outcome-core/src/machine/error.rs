@@ -18,6 +18,55 @@ impl Error {
     pub fn new(location: LocationInfo, kind: ErrorKind) -> Self {
         Self { location, kind }
     }
+
+    pub fn location(&self) -> &LocationInfo {
+        &self.location
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Gets the error message without the location information.
+    ///
+    /// Unlike the `Display` implementation this never reads the source
+    /// file, which makes it usable for scripts that only exist in memory.
+    pub fn message(&self) -> String {
+        match &self.kind {
+            ErrorKind::ErrorReadingFile(file) => format!("error reading file: {}", file),
+            ErrorKind::Initialization(msg) => msg.to_string(),
+            ErrorKind::ControlWithoutValidValue => {
+                "control character found without a valid value".to_string()
+            }
+            ErrorKind::InvalidControlLocation => "invalid control character location".to_string(),
+            ErrorKind::MissingEndQuotes => "missing end quotes".to_string(),
+            ErrorKind::MissingOutputVariableName => "missing variable name".to_string(),
+            ErrorKind::InvalidEqualsLocation => "invalid equals sign location".to_string(),
+            ErrorKind::InvalidQuotesLocation => "invalid quotes location".to_string(),
+            ErrorKind::EmptyTag => "empty tag".to_string(),
+            ErrorKind::NoDirectivePresent => "no directive present".to_string(),
+            ErrorKind::UnknownDirective => "unknown directive".to_string(),
+            ErrorKind::ErrorProcessingDirective(msg) => msg.to_string(),
+            ErrorKind::NoCommandPresent => "no command present".to_string(),
+            ErrorKind::UnknownCommand(name) => format!("unknown command: {}", name),
+            ErrorKind::InvalidCommandBody(msg) => {
+                format!("failed initializing command: {}", msg)
+            }
+            ErrorKind::FailedGettingFromStorage(addr) => {
+                format!("failed getting variable from storage: {}", addr)
+            }
+            ErrorKind::FailedGettingComponent(addr) => {
+                format!("failed getting component: {}", addr)
+            }
+            ErrorKind::CommandSearchFailed(msg) => format!("command search failed: {}", msg),
+            ErrorKind::InvalidAddress(msg) => format!("invalid address: {}", msg),
+            ErrorKind::CoreError(msg) => format!("core error: {}", msg),
+            ErrorKind::ParseError(msg) => format!("parse error: {}", msg),
+            ErrorKind::Panic => "panic".to_string(),
+            ErrorKind::StackEmpty => "stack empty".to_string(),
+            ErrorKind::Other(msg) => format!("other error: {}", msg),
+        }
+    }
 }
 
 impl std::error::Error for Error {}
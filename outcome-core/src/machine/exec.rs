@@ -8,7 +8,10 @@ use crate::{Address, CompName, EntityId, EntityName, StringId};
 use crate::{Sim, SimModel};
 
 use super::cmd::{CentralRemoteCommand, Command, CommandResult, ExtCommand};
-use super::{error::Error, CallStackVec, ExecutionContext, LocationInfo, Registry};
+use super::{
+    error::Error, CallStackVec, CommandResultVec, ExecutionContext, LocationInfo, Registry,
+    ScopeStackVec,
+};
 
 use crate::machine::{ErrorKind, Result};
 
@@ -30,12 +33,16 @@ pub(crate) fn execute_central_ext(
     Ok(())
 }
 /// Executes a given set of external commands.
+///
+/// Commands are executed in the order of the ids of the entities that
+/// issued them, see [`sort_ext`].
 //TODO missing component uid information
 pub(crate) fn execute_ext(
-    ext_cmds: &Vec<(ExecutionContext, ExtCommand)>,
+    ext_cmds: &mut Vec<(ExecutionContext, ExtCommand)>,
     sim: &mut Sim,
 ) -> Result<()> {
-    for (exec_ctx, ext_cmd) in ext_cmds {
+    sort_ext(ext_cmds);
+    for (exec_ctx, ext_cmd) in ext_cmds.iter() {
         if let Err(e) = ext_cmd.execute(sim, &exec_ctx.ent, &exec_ctx.comp, &exec_ctx.location) {
             error!("{}", e);
         }
@@ -43,6 +50,16 @@ pub(crate) fn execute_ext(
     Ok(())
 }
 
+/// Sorts external commands by the id of the issuing entity.
+///
+/// Entities are processed in parallel, so commands are collected in no
+/// particular order. Sorting is stable, keeping the commands issued by
+/// a single entity in the order they were issued, which makes the result
+/// of executing them deterministic.
+pub(crate) fn sort_ext(ext_cmds: &mut Vec<(ExecutionContext, ExtCommand)>) {
+    ext_cmds.sort_by_key(|(exec_ctx, _)| exec_ctx.ent);
+}
+
 /// Executes a given set of commands within a local entity scope.
 ///
 /// Most of the errors occurring during execution of commands are non-breaking.
@@ -87,12 +104,13 @@ pub(crate) fn execute_loc(
 
     // initialize a new call stack
     let mut call_stack = CallStackVec::new();
+    let mut scopes = ScopeStackVec::new();
     let mut registry = Registry::new();
     let mut cmd_n = match start {
         Some(s) => s,
         None => 0,
     };
-    'outer: loop {
+    loop {
         if cmd_n >= cmds.len() {
             break;
         }
@@ -117,6 +135,7 @@ pub(crate) fn execute_loc(
             &mut ent_insta,
             &mut comp_state,
             &mut call_stack,
+            &mut scopes,
             &mut registry,
             comp_uid,
             ent_uid,
@@ -125,56 +144,92 @@ pub(crate) fn execute_loc(
             #[cfg(feature = "machine_dynlib")]
             libs,
         );
-        for result in results {
-            match result {
-                CommandResult::Continue => (),
-                CommandResult::Break => break 'outer,
-                CommandResult::JumpToLine(n) => {
-                    cmd_n = n;
-                    continue 'outer;
-                }
-                CommandResult::JumpToTag(t) => {
-                    if let Some((line, _)) = locations
-                        .iter()
-                        .enumerate()
-                        .find(|(_, l)| l.tag == Some(t.clone()))
-                    {
-                        cmd_n = line;
-                        continue 'outer;
-                    }
-                }
-                CommandResult::ExecExt(ext_cmd) => {
-                    // push external command to an aggregate vec
-                    ext_cmds.lock().unwrap().push((
-                        ExecutionContext {
-                            ent: *ent_uid,
-                            comp: comp_uid.clone(),
-                            location: location_info.clone(),
-                        },
-                        ext_cmd,
-                    ));
-                }
-                CommandResult::ExecCentralExt(cext_cmd) => {
-                    // push central external command to an aggregate vec
-                    central_ext_cmds.lock().unwrap().push((
-                        ExecutionContext {
-                            ent: *ent_uid,
-                            comp: comp_uid.clone(),
-                            location: location_info.clone(),
-                        },
-                        cext_cmd,
-                    ));
-                }
-                CommandResult::Err(e) => {
-                    //TODO implement configurable system for deciding whether to
-                    // break state, panic or just print when given error occurs
-                    error!("{}", e);
+        match handle_loc_results(
+            results,
+            locations,
+            location_info,
+            ent_uid,
+            comp_uid,
+            ext_cmds,
+            central_ext_cmds,
+        ) {
+            Flow::Next => cmd_n += 1,
+            Flow::Jump(n) => cmd_n = n,
+            Flow::Break => break,
+        }
+    }
+    Ok(())
+}
+
+/// Describes where execution should go after a single command.
+pub(crate) enum Flow {
+    /// Proceed to the next command
+    Next,
+    /// Jump to the command at the given index
+    Jump(usize),
+    /// Stop executing
+    Break,
+}
+
+/// Handles results of a single command executed within a local entity
+/// scope.
+///
+/// External and central-external commands are pushed to the appropriate
+/// aggregate vecs, while errors are logged. Returns the resulting control
+/// flow.
+pub(crate) fn handle_loc_results(
+    results: CommandResultVec,
+    locations: &Vec<LocationInfo>,
+    location_info: &LocationInfo,
+    ent_uid: &EntityId,
+    comp_uid: &CompName,
+    ext_cmds: &Arc<Mutex<Vec<(ExecutionContext, ExtCommand)>>>,
+    central_ext_cmds: &Arc<Mutex<Vec<(ExecutionContext, CentralRemoteCommand)>>>,
+) -> Flow {
+    for result in results {
+        match result {
+            CommandResult::Continue => (),
+            CommandResult::Break => return Flow::Break,
+            CommandResult::JumpToLine(n) => return Flow::Jump(n),
+            CommandResult::JumpToTag(t) => {
+                if let Some((line, _)) = locations
+                    .iter()
+                    .enumerate()
+                    .find(|(_, l)| l.tag == Some(t.clone()))
+                {
+                    return Flow::Jump(line);
                 }
             }
+            CommandResult::ExecExt(ext_cmd) => {
+                // push external command to an aggregate vec
+                ext_cmds.lock().unwrap().push((
+                    ExecutionContext {
+                        ent: *ent_uid,
+                        comp: comp_uid.clone(),
+                        location: location_info.clone(),
+                    },
+                    ext_cmd,
+                ));
+            }
+            CommandResult::ExecCentralExt(cext_cmd) => {
+                // push central external command to an aggregate vec
+                central_ext_cmds.lock().unwrap().push((
+                    ExecutionContext {
+                        ent: *ent_uid,
+                        comp: comp_uid.clone(),
+                        location: location_info.clone(),
+                    },
+                    cext_cmd,
+                ));
+            }
+            CommandResult::Err(e) => {
+                //TODO implement configurable system for deciding whether to
+                // break state, panic or just print when given error occurs
+                error!("{}", e);
+            }
         }
-        cmd_n += 1;
     }
-    Ok(())
+    Flow::Next
 }
 
 /// Executes given set of commands within global sim scope.
@@ -189,6 +244,7 @@ pub fn execute(
 ) -> Result<()> {
     // initialize a new call stack
     let mut call_stack = CallStackVec::new();
+    let mut scopes = ScopeStackVec::new();
     let mut registry = Registry::new();
 
     let mut empty_locinfo = LocationInfo::empty();
@@ -251,6 +307,7 @@ pub fn execute(
             &mut entity.insta,
             &mut comp_state,
             &mut call_stack,
+            &mut scopes,
             &mut registry,
             comp_uid,
             ent_id,
@@ -285,3 +342,43 @@ pub fn execute(
     }
     Ok(())
 }
+
+#[test]
+fn ext_writes_ordered_by_entity_id() {
+    use super::cmd::get_set::{ExtWrite, MergePolicy};
+    use crate::{Int, Var};
+    use std::str::FromStr;
+
+    let write = |ent: EntityId, value: Int| {
+        (
+            ExecutionContext {
+                ent,
+                comp: Default::default(),
+                location: LocationInfo::empty(),
+            },
+            ExtCommand::Write(ExtWrite {
+                target: Address::from_str("target:stats:int:score").unwrap(),
+                value: Var::Int(value),
+                policy: MergePolicy::LastWriterWins,
+            }),
+        )
+    };
+    let mut ext_cmds = vec![write(3, 30), write(1, 10), write(3, 31), write(2, 20)];
+    sort_ext(&mut ext_cmds);
+
+    let mut target = Var::Int(0);
+    let mut applied = Vec::new();
+    for (_, cmd) in ext_cmds {
+        if let ExtCommand::Write(write) = cmd {
+            applied.push(write.value.clone());
+            write.policy.merge(&mut target, write.value).unwrap();
+        }
+    }
+    // writes of a single entity keep the order they were issued in
+    assert_eq!(
+        applied,
+        vec![Var::Int(10), Var::Int(20), Var::Int(30), Var::Int(31)]
+    );
+    // writer with the highest entity id wins
+    assert_eq!(target, Var::Int(31));
+}
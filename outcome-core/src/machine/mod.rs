@@ -1,6 +1,7 @@
 //! Logic execution capability for the runtime.
 
 pub mod cmd;
+pub mod debug;
 pub mod error;
 pub mod exec;
 pub mod script;
@@ -8,15 +9,21 @@ pub mod script;
 pub use error::{Error, ErrorKind, Result};
 
 use arrayvec::ArrayVec;
+use fnv::FnvHashMap;
 use smallvec::SmallVec;
 
 use crate::address::LocalAddress;
-use crate::entity::StorageIndex;
-use crate::{CompName, EntityId, EntityName, LongString, ShortString, StringId, VarType};
+use crate::entity::{Storage, StorageIndex};
+use crate::{CompName, EntityId, EntityName, LongString, ShortString, StringId, Var, VarType};
 use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
 
 pub const START_STATE_NAME: &'static str = "start";
 
+/// Maximum number of procedure calls that can be nested within one
+/// another, including recursive calls.
+pub const MAX_PROCEDURE_DEPTH: usize = 16;
+
 #[cfg(feature = "machine_dynlib")]
 pub type Libraries = BTreeMap<String, Library>;
 #[cfg(feature = "machine_dynlib")]
@@ -69,7 +76,7 @@ impl LocationInfo {
 
 /// Command in it's simplest form, ready to be turned into a more concrete
 /// representation.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct CommandPrototype {
     /// Command name
     pub name: Option<String>,
@@ -125,7 +132,6 @@ pub enum RegistryTarget {
 
 /// Information about a single call.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(feature = "stack_stringid", derive(Copy))]
 pub enum CallInfo {
     Procedure(ProcedureCallInfo),
     ForIn(ForInCallInfo),
@@ -135,13 +141,82 @@ pub enum CallInfo {
     Component(ComponentCallInfo),
 }
 
+/// Vars local to a single procedure call, including its parameters.
+pub type LocalScope = FnvHashMap<StorageIndex, Var>;
+
+/// Local scopes of the procedure calls that are currently in progress, the
+/// innermost call's scope being the last one. Kept by the executor next to
+/// the call stack.
+pub(crate) type ScopeStackVec = ArrayVec<[LocalScope; MAX_PROCEDURE_DEPTH]>;
+
+/// Entity storage as seen by commands executed within a single component.
+///
+/// Vars within the local scope of the innermost procedure call shadow the
+/// entity's own vars with the same index. Storage is otherwise accessible
+/// through deref.
+pub struct ScopedStorage<'a> {
+    pub storage: &'a mut Storage,
+    pub scopes: &'a mut ScopeStackVec,
+}
+
+impl<'a> ScopedStorage<'a> {
+    pub fn new(storage: &'a mut Storage, scopes: &'a mut ScopeStackVec) -> Self {
+        ScopedStorage { storage, scopes }
+    }
+
+    pub fn get_var(&self, idx: &StorageIndex) -> crate::error::Result<&Var> {
+        match self.scopes.last().and_then(|locals| locals.get(idx)) {
+            Some(var) => Ok(var),
+            None => self.storage.get_var(idx),
+        }
+    }
+
+    pub fn get_var_mut(&mut self, idx: &StorageIndex) -> crate::error::Result<&mut Var> {
+        match self
+            .scopes
+            .last_mut()
+            .and_then(|locals| locals.get_mut(idx))
+        {
+            Some(var) => Ok(var),
+            None => self.storage.get_var_mut(idx),
+        }
+    }
+
+    pub fn insert(&mut self, idx: StorageIndex, var: Var) {
+        match self
+            .scopes
+            .last_mut()
+            .and_then(|locals| locals.get_mut(&idx))
+        {
+            Some(local) => *local = var,
+            None => self.storage.insert(idx, var),
+        }
+    }
+}
+
+impl<'a> Deref for ScopedStorage<'a> {
+    type Target = Storage;
+    fn deref(&self) -> &Storage {
+        self.storage
+    }
+}
+
+impl<'a> DerefMut for ScopedStorage<'a> {
+    fn deref_mut(&mut self) -> &mut Storage {
+        self.storage
+    }
+}
+
 /// Information about a single procedure call.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProcedureCallInfo {
     pub call_line: usize,
     pub start_line: usize,
     pub end_line: usize,
-    // pub output_variable: Option<String>,
+    /// Var the returned value is written to once the call is finished
+    pub output: Option<StorageIndex>,
+    /// Type of the value returned by the procedure
+    pub returns: Option<VarType>,
 }
 
 /// Information about a single forin call.
@@ -153,6 +228,8 @@ pub struct ForInCallInfo {
     pub target_len: usize,
     /// Variable to update while iterating
     pub variable: Option<LocalAddress>,
+    /// Whether the entity's inbox is iterated over instead of the target
+    pub inbox: bool,
     // pub variable_type: Option<VarType>,
     /// Current iteration
     pub iteration: usize,
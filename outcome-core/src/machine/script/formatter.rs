@@ -0,0 +1,289 @@
+//! Formatter turns scripts into their canonical form.
+//!
+//! Formatting keeps comments and directives in place, while normalizing
+//! the indentation and the spacing between command arguments:
+//!
+//! - lines inside `component`, `state`, `if`, `for`, `loop` and `proc`
+//!   blocks are indented with a single tab per level, with `else` lines
+//!   aligned to the opening line of the block
+//! - arguments are separated with single spaces, quoted arguments are left
+//!   untouched
+//! - multiple commands on a single line are separated with `; `
+//! - continuation lines of multiline commands are indented one level
+//!   deeper than the first line, with spacing normalized on each of the
+//!   lines and the multiline symbol kept at the end
+//! - runs of empty lines are collapsed into a single one
+//!
+//! Each formatted line is parsed again and compared against the original,
+//! so that formatting never changes the meaning of a script.
+
+use crate::machine::cmd::flow;
+use crate::machine::error::{Error, ErrorKind, Result};
+use crate::machine::LocationInfo;
+
+use super::{parser, Instruction, InstructionType};
+
+static INDENT: &str = "\t";
+static COMMENT_SYMBOL: char = '#';
+static MULTILINE_SYMBOL: char = '\\';
+static END_LINE_SYMBOL: char = ';';
+
+/// Formats the script, returning the canonical version of the text.
+///
+/// Scripts that can't be parsed are not formatted, returning the parsing
+/// error instead.
+pub fn format(text: &str) -> Result<String> {
+    let mut out = String::new();
+    let mut level = 0;
+    let mut empty_line = false;
+
+    let lines = text.lines().collect::<Vec<&str>>();
+    let mut n = 0;
+    while n < lines.len() {
+        let mut location = LocationInfo::empty();
+        location.source_line = Some(n + 1);
+
+        // gather all the lines making up the statement
+        let mut statement = vec![lines[n].trim()];
+        while statement.last().unwrap().ends_with(MULTILINE_SYMBOL) && n + 1 < lines.len() {
+            n += 1;
+            statement.push(lines[n].trim());
+        }
+        n += 1;
+
+        if statement[0].is_empty() {
+            empty_line = !out.is_empty();
+            continue;
+        }
+        if empty_line {
+            out.push('\n');
+            empty_line = false;
+        }
+        if statement[0].starts_with(COMMENT_SYMBOL) {
+            push_line(&mut out, level, statement[0]);
+            continue;
+        }
+
+        let original = parse(&statement.join("\n"), &location)?;
+        let formatted = statement
+            .iter()
+            .map(|line| format_statement_line(line))
+            .collect::<Vec<_>>();
+        if parse(&formatted.join("\n"), &location)? != original {
+            return Err(Error::new(
+                location,
+                ErrorKind::Other("formatting would change the meaning of the line".to_string()),
+            ));
+        }
+
+        let mut line_level = level;
+        for (i, (instruction, _)) in original.iter().enumerate() {
+            let prototype = match instruction {
+                InstructionType::Command(proto) => proto,
+                _ => continue,
+            };
+            let name = prototype.name.as_deref().unwrap_or("");
+            let args = prototype.arguments.as_deref().unwrap_or(&[]);
+            if flow::end::COMMAND_NAMES.contains(&name) {
+                level = level.saturating_sub(1);
+                if i == 0 {
+                    line_level = level;
+                }
+            } else if flow::ifelse::ELSE_COMMAND_NAMES.contains(&name) {
+                if i == 0 {
+                    line_level = level.saturating_sub(1);
+                }
+            } else if is_block_start(name, args) {
+                level += 1;
+            }
+        }
+
+        for (i, line) in formatted.iter().enumerate() {
+            push_line(&mut out, line_level + i.min(1), line);
+        }
+    }
+    Ok(out)
+}
+
+/// Parses the statement, returning the parts of the instructions that
+/// define its meaning.
+fn parse(
+    statement: &str,
+    location: &LocationInfo,
+) -> Result<Vec<(InstructionType, Option<crate::StringId>)>> {
+    let instructions = parser::parse_lines(statement, location.clone()).map_err(|e| {
+        // parsed statement always starts at the first line
+        let mut error_location = e.location().clone();
+        error_location.source_line = location.source_line;
+        Error::new(error_location, e.kind().clone())
+    })?;
+    Ok(instructions
+        .into_iter()
+        .map(|i: Instruction| (i.type_, i.location.tag))
+        .collect())
+}
+
+fn is_block_start(name: &str, args: &[String]) -> bool {
+    match name {
+        // components declared with the marker flag don't have a body
+        "component" | "comp" => !args.iter().any(|a| a == "--marker"),
+        _ => {
+            flow::state::COMMAND_NAMES.contains(&name)
+                || flow::ifelse::IF_COMMAND_NAMES.contains(&name)
+                || flow::forin::COMMAND_NAMES.contains(&name)
+                || flow::_loop::LOOP_COMMAND_NAMES.contains(&name)
+                || flow::procedure::COMMAND_NAMES.contains(&name)
+        }
+    }
+}
+
+fn push_line(out: &mut String, level: usize, line: &str) {
+    for _ in 0..level {
+        out.push_str(INDENT);
+    }
+    out.push_str(line);
+    out.push('\n');
+}
+
+/// Normalizes spacing within a line of a possibly multiline statement.
+///
+/// Multiline symbol stays attached to the last argument if it was attached
+/// in the original line, as the continuation line is then concatenated to
+/// that argument.
+fn format_statement_line(line: &str) -> String {
+    let code = match line.strip_suffix(MULTILINE_SYMBOL) {
+        Some(code) => code,
+        None => return format_line(line),
+    };
+    let mut out = format_line(code).trim_end().to_string();
+    if !out.is_empty() && code.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+    out.push(MULTILINE_SYMBOL);
+    out
+}
+
+/// Normalizes spacing within a single line, keeping the trailing comment.
+fn format_line(line: &str) -> String {
+    let (code, comment) = split_comment(line);
+    let mut out = code
+        .split(END_LINE_SYMBOL)
+        .map(|part| split_arguments(part).join(" "))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(&format!("{} ", END_LINE_SYMBOL));
+    if let Some(comment) = comment {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(comment);
+    }
+    out
+}
+
+/// Splits the line into code and the trailing comment, if any.
+///
+/// Comment starts with a comment symbol at the beginning of a word that's
+/// not inside quotes.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut word_start = true;
+    for (n, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == MULTILINE_SYMBOL && quote != Some('\'') {
+            escaped = true;
+        } else if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if c == COMMENT_SYMBOL && word_start {
+            return (line[..n].trim_end(), Some(&line[n..]));
+        }
+        word_start = c.is_whitespace();
+    }
+    (line, None)
+}
+
+/// Splits the text on whitespace outside of quotes, keeping the quotes and
+/// escape characters within the resulting arguments.
+fn split_arguments(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == MULTILINE_SYMBOL && quote != Some('\'') {
+            escaped = true;
+        } else if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+        } else if c == '"' || c == '\'' {
+            quote = Some(c);
+        } else if c.is_whitespace() {
+            if !arg.is_empty() {
+                args.push(std::mem::take(&mut arg));
+            }
+            continue;
+        }
+        arg.push(c);
+    }
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+    args
+}
+
+#[test]
+fn format_blocks() {
+    let text = "
+component guard
+  var int:health   100 # starting health
+
+
+    state patrol
+if int:health < 10
+goto flee
+      else
+    print \"still  patrolling\"
+end
+    end
+end
+";
+    let expected = "component guard
+\tvar int:health 100 # starting health
+
+\tstate patrol
+\t\tif int:health < 10
+\t\t\tgoto flee
+\t\telse
+\t\t\tprint \"still  patrolling\"
+\t\tend
+\tend
+end
+";
+    assert_eq!(format(text).unwrap(), expected);
+    assert_eq!(format(expected).unwrap(), expected);
+}
+
+#[test]
+fn format_keeps_comments_and_directives() {
+    let text =
+        "#  header comment\n!include   other.outcome\nproc work\n# inner\nprint a ;print b\nend\n";
+    let expected = "#  header comment\n!include other.outcome\nproc work\n\t# inner\n\tprint a; print b\nend\n";
+    assert_eq!(format(text).unwrap(), expected);
+}
+
+#[test]
+fn format_multiline_statements() {
+    let text = "proc work\nset   int:a \\\n   =    1\nprint  \"a\"\\\nb\nend\n";
+    let expected = "proc work\n\tset int:a \\\n\t\t= 1\n\tprint \"a\"\\\n\t\tb\nend\n";
+    assert_eq!(format(text).unwrap(), expected);
+    assert_eq!(format(expected).unwrap(), expected);
+}
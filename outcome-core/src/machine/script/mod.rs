@@ -172,13 +172,14 @@
 //! assemble the model.
 
 pub mod bridge;
+pub mod formatter;
 pub mod parser;
 pub mod preprocessor;
 pub mod util;
 
 pub(crate) use self::parser::parse_script_at;
 
-pub(crate) const SCRIPT_FILE_EXTENSION: &str = ".outcome";
+pub const SCRIPT_FILE_EXTENSION: &str = ".outcome";
 
 use super::{CommandPrototype, LocationInfo};
 
@@ -191,7 +192,7 @@ pub struct Instruction {
 
 /// All the possible kinds of instructions, including `None`
 /// for empty lines.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum InstructionType {
     Directive(DirectivePrototype),
     Command(CommandPrototype),
@@ -199,7 +200,7 @@ pub enum InstructionType {
 }
 
 /// Directive instruction in it's simplest form.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DirectivePrototype {
     /// Directive name
     pub name: Option<String>,
@@ -136,6 +136,38 @@ fn run_includes(instructions: &mut Vec<Instruction>, sim_model: &SimModel) -> Re
     Ok(())
 }
 
+/// Lists files referenced by the include directives found within the given
+/// set of instructions, along with the locations of the directives.
+///
+/// Included file paths are resolved relative to the source of the
+/// including instruction, same as when running the include directives.
+/// Instructions without a known source are resolved relative to the
+/// project root.
+pub fn list_includes(instructions: &[Instruction]) -> Vec<(LocationInfo, PathBuf)> {
+    let mut includes = Vec::new();
+    for instr in instructions {
+        let args = match &instr.type_ {
+            InstructionType::Directive(dp) if dp.name.as_deref() == Some("include") => {
+                match &dp.arguments {
+                    Some(args) => args,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let parent = instr
+            .location
+            .source
+            .as_ref()
+            .and_then(|s| PathBuf::from(s.as_str()).parent().map(|p| p.to_path_buf()))
+            .unwrap_or_default();
+        for arg in args {
+            includes.push((instr.location.clone(), parent.join(arg)));
+        }
+    }
+    includes
+}
+
 /// Processes the conditional directives, removing instructions that are inside
 /// the conditional blocks that are evaluated to false.
 fn run_conditionals(instructions: &mut Vec<Instruction>) -> Result<()> {
@@ -352,3 +384,14 @@ impl Print {
         info!("{}", self.body);
     }
 }
+
+#[test]
+fn list_includes_relative_to_source() {
+    let location = LocationInfo::empty().with_source("/project", "mods/core/mod.outcome");
+    let text = "print hello\n!include guard.outcome";
+    let instructions = super::parser::parse_lines(text, location).unwrap();
+    let includes = list_includes(&instructions);
+    assert_eq!(includes.len(), 1);
+    assert_eq!(includes[0].0.source_line, Some(2));
+    assert_eq!(includes[0].1, PathBuf::from("mods/core/guard.outcome"));
+}
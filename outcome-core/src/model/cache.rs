@@ -0,0 +1,306 @@
+//! Module search paths and the local module cache.
+//!
+//! Apart from the project's own mods directory, modules are looked up in
+//! the following locations, in order:
+//!
+//! 1. directories listed in the `OUTCOME_MOD_PATH` environment variable
+//! 2. directories listed as `mod_paths` in the user config file
+//! 3. the local module cache
+//!
+//! User config file is located at `~/.outcome/config.toml`, unless
+//! a different path is provided with the `OUTCOME_CONFIG` environment
+//! variable.
+//!
+//! # Module cache
+//!
+//! Module cache is a directory shared by all projects on the machine,
+//! located at `~/.outcome/mods` by default. It can be changed with the
+//! `OUTCOME_MOD_CACHE` environment variable or the `mod_cache` config
+//! entry. Each installed module version is kept in a separate
+//! `<name>-<version>` directory, which allows different scenarios to use
+//! different versions of the same module.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+
+use crate::error::Error;
+use crate::model::resolver::{self, Candidate};
+use crate::model::ModuleManifest;
+use crate::{util, Result};
+
+/// Environment variable listing additional module directories.
+pub const MOD_PATH_ENV: &str = "OUTCOME_MOD_PATH";
+/// Environment variable overriding the module cache location.
+pub const MOD_CACHE_ENV: &str = "OUTCOME_MOD_CACHE";
+/// Environment variable overriding the user config file location.
+pub const CONFIG_ENV: &str = "OUTCOME_CONFIG";
+
+/// Name of the per-user directory, located within the home directory.
+pub const USER_DIR_NAME: &str = ".outcome";
+pub const USER_CONFIG_FILE: &str = "config.toml";
+pub const CACHE_DIR_NAME: &str = "mods";
+
+/// User-level configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserConfig {
+    /// Additional directories to search for modules
+    pub mod_paths: Vec<PathBuf>,
+    /// Custom module cache location
+    pub mod_cache: Option<PathBuf>,
+}
+
+impl UserConfig {
+    /// Loads the user config file, returning the default config if the
+    /// file doesn't exist.
+    pub fn load() -> Result<UserConfig> {
+        match config_path() {
+            Some(path) if path.is_file() => util::deser_struct_from_path(path),
+            _ => Ok(UserConfig::default()),
+        }
+    }
+}
+
+/// Gets the path to the per-user directory.
+pub fn user_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(USER_DIR_NAME))
+}
+
+/// Gets the path to the user config file.
+pub fn config_path() -> Option<PathBuf> {
+    match env::var_os(CONFIG_ENV) {
+        Some(path) => Some(PathBuf::from(path)),
+        None => user_dir().map(|dir| dir.join(USER_CONFIG_FILE)),
+    }
+}
+
+/// Gets the path to the module cache directory.
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(MOD_CACHE_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    if let Some(path) = load_config().mod_cache {
+        return Some(path);
+    }
+    user_dir().map(|dir| dir.join(CACHE_DIR_NAME))
+}
+
+/// Gets the list of module directories to search, not including the
+/// project's own mods directory.
+pub fn search_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(paths) = env::var_os(MOD_PATH_ENV) {
+        dirs.extend(env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+    }
+    dirs.extend(load_config().mod_paths);
+    if let Some(cache) = cache_dir() {
+        dirs.push(cache);
+    }
+    dirs
+}
+
+fn load_config() -> UserConfig {
+    UserConfig::load().unwrap_or_else(|e| {
+        warn!("failed reading user config, using defaults: {}", e);
+        UserConfig::default()
+    })
+}
+
+/// Lists modules installed in the cache, sorted by name and version.
+pub fn list_cached() -> Vec<Candidate> {
+    match cache_dir() {
+        Some(dir) => list_cached_in(&dir),
+        None => Vec::new(),
+    }
+}
+
+/// Lists modules installed in the given cache directory, sorted by name
+/// and version.
+pub fn list_cached_in(cache: &Path) -> Vec<Candidate> {
+    let mut cached = resolver::find_candidates(&[cache.to_path_buf()])
+        .into_iter()
+        .flat_map(|(_, versions)| versions)
+        .collect::<Vec<_>>();
+    cached.sort_by(|a, b| {
+        a.manifest
+            .name
+            .cmp(&b.manifest.name)
+            .then(a.version.cmp(&b.version))
+    });
+    cached
+}
+
+/// Checks whether the module name can be safely used as part of the cache
+/// directory path.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(|c| c == '/' || c == '\\') || name.contains("..") {
+        return Err(Error::Other(format!("invalid mod name: \"{}\"", name)));
+    }
+    Ok(())
+}
+
+/// Installs module from the given directory into the cache.
+///
+/// Installing a module version that is already present fails unless
+/// `force` is set, in which case the existing copy is replaced.
+pub fn install(src: &Path, force: bool) -> Result<Candidate> {
+    let cache = cache_dir()
+        .ok_or_else(|| Error::Other("unable to determine module cache location".to_string()))?;
+    install_into(&cache, src, force)
+}
+
+/// Installs module from the given directory into the given cache
+/// directory, see [`install`].
+pub fn install_into(cache: &Path, src: &Path, force: bool) -> Result<Candidate> {
+    let manifest = ModuleManifest::from_dir_at(src.to_path_buf())?;
+    validate_name(&manifest.name)?;
+    let version = Version::parse(&manifest.version)?;
+    let target = cache.join(format!("{}-{}", manifest.name, version));
+    if target.exists() {
+        if !force {
+            return Err(Error::Other(format!(
+                "mod \"{}\" version \"{}\" is already installed at: {}",
+                manifest.name,
+                version,
+                target.to_string_lossy()
+            )));
+        }
+        fs::remove_dir_all(&target)?;
+    }
+    fs::create_dir_all(cache)?;
+    copy_dir(src, &target)?;
+    info!(
+        "installed mod \"{}\" version \"{}\" at: {}",
+        manifest.name,
+        version,
+        target.to_string_lossy()
+    );
+    Ok(Candidate {
+        manifest,
+        version,
+        path: target,
+    })
+}
+
+/// Removes module from the cache.
+///
+/// If version is not provided all installed versions of the module are
+/// removed. Returns the list of removed modules.
+pub fn remove(name: &str, version: Option<&str>) -> Result<Vec<Candidate>> {
+    let cache = cache_dir()
+        .ok_or_else(|| Error::Other("unable to determine module cache location".to_string()))?;
+    remove_from(&cache, name, version)
+}
+
+/// Removes module from the given cache directory, see [`remove`].
+pub fn remove_from(cache: &Path, name: &str, version: Option<&str>) -> Result<Vec<Candidate>> {
+    validate_name(name)?;
+    let version = match version {
+        Some(v) => Some(Version::parse(v)?),
+        None => None,
+    };
+    let removed = list_cached_in(cache)
+        .into_iter()
+        .filter(|c| c.manifest.name == name)
+        .filter(|c| version.as_ref().map_or(true, |v| &c.version == v))
+        .collect::<Vec<_>>();
+    if removed.is_empty() {
+        return Err(Error::Other(format!(
+            "mod \"{}\"{} is not installed",
+            name,
+            version
+                .map(|v| format!(" version \"{}\"", v))
+                .unwrap_or_default()
+        )));
+    }
+    for module in &removed {
+        fs::remove_dir_all(&module.path)?;
+    }
+    Ok(removed)
+}
+
+/// Recursively copies directory contents.
+fn copy_dir(src: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = dst.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn write_mod(dir: &Path, name: &str, version: &str) -> PathBuf {
+    let path = dir.join(format!("src-{}-{}", name.replace('/', "_"), version));
+    fs::create_dir_all(path.join("data")).unwrap();
+    fs::write(
+        path.join(crate::MODULE_MANIFEST_FILE),
+        format!(
+            "[mod]\nname = \"{}\"\nversion = \"{}\"\nengine = \"*\"\n",
+            name, version
+        ),
+    )
+    .unwrap();
+    fs::write(path.join("data").join("values.txt"), "1").unwrap();
+    path
+}
+
+#[test]
+fn cache_install_and_remove() {
+    let dir = env::temp_dir().join(format!("outcome-cache-{}", std::process::id()));
+    let cache = dir.join("cache");
+    let first = write_mod(&dir, "weather", "0.1.0");
+    let second = write_mod(&dir, "weather", "0.2.0");
+
+    let installed = install_into(&cache, &first, false).unwrap();
+    assert_eq!(installed.path, cache.join("weather-0.1.0"));
+    assert!(installed.path.join("data").join("values.txt").is_file());
+    install_into(&cache, &second, false).unwrap();
+    // same version can only be installed again when forced
+    assert!(install_into(&cache, &first, false).is_err());
+    assert!(install_into(&cache, &first, true).is_ok());
+    let versions = list_cached_in(&cache)
+        .iter()
+        .map(|c| c.version.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(versions, vec!["0.1.0", "0.2.0"]);
+
+    let removed = remove_from(&cache, "weather", Some("0.1.0")).unwrap();
+    assert_eq!(removed.len(), 1);
+    assert!(!cache.join("weather-0.1.0").exists());
+    assert!(remove_from(&cache, "weather", Some("0.1.0")).is_err());
+    assert_eq!(remove_from(&cache, "weather", None).unwrap().len(), 1);
+    assert!(list_cached_in(&cache).is_empty());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn cache_rejects_invalid_mod_names() {
+    assert!(validate_name("weather").is_ok());
+    assert!(validate_name("weather_2").is_ok());
+    for name in &["", "..", "../weather", "nested/weather", "nested\\weather"] {
+        assert!(validate_name(name).is_err(), "{}", name);
+    }
+
+    let dir = env::temp_dir().join(format!("outcome-cache-invalid-{}", std::process::id()));
+    let cache = dir.join("cache");
+    let traversal = write_mod(&dir, "../escaped", "0.1.0");
+    assert!(install_into(&cache, &traversal, false).is_err());
+    assert!(!dir.join("escaped-0.1.0").exists());
+    assert!(!cache.exists());
+    assert!(remove_from(&cache, "../escaped", None).is_err());
+
+    fs::remove_dir_all(&dir).ok();
+}
@@ -18,6 +18,8 @@ use std::collections::HashMap;
 
 use self::linked_hash_map::LinkedHashMap;
 
+use crate::checkpoint::CheckpointSettings;
+
 // use self::serde_yaml::Value;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +40,40 @@ pub struct ModuleManifest {
     pub libraries: HashMap<String, toml::Value>,
     #[serde(default)]
     pub services: HashMap<String, toml::Value>,
+    #[serde(default)]
+    pub lua: ModuleManifestLua,
+    #[serde(default)]
+    pub data: ManifestData,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ManifestData {
+    /// Paths to entity tables spawned at initialization
+    #[serde(default)]
+    pub entity_tables: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScenarioManifestData {
+    /// Paths to entity tables spawned at initialization
+    #[serde(default)]
+    pub entity_tables: Vec<String>,
+    /// Values applied to vars at initialization, keyed by address
+    #[serde(default)]
+    pub values: LinkedHashMap<String, toml::Value>,
+    /// Types of the data files applied at initialization, keyed by path
+    #[serde(default)]
+    pub files: LinkedHashMap<String, String>,
+    /// Images applied to grid vars at initialization, keyed by address
+    #[serde(default)]
+    pub images: LinkedHashMap<String, toml::Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleManifestLua {
+    /// Names of globals persisted in snapshots
+    #[serde(default)]
+    pub globals: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,17 +102,30 @@ pub struct ScenarioManifest {
     #[serde(default)]
     pub mods: LinkedHashMap<String, toml::Value>,
     #[serde(default)]
-    pub settings: HashMap<String, toml::Value>,
+    pub settings: LinkedHashMap<String, toml::Value>,
     #[serde(default)]
     pub services: HashMap<String, toml::Value>,
+    #[serde(default)]
+    pub checkpoint: Option<CheckpointSettings>,
+    #[serde(default)]
+    pub data: ScenarioManifestData,
+    /// Entries inherited from the base scenario that should be removed
+    #[serde(default)]
+    pub remove: ScenarioManifestRemove,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScenarioManifestScenario {
     // required
     pub name: String,
+    // required unless inherited from the base scenario
+    #[serde(default)]
     pub version: String,
+    #[serde(default)]
     pub engine: String,
 
+    /// Name of the base scenario
+    #[serde(default)]
+    pub extends: Option<String>,
     #[serde(default)]
     pub title: String,
     #[serde(default)]
@@ -89,6 +138,19 @@ pub struct ScenarioManifestScenario {
     pub website: String,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScenarioManifestRemove {
+    #[serde(default)]
+    pub mods: Vec<String>,
+    #[serde(default)]
+    pub settings: Vec<String>,
+    #[serde(default)]
+    pub entity_tables: Vec<String>,
+    /// Addresses of data values and images, or paths of data files
+    #[serde(default)]
+    pub data: Vec<String>,
+}
+
 // TODO
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProofManifest {
@@ -114,6 +176,19 @@ pub struct ProofManifest {
 pub struct DataFile {
     #[serde(default)]
     pub components: HashMap<String, Option<ComponentEntry>>,
+    #[serde(default)]
+    pub prefabs: HashMap<String, Option<PrefabEntry>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrefabEntry {
+    #[serde(default)]
+    pub extends: Option<String>,
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Initial var values keyed by `comp:type:var` addresses
+    #[serde(default)]
+    pub vars: HashMap<String, VarEntry>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,249 @@
+//! Differences between two versions of the simulation model.
+//!
+//! Used for applying model changes, for example following edits to module
+//! scripts, to an already running simulation without losing its state.
+
+use std::fmt;
+
+use crate::model::{ComponentModel, SimModel};
+use crate::{CompName, EventName, StringId, VarName, VarType};
+
+/// Changes between two versions of the simulation model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelDiff {
+    pub added_components: Vec<CompName>,
+    pub removed_components: Vec<CompName>,
+    /// Components present in both models that have changed
+    pub changed_components: Vec<ComponentDiff>,
+    pub added_events: Vec<EventName>,
+    pub removed_events: Vec<EventName>,
+}
+
+/// Changes to a single component model.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ComponentDiff {
+    pub name: CompName,
+    pub added_vars: Vec<VarName>,
+    pub removed_vars: Vec<VarName>,
+    /// Vars that changed type, along with the old and the new type
+    pub retyped_vars: Vec<(VarName, VarType, VarType)>,
+    pub added_triggers: Vec<StringId>,
+    pub removed_triggers: Vec<StringId>,
+    pub added_states: Vec<StringId>,
+    pub removed_states: Vec<StringId>,
+}
+
+/// Result of applying a new model to a running simulation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelUpdate {
+    pub diff: ModelDiff,
+    /// Changes that couldn't be applied to existing entities without
+    /// losing data, e.g. vars reset because of a type change
+    pub incompatible: Vec<String>,
+}
+
+impl ModelDiff {
+    /// Compares two versions of the model.
+    pub fn new(old: &SimModel, new: &SimModel) -> ModelDiff {
+        let mut diff = ModelDiff::default();
+
+        for comp in &new.components {
+            match old.components.iter().find(|c| c.name == comp.name) {
+                Some(old_comp) => {
+                    let comp_diff = ComponentDiff::new(old_comp, comp);
+                    if !comp_diff.is_empty() {
+                        diff.changed_components.push(comp_diff);
+                    }
+                }
+                None => diff.added_components.push(comp.name.clone()),
+            }
+        }
+        for comp in &old.components {
+            if !new.components.iter().any(|c| c.name == comp.name) {
+                diff.removed_components.push(comp.name.clone());
+            }
+        }
+
+        for event in &new.events {
+            if !old.events.iter().any(|e| e.id == event.id) {
+                diff.added_events.push(event.id.clone());
+            }
+        }
+        for event in &old.events {
+            if !new.events.iter().any(|e| e.id == event.id) {
+                diff.removed_events.push(event.id.clone());
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_components.is_empty()
+            && self.removed_components.is_empty()
+            && self.changed_components.is_empty()
+            && self.added_events.is_empty()
+            && self.removed_events.is_empty()
+    }
+}
+
+impl ComponentDiff {
+    /// Compares two versions of the same component model.
+    pub fn new(old: &ComponentModel, new: &ComponentModel) -> ComponentDiff {
+        let mut diff = ComponentDiff {
+            name: new.name.clone(),
+            ..ComponentDiff::default()
+        };
+
+        for var in &new.vars {
+            match old.vars.iter().find(|v| v.name == var.name) {
+                Some(old_var) if old_var.type_ != var.type_ => {
+                    diff.retyped_vars
+                        .push((var.name.clone(), old_var.type_, var.type_))
+                }
+                Some(_) => (),
+                None => diff.added_vars.push(var.name.clone()),
+            }
+        }
+        for var in &old.vars {
+            if !new.vars.iter().any(|v| v.name == var.name) {
+                diff.removed_vars.push(var.name.clone());
+            }
+        }
+
+        for trigger in &new.triggers {
+            if !old.triggers.contains(trigger) {
+                diff.added_triggers.push(trigger.clone());
+            }
+        }
+        for trigger in &old.triggers {
+            if !new.triggers.contains(trigger) {
+                diff.removed_triggers.push(trigger.clone());
+            }
+        }
+
+        #[cfg(feature = "machine")]
+        {
+            for state in new.logic.states.keys() {
+                if !old.logic.states.contains_key(state) {
+                    diff.added_states.push(state.clone());
+                }
+            }
+            for state in old.logic.states.keys() {
+                if !new.logic.states.contains_key(state) {
+                    diff.removed_states.push(state.clone());
+                }
+            }
+            diff.added_states.sort();
+            diff.removed_states.sort();
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_vars.is_empty()
+            && self.removed_vars.is_empty()
+            && self.retyped_vars.is_empty()
+            && self.added_triggers.is_empty()
+            && self.removed_triggers.is_empty()
+            && self.added_states.is_empty()
+            && self.removed_states.is_empty()
+    }
+}
+
+impl fmt::Display for ModelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no changes");
+        }
+        for comp in &self.added_components {
+            writeln!(f, "+ component {}", comp)?;
+        }
+        for comp in &self.removed_components {
+            writeln!(f, "- component {}", comp)?;
+        }
+        for comp in &self.changed_components {
+            writeln!(f, "~ component {}", comp.name)?;
+            for var in &comp.added_vars {
+                writeln!(f, "    + var {}", var)?;
+            }
+            for var in &comp.removed_vars {
+                writeln!(f, "    - var {}", var)?;
+            }
+            for (var, old_type, new_type) in &comp.retyped_vars {
+                writeln!(
+                    f,
+                    "    ~ var {}: {} -> {}",
+                    var,
+                    old_type.to_str(),
+                    new_type.to_str()
+                )?;
+            }
+            for trigger in &comp.added_triggers {
+                writeln!(f, "    + trigger {}", trigger)?;
+            }
+            for trigger in &comp.removed_triggers {
+                writeln!(f, "    - trigger {}", trigger)?;
+            }
+            for state in &comp.added_states {
+                writeln!(f, "    + state {}", state)?;
+            }
+            for state in &comp.removed_states {
+                writeln!(f, "    - state {}", state)?;
+            }
+        }
+        for event in &self.added_events {
+            writeln!(f, "+ event {}", event)?;
+        }
+        for event in &self.removed_events {
+            writeln!(f, "- event {}", event)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn model_diff_components() {
+    use crate::model::VarModel;
+
+    let id = |s: &str| -> StringId { crate::string::new_truncate(s) };
+    let var = |name: &str, type_: VarType| VarModel {
+        name: id(name),
+        type_,
+        default: None,
+    };
+    let comp = |name: &str, vars: Vec<VarModel>| ComponentModel {
+        name: id(name),
+        vars,
+        triggers: Vec::new(),
+        #[cfg(feature = "machine")]
+        logic: crate::model::LogicModel::empty(),
+    };
+
+    let mut old = SimModel::default();
+    old.components.push(comp(
+        "health",
+        vec![var("current", VarType::Int), var("max", VarType::Int)],
+    ));
+    old.components.push(comp("legacy", vec![]));
+    let mut new = SimModel::default();
+    new.components.push(comp(
+        "health",
+        vec![var("current", VarType::Float), var("regen", VarType::Float)],
+    ));
+    new.components.push(comp("position", vec![]));
+
+    let diff = ModelDiff::new(&old, &new);
+    assert_eq!(diff.added_components, vec![id("position")]);
+    assert_eq!(diff.removed_components, vec![id("legacy")]);
+    assert_eq!(diff.changed_components.len(), 1);
+    let health = &diff.changed_components[0];
+    assert_eq!(health.added_vars, vec![id("regen")]);
+    assert_eq!(health.removed_vars, vec![id("max")]);
+    assert_eq!(
+        health.retyped_vars,
+        vec![(id("current"), VarType::Int, VarType::Float)]
+    );
+    assert!(ModelDiff::new(&new, &new).is_empty());
+}
@@ -871,10 +871,18 @@ impl DataImageEntry {
         if addr_str.contains("/int_grid/") {
             match type_.as_str() {
                 "bmp_u8" => return Some(DataImageEntry::BmpU8(addr_str, path)),
+                "bmp_u8u8u8" => return Some(DataImageEntry::BmpU8U8U8(addr_str, path)),
+                "bmp_u8u8u8u8_combine" => {
+                    return Some(DataImageEntry::BmpCombineU8U8U8U8(addr_str, path))
+                }
+                "png_u8" => return Some(DataImageEntry::PngU8(addr_str, path)),
                 "png_u8u8u8_concat" => {
                     return Some(DataImageEntry::PngU8U8U8Concat(addr_str, path))
                 }
                 "png_u8u8u8" => return Some(DataImageEntry::PngU8U8U8(addr_str, path)),
+                "png_u8u8u8u8_combine" => {
+                    return Some(DataImageEntry::PngCombineU8U8U8U8(addr_str, path))
+                }
                 _ => return None,
             }
         }
@@ -0,0 +1,401 @@
+//! Introspection of the resolved simulation model.
+//!
+//! [`ModelInfo`] is a serializable summary of everything the model
+//! contains once all the modules are loaded, meant for exporting to
+//! formats such as JSON. It can also be rendered as a Graphviz DOT graph
+//! showing component state machines along with the events triggering each
+//! component.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "machine")]
+use crate::machine::{cmd::Command, LocationInfo};
+use crate::model::{ComponentModel, SimModel};
+use crate::{CompName, EntityName, EventName, StringId, VarName};
+
+/// Summary of the simulation model.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelInfo {
+    /// Name of the scenario the model was created from
+    pub scenario: String,
+    pub modules: Vec<ModuleInfo>,
+    pub components: Vec<ComponentInfo>,
+    pub events: Vec<EventInfo>,
+    pub prefabs: Vec<PrefabInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub version: String,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComponentInfo {
+    pub name: CompName,
+    /// Module the component was declared in, if known
+    pub module: Option<String>,
+    pub vars: Vec<VarInfo>,
+    /// Events triggering the component
+    pub triggers: Vec<EventName>,
+    pub start_state: Option<StringId>,
+    pub states: Vec<StringId>,
+    /// State transitions as found in `goto` commands, pairs of source and
+    /// target state
+    pub transitions: Vec<(StringId, StringId)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarInfo {
+    pub name: VarName,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventInfo {
+    pub name: EventName,
+    /// Components triggered by the event
+    pub triggers: Vec<CompName>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrefabInfo {
+    pub name: EntityName,
+    /// Module the prefab was declared in, if known
+    pub module: Option<String>,
+    pub components: Vec<CompName>,
+    /// Name of the prefab this one extends
+    pub extends: Option<EntityName>,
+}
+
+impl ModelInfo {
+    /// Collects information about the model.
+    pub fn new(model: &SimModel) -> ModelInfo {
+        ModelInfo {
+            scenario: model.scenario.manifest.name.clone(),
+            modules: model
+                .scenario
+                .modules
+                .iter()
+                .map(|module| ModuleInfo {
+                    name: module.manifest.name.clone(),
+                    version: module.manifest.version.clone(),
+                    path: module.path.clone(),
+                })
+                .collect(),
+            components: model
+                .components
+                .iter()
+                .map(|comp| ComponentInfo::new(model, comp))
+                .collect(),
+            events: model
+                .events
+                .iter()
+                .map(|event| EventInfo {
+                    name: event.id.clone(),
+                    triggers: model
+                        .components
+                        .iter()
+                        .filter(|c| c.triggers.contains(&event.id))
+                        .map(|c| c.name.clone())
+                        .collect(),
+                })
+                .collect(),
+            prefabs: model
+                .entities
+                .iter()
+                .map(|prefab| PrefabInfo {
+                    name: prefab.name.clone(),
+                    module: prefab_module(model, &prefab.name),
+                    components: prefab.components.clone(),
+                    extends: prefab.extends.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Renders component state machines and event trigger wiring as a
+    /// Graphviz DOT graph.
+    ///
+    /// Each component is drawn as a cluster of its states, with edges for
+    /// `goto` transitions. Events are drawn as separate nodes with edges
+    /// to the components they trigger. Internal components and events,
+    /// i.e. ones prefixed with `_` and module init components, are left
+    /// out.
+    pub fn to_dot(&self) -> String {
+        let init_comps = self
+            .prefabs
+            .iter()
+            .find(|p| p.name.as_str() == "_mod_init")
+            .map(|p| p.components.clone())
+            .unwrap_or_default();
+        let components = self
+            .components
+            .iter()
+            .filter(|c| !c.name.starts_with('_') && !init_comps.contains(&c.name))
+            .collect::<Vec<_>>();
+
+        let mut out = String::new();
+        writeln!(out, "digraph {} {{", quote(&self.scenario)).unwrap();
+        writeln!(out, "    compound=true;").unwrap();
+        writeln!(out, "    rankdir=LR;").unwrap();
+
+        for comp in &components {
+            writeln!(
+                out,
+                "    subgraph {} {{",
+                quote(&format!("cluster_{}", comp.name))
+            )
+            .unwrap();
+            writeln!(out, "        label={};", quote(&comp.name)).unwrap();
+            let states = comp.all_states();
+            if states.is_empty() {
+                writeln!(
+                    out,
+                    "        {} [label={}, shape=box];",
+                    quote(&comp.anchor_node()),
+                    quote(&comp.name)
+                )
+                .unwrap();
+            }
+            for state in &states {
+                let peripheries = if Some(state) == comp.start_state.as_ref() {
+                    2
+                } else {
+                    1
+                };
+                writeln!(
+                    out,
+                    "        {} [label={}, shape=ellipse, peripheries={}];",
+                    quote(&state_node(&comp.name, state)),
+                    quote(state),
+                    peripheries
+                )
+                .unwrap();
+            }
+            for (from, to) in &comp.transitions {
+                writeln!(
+                    out,
+                    "        {} -> {};",
+                    quote(&state_node(&comp.name, from)),
+                    quote(&state_node(&comp.name, to))
+                )
+                .unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+
+        for event in &self.events {
+            if event.name.starts_with('_') {
+                continue;
+            }
+            let event_node = format!("event.{}", event.name);
+            writeln!(
+                out,
+                "    {} [label={}, shape=box, style=filled];",
+                quote(&event_node),
+                quote(&event.name)
+            )
+            .unwrap();
+            for comp in components
+                .iter()
+                .filter(|c| event.triggers.contains(&c.name))
+            {
+                writeln!(
+                    out,
+                    "    {} -> {} [lhead={}, style=dashed];",
+                    quote(&event_node),
+                    quote(&comp.anchor_node()),
+                    quote(&format!("cluster_{}", comp.name))
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl ComponentInfo {
+    fn new(model: &SimModel, comp: &ComponentModel) -> ComponentInfo {
+        let mut info = ComponentInfo {
+            name: comp.name.clone(),
+            module: component_module(model, comp),
+            vars: comp
+                .vars
+                .iter()
+                .map(|var| VarInfo {
+                    name: var.name.clone(),
+                    type_: var.type_.to_str().to_string(),
+                    default: var.default.as_ref().map(|v| v.to_string()),
+                })
+                .collect(),
+            triggers: comp.triggers.clone(),
+            start_state: None,
+            states: Vec::new(),
+            transitions: Vec::new(),
+        };
+
+        #[cfg(feature = "machine")]
+        {
+            let logic = &comp.logic;
+            info.start_state = Some(logic.start_state.clone());
+            info.states = logic.states.keys().cloned().collect();
+            info.states.sort();
+            for (state, (start, end)) in &logic.states {
+                for cmd in logic.commands.iter().take(*end).skip(*start) {
+                    if let Command::Goto(goto) = cmd {
+                        let transition = (state.clone(), goto.target_state.clone());
+                        if !info.transitions.contains(&transition) {
+                            info.transitions.push(transition);
+                        }
+                    }
+                }
+            }
+            info.transitions.sort();
+        }
+
+        info
+    }
+
+    /// Gets all the states including the start state and any `goto`
+    /// targets, even if they're not declared.
+    fn all_states(&self) -> Vec<StringId> {
+        let mut states = self.states.clone();
+        let referenced = self
+            .start_state
+            .iter()
+            .chain(self.transitions.iter().map(|(_, to)| to));
+        for state in referenced {
+            if !states.contains(state) {
+                states.push(state.clone());
+            }
+        }
+        states
+    }
+
+    /// Gets the node that edges pointing at the component connect to.
+    fn anchor_node(&self) -> String {
+        match &self.start_state {
+            Some(state) => state_node(&self.name, state),
+            None => format!("comp.{}", self.name),
+        }
+    }
+}
+
+fn state_node(comp: &str, state: &str) -> String {
+    format!("{}.{}", comp, state)
+}
+
+/// Quotes the string to be used as a DOT identifier.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
+
+/// Finds the module the component was declared in, based on the location
+/// of the component's commands.
+#[cfg(feature = "machine")]
+fn component_module(model: &SimModel, comp: &ComponentModel) -> Option<String> {
+    let location = comp.logic.cmd_location_map.first().or_else(|| {
+        find_location(model, |cmd| match cmd {
+            Command::RegisterComponent(reg) => reg.name == comp.name,
+            Command::Component(block) => block.name == comp.name,
+            _ => false,
+        })
+    })?;
+    location_module(model, location)
+}
+
+#[cfg(not(feature = "machine"))]
+fn component_module(_model: &SimModel, _comp: &ComponentModel) -> Option<String> {
+    None
+}
+
+/// Finds the module the prefab was declared in.
+#[cfg(feature = "machine")]
+fn prefab_module(model: &SimModel, name: &EntityName) -> Option<String> {
+    let location = find_location(model, |cmd| match cmd {
+        Command::RegisterEntityPrefab(reg) => &reg.name == name,
+        _ => false,
+    })?;
+    location_module(model, location)
+}
+
+#[cfg(not(feature = "machine"))]
+fn prefab_module(_model: &SimModel, _name: &EntityName) -> Option<String> {
+    None
+}
+
+/// Gets the name of the module containing the source file pointed to by
+/// the location.
+#[cfg(feature = "machine")]
+fn location_module(model: &SimModel, location: &LocationInfo) -> Option<String> {
+    let source = Path::new(location.source.as_ref()?.as_str());
+    let name = source
+        .strip_prefix(crate::MODULES_DIR_NAME)
+        .ok()?
+        .components()
+        .next()?
+        .as_os_str()
+        .to_str()?;
+    model
+        .scenario
+        .modules
+        .iter()
+        .find(|module| module.manifest.name == name)
+        .map(|module| module.manifest.name.clone())
+}
+
+/// Finds location of the first command matching the predicate.
+#[cfg(feature = "machine")]
+pub(crate) fn find_location<F>(model: &SimModel, predicate: F) -> Option<&LocationInfo>
+where
+    F: Fn(&Command) -> bool,
+{
+    model.components.iter().find_map(|comp| {
+        comp.logic
+            .commands
+            .iter()
+            .position(|cmd| predicate(cmd))
+            .and_then(|n| comp.logic.cmd_location_map.get(n))
+    })
+}
+
+#[test]
+#[cfg(feature = "machine")]
+fn model_info_state_transitions() {
+    use crate::machine::cmd::Goto;
+    use crate::model::{EventModel, LogicModel};
+
+    let id = |s: &str| -> StringId { crate::string::new_truncate(s) };
+    let mut logic = LogicModel::empty();
+    logic.commands.push(Command::Goto(Goto {
+        target_state: id("idle"),
+    }));
+    logic.states.insert(id("start"), (0, 1));
+    logic.states.insert(id("idle"), (1, 1));
+
+    let mut model = SimModel::default();
+    model.components.push(ComponentModel {
+        name: id("guard"),
+        vars: Vec::new(),
+        triggers: vec![id("step")],
+        logic,
+    });
+    model.events.push(EventModel { id: id("step") });
+
+    let info = ModelInfo::new(&model);
+    let guard = &info.components[0];
+    assert_eq!(guard.states, vec![id("idle"), id("start")]);
+    assert_eq!(guard.transitions, vec![(id("start"), id("idle"))]);
+    assert_eq!(info.events[0].triggers, vec![id("guard")]);
+
+    let dot = info.to_dot();
+    assert!(dot.contains("\"guard.start\" -> \"guard.idle\";"));
+    assert!(dot.contains("\"event.step\" -> \"guard.start\""));
+}
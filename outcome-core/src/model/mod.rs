@@ -3,7 +3,12 @@
 
 #![allow(unused)]
 
+pub mod cache;
 mod deser;
+pub mod diff;
+pub mod introspect;
+pub mod resolver;
+pub mod table;
 
 use std::collections::HashMap;
 use std::fs::{read, read_dir, File};
@@ -16,6 +21,7 @@ use semver::{Version, VersionReq};
 use toml::Value;
 
 use crate::address::{Address, LocalAddress, ShortLocalAddress};
+use crate::checkpoint::CheckpointSettings;
 use crate::error::Error;
 use crate::util;
 use crate::{string, ShortString, StringId};
@@ -62,6 +68,8 @@ pub struct SimModel {
     pub data: Vec<DataEntry>,
     pub data_files: Vec<DataFileEntry>,
     pub data_imgs: Vec<DataImageEntry>,
+    /// Entity tables spawned at initialization
+    pub entity_tables: Vec<PathBuf>,
     pub services: Vec<ServiceModel>,
 }
 
@@ -78,6 +86,7 @@ impl SimModel {
             data: Vec::new(),
             data_files: Vec::new(),
             data_imgs: Vec::new(),
+            entity_tables: Vec::new(),
             services: Vec::new(),
         };
 
@@ -100,19 +109,26 @@ impl SimModel {
                 model.services.push(module_service.clone());
             }
 
+            // entity tables
+            for table in &module.manifest.entity_tables {
+                model.entity_tables.push(module.path.join(table));
+            }
+
             // load from structured data
-            #[cfg(feature = "yaml")]
             {
+                let mut extensions = vec!["toml"];
+                #[cfg(feature = "yaml")]
+                extensions.extend(&["yaml", "yml"]);
                 let files = util::find_files_with_extension(
                     module.path.clone(),
-                    vec!["yaml", "yml"],
+                    extensions,
                     true,
-                    None,
+                    Some(vec![MODULE_MANIFEST_FILE.to_string()]),
                 );
-                debug!("yaml files: {:?}", files);
+                debug!("structured data files: {:?}", files);
                 for file in files {
                     if let Ok(file_struct) = util::deser_struct_from_path(file.clone()) {
-                        trace!("yaml file struct: {:?}", file_struct);
+                        trace!("data file struct: {:?}", file_struct);
                         model.apply_from_structured_file(file_struct)?;
                     } else {
                         warn!("unable to parse file: {}", file.to_string_lossy());
@@ -229,6 +245,29 @@ impl SimModel {
         }
         model.entities.push(mod_init_prefab);
 
+        // scenario entity tables are spawned after the ones from modules
+        for table in &scenario.manifest.entity_tables {
+            model.entity_tables.push(scenario.path.join(table));
+        }
+
+        // scenario data is applied after the data from modules
+        model.data.extend(scenario.manifest.data.iter().cloned());
+        for file in &scenario.manifest.data_files {
+            let path = scenario.path.join(file.path());
+            model.data_files.push(DataFileEntry::from_type_str(
+                file.type_str(),
+                path.to_string_lossy().to_string(),
+            )?);
+        }
+        for img in &scenario.manifest.data_imgs {
+            let path = scenario.path.join(img.path());
+            model.data_imgs.push(DataImageEntry::from_type_str(
+                img.type_str(),
+                img.address().to_string(),
+                path.to_string_lossy().to_string(),
+            )?);
+        }
+
         Ok(model)
     }
 }
@@ -242,6 +281,11 @@ impl SimModel {
                 self.components.push(comp_model);
             }
         }
+        for (name, prefab) in file_struct.prefabs {
+            trace!("file struct prefab: {}", name);
+            self.entities
+                .push(EntityPrefab::from_deser(&name, prefab.unwrap_or_default())?);
+        }
 
         Ok(())
     }
@@ -253,6 +297,50 @@ impl SimModel {
             .find(|entity| &entity.name.as_str() == &name.as_str())
     }
 
+    /// Gets the prefab with its inheritance chain applied.
+    ///
+    /// Components of base prefabs come first, followed by components
+    /// added by the derived prefabs. Var values set by derived prefabs
+    /// take precedence over the ones set by base prefabs.
+    pub fn resolve_prefab(&self, name: &EntityName) -> Result<EntityPrefab> {
+        let mut chain: Vec<&EntityPrefab> = Vec::new();
+        let mut next = Some(name);
+        while let Some(name) = next {
+            let prefab = self
+                .get_entity(name)
+                .ok_or_else(|| Error::NoEntityPrefab(name.clone()))?;
+            if chain.iter().any(|p| p.name == prefab.name) {
+                let mut names = chain.iter().map(|p| p.name.to_string()).collect::<Vec<_>>();
+                names.push(prefab.name.to_string());
+                return Err(Error::InvalidEntityPrefab(
+                    chain[0].name.to_string(),
+                    format!("inheritance cycle: {}", names.join(" -> ")),
+                ));
+            }
+            chain.push(prefab);
+            next = prefab.extends.as_ref();
+        }
+
+        let mut resolved = EntityPrefab {
+            name: name.clone(),
+            ..EntityPrefab::default()
+        };
+        for prefab in chain.iter().rev() {
+            for comp in &prefab.components {
+                if !resolved.components.contains(comp) {
+                    resolved.components.push(comp.clone());
+                }
+            }
+            for var in &prefab.vars {
+                resolved
+                    .vars
+                    .retain(|v| v.address.storage_index() != var.address.storage_index());
+                resolved.vars.push(var.clone());
+            }
+        }
+        Ok(resolved)
+    }
+
     /// Get mutable reference to entity prefab using `type_` and `id` args.
     pub fn get_entity_mut(&mut self, name: &StringId) -> Option<&mut EntityPrefab> {
         self.entities.iter_mut().find(|entity| &entity.name == name)
@@ -270,6 +358,21 @@ impl SimModel {
     pub fn get_component_mut(&mut self, name: &StringId) -> Option<&mut ComponentModel> {
         self.components.iter_mut().find(|comp| &comp.name == name)
     }
+
+    /// Get names of Lua globals declared for persistence by all the
+    /// scenario modules.
+    #[cfg(feature = "machine_lua")]
+    pub fn lua_globals(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for module in &self.scenario.modules {
+            for name in &module.manifest.lua_globals {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        names
+    }
 }
 
 /// Scenario manifest model.
@@ -284,8 +387,24 @@ pub struct ScenarioManifest {
 
     /// List of the module dependencies for the scenario
     pub mods: Vec<ScenarioModuleDep>,
-    /// Map of settings, each being essentially an arbitrary data setter
-    pub settings: HashMap<String, String>,
+    /// List of typed settings, applied at initialization
+    pub settings: Vec<ScenarioSetting>,
+    /// Automatic checkpointing settings, not stored in snapshots
+    #[serde(skip)]
+    pub checkpoint: Option<CheckpointSettings>,
+    /// Paths to entity tables spawned at initialization, relative to
+    /// project root
+    pub entity_tables: Vec<String>,
+    /// Values applied to vars at initialization
+    pub data: Vec<DataEntry>,
+    /// Data files applied at initialization, relative to project root
+    pub data_files: Vec<DataFileEntry>,
+    /// Images applied to grid vars at initialization, relative to project
+    /// root
+    pub data_imgs: Vec<DataImageEntry>,
+    /// Names of the scenarios this one inherits from, nearest base first
+    #[serde(default)]
+    pub extends: Vec<String>,
 
     /// More free-form than the name
     pub title: Option<String>,
@@ -301,51 +420,439 @@ pub struct ScenarioManifest {
 
 impl ScenarioManifest {
     /// Creates new scenario manifest object from path reference.
+    ///
+    /// If the manifest extends another scenario, the chain of base
+    /// scenarios is resolved first and the manifest is applied on top of
+    /// the resulting base manifest. Base scenarios are looked for in the
+    /// same directory, using the scenario name or the file stem.
+    ///
+    /// Mods and settings are inherited and can be overridden by declaring
+    /// an entry with the same name. Plain values override the value of an
+    /// inherited setting while keeping its type and description. Inherited
+    /// entries can be removed by listing them in the `[remove]` section.
+    /// Entity tables from the `[data]` section are layered on top of the
+    /// inherited ones.
+    ///
+    /// ```toml
+    /// [scenario]
+    /// name = "big_map"
+    /// extends = "base"
+    ///
+    /// [settings]
+    /// map_size = 512
+    ///
+    /// [remove]
+    /// mods = ["debug_tools"]
+    /// ```
     pub fn from_path(path: PathBuf) -> Result<ScenarioManifest> {
-        // let manifest_path = path.join(SCENARIO_MANIFEST_FILE);
-        let manifest_path = path;
-        let deser_manifest: deser::ScenarioManifest = util::deser_struct_from_path(manifest_path)?;
-        let mut mods = Vec::new();
-        for module in deser_manifest.mods {
-            let (name, value) = module;
+        ScenarioManifest::from_path_with_chain(path, &mut Vec::new())
+    }
+
+    /// Creates the manifest, keeping track of the scenarios already
+    /// visited while resolving the inheritance chain.
+    fn from_path_with_chain(path: PathBuf, chain: &mut Vec<String>) -> Result<ScenarioManifest> {
+        let deser_manifest: deser::ScenarioManifest = util::deser_struct_from_path(path.clone())?;
+        let name = deser_manifest.scenario.name.clone();
+        if chain.contains(&name) {
+            chain.push(name);
+            return Err(Error::ScenarioInheritanceCycle(chain.join(" -> ")));
+        }
+        chain.push(name.clone());
+
+        let base = match &deser_manifest.scenario.extends {
+            Some(base_name) => {
+                let base_path = find_base_scenario(&path, base_name)
+                    .ok_or(Error::BaseScenarioNotFound(base_name.clone(), name))?;
+                Some(ScenarioManifest::from_path_with_chain(base_path, chain)?)
+            }
+            None => None,
+        };
+        ScenarioManifest::from_deser(deser_manifest, base)
+    }
+
+    /// Creates the manifest from the deserialized representation, applying
+    /// it on top of the base manifest if one is provided.
+    fn from_deser(
+        deser_manifest: deser::ScenarioManifest,
+        base: Option<ScenarioManifest>,
+    ) -> Result<ScenarioManifest> {
+        let mut manifest = match base {
+            Some(base) => {
+                let mut extends = vec![base.name.clone()];
+                extends.extend(base.extends.iter().cloned());
+                ScenarioManifest { extends, ..base }
+            }
+            None => ScenarioManifest::default(),
+        };
 
+        let scenario = deser_manifest.scenario;
+        manifest.name = scenario.name;
+        if !scenario.version.is_empty() {
+            manifest.version = scenario.version;
+        }
+        if !scenario.engine.is_empty() {
+            manifest.engine = scenario.engine;
+        }
+        if manifest.version.is_empty() || manifest.engine.is_empty() {
+            return Err(Error::Other(format!(
+                "scenario \"{}\" is missing required version or engine entry",
+                manifest.name
+            )));
+        }
+        let non_empty = |s: String| if s.is_empty() { None } else { Some(s) };
+        manifest.title = non_empty(scenario.title).or(manifest.title);
+        manifest.desc = non_empty(scenario.desc).or(manifest.desc);
+        manifest.desc_long = non_empty(scenario.desc_long).or(manifest.desc_long);
+        manifest.author = non_empty(scenario.author).or(manifest.author);
+        manifest.website = non_empty(scenario.website).or(manifest.website);
+
+        let remove = deser_manifest.remove;
+        manifest.mods.retain(|m| !remove.mods.contains(&m.name));
+        manifest.settings.retain(|s| !remove.settings.contains(&s.name));
+        manifest
+            .entity_tables
+            .retain(|t| !remove.entity_tables.contains(t));
+        let removed = |key: &str| remove.data.iter().any(|r| r == key);
+        manifest.data.retain(|d| !removed(d.address()));
+        manifest.data_files.retain(|f| !removed(f.path()));
+        manifest.data_imgs.retain(|i| !removed(i.address()));
+
+        for (name, value) in deser_manifest.mods {
             // TODO better errors
-            mods.push(ScenarioModuleDep::from_toml_value(&name, &value).unwrap());
+            let dep = ScenarioModuleDep::from_toml_value(&name, &value).unwrap();
+            match manifest.mods.iter_mut().find(|m| m.name == name) {
+                Some(existing) => *existing = dep,
+                None => manifest.mods.push(dep),
+            }
         }
 
-        Ok(ScenarioManifest {
-            name: deser_manifest.scenario.name,
-            version: deser_manifest.scenario.version,
-            engine: deser_manifest.scenario.engine,
+        for (name, value) in &deser_manifest.settings {
+            match manifest.settings.iter_mut().find(|s| &s.name == name) {
+                // plain value only overrides the value of inherited setting
+                Some(setting) if !value.is_table() => {
+                    setting.default = Var::from_str(
+                        &util::coerce_toml_val_to_string(value),
+                        Some(setting.type_),
+                    )
+                    .map_err(|e| Error::InvalidScenarioSetting(name.clone(), e.to_string()))?;
+                    setting.value = setting.default.clone();
+                }
+                Some(setting) => *setting = ScenarioSetting::from_toml_value(name, value)?,
+                None => manifest
+                    .settings
+                    .push(ScenarioSetting::from_toml_value(name, value)?),
+            }
+        }
 
-            settings: deser_manifest
-                .settings
+        if deser_manifest.checkpoint.is_some() {
+            manifest.checkpoint = deser_manifest.checkpoint;
+        }
+        for table in deser_manifest.data.entity_tables {
+            if !manifest.entity_tables.contains(&table) {
+                manifest.entity_tables.push(table);
+            }
+        }
+        for (address, value) in &deser_manifest.data.values {
+            let entry = DataEntry::from_toml_value(address, value)?;
+            match manifest.data.iter_mut().find(|d| d.address() == address) {
+                Some(existing) => *existing = entry,
+                None => manifest.data.push(entry),
+            }
+        }
+        for (path, type_) in deser_manifest.data.files {
+            let entry = DataFileEntry::from_type_str(&type_, path.clone())?;
+            match manifest.data_files.iter_mut().find(|f| f.path() == path) {
+                Some(existing) => *existing = entry,
+                None => manifest.data_files.push(entry),
+            }
+        }
+        for (address, value) in &deser_manifest.data.images {
+            let entry = DataImageEntry::from_toml_value(address, value)?;
+            match manifest
+                .data_imgs
+                .iter_mut()
+                .find(|i| i.address() == address)
+            {
+                Some(existing) => *existing = entry,
+                None => manifest.data_imgs.push(entry),
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Serializes the manifest back into the toml format.
+    ///
+    /// For manifests extending other scenarios this is the effective
+    /// manifest, with all the inherited entries included and no `extends`
+    /// entry.
+    pub fn to_toml_string(&self) -> Result<String> {
+        use toml::value::Table;
+
+        let mut scenario = Table::new();
+        scenario.insert("name".to_string(), Value::from(self.name.as_str()));
+        scenario.insert("version".to_string(), Value::from(self.version.as_str()));
+        scenario.insert("engine".to_string(), Value::from(self.engine.as_str()));
+        let optional = [
+            ("title", &self.title),
+            ("desc", &self.desc),
+            ("desc_long", &self.desc_long),
+            ("author", &self.author),
+            ("website", &self.website),
+        ];
+        for (key, value) in optional.iter() {
+            if let Some(value) = value {
+                scenario.insert(key.to_string(), Value::from(value.as_str()));
+            }
+        }
+
+        let mut mods = Table::new();
+        for dep in &self.mods {
+            let value = match &dep.git_address {
+                Some(git) => {
+                    let mut table = Table::new();
+                    table.insert("version".to_string(), Value::from(dep.version_req.as_str()));
+                    table.insert("git".to_string(), Value::from(git.as_str()));
+                    Value::Table(table)
+                }
+                None => Value::from(dep.version_req.as_str()),
+            };
+            mods.insert(dep.name.clone(), value);
+        }
+
+        let mut settings = Table::new();
+        for setting in &self.settings {
+            let mut table = Table::new();
+            table.insert("type".to_string(), Value::from(setting.type_.to_str()));
+            table.insert("default".to_string(), var_to_toml(&setting.value));
+            if let Some(desc) = &setting.desc {
+                table.insert("desc".to_string(), Value::from(desc.as_str()));
+            }
+            table.insert(
+                "target".to_string(),
+                Value::from(setting.target.to_string()),
+            );
+            settings.insert(setting.name.clone(), Value::Table(table));
+        }
+
+        let mut root = Table::new();
+        root.insert("scenario".to_string(), Value::Table(scenario));
+        root.insert("mods".to_string(), Value::Table(mods));
+        root.insert("settings".to_string(), Value::Table(settings));
+        if let Some(checkpoint) = &self.checkpoint {
+            root.insert(
+                "checkpoint".to_string(),
+                Value::try_from(checkpoint).map_err(|e| Error::Other(e.to_string()))?,
+            );
+        }
+        let mut data = Table::new();
+        if !self.entity_tables.is_empty() {
+            data.insert(
+                "entity_tables".to_string(),
+                Value::from(self.entity_tables.clone()),
+            );
+        }
+        if !self.data.is_empty() {
+            let values = self
+                .data
                 .iter()
-                .map(|(s, v)| (s.to_string(), v.to_string()))
-                .collect(),
-            title: match deser_manifest.scenario.title.as_str() {
-                "" => None,
-                s => Some(s.to_owned()),
-            },
-            desc: match deser_manifest.scenario.desc.as_str() {
-                "" => None,
-                s => Some(s.to_owned()),
-            },
-            desc_long: match deser_manifest.scenario.desc_long.as_str() {
-                "" => None,
-                s => Some(s.to_owned()),
-            },
-            author: match deser_manifest.scenario.author.as_str() {
-                "" => None,
-                s => Some(s.to_owned()),
-            },
-            website: match deser_manifest.scenario.website.as_str() {
-                "" => None,
-                s => Some(s.to_owned()),
-            },
-            mods,
+                .map(|d| (d.address().to_string(), d.to_toml_value()))
+                .collect::<Table>();
+            data.insert("values".to_string(), Value::Table(values));
+        }
+        if !self.data_files.is_empty() {
+            let files = self
+                .data_files
+                .iter()
+                .map(|f| (f.path().to_string(), Value::from(f.type_str())))
+                .collect::<Table>();
+            data.insert("files".to_string(), Value::Table(files));
+        }
+        if !self.data_imgs.is_empty() {
+            let images = self
+                .data_imgs
+                .iter()
+                .map(|i| (i.address().to_string(), i.to_toml_value()))
+                .collect::<Table>();
+            data.insert("images".to_string(), Value::Table(images));
+        }
+        if !data.is_empty() {
+            root.insert("data".to_string(), Value::Table(data));
+        }
+        toml::to_string(&Value::Table(root)).map_err(|e| Error::Other(e.to_string()))
+    }
+
+    /// Gets setting with the given name.
+    pub fn get_setting(&self, name: &str) -> Option<&ScenarioSetting> {
+        self.settings.iter().find(|setting| setting.name == name)
+    }
+
+    /// Overrides the value of a declared setting, parsing the provided
+    /// string based on the setting type.
+    pub fn set_setting(&mut self, name: &str, value: &str) -> Result<()> {
+        self.settings
+            .iter_mut()
+            .find(|setting| setting.name == name)
+            .ok_or(Error::InvalidScenarioSetting(
+                name.to_string(),
+                "not declared in the scenario manifest".to_string(),
+            ))?
+            .set_from_str(value)
+    }
+}
+
+/// Finds the manifest of the base scenario among the manifests in the
+/// same directory, first by scenario name and then by file stem.
+fn find_base_scenario(path: &Path, base_name: &str) -> Option<PathBuf> {
+    let dir = path.parent()?;
+    let candidates = read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().map_or(false, |e| e == "toml"))
+        .collect::<Vec<_>>();
+    let by_name = candidates.iter().find(|p| {
+        util::deser_struct_from_path::<deser::ScenarioManifest>(p.clone())
+            .map(|m| m.scenario.name == base_name)
+            .unwrap_or(false)
+    });
+    by_name
+        .or_else(|| {
+            candidates
+                .iter()
+                .find(|p| p.file_stem().map_or(false, |s| s == base_name))
+        })
+        .cloned()
+}
+
+/// Converts the var into a toml value, using native toml types where
+/// possible.
+fn var_to_toml(var: &Var) -> Value {
+    match var {
+        Var::Int(v) => Value::Integer(*v as i64),
+        Var::Float(v) => Value::Float(*v as f64),
+        Var::Bool(v) => Value::Boolean(*v),
+        _ => Value::String(var.to_string()),
+    }
+}
+
+/// Typed scenario setting.
+///
+/// Settings are declared in the scenario manifest, either as a table with
+/// `type`, `default`, `desc` and `target` entries, or as a plain value
+/// using the target address as the setting name.
+///
+/// Each setting is applied to the var at its target address at
+/// initialization, which is how scripts read it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScenarioSetting {
+    /// Name of the setting, used when overriding the value
+    pub name: String,
+    /// Type of the setting value
+    pub type_: VarType,
+    /// Value used if the setting is not overridden
+    pub default: Var,
+    /// Current value of the setting
+    pub value: Var,
+    /// Description of the setting
+    pub desc: Option<String>,
+    /// Address of the var the setting is applied to
+    pub target: Address,
+}
+
+impl ScenarioSetting {
+    /// Creates a setting from a scenario manifest entry.
+    pub fn from_toml_value(name: &str, value: &Value) -> Result<ScenarioSetting> {
+        let invalid = |msg: String| Error::InvalidScenarioSetting(name.to_string(), msg);
+
+        // plain value entries use the target address as the name
+        let table = match value.as_table() {
+            Some(t) => t,
+            None => {
+                let target = Address::from_str(name).map_err(|_| {
+                    invalid("name of a plain value setting must be a valid address".to_string())
+                })?;
+                let default = Var::from_str(
+                    &util::coerce_toml_val_to_string(value),
+                    Some(target.var_type),
+                )
+                .map_err(|e| invalid(e.to_string()))?;
+                return Ok(ScenarioSetting {
+                    name: name.to_string(),
+                    type_: target.var_type,
+                    value: default.clone(),
+                    default,
+                    desc: None,
+                    target,
+                });
+            }
+        };
+
+        // settings are only accessible through the vars they're applied to
+        let target = Address::from_str(
+            table
+                .get("target")
+                .ok_or(invalid("missing target".to_string()))?
+                .as_str()
+                .ok_or(invalid("target must be a string".to_string()))?,
+        )?;
+        let type_ = match table.get("type") {
+            Some(t) => VarType::from_str(
+                t.as_str()
+                    .ok_or(invalid("type must be a string".to_string()))?,
+            )?,
+            None => target.var_type,
+        };
+        if target.var_type != type_ {
+            return Err(invalid(format!(
+                "type {} doesn't match the type of target address {}",
+                type_, target
+            )));
+        }
+        let default = match table.get("default") {
+            Some(v) => Var::from_str(&util::coerce_toml_val_to_string(v), Some(type_))
+                .map_err(|e| invalid(e.to_string()))?,
+            None => type_.default_value(),
+        };
+        let desc = table
+            .get("desc")
+            .and_then(|d| d.as_str())
+            .map(|d| d.to_string());
+
+        Ok(ScenarioSetting {
+            name: name.to_string(),
+            type_,
+            value: default.clone(),
+            default,
+            desc,
+            target,
         })
     }
+
+    /// Creates a setting from the untyped representation used before
+    /// typed settings were introduced, where the name is the target address
+    /// and the value is a string representation of a toml value.
+    ///
+    /// Returns `None` if the name is not a valid address.
+    pub fn from_legacy(name: &str, value: &str) -> Option<ScenarioSetting> {
+        let target = Address::from_str(name).ok()?;
+        let value = Var::from_str(value.trim_matches('"'), Some(target.var_type)).ok()?;
+        Some(ScenarioSetting {
+            name: name.to_string(),
+            type_: target.var_type,
+            default: value.clone(),
+            value,
+            desc: None,
+            target,
+        })
+    }
+
+    /// Sets the current value, parsing the provided string based on the
+    /// setting type.
+    pub fn set_from_str(&mut self, value: &str) -> Result<()> {
+        self.value = Var::from_str(value, Some(self.type_))
+            .map_err(|e| Error::InvalidScenarioSetting(self.name.clone(), e.to_string()))?;
+        Ok(())
+    }
 }
 
 /// Scenario module dependency.
@@ -382,17 +889,21 @@ impl ScenarioModuleDep {
         }
         // otherwise it's a mapping with different kinds of entries
         else if let Some(mapping) = value.as_table() {
-            unimplemented!();
-            if let Ok(vr) = VersionReq::parse(value.as_str().unwrap()) {
-                version_req = vr.to_string();
-            } else {
-                // TODO print warning about the version_req
+            if let Some(s) = mapping.get(version_field).and_then(|v| v.as_str()) {
+                match VersionReq::parse(s) {
+                    Ok(vr) => version_req = vr.to_string(),
+                    Err(e) => warn!(
+                        "failed parsing scenario module dep version req \"{}\" ({}), \
+                         using default \"*\" (any)",
+                        s, e
+                    ),
+                }
             }
             // `git_address` is optional, default is `None`
-            git_address = match value.get(git_field) {
-                Some(v) => Some(String::from(v.as_str().unwrap())),
-                None => None,
-            };
+            git_address = mapping
+                .get(git_field)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
         } else {
             error!(
                 "module dep has to be either a string (version specifier)\
@@ -421,7 +932,19 @@ pub struct Scenario {
 
 impl Scenario {
     /// Create a scenario model from a path reference to scenario manifest.
+    ///
+    /// Apart from the project's mods directory, modules are searched for
+    /// in the directories returned by [`cache::search_dirs`].
     pub fn from_path(path: PathBuf) -> Result<Scenario> {
+        Self::from_path_with_mod_dirs(path, &cache::search_dirs())
+    }
+
+    /// Create a scenario model from a path reference to scenario manifest,
+    /// additionally looking for modules in the provided directories.
+    ///
+    /// Modules are resolved using [`resolver::resolve`], the project's
+    /// mods directory is always searched first.
+    pub fn from_path_with_mod_dirs(path: PathBuf, mod_dirs: &[PathBuf]) -> Result<Scenario> {
         // resolve project root path
         let mut dir_path = path.parent().ok_or(Error::Other(format!(
             "unable to get parent of path: {}",
@@ -446,6 +969,13 @@ impl Scenario {
 
         // get the scenario manifest
         let scenario_manifest = ScenarioManifest::from_path(path.clone())?;
+        if !scenario_manifest.extends.is_empty() {
+            info!(
+                "scenario \"{}\" extends: {}",
+                scenario_manifest.name,
+                scenario_manifest.extends.join(" -> ")
+            );
+        }
 
         // if the version requirement for the engine specified in
         // the scenario manifest is not met return an error
@@ -460,189 +990,60 @@ impl Scenario {
             ));
         }
         // get the map of mods to load from the manifest (only mods
-        // listed there will be loaded)
+        // listed there, and their dependencies, will be loaded)
         let mods_to_load = &scenario_manifest.mods;
         info!(
             "there are {} mods listed in the scenario manifest",
             &mods_to_load.len()
         );
-        // get the path to scenario mods directory
-        let scenario_mods_path = dir_path.join(MODULES_DIR_NAME);
-        // found matching mods will be added to this vec
-        let mut matching_mods: Vec<Module> = Vec::new();
-        // this vec is for storing mod_not_found messages to print
-        // them after the loop
-        let mut mod_not_found_msgs: Vec<String> = Vec::new();
-        // this bool will turn false if any of the mods from the
-        // manifest wasn't found based on it the process of
-        // creating the scenario can be halted
-        let mut all_mods_found = true;
-        // try to find all the mods specified in the scenario
-        // manifest
-        for mod_to_load in mods_to_load {
-            let mod_to_load_name = mod_to_load.name.clone();
-            let mod_version_req = mod_to_load.version_req.clone();
-            let mut found_mod_match = false;
-            // only the top directories within the mods directory are
-            // considered
-            for mod_dir in util::get_top_dirs_at(scenario_mods_path.clone()) {
-                let mod_dir_name = mod_dir.file_name().unwrap().to_str().unwrap();
-                // we only want matching dir names
-                if mod_dir_name != mod_to_load_name {
-                    continue;
-                };
-                // path of the mod manifest we need to look for
-                let mod_manifest_path = mod_dir.join(MODULE_MANIFEST_FILE);
-                if mod_manifest_path.exists() {
-                    let module_manifest: ModuleManifest =
-                        ModuleManifest::from_dir_at(mod_dir.clone())?;
-                    // is the engine version requirement met?
-                    if !VersionReq::parse(&module_manifest.engine_version_req)?
-                        .matches(&Version::parse(VERSION)?)
-                    {
-                        return Err(Error::Other(format!("mod \"{}\" specifies a version requirement for `outcome` (\"engine\" entry) that does not match \
-                        the version this program is using (version of `outcome` used: \"{}\", version requirement: \"{}\")",
-                               module_manifest.name, VERSION, module_manifest.engine_version_req)));
-                    }
-                    // are the engine feature requirements met?
-                    for feature_req in &module_manifest.engine_features {
-                        match feature_req.as_str() {
-                            // TODO add more features
-                            crate::FEATURE_NAME_MACHINE_SYSINFO => {
-                                if !crate::FEATURE_MACHINE_SYSINFO {
-                                    return Err(Error::RequiredEngineFeatureNotAvailable(
-                                        crate::FEATURE_NAME_MACHINE_SYSINFO.to_string(),
-                                        module_manifest.name.clone(),
-                                    ));
-                                }
-                            }
-                            crate::FEATURE_NAME_MACHINE => {
-                                if !crate::FEATURE_MACHINE {
-                                    return Err(Error::RequiredEngineFeatureNotAvailable(
-                                        crate::FEATURE_NAME_MACHINE.to_string(),
-                                        module_manifest.name.clone(),
-                                    ));
-                                }
-                            }
-                            crate::FEATURE_NAME_MACHINE_DYNLIB => {
-                                if !crate::FEATURE_MACHINE_DYNLIB {
-                                    return Err(Error::RequiredEngineFeatureNotAvailable(
-                                        crate::FEATURE_NAME_MACHINE_DYNLIB.to_string(),
-                                        module_manifest.name.clone(),
-                                    ));
-                                }
-                            }
-                            f => unimplemented!(
-                                "unimplemented engine feature requirement: {}, module: {}",
-                                f,
-                                module_manifest.name
-                            ),
-                        }
-                    }
-
-                    // found mod that matches the name and version from scenario manifest
-                    if module_manifest.name == mod_to_load_name
-                        && VersionReq::parse(&mod_version_req)
-                            .unwrap_or(VersionReq::any())
-                            .matches(
-                                &Version::parse(&module_manifest.version)
-                                    .unwrap_or(Version::new(0, 1, 0)),
-                            )
-                    {
-                        info!(
-                            "mod found: \"{}\" version: \"{}\" (\"{}\")",
-                            mod_to_load_name,
-                            module_manifest.version.to_string(),
-                            mod_version_req.to_string()
-                        );
-                        let module = match Module::from_dir_at(mod_dir.clone()) {
-                            Ok(m) => m,
-                            Err(_) => {
-                                error!("failed creating module from path: {:?}", mod_dir.clone());
-                                continue;
-                            }
-                        };
-                        matching_mods.push(module);
-                        found_mod_match = true;
-                        break;
-                    }
-                }
-            }
-            // if no matching mod was found
-            if !found_mod_match {
-                all_mods_found = false;
-                mod_not_found_msgs.push(format!(
-                    "mod not found: name:\"{}\" version:\"{}\" specified in scenario manifest was not \
-                        found",
-                    mod_to_load_name, mod_version_req.to_string()));
-            }
-        }
-
-        // check if mod dependencies are present
-        if matching_mods.len() > 0 {
-            for n in 0..matching_mods.len() - 1 {
-                let module = &matching_mods[n].clone();
-                let mut missing_deps: Vec<ModuleDep> = Vec::new();
-                for (dep_name, dep) in &module.manifest.dependencies {
-                    // is the dependency mod present?
-                    if matching_mods.iter().any(|m| {
-                        &m.manifest.name == dep_name
-                            && VersionReq::parse(&dep.version_req)
-                                .unwrap_or(VersionReq::any())
-                                .matches(
-                                    &Version::parse(&m.manifest.version)
-                                        .unwrap_or(Version::new(0, 1, 0)),
-                                )
-                    }) {
-                        // we're fine
-                    } else {
-                        // dependency not present, throw an error
-                        error!(
-                            "dependency not available: \"{}\" (\"{}\"), \
-                             required by \"{}\" (\"{}\")",
-                            dep_name.clone(),
-                            dep.version_req.to_string(),
-                            module.manifest.name,
-                            module.manifest.version.to_string()
-                        );
-                        missing_deps.push(dep.clone());
-                        all_mods_found = false;
-                    }
-                }
-                if !missing_deps.is_empty() {
-                    matching_mods.remove(n);
-                }
-            }
-        }
-
-        // show errors about mods not found, they are shown after
-        // the mod found messages
-        for err_msg in mod_not_found_msgs {
-            error!("{}", err_msg);
-        }
-
-        // break if not all the mods were found
-        if !all_mods_found {
-            error!(
-                "failed to load all mods listed in the scenario manifest ({}/{})",
-                matching_mods.len(),
-                mods_to_load.len()
-            );
-            // error!("scenario creation process halted: missing modules");
-            return Err(Error::ScenarioMissingModules);
-        } else {
-            info!(
-                "found all mods listed in the scenario manifest ({})",
-                mods_to_load.len()
-            );
-        }
+        // mods found in the project's own mods directory take precedence
+        let mut search_dirs = vec![dir_path.join(MODULES_DIR_NAME)];
+        search_dirs.extend(mod_dirs.iter().cloned());
+        let modules = resolver::resolve(&scenario_manifest.name, mods_to_load, &search_dirs)?;
+        info!(
+            "resolved {} mods ({} listed in the scenario manifest)",
+            modules.len(),
+            mods_to_load.len()
+        );
 
         Ok(Scenario {
             path: dir_path.to_path_buf(),
             manifest: scenario_manifest,
-            modules: matching_mods,
+            modules,
         })
     }
+
+    /// Reads the scenario again from disk, keeping current values of
+    /// settings whose name and type didn't change.
+    ///
+    /// Manifest file is located within the project's scenarios directory
+    /// based on the scenario name.
+    pub fn reload(&self) -> Result<Scenario> {
+        let manifest_path = util::get_scenario_paths(self.path.clone())?
+            .into_iter()
+            .find(|path| {
+                ScenarioManifest::from_path(path.clone())
+                    .map(|m| m.name == self.manifest.name)
+                    .unwrap_or(false)
+            })
+            .ok_or(Error::Other(format!(
+                "failed finding manifest for scenario \"{}\" at: {}",
+                self.manifest.name,
+                self.path.to_string_lossy()
+            )))?;
+        let mut scenario = Scenario::from_path(manifest_path)?;
+        for setting in &self.manifest.settings {
+            if let Some(new_setting) = scenario
+                .manifest
+                .settings
+                .iter_mut()
+                .find(|s| s.name == setting.name && s.type_ == setting.type_)
+            {
+                new_setting.value = setting.value.clone();
+            }
+        }
+        Ok(scenario)
+    }
 }
 
 /// Module manifest model.
@@ -664,6 +1065,12 @@ pub struct ModuleManifest {
 
     pub libraries: Vec<ModuleLib>,
     pub services: Vec<ServiceModel>,
+    /// Lua globals persisted in snapshots for each entity with a Lua state
+    #[cfg(feature = "machine_lua")]
+    pub lua_globals: Vec<String>,
+    /// Paths to entity tables spawned at initialization, relative to
+    /// module directory
+    pub entity_tables: Vec<String>,
 
     // optional
     /// Free-form module name
@@ -686,10 +1093,11 @@ impl ModuleManifest {
             util::deser_struct_from_path(manifest_path.clone())?;
         let mut dep_map: HashMap<String, ModuleDep> = HashMap::new();
         for (name, value) in deser_manifest.dependencies {
-            // TODO
-            // dep_map.insert(name.clone(),
-            // ModuleDep::from_toml_value(&name,
-            // &value));
+            let dep = ModuleDep::from_toml_value(&name, &value).ok_or(Error::Other(format!(
+                "invalid dependency entry \"{}\" in manifest of mod \"{}\"",
+                name, deser_manifest._mod.name
+            )))?;
+            dep_map.insert(name, dep);
         }
         let mut req_vec: Vec<String> = Vec::new();
         for req in deser_manifest.reqs {
@@ -838,6 +1246,9 @@ impl ModuleManifest {
             reqs: req_vec,
             libraries: libs,
             services,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: deser_manifest.lua.globals,
+            entity_tables: deser_manifest.data.entity_tables,
             title: match deser_manifest._mod.title.as_str() {
                 "" => None,
                 s => Some(s.to_owned()),
@@ -870,6 +1281,49 @@ pub struct ModuleDep {
     pub git_address: Option<String>,
 }
 
+impl ModuleDep {
+    /// Create module dependency object from a serde value representation.
+    ///
+    /// Value can be either a version requirement string or a table with
+    /// `version` and `git` entries.
+    pub fn from_toml_value(name: &str, value: &Value) -> Option<ModuleDep> {
+        let (version, git_address) = if let Some(s) = value.as_str() {
+            (Some(s), None)
+        } else if let Some(mapping) = value.as_table() {
+            (
+                mapping.get("version").and_then(|v| v.as_str()),
+                mapping
+                    .get("git")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string()),
+            )
+        } else {
+            error!(
+                "module dep has to be either a string (version specifier) \
+                 or a mapping"
+            );
+            return None;
+        };
+        let version_req = match version.map(VersionReq::parse) {
+            Some(Ok(vr)) => vr.to_string(),
+            Some(Err(e)) => {
+                warn!(
+                    "failed parsing module dep version req for \"{}\" ({}), \
+                     using default \"*\" (any)",
+                    name, e
+                );
+                VersionReq::any().to_string()
+            }
+            None => VersionReq::any().to_string(),
+        };
+        Some(ModuleDep {
+            name: name.to_string(),
+            version_req,
+            git_address,
+        })
+    }
+}
+
 /// Library declared by a module.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleLib {
@@ -927,10 +1381,74 @@ pub struct EventModel {
 }
 
 /// Entity prefab model.
+///
+/// Prefabs can extend other prefabs, inheriting their components and var
+/// values. Use [`SimModel::resolve_prefab`] to get the prefab with the
+/// whole inheritance chain applied.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EntityPrefab {
     pub name: EntityName,
     pub components: Vec<CompName>,
+    /// Name of the prefab this one extends
+    pub extends: Option<EntityName>,
+    /// Initial var values, overriding component defaults
+    pub vars: Vec<VarOverride>,
+}
+
+/// Initial value of a single var, overriding the default value declared
+/// by the component.
+///
+/// Can be parsed from the `comp:type:var=value` format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VarOverride {
+    pub address: LocalAddress,
+    pub value: Var,
+}
+
+impl VarOverride {
+    /// Creates the override from separate address and value strings, with
+    /// the value parsed based on the address var type.
+    pub fn from_parts(address: &str, value: &str) -> Result<VarOverride> {
+        let address = LocalAddress::from_str(address)?;
+        let value = Var::from_str(value, Some(address.var_type))?;
+        Ok(VarOverride { address, value })
+    }
+}
+
+impl FromStr for VarOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let split = s.splitn(2, '=').collect::<Vec<&str>>();
+        if split.len() != 2 {
+            return Err(Error::Other(format!(
+                "invalid var override: {}, expected comp:type:var=value",
+                s
+            )));
+        }
+        VarOverride::from_parts(split[0].trim(), split[1].trim())
+    }
+}
+
+impl EntityPrefab {
+    pub fn from_deser(key: &str, val: deser::PrefabEntry) -> Result<Self> {
+        Ok(EntityPrefab {
+            name: string::new_truncate(key),
+            components: val
+                .components
+                .iter()
+                .map(|c| string::new_truncate(c))
+                .collect(),
+            extends: val.extends.map(|e| string::new_truncate(&e)),
+            vars: val
+                .vars
+                .into_iter()
+                .map(|(addr, value)| {
+                    VarOverride::from_parts(&addr, &Var::from(value).to_string())
+                })
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
 }
 
 // cfg_if! {
@@ -1064,6 +1582,64 @@ pub enum DataEntry {
     Grid((String, Vec<Vec<String>>)),
 }
 
+impl DataEntry {
+    /// Creates the entry from a toml value. Arrays are read as lists and
+    /// arrays of arrays as grids.
+    pub fn from_toml_value(address: &str, value: &Value) -> Result<DataEntry> {
+        let to_strings = |values: &Vec<Value>| {
+            values
+                .iter()
+                .map(util::coerce_toml_val_to_string)
+                .collect::<Vec<_>>()
+        };
+        match value {
+            #[cfg(feature = "grids")]
+            Value::Array(rows) if rows.iter().any(|r| r.is_array()) => {
+                let mut grid = Vec::new();
+                for row in rows {
+                    match row.as_array() {
+                        Some(row) => grid.push(to_strings(row)),
+                        None => {
+                            return Err(Error::InvalidDataEntry(
+                                address.to_string(),
+                                "grid rows must be arrays".to_string(),
+                            ))
+                        }
+                    }
+                }
+                Ok(DataEntry::Grid((address.to_string(), grid)))
+            }
+            #[cfg(not(feature = "grids"))]
+            Value::Array(rows) if rows.iter().any(|r| r.is_array()) => Err(
+                Error::InvalidDataEntry(address.to_string(), "grids not supported".to_string()),
+            ),
+            Value::Array(values) => Ok(DataEntry::List((address.to_string(), to_strings(values)))),
+            _ => Ok(DataEntry::Simple((
+                address.to_string(),
+                util::coerce_toml_val_to_string(value),
+            ))),
+        }
+    }
+
+    /// Gets the address of the var the entry is applied to.
+    pub fn address(&self) -> &str {
+        match self {
+            DataEntry::Simple((address, _)) | DataEntry::List((address, _)) => address,
+            #[cfg(feature = "grids")]
+            DataEntry::Grid((address, _)) => address,
+        }
+    }
+
+    fn to_toml_value(&self) -> Value {
+        match self {
+            DataEntry::Simple((_, value)) => Value::from(value.as_str()),
+            DataEntry::List((_, values)) => Value::from(values.clone()),
+            #[cfg(feature = "grids")]
+            DataEntry::Grid((_, rows)) => Value::from(rows.clone()),
+        }
+    }
+}
+
 /// Data file entry model.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DataFileEntry {
@@ -1077,15 +1653,257 @@ pub enum DataFileEntry {
     CsvGrid(String),
 }
 
+impl DataFileEntry {
+    /// Creates the entry using the file type name, e.g. `json_list`.
+    pub fn from_type_str(type_: &str, path: String) -> Result<DataFileEntry> {
+        Ok(match type_ {
+            "json" => DataFileEntry::Json(path),
+            "json_list" => DataFileEntry::JsonList(path),
+            "json_grid" => DataFileEntry::JsonGrid(path),
+            "yaml" => DataFileEntry::Yaml(path),
+            "yaml_list" => DataFileEntry::YamlList(path),
+            "yaml_grid" => DataFileEntry::YamlGrid(path),
+            "csv_list" => DataFileEntry::CsvList(path),
+            "csv_grid" => DataFileEntry::CsvGrid(path),
+            _ => {
+                return Err(Error::InvalidDataEntry(
+                    path,
+                    format!("unknown file type: {}", type_),
+                ))
+            }
+        })
+    }
+
+    /// Gets the name of the file type.
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            DataFileEntry::Json(_) => "json",
+            DataFileEntry::JsonList(_) => "json_list",
+            DataFileEntry::JsonGrid(_) => "json_grid",
+            DataFileEntry::Yaml(_) => "yaml",
+            DataFileEntry::YamlList(_) => "yaml_list",
+            DataFileEntry::YamlGrid(_) => "yaml_grid",
+            DataFileEntry::CsvList(_) => "csv_list",
+            DataFileEntry::CsvGrid(_) => "csv_grid",
+        }
+    }
+
+    /// Gets the path of the data file.
+    pub fn path(&self) -> &str {
+        match self {
+            DataFileEntry::Json(path)
+            | DataFileEntry::JsonList(path)
+            | DataFileEntry::JsonGrid(path)
+            | DataFileEntry::Yaml(path)
+            | DataFileEntry::YamlList(path)
+            | DataFileEntry::YamlGrid(path)
+            | DataFileEntry::CsvList(path)
+            | DataFileEntry::CsvGrid(path) => path,
+        }
+    }
+}
+
 /// Data image entry model. Used specifically for importing grid data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DataImageEntry {
     BmpU8(String, String),
     BmpU8U8U8(String, String),
-    // BmpCombineU8U8U8U8Int(String, String),
-    // TODO
     PngU8(String, String),
     PngU8U8U8(String, String),
     PngU8U8U8Concat(String, String),
-    // PngCombineU8U8U8U8(String, String),
+    PngCombineU8U8U8U8(String, String),
+    BmpCombineU8U8U8U8(String, String),
+}
+
+impl DataImageEntry {
+    /// Creates the entry from a toml table containing the image `type`,
+    /// e.g. `png_u8`, and `path`.
+    pub fn from_toml_value(address: &str, value: &Value) -> Result<DataImageEntry> {
+        let get = |key: &str| {
+            value.get(key).and_then(|v| v.as_str()).ok_or_else(|| {
+                Error::InvalidDataEntry(address.to_string(), format!("missing {}", key))
+            })
+        };
+        DataImageEntry::from_type_str(get("type")?, address.to_string(), get("path")?.to_string())
+    }
+
+    /// Creates the entry using the image type name, e.g. `png_u8`.
+    pub fn from_type_str(type_: &str, address: String, path: String) -> Result<DataImageEntry> {
+        Ok(match type_ {
+            "bmp_u8" => DataImageEntry::BmpU8(address, path),
+            "bmp_u8u8u8" => DataImageEntry::BmpU8U8U8(address, path),
+            "bmp_u8u8u8u8_combine" => DataImageEntry::BmpCombineU8U8U8U8(address, path),
+            "png_u8" => DataImageEntry::PngU8(address, path),
+            "png_u8u8u8" => DataImageEntry::PngU8U8U8(address, path),
+            "png_u8u8u8_concat" => DataImageEntry::PngU8U8U8Concat(address, path),
+            "png_u8u8u8u8_combine" => DataImageEntry::PngCombineU8U8U8U8(address, path),
+            _ => {
+                return Err(Error::InvalidDataEntry(
+                    address,
+                    format!("unknown image type: {}", type_),
+                ))
+            }
+        })
+    }
+
+    /// Gets the name of the image type.
+    pub fn type_str(&self) -> &'static str {
+        match self {
+            DataImageEntry::BmpU8(..) => "bmp_u8",
+            DataImageEntry::BmpU8U8U8(..) => "bmp_u8u8u8",
+            DataImageEntry::BmpCombineU8U8U8U8(..) => "bmp_u8u8u8u8_combine",
+            DataImageEntry::PngU8(..) => "png_u8",
+            DataImageEntry::PngU8U8U8(..) => "png_u8u8u8",
+            DataImageEntry::PngU8U8U8Concat(..) => "png_u8u8u8_concat",
+            DataImageEntry::PngCombineU8U8U8U8(..) => "png_u8u8u8u8_combine",
+        }
+    }
+
+    /// Gets the address of the grid var the image is applied to.
+    pub fn address(&self) -> &str {
+        self.parts().0
+    }
+
+    /// Gets the path of the image.
+    pub fn path(&self) -> &str {
+        self.parts().1
+    }
+
+    fn parts(&self) -> (&str, &str) {
+        match self {
+            DataImageEntry::BmpU8(address, path)
+            | DataImageEntry::BmpU8U8U8(address, path)
+            | DataImageEntry::BmpCombineU8U8U8U8(address, path)
+            | DataImageEntry::PngU8(address, path)
+            | DataImageEntry::PngU8U8U8(address, path)
+            | DataImageEntry::PngU8U8U8Concat(address, path)
+            | DataImageEntry::PngCombineU8U8U8U8(address, path) => {
+                (address.as_str(), path.as_str())
+            }
+        }
+    }
+
+    fn to_toml_value(&self) -> Value {
+        let mut table = toml::value::Table::new();
+        table.insert("type".to_string(), Value::from(self.type_str()));
+        table.insert("path".to_string(), Value::from(self.path()));
+        Value::Table(table)
+    }
+}
+
+#[test]
+fn scenario_manifest_extends() {
+    let dir = std::env::temp_dir().join(format!("outcome-extends-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("base.toml"),
+        "[scenario]\nname = \"base\"\nversion = \"0.1.0\"\nengine = \"*\"\n\
+         [mods]\ncore = \"0.1\"\ndebug_tools = \"*\"\n\
+         [settings.map_size]\ndefault = 64\ndesc = \"Map side length\"\n\
+         target = \"map:size:int:side\"\n\
+         [settings.seed]\ntarget = \"map:rng:int:seed\"\n\
+         [data.values]\n\"map:size:int:side\" = 64\n\"map:rng:int:seed\" = 1\n\
+         [data.images]\n\"map:terrain:int_grid:height\" = \
+         { type = \"png_u8\", path = \"img/height.png\" }\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("big.toml"),
+        "[scenario]\nname = \"big_map\"\nextends = \"base\"\n\
+         [mods]\nweather = \"0.2\"\n\
+         [settings]\nmap_size = 512\n\
+         [data.values]\n\"map:size:int:side\" = 512\n\
+         [remove]\nmods = [\"debug_tools\"]\nsettings = [\"seed\"]\n\
+         data = [\"map:rng:int:seed\"]\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("cycle_a.toml"),
+        "[scenario]\nname = \"cycle_a\"\nextends = \"cycle_b\"\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("cycle_b.toml"),
+        "[scenario]\nname = \"cycle_b\"\nextends = \"cycle_a\"\n",
+    )
+    .unwrap();
+
+    let manifest = ScenarioManifest::from_path(dir.join("big.toml"));
+    let cycle = ScenarioManifest::from_path(dir.join("cycle_a.toml"));
+    std::fs::remove_dir_all(&dir).ok();
+
+    let manifest = manifest.unwrap();
+    assert_eq!(manifest.name, "big_map");
+    assert_eq!(manifest.version, "0.1.0");
+    assert_eq!(manifest.extends, vec!["base".to_string()]);
+    let mods = manifest.mods.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(mods, vec!["core", "weather"]);
+    assert_eq!(manifest.settings.len(), 1);
+    let map_size = manifest.get_setting("map_size").unwrap();
+    assert_eq!(map_size.value, Var::Int(512));
+    assert!(map_size.desc.is_some());
+    match manifest.data.as_slice() {
+        [DataEntry::Simple((address, value))] => {
+            assert_eq!(address, "map:size:int:side");
+            assert_eq!(value, "512");
+        }
+        data => panic!("unexpected data: {:?}", data),
+    }
+    assert_eq!(manifest.data_imgs.len(), 1);
+    let toml = manifest.to_toml_string().unwrap();
+    assert!(toml.contains("weather"));
+    assert!(toml.contains("png_u8"));
+
+    match cycle {
+        Err(Error::ScenarioInheritanceCycle(chain)) => {
+            assert_eq!(chain, "cycle_a -> cycle_b -> cycle_a")
+        }
+        r => panic!("expected cycle error, got: {:?}", r.map(|_| ())),
+    }
+}
+
+#[test]
+fn scenario_setting_requires_target() {
+    let value = |s: &str| s.parse::<Value>().unwrap();
+    let setting =
+        ScenarioSetting::from_toml_value("seed", &value("target = \"map:rng:int:seed\"")).unwrap();
+    assert_eq!(setting.type_, VarType::Int);
+    assert!(ScenarioSetting::from_toml_value("seed", &value("type = \"int\"")).is_err());
+    assert!(ScenarioSetting::from_toml_value("seed", &value("default = 1")).is_err());
+}
+
+#[test]
+fn resolve_prefab_inheritance() {
+    let id = |s: &str| -> StringId { string::new_truncate(s) };
+    let mut model = SimModel::default();
+    model.entities.push(EntityPrefab {
+        name: id("bird"),
+        components: vec![id("position"), id("flight")],
+        extends: None,
+        vars: vec!["flight:float:speed=2".parse().unwrap()],
+    });
+    model.entities.push(EntityPrefab {
+        name: id("hawk"),
+        components: vec![id("hunter")],
+        extends: Some(id("bird")),
+        vars: vec![
+            "flight:float:speed=5.5".parse().unwrap(),
+            "hunter:int:range=10".parse().unwrap(),
+        ],
+    });
+    model.entities.push(EntityPrefab {
+        name: id("loop"),
+        extends: Some(id("loop")),
+        ..EntityPrefab::default()
+    });
+
+    let hawk = model.resolve_prefab(&id("hawk")).unwrap();
+    assert_eq!(hawk.components, vec![id("position"), id("flight"), id("hunter")]);
+    assert_eq!(hawk.vars.len(), 2);
+    match &hawk.vars[0].value {
+        Var::Float(speed) => assert_eq!(*speed, 5.5),
+        v => panic!("unexpected value: {:?}", v),
+    }
+    assert!(model.resolve_prefab(&id("loop")).is_err());
+    assert!("flight:float:speed".parse::<VarOverride>().is_err());
 }
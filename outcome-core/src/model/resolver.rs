@@ -0,0 +1,483 @@
+//! Module dependency resolution.
+//!
+//! Modules can be found in a number of candidate directories. Each top
+//! directory within a candidate directory that contains a module manifest
+//! is considered a module candidate, regardless of the directory name,
+//! which makes it possible to keep multiple versions of the same module
+//! side by side, e.g. `physics-0.1.0` and `physics-0.2.0`.
+//!
+//! Resolution picks the highest version of each module that satisfies all
+//! the requirements placed on it, both by the scenario and by other
+//! selected modules, and that is compatible with the current engine.
+//! Selected modules are then ordered so that each module is loaded after
+//! all of its dependencies.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use semver::{Version, VersionReq};
+
+use crate::error::Error;
+use crate::model::{Module, ModuleManifest, ScenarioModuleDep};
+use crate::{util, Result, MODULE_MANIFEST_FILE, VERSION};
+
+/// Maximum number of re-selection passes before giving up.
+const MAX_RESOLVE_PASSES: usize = 100;
+
+/// Version requirement placed on a module.
+#[derive(Debug, Clone)]
+pub struct Requirement {
+    /// Name of the required module
+    pub name: String,
+    pub version_req: VersionReq,
+    /// Description of where the requirement comes from
+    pub required_by: String,
+}
+
+/// Module found within one of the candidate directories.
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub manifest: ModuleManifest,
+    pub version: Version,
+    pub path: PathBuf,
+}
+
+/// Resolves scenario module dependencies against modules found in the
+/// given directories.
+///
+/// Directories are searched in order. If the same version of a module is
+/// present in multiple directories, the one found first is used.
+///
+/// Returned modules are ordered so that dependencies come before the
+/// modules that depend on them.
+pub fn resolve(
+    scenario_name: &str,
+    mods: &[ScenarioModuleDep],
+    dirs: &[PathBuf],
+) -> Result<Vec<Module>> {
+    let mut requirements = Vec::new();
+    for dep in mods {
+        requirements.push(Requirement {
+            name: dep.name.clone(),
+            version_req: parse_req(&dep.version_req, &dep.name)?,
+            required_by: format!("scenario \"{}\"", scenario_name),
+        });
+    }
+    let candidates = find_candidates(dirs);
+    let resolved = resolve_candidates(&requirements, &candidates)?;
+    Ok(resolved
+        .into_iter()
+        .map(|c| {
+            info!(
+                "using mod \"{}\" version \"{}\" ({})",
+                c.manifest.name,
+                c.version,
+                c.path.to_string_lossy()
+            );
+            Module {
+                manifest: c.manifest,
+                path: c.path,
+            }
+        })
+        .collect())
+}
+
+/// Finds module candidates in the given directories, grouped by module
+/// name.
+pub fn find_candidates(dirs: &[PathBuf]) -> HashMap<String, Vec<Candidate>> {
+    let mut candidates: HashMap<String, Vec<Candidate>> = HashMap::new();
+    for dir in dirs {
+        if !dir.is_dir() {
+            continue;
+        }
+        for mod_dir in util::get_top_dirs_at(dir.clone()) {
+            if !mod_dir.join(MODULE_MANIFEST_FILE).is_file() {
+                continue;
+            }
+            let manifest = match ModuleManifest::from_dir_at(mod_dir.clone()) {
+                Ok(m) => m,
+                Err(e) => {
+                    warn!(
+                        "skipping mod at {}: failed reading manifest: {}",
+                        mod_dir.to_string_lossy(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            let version = match Version::parse(&manifest.version) {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!(
+                        "skipping mod at {}: invalid version \"{}\": {}",
+                        mod_dir.to_string_lossy(),
+                        manifest.version,
+                        e
+                    );
+                    continue;
+                }
+            };
+            let entry = candidates.entry(manifest.name.clone()).or_default();
+            if entry.iter().any(|c| c.version == version) {
+                debug!(
+                    "ignoring duplicate mod \"{}\" version \"{}\" at {}",
+                    manifest.name,
+                    version,
+                    mod_dir.to_string_lossy()
+                );
+                continue;
+            }
+            entry.push(Candidate {
+                manifest,
+                version,
+                path: mod_dir,
+            });
+        }
+    }
+    candidates
+}
+
+/// Selects a single candidate for each required module and returns them
+/// in load order.
+pub fn resolve_candidates(
+    requirements: &[Requirement],
+    candidates: &HashMap<String, Vec<Candidate>>,
+) -> Result<Vec<Candidate>> {
+    let mut selected: HashMap<String, &Candidate> = HashMap::new();
+    for _ in 0..MAX_RESOLVE_PASSES {
+        // collect requirements from the scenario and all currently
+        // selected modules
+        let mut all_reqs: Vec<Requirement> = requirements.to_vec();
+        for candidate in selected.values() {
+            all_reqs.extend(module_requirements(&candidate.manifest)?);
+        }
+        let mut reqs_by_name: HashMap<&str, Vec<&Requirement>> = HashMap::new();
+        for req in &all_reqs {
+            reqs_by_name.entry(&req.name).or_default().push(req);
+        }
+
+        // drop modules that are no longer required by anything
+        selected.retain(|name, _| reqs_by_name.contains_key(name.as_str()));
+
+        let mut names = reqs_by_name.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let mut changed = false;
+        for name in names {
+            let reqs = &reqs_by_name[name];
+            if let Some(current) = selected.get(name) {
+                if reqs.iter().all(|r| r.version_req.matches(&current.version)) {
+                    continue;
+                }
+            }
+            let candidate = select_candidate(name, reqs, candidates)?;
+            selected.insert(name.to_string(), candidate);
+            changed = true;
+        }
+        if !changed {
+            let order = load_order(requirements, &selected)?;
+            return Ok(order
+                .into_iter()
+                .map(|name| selected[&name].clone())
+                .collect());
+        }
+    }
+    Err(Error::ModuleVersionConflict(
+        "*".to_string(),
+        format!(
+            "resolution did not settle after {} passes",
+            MAX_RESOLVE_PASSES
+        ),
+    ))
+}
+
+/// Picks the highest compatible version of a module matching all the
+/// requirements.
+fn select_candidate<'a>(
+    name: &str,
+    reqs: &[&Requirement],
+    candidates: &'a HashMap<String, Vec<Candidate>>,
+) -> Result<&'a Candidate> {
+    let available = match candidates.get(name) {
+        Some(c) if !c.is_empty() => c,
+        _ => {
+            let msg = describe_requirements(reqs);
+            error!("mod not found: \"{}\", required by: {}", name, msg);
+            return Err(Error::ModuleNotFound(name.to_string(), msg));
+        }
+    };
+
+    let mut rejected = Vec::new();
+    let mut best: Option<&Candidate> = None;
+    for candidate in available {
+        if !reqs.iter().all(|r| r.version_req.matches(&candidate.version)) {
+            continue;
+        }
+        if let Err(e) = check_engine_compat(&candidate.manifest) {
+            rejected.push(format!("{}: {}", candidate.version, e));
+            continue;
+        }
+        if best.map_or(true, |b| candidate.version > b.version) {
+            best = Some(candidate);
+        }
+    }
+
+    match best {
+        Some(candidate) => Ok(candidate),
+        None => {
+            let mut versions = available
+                .iter()
+                .map(|c| c.version.to_string())
+                .collect::<Vec<_>>();
+            versions.sort();
+            let mut msg = format!(
+                "required: {}; available versions: {}",
+                describe_requirements(reqs),
+                versions.join(", ")
+            );
+            if !rejected.is_empty() {
+                msg.push_str(&format!("; incompatible with engine: {}", rejected.join(", ")));
+            }
+            error!("no matching version of mod \"{}\": {}", name, msg);
+            Err(Error::ModuleVersionConflict(name.to_string(), msg))
+        }
+    }
+}
+
+/// Orders selected modules so that dependencies come first.
+///
+/// Modules are visited in the order they are listed in the scenario
+/// manifest, dependencies of a single module are visited in alphabetical
+/// order.
+fn load_order(
+    requirements: &[Requirement],
+    selected: &HashMap<String, &Candidate>,
+) -> Result<Vec<String>> {
+    fn visit(
+        name: &str,
+        selected: &HashMap<String, &Candidate>,
+        stack: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if let Some(pos) = stack.iter().position(|n| n == name) {
+            let mut cycle = stack[pos..].to_vec();
+            cycle.push(name.to_string());
+            let cycle = cycle.join(" -> ");
+            error!("mod dependency cycle detected: {}", cycle);
+            return Err(Error::ModuleDependencyCycle(cycle));
+        }
+        stack.push(name.to_string());
+        let mut deps = selected[name]
+            .manifest
+            .dependencies
+            .keys()
+            .collect::<Vec<_>>();
+        deps.sort();
+        for dep in deps {
+            visit(dep, selected, stack, order)?;
+        }
+        stack.pop();
+        order.push(name.to_string());
+        Ok(())
+    }
+
+    let mut order = Vec::new();
+    let mut stack = Vec::new();
+    for req in requirements {
+        visit(&req.name, selected, &mut stack, &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Gathers requirements placed on other modules by the given module.
+fn module_requirements(manifest: &ModuleManifest) -> Result<Vec<Requirement>> {
+    let mut reqs = Vec::new();
+    for (name, dep) in &manifest.dependencies {
+        reqs.push(Requirement {
+            name: name.clone(),
+            version_req: parse_req(&dep.version_req, name)?,
+            required_by: format!("mod \"{}\" ({})", manifest.name, manifest.version),
+        });
+    }
+    Ok(reqs)
+}
+
+fn describe_requirements(reqs: &[&Requirement]) -> String {
+    reqs.iter()
+        .map(|r| format!("\"{}\" by {}", r.version_req, r.required_by))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_req(req: &str, name: &str) -> Result<VersionReq> {
+    if req.trim().is_empty() {
+        return Ok(VersionReq::any());
+    }
+    VersionReq::parse(req).map_err(|e| {
+        Error::ModuleVersionConflict(
+            name.to_string(),
+            format!("invalid version requirement \"{}\": {}", req, e),
+        )
+    })
+}
+
+/// Checks whether the engine version and features requested by the module
+/// are satisfied.
+pub fn check_engine_compat(manifest: &ModuleManifest) -> Result<()> {
+    if !manifest.engine_version_req.trim().is_empty()
+        && !VersionReq::parse(&manifest.engine_version_req)?.matches(&Version::parse(VERSION)?)
+    {
+        return Err(Error::ModuleEngineVersionMismatch(
+            manifest.name.clone(),
+            format!(
+                "requires engine \"{}\", current engine version: \"{}\"",
+                manifest.engine_version_req, VERSION
+            ),
+        ));
+    }
+    for feature in &manifest.engine_features {
+        match engine_feature(feature) {
+            Some(true) => (),
+            Some(false) => {
+                return Err(Error::RequiredEngineFeatureNotAvailable(
+                    feature.clone(),
+                    manifest.name.clone(),
+                ))
+            }
+            None => {
+                return Err(Error::UnknownEngineFeature(
+                    feature.clone(),
+                    manifest.name.clone(),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether the engine feature with the given name is enabled.
+///
+/// Returns `None` if the feature name is not known.
+pub fn engine_feature(name: &str) -> Option<bool> {
+    let enabled = match name {
+        crate::FEATURE_NAME_BIG_NUMS => crate::FEATURE_BIG_NUMS,
+        crate::FEATURE_NAME_STACK_STRINGID => crate::FEATURE_STACK_STRINGID,
+        crate::FEATURE_NAME_SHORT_STRINGID => crate::FEATURE_SHORT_STRINGID,
+        crate::FEATURE_NAME_MACHINE_SYSINFO => crate::FEATURE_MACHINE_SYSINFO,
+        crate::FEATURE_NAME_MACHINE_SCRIPT => crate::FEATURE_MACHINE_SCRIPT,
+        crate::FEATURE_NAME_MACHINE => crate::FEATURE_MACHINE,
+        crate::FEATURE_NAME_MACHINE_DYNLIB => crate::FEATURE_MACHINE_DYNLIB,
+        crate::FEATURE_NAME_MACHINE_LUA => crate::FEATURE_MACHINE_LUA,
+        crate::FEATURE_NAME_MACHINE_SANDBOX => crate::FEATURE_MACHINE_SANDBOX,
+        crate::FEATURE_NAME_MACHINE_COMPLETE => crate::FEATURE_MACHINE_COMPLETE,
+        _ => return None,
+    };
+    Some(enabled)
+}
+
+#[cfg(test)]
+fn test_candidate(name: &str, version: &str, deps: &[(&str, &str)]) -> Candidate {
+    use crate::model::ModuleDep;
+    let dependencies = deps
+        .iter()
+        .map(|(n, v)| {
+            (
+                n.to_string(),
+                ModuleDep {
+                    name: n.to_string(),
+                    version_req: v.to_string(),
+                    git_address: None,
+                },
+            )
+        })
+        .collect();
+    Candidate {
+        manifest: ModuleManifest {
+            name: name.to_string(),
+            version: version.to_string(),
+            engine_version_req: String::new(),
+            engine_features: Vec::new(),
+            dependencies,
+            reqs: Vec::new(),
+            libraries: Vec::new(),
+            services: Vec::new(),
+            #[cfg(feature = "machine_lua")]
+            lua_globals: Vec::new(),
+            entity_tables: Vec::new(),
+            title: None,
+            desc: None,
+            desc_long: None,
+            author: None,
+            website: None,
+        },
+        version: Version::parse(version).unwrap(),
+        path: PathBuf::new(),
+    }
+}
+
+#[test]
+fn resolve_picks_highest_matching_in_load_order() {
+    let mut candidates: HashMap<String, Vec<Candidate>> = HashMap::new();
+    candidates.insert(
+        "app".to_string(),
+        vec![test_candidate("app", "1.0.0", &[("base", "^0.1")])],
+    );
+    candidates.insert(
+        "base".to_string(),
+        vec![
+            test_candidate("base", "0.1.0", &[]),
+            test_candidate("base", "0.1.4", &[]),
+            test_candidate("base", "0.2.0", &[]),
+        ],
+    );
+    let reqs = vec![Requirement {
+        name: "app".to_string(),
+        version_req: VersionReq::any(),
+        required_by: "test".to_string(),
+    }];
+    let resolved = resolve_candidates(&reqs, &candidates).unwrap();
+    let resolved = resolved
+        .iter()
+        .map(|c| (c.manifest.name.as_str(), c.version.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        resolved,
+        vec![("base", "0.1.4".to_string()), ("app", "1.0.0".to_string())]
+    );
+
+    // conflicting requirement on the dependency
+    let mut conflicting = reqs.clone();
+    conflicting.push(Requirement {
+        name: "base".to_string(),
+        version_req: VersionReq::parse("^0.2").unwrap(),
+        required_by: "test".to_string(),
+    });
+    assert!(matches!(
+        resolve_candidates(&conflicting, &candidates),
+        Err(Error::ModuleVersionConflict(..))
+    ));
+}
+
+#[test]
+fn resolve_detects_cycles() {
+    let mut candidates: HashMap<String, Vec<Candidate>> = HashMap::new();
+    candidates.insert(
+        "a".to_string(),
+        vec![test_candidate("a", "1.0.0", &[("b", "*")])],
+    );
+    candidates.insert(
+        "b".to_string(),
+        vec![test_candidate("b", "1.0.0", &[("a", "*")])],
+    );
+    let reqs = vec![Requirement {
+        name: "a".to_string(),
+        version_req: VersionReq::any(),
+        required_by: "test".to_string(),
+    }];
+    match resolve_candidates(&reqs, &candidates) {
+        Err(Error::ModuleDependencyCycle(cycle)) => assert_eq!(cycle, "a -> b -> a"),
+        r => panic!("expected cycle error, got: {:?}", r.map(|_| ())),
+    }
+}
@@ -0,0 +1,268 @@
+//! Entity tables, used for spawning entities in bulk.
+//!
+//! Entity table is a tabular data file where each row describes a single
+//! entity. The `prefab` column is required and holds the name of the prefab
+//! the entity is spawned from. The optional `name` column holds the entity
+//! name, empty values meaning the entity is left unnamed. All the other
+//! columns are addresses of the vars to set on the spawned entity, in the
+//! `comp:type:var` format, holding values of the matching type. Empty cells
+//! leave the var at the value defined by the prefab.
+//!
+//! Supported formats are CSV files with a header row, as well as JSON and
+//! YAML files holding a list of objects keyed with column names.
+//!
+//! ```text
+//! prefab,name,household:int:members,household:float:income
+//! household,,4,52000
+//! household,mayor,2,98000
+//! ```
+//!
+//! Tables can be listed in the scenario and module manifests, in which
+//! case they are spawned at initialization, or spawned at runtime using
+//! the `spawn_from` command.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::address::{LocalAddress, ShortLocalAddress};
+use crate::error::Error;
+use crate::{string, EntityName, Result, Var};
+
+/// Name of the column holding prefab names.
+pub const PREFAB_COLUMN: &str = "prefab";
+/// Name of the column holding entity names.
+pub const NAME_COLUMN: &str = "name";
+
+/// Table of entities to spawn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EntityTable {
+    /// Addresses of the vars set using the table values
+    pub columns: Vec<LocalAddress>,
+    pub rows: Vec<EntityRow>,
+}
+
+/// Single entity definition from the table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityRow {
+    pub prefab: EntityName,
+    pub name: Option<EntityName>,
+    /// Values for each of the table columns, `None` for empty cells
+    pub values: Vec<Option<Var>>,
+}
+
+impl EntityTable {
+    /// Reads the table from file, choosing the format based on the file
+    /// extension.
+    pub fn from_path(path: &Path) -> Result<EntityTable> {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        match ext.as_str() {
+            "csv" => EntityTable::from_csv(path),
+            #[cfg(feature = "yaml")]
+            "json" | "yaml" | "yml" => EntityTable::from_structured(path),
+            _ => Err(invalid(
+                path,
+                &format!(
+                    "unsupported file format \"{}\", supported formats: csv, json, yaml",
+                    ext
+                ),
+            )),
+        }
+    }
+
+    fn from_csv(path: &Path) -> Result<EntityTable> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_path(path)
+            .map_err(|e| invalid(path, &e.to_string()))?;
+        let header = reader
+            .headers()
+            .map_err(|e| invalid(path, &e.to_string()))?
+            .iter()
+            .map(|h| h.to_string())
+            .collect::<Vec<_>>();
+        let mut builder = TableBuilder::new(path, &header)?;
+        for (n, record) in reader.records().enumerate() {
+            let record = record.map_err(|e| invalid(path, &e.to_string()))?;
+            // account for the header row and 1-based line numbering
+            builder.push_row(n + 2, |i| record.get(i).filter(|s| !s.is_empty()))?;
+        }
+        Ok(builder.table)
+    }
+
+    /// Reads the table from a JSON or YAML list of objects. JSON is read
+    /// using the YAML parser, as it's a subset of YAML.
+    #[cfg(feature = "yaml")]
+    fn from_structured(path: &Path) -> Result<EntityTable> {
+        use serde_yaml::{Mapping, Value};
+
+        let file = File::open(path)?;
+        let entries: Vec<Mapping> = serde_yaml::from_reader(BufReader::new(file))
+            .map_err(|e| invalid(path, &e.to_string()))?;
+
+        // columns are collected from all the entries, in order of appearance
+        let mut header: Vec<String> = Vec::new();
+        for entry in &entries {
+            for key in entry.keys() {
+                let key = key
+                    .as_str()
+                    .ok_or_else(|| invalid(path, "object keys must be strings"))?;
+                if !header.iter().any(|h| h == key) {
+                    header.push(key.to_string());
+                }
+            }
+        }
+
+        let mut builder = TableBuilder::new(path, &header)?;
+        for (n, entry) in entries.iter().enumerate() {
+            let cells = header
+                .iter()
+                .map(|column| match entry.get(&Value::String(column.clone())) {
+                    Some(Value::String(s)) => Some(s.clone()),
+                    Some(Value::Number(num)) => Some(num.to_string()),
+                    Some(Value::Bool(b)) => Some(b.to_string()),
+                    Some(Value::Sequence(seq)) => Some(
+                        seq.iter()
+                            .map(|v| match v {
+                                Value::String(s) => s.clone(),
+                                Value::Number(num) => num.to_string(),
+                                Value::Bool(b) => b.to_string(),
+                                _ => String::new(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    ),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            builder.push_row(n + 1, |i| cells[i].as_deref().filter(|s| !s.is_empty()))?;
+        }
+        Ok(builder.table)
+    }
+}
+
+/// Builds the table row by row, parsing values based on the column
+/// addresses.
+struct TableBuilder<'a> {
+    path: &'a Path,
+    prefab_idx: usize,
+    name_idx: Option<usize>,
+    /// Indices of value columns within the header
+    value_idxs: Vec<usize>,
+    table: EntityTable,
+}
+
+impl<'a> TableBuilder<'a> {
+    fn new(path: &'a Path, header: &[String]) -> Result<Self> {
+        let mut prefab_idx = None;
+        let mut name_idx = None;
+        let mut value_idxs = Vec::new();
+        let mut table = EntityTable::default();
+        for (n, column) in header.iter().enumerate() {
+            match column.as_str() {
+                PREFAB_COLUMN => prefab_idx = Some(n),
+                NAME_COLUMN => name_idx = Some(n),
+                _ => {
+                    let addr = ShortLocalAddress::from_str(column)
+                        .map_err(|e| invalid(path, &format!("column \"{}\": {}", column, e)))?;
+                    let comp = addr.comp.ok_or_else(|| {
+                        invalid(
+                            path,
+                            &format!(
+                                "column \"{}\": address must include the component name",
+                                column
+                            ),
+                        )
+                    })?;
+                    value_idxs.push(n);
+                    table.columns.push(LocalAddress {
+                        comp,
+                        var_type: addr.var_type,
+                        var_name: addr.var_name,
+                    });
+                }
+            }
+        }
+        let prefab_idx = prefab_idx.ok_or_else(|| {
+            invalid(path, &format!("missing \"{}\" column", PREFAB_COLUMN))
+        })?;
+        Ok(TableBuilder {
+            path,
+            prefab_idx,
+            name_idx,
+            value_idxs,
+            table,
+        })
+    }
+
+    /// Parses a single row, with cells accessed by header index.
+    fn push_row<'b, F>(&mut self, line: usize, cell: F) -> Result<()>
+    where
+        F: Fn(usize) -> Option<&'b str>,
+    {
+        let prefab = cell(self.prefab_idx).ok_or_else(|| {
+            invalid(self.path, &format!("row {}: missing prefab name", line))
+        })?;
+        let name = self.name_idx.and_then(|i| cell(i));
+        let mut values = Vec::with_capacity(self.value_idxs.len());
+        for (column, idx) in self.table.columns.iter().zip(&self.value_idxs) {
+            let value = match cell(*idx) {
+                Some(s) => Some(Var::from_str(s, Some(column.var_type)).map_err(|e| {
+                    invalid(
+                        self.path,
+                        &format!(
+                            "row {}: invalid {} value for \"{}:{}\": {}",
+                            line,
+                            column.var_type.to_str(),
+                            column.comp,
+                            column.var_name,
+                            e
+                        ),
+                    )
+                })?),
+                None => None,
+            };
+            values.push(value);
+        }
+        self.table.rows.push(EntityRow {
+            prefab: string::new_truncate(prefab),
+            name: name.map(|n| string::new_truncate(n)),
+            values,
+        });
+        Ok(())
+    }
+}
+
+fn invalid(path: &Path, msg: &str) -> Error {
+    Error::InvalidEntityTable(path.to_string_lossy().to_string(), msg.to_string())
+}
+
+#[test]
+fn entity_table_from_csv() {
+    let path = std::env::temp_dir().join(format!("outcome-table-{}.csv", std::process::id()));
+    std::fs::write(
+        &path,
+        "prefab,name,household:int:members,household:float:income\n\
+         household,,4,52000\n\
+         household,mayor,,98000.5\n",
+    )
+    .unwrap();
+    let table = EntityTable::from_path(&path);
+    std::fs::remove_file(&path).ok();
+    let table = table.unwrap();
+
+    assert_eq!(table.columns.len(), 2);
+    assert_eq!(table.rows.len(), 2);
+    assert!(table.rows[0].name.is_none());
+    assert_eq!(table.rows[1].name.as_deref(), Some("mayor"));
+    assert!(table.rows[1].values[0].is_none());
+    match &table.rows[0].values[0] {
+        Some(Var::Int(members)) => assert_eq!(*members, 4),
+        v => panic!("unexpected value: {:?}", v),
+    }
+}
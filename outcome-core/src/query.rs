@@ -1,6 +1,7 @@
 //! Data query system.
 
 use crate::entity::Entity;
+use crate::error::Error;
 use crate::{
     Address, CompName, EntityId, EntityName, EventName, Float, Int, Result, StringId, Var, VarName,
     VarType,
@@ -56,6 +57,101 @@ impl QueryProduct {
 
         final_product
     }
+
+    /// Flattens addressed products into a single address-var map.
+    pub fn to_addressed_vars(&self) -> Result<FnvHashMap<Address, Var>> {
+        match self {
+            QueryProduct::AddressedVar(map) => Ok(map.clone()),
+            QueryProduct::AddressedTyped(atm) => {
+                let mut map = FnvHashMap::default();
+                for (addr, v) in &atm.strings {
+                    map.insert(addr.clone(), Var::String(v.clone()));
+                }
+                for (addr, v) in &atm.ints {
+                    map.insert(addr.clone(), Var::Int(*v));
+                }
+                for (addr, v) in &atm.floats {
+                    map.insert(addr.clone(), Var::Float(*v));
+                }
+                for (addr, v) in &atm.bools {
+                    map.insert(addr.clone(), Var::Bool(*v));
+                }
+                Ok(map)
+            }
+            QueryProduct::Empty => Ok(FnvHashMap::default()),
+            _ => Err(Error::Other(
+                "only addressed query products can be flattened to address-var map".to_string(),
+            )),
+        }
+    }
+
+    /// Calculates the difference between this product and a previous one.
+    ///
+    /// If there is no previous product, everything is reported as added.
+    pub fn diff(&self, previous: Option<&FnvHashMap<Address, Var>>) -> Result<QueryProductDiff> {
+        let current = self.to_addressed_vars()?;
+        Ok(QueryProductDiff::between(previous, &current))
+    }
+}
+
+/// Incremental query product, describing changes since the last product
+/// sent for the same query.
+#[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct QueryProductDiff {
+    /// Vars present in the current product but not in the previous one
+    pub added: FnvHashMap<Address, Var>,
+    /// Vars present in both products, with changed values
+    pub changed: FnvHashMap<Address, Var>,
+    /// Addresses present in the previous product but not in the current one
+    pub removed: Vec<Address>,
+}
+
+impl QueryProductDiff {
+    /// Creates a diff between two address-var maps.
+    pub fn between(
+        previous: Option<&FnvHashMap<Address, Var>>,
+        current: &FnvHashMap<Address, Var>,
+    ) -> Self {
+        let mut diff = QueryProductDiff::default();
+        match previous {
+            Some(previous) => {
+                for (addr, var) in current {
+                    match previous.get(addr) {
+                        Some(prev_var) => {
+                            if prev_var != var {
+                                diff.changed.insert(addr.clone(), var.clone());
+                            }
+                        }
+                        None => {
+                            diff.added.insert(addr.clone(), var.clone());
+                        }
+                    }
+                }
+                for addr in previous.keys() {
+                    if !current.contains_key(addr) {
+                        diff.removed.push(addr.clone());
+                    }
+                }
+            }
+            None => diff.added = current.clone(),
+        }
+        diff
+    }
+
+    /// Returns true if the diff doesn't carry any changes.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+
+    /// Applies the diff to an address-var map, bringing it up to date.
+    pub fn apply_to(&self, map: &mut FnvHashMap<Address, Var>) {
+        for addr in &self.removed {
+            map.remove(addr);
+        }
+        for (addr, var) in self.added.iter().chain(self.changed.iter()) {
+            map.insert(addr.clone(), var.clone());
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -475,3 +571,22 @@ pub enum Layout {
     Typed,
     // TypedSubset(Vec<VarType>),
 }
+
+#[test]
+fn query_product_diff_roundtrip() {
+    let addr = |s: &str| -> Address { s.parse().unwrap() };
+    let mut previous = FnvHashMap::default();
+    previous.insert(addr("1:position:float:x"), Var::Float(1.));
+    previous.insert(addr("1:position:float:y"), Var::Float(2.));
+    let mut current = FnvHashMap::default();
+    current.insert(addr("1:position:float:x"), Var::Float(3.));
+    current.insert(addr("2:position:float:x"), Var::Float(4.));
+
+    let diff = QueryProductDiff::between(Some(&previous), &current);
+    assert_eq!(diff.added.len(), 1);
+    assert_eq!(diff.changed.len(), 1);
+    assert_eq!(diff.removed, vec![addr("1:position:float:y")]);
+
+    diff.apply_to(&mut previous);
+    assert_eq!(previous, current);
+}
@@ -0,0 +1,260 @@
+//! Debugging functions for the `Sim` struct.
+
+use crate::error::Error;
+use crate::machine::debug::{
+    DebugStatus, Debugger, Frame, Locals, PauseInfo, PauseReason, RunMode, StepProgress,
+};
+use crate::machine::exec::{self, Flow};
+use crate::machine::{CallStackVec, LocationInfo, Registry, ScopeStackVec};
+use crate::{CompName, EntityId, Result};
+
+use super::Sim;
+
+/// Debugging of the component logic.
+impl Sim {
+    /// Attaches a new debugger if one isn't attached already, returning a
+    /// mutable reference to it.
+    ///
+    /// While the debugger is attached, simulation steps are processed
+    /// sequentially, one entity at a time.
+    pub fn attach_debugger(&mut self) -> &mut Debugger {
+        self.debugger.get_or_insert_with(Debugger::new)
+    }
+
+    /// Detaches the debugger. If a step is currently paused it's finished
+    /// first, ignoring any breakpoints.
+    pub fn detach_debugger(&mut self) -> Result<Option<Debugger>> {
+        if self.debugger.as_ref().map(|d| d.progress.is_some()) == Some(true) {
+            self.debug_run(RunMode::Finish)?;
+        }
+        Ok(self.debugger.take())
+    }
+
+    pub fn debugger(&self) -> Option<&Debugger> {
+        self.debugger.as_ref()
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    /// Checks whether the simulation is paused in the middle of a step.
+    pub fn is_paused(&self) -> bool {
+        self.debugger
+            .as_ref()
+            .map(|d| d.paused.is_some())
+            .unwrap_or(false)
+    }
+
+    /// Runs until the next breakpoint is hit or until the current step is
+    /// finished. If no step is being processed a new one is started.
+    pub fn debug_continue(&mut self) -> Result<DebugStatus> {
+        self.debug_run(RunMode::Continue)
+    }
+
+    /// Executes a single command and pauses before the next one. If no
+    /// step is being processed a new one is started, pausing before its
+    /// first command.
+    pub fn debug_step_cmd(&mut self) -> Result<DebugStatus> {
+        self.debug_run(RunMode::StepCmd)
+    }
+
+    /// Gets the vars of the paused entity along with the local vars, the
+    /// call stack and the registry of the paused execution.
+    pub fn debug_locals(&self) -> Option<Locals> {
+        let debugger = self.debugger.as_ref()?;
+        debugger.paused.as_ref()?;
+        let frame = debugger.progress.as_ref()?.frame.as_ref()?;
+        let entity = self.entities.get(&frame.ent)?;
+        let mut vars = entity
+            .storage
+            .map
+            .iter()
+            .map(|(idx, var)| (idx.clone(), var.clone()))
+            .collect::<Vec<_>>();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut local_vars = frame
+            .scopes
+            .last()
+            .map(|locals| {
+                locals
+                    .iter()
+                    .map(|(idx, var)| (idx.clone(), var.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        local_vars.sort_by(|a, b| a.0.cmp(&b.0));
+        Some(Locals {
+            vars,
+            local_vars,
+            call_stack: frame.call_stack.iter().cloned().collect(),
+            registry: frame.registry,
+        })
+    }
+
+    fn debug_run(&mut self, mode: RunMode) -> Result<DebugStatus> {
+        let mut debugger = self
+            .debugger
+            .take()
+            .ok_or_else(|| Error::Other("debugger not attached".to_string()))?;
+        let status = self.debug_run_with(&mut debugger, mode);
+        self.debugger = Some(debugger);
+        status
+    }
+
+    fn debug_run_with(&mut self, debugger: &mut Debugger, mode: RunMode) -> Result<DebugStatus> {
+        let resuming = debugger.paused.take().is_some();
+        let mut progress = match debugger.progress.take() {
+            Some(progress) => progress,
+            None => StepProgress::new(&self.take_event_queue(), &self.entities),
+        };
+
+        let mut executed = 0;
+        loop {
+            let frame = match progress.frame.as_mut() {
+                Some(frame) => frame,
+                None => match progress.tasks.pop_front() {
+                    Some((ent, comp)) => {
+                        progress.frame = self.start_frame(ent, comp);
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+
+            let comp_model = self.model.get_component(&frame.comp)?;
+            let commands = &comp_model.logic.commands;
+            let locations = &comp_model.logic.cmd_location_map;
+            let done = frame.cmd_n >= commands.len()
+                || (frame.call_stack.is_empty()
+                    && frame.end.map(|end| frame.cmd_n >= end).unwrap_or(false));
+            if done {
+                progress.frame = None;
+                continue;
+            }
+            let location = locations.get(frame.cmd_n).ok_or_else(|| {
+                Error::Other(format!(
+                    "location info not found for command: {:?}",
+                    commands[frame.cmd_n]
+                ))
+            })?;
+
+            // the command the execution was paused at is executed without
+            // checking breakpoints again
+            let first = executed == 0;
+            let reason = match mode {
+                RunMode::StepCmd if !first || !resuming => Some(PauseReason::Step),
+                RunMode::Continue if !(first && resuming) => {
+                    debugger.hit(frame, location).map(PauseReason::Breakpoint)
+                }
+                _ => None,
+            };
+            if let Some(reason) = reason {
+                let info = self.pause_info(frame, reason, location.clone());
+                debugger.paused = Some(info.clone());
+                debugger.progress = Some(progress);
+                return Ok(DebugStatus::Paused(info));
+            }
+
+            let entity = match self.entities.get_mut(&frame.ent) {
+                Some(entity) => entity,
+                None => {
+                    progress.frame = None;
+                    continue;
+                }
+            };
+            let comp_state = match entity.comp_state.get_mut(&frame.comp) {
+                Some(state) => state,
+                None => {
+                    progress.frame = None;
+                    continue;
+                }
+            };
+            let results = commands[frame.cmd_n].execute(
+                &mut entity.storage,
+                &mut entity.insta,
+                comp_state,
+                &mut frame.call_stack,
+                &mut frame.scopes,
+                &mut frame.registry,
+                &frame.comp,
+                &frame.ent,
+                &self.model,
+                location,
+                #[cfg(feature = "machine_dynlib")]
+                &self.libs,
+            );
+            executed += 1;
+            frame.executed += 1;
+            match exec::handle_loc_results(
+                results,
+                locations,
+                location,
+                &frame.ent,
+                &frame.comp,
+                &progress.ext_cmds,
+                &progress.central_ext_cmds,
+            ) {
+                Flow::Next => frame.cmd_n += 1,
+                Flow::Jump(n) => frame.cmd_n = n,
+                Flow::Break => progress.frame = None,
+            }
+        }
+
+        self.clear_inboxes();
+        exec::execute_ext(&mut progress.ext_cmds.lock().unwrap(), self)?;
+        exec::execute_central_ext(&progress.central_ext_cmds.lock().unwrap(), self)?;
+        self.finish_step()?;
+        Ok(DebugStatus::Finished)
+    }
+
+    /// Prepares execution of the component's current state, returning
+    /// `None` if there's nothing to execute.
+    fn start_frame(&self, ent: EntityId, comp: CompName) -> Option<Frame> {
+        let state = self.entities.get(&ent)?.comp_state.get(&comp)?.clone();
+        if state.as_str() == "idle" {
+            return None;
+        }
+        let (start, end) = *self
+            .model
+            .get_component(&comp)
+            .ok()?
+            .logic
+            .states
+            .get(&state)?;
+        Some(Frame {
+            ent,
+            comp,
+            state,
+            cmd_n: start,
+            end: Some(end),
+            executed: 0,
+            call_stack: CallStackVec::new(),
+            scopes: ScopeStackVec::new(),
+            registry: Registry::new(),
+        })
+    }
+
+    fn pause_info(&self, frame: &Frame, reason: PauseReason, location: LocationInfo) -> PauseInfo {
+        let state = self
+            .entities
+            .get(&frame.ent)
+            .and_then(|e| e.comp_state.get(&frame.comp))
+            .unwrap_or(&frame.state)
+            .clone();
+        let entity_name = self
+            .entity_idx
+            .iter()
+            .find(|(_, id)| **id == frame.ent)
+            .map(|(name, _)| name.clone());
+        PauseInfo {
+            reason,
+            entity: frame.ent,
+            entity_name,
+            comp: frame.comp.clone(),
+            state,
+            cmd_index: frame.cmd_n,
+            location,
+        }
+    }
+}
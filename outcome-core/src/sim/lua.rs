@@ -0,0 +1,147 @@
+//! Persistence of selected Lua globals.
+//!
+//! Lua states can't be serialized as a whole. Instead, modules declare
+//! a list of globals that should be persisted, and values of those globals
+//! are stored within snapshots for each entity that has a Lua state.
+//!
+//! Only primitive values and tables of primitives are supported. Globals
+//! holding other kinds of values are skipped with a warning.
+
+use rlua::{Context, Lua, Value};
+
+use crate::error::Error;
+use crate::Result;
+
+/// Persisted globals of a single Lua state.
+pub type LuaGlobals = Vec<(String, LuaGlobal)>;
+
+/// Serializable representation of a primitive Lua value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LuaPrimitive {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    String(String),
+}
+
+impl LuaPrimitive {
+    fn from_lua(value: Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(LuaPrimitive::Boolean(b)),
+            Value::Integer(i) => Some(LuaPrimitive::Integer(i)),
+            Value::Number(n) => Some(LuaPrimitive::Number(n)),
+            Value::String(s) => s.to_str().ok().map(|s| LuaPrimitive::String(s.to_string())),
+            _ => None,
+        }
+    }
+
+    fn to_lua<'lua>(&self, ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        Ok(match self {
+            LuaPrimitive::Boolean(b) => Value::Boolean(*b),
+            LuaPrimitive::Integer(i) => Value::Integer(*i),
+            LuaPrimitive::Number(n) => Value::Number(*n),
+            LuaPrimitive::String(s) => Value::String(ctx.create_string(s)?),
+        })
+    }
+}
+
+/// Serializable representation of a persisted Lua global.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LuaGlobal {
+    Primitive(LuaPrimitive),
+    /// Table with primitive keys and values
+    Table(Vec<(LuaPrimitive, LuaPrimitive)>),
+}
+
+impl LuaGlobal {
+    fn from_lua(value: Value) -> Option<Self> {
+        match value {
+            Value::Table(table) => {
+                let mut entries = Vec::new();
+                for pair in table.pairs::<Value, Value>() {
+                    let (key, value) = pair.ok()?;
+                    entries.push((LuaPrimitive::from_lua(key)?, LuaPrimitive::from_lua(value)?));
+                }
+                Some(LuaGlobal::Table(entries))
+            }
+            value => LuaPrimitive::from_lua(value).map(LuaGlobal::Primitive),
+        }
+    }
+
+    fn to_lua<'lua>(&self, ctx: Context<'lua>) -> rlua::Result<Value<'lua>> {
+        match self {
+            LuaGlobal::Primitive(primitive) => primitive.to_lua(ctx),
+            LuaGlobal::Table(entries) => {
+                let table = ctx.create_table()?;
+                for (key, value) in entries {
+                    table.set(key.to_lua(ctx)?, value.to_lua(ctx)?)?;
+                }
+                Ok(Value::Table(table))
+            }
+        }
+    }
+}
+
+/// Captures values of the globals with the given names.
+///
+/// Globals that are not set are omitted.
+pub fn capture_globals(lua: &Lua, names: &[String]) -> LuaGlobals {
+    lua.context(|ctx| {
+        let globals = ctx.globals();
+        let mut out = Vec::new();
+        for name in names {
+            match globals.get::<_, Value>(name.as_str()) {
+                Ok(Value::Nil) => (),
+                Ok(value) => match LuaGlobal::from_lua(value) {
+                    Some(global) => out.push((name.clone(), global)),
+                    None => warn!(
+                        "lua global \"{}\" is neither a primitive nor a table of primitives, \
+                         it won't be persisted",
+                        name
+                    ),
+                },
+                Err(e) => warn!("failed reading lua global \"{}\": {}", name, e),
+            }
+        }
+        out
+    })
+}
+
+/// Sets previously captured globals on the given Lua state.
+pub fn restore_globals(lua: &Lua, globals: &LuaGlobals) -> Result<()> {
+    lua.context(|ctx| {
+        let table = ctx.globals();
+        for (name, global) in globals {
+            global
+                .to_lua(ctx)
+                .and_then(|value| table.set(name.as_str(), value))
+                .map_err(|e| {
+                    Error::FailedReadingSnapshot(format!(
+                        "failed restoring lua global \"{}\": {}",
+                        name, e
+                    ))
+                })?;
+        }
+        Ok(())
+    })
+}
+
+#[test]
+fn lua_globals_roundtrip() {
+    let lua = Lua::new();
+    lua.context(|ctx| {
+        ctx.load("counter = 3; name = 'abc'; items = { 1, 2 }; f = function() end")
+            .exec()
+            .unwrap();
+    });
+    let names = ["counter", "name", "items", "f", "missing"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+    let globals = capture_globals(&lua, &names);
+    assert_eq!(globals.len(), 3);
+
+    let restored = Lua::new();
+    restore_globals(&restored, &globals).unwrap();
+    assert_eq!(capture_globals(&restored, &names), globals);
+}
@@ -2,10 +2,18 @@
 
 pub mod step;
 
+pub use step::StepStatus;
+
+#[cfg(feature = "machine")]
+pub mod debug;
+
+#[cfg(feature = "machine_lua")]
+pub mod lua;
+
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{Read, Stdout, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -18,14 +26,24 @@ use libloading::Library;
 #[cfg(feature = "machine_lua")]
 use rlua::Lua;
 
+#[cfg(feature = "machine_lua")]
+use self::lua::LuaGlobals;
+
 use fnv::FnvHashMap;
 use id_pool::IdPool;
 
 use crate::address::Address;
+use crate::checkpoint::{CheckpointSettings, Checkpointer, CHECKPOINTS_DIR_NAME};
 use crate::entity::{Entity, Storage};
 use crate::error::Error;
-use crate::model::{DataEntry, DataImageEntry, EventModel, Scenario};
-use crate::snapshot::{Snap, Snapshot};
+#[cfg(feature = "save_img")]
+use crate::img::{self, GridExport, GridImageOptions};
+#[cfg(feature = "machine")]
+use crate::machine::debug::Debugger;
+use crate::model::diff::{ModelDiff, ModelUpdate};
+use crate::model::table::EntityTable;
+use crate::model::{DataEntry, DataImageEntry, EventModel, Scenario, VarOverride};
+use crate::snapshot::{self, Snap, Snapshot, SnapshotBase, SnapshotDelta};
 use crate::{
     model, string, CompName, EntityId, EntityName, EventName, Result, SimModel, SimStarter,
     StringId, Var, VarType, FEATURE_NAME_SHORT_STRINGID, FEATURE_NAME_STACK_STRINGID,
@@ -81,6 +99,26 @@ pub struct Sim {
     #[cfg(feature = "machine_dynlib")]
     #[serde(skip)]
     pub libs: BTreeMap<String, libloading::Library>,
+    /// Whether loading dynamic libraries was already attempted
+    #[cfg(feature = "machine_dynlib")]
+    #[serde(skip)]
+    pub libs_loaded: bool,
+
+    /// Automatic checkpointing, if enabled
+    #[serde(skip)]
+    pub checkpointer: Option<Checkpointer>,
+    /// State of the most recently saved or loaded snapshot, used as the
+    /// base for delta snapshots
+    #[serde(skip)]
+    pub(crate) snapshot_base: Option<SnapshotBase>,
+    /// Grid vars periodically exported as images
+    #[cfg(feature = "save_img")]
+    #[serde(skip)]
+    pub grid_exports: Vec<GridExport>,
+    /// Debugger for the component logic, if attached
+    #[cfg(feature = "machine")]
+    #[serde(skip)]
+    pub(crate) debugger: Option<Debugger>,
 }
 
 /// Snapshot functionality.
@@ -90,63 +128,164 @@ impl Sim {
     /// # Compression
     ///
     /// Optional compression using LZ4 algorithm can be performed.
-    pub fn save_snapshot(&self, name: &str, compress: bool) -> Result<()> {
-        let mut data = self.to_snapshot()?;
+    pub fn save_snapshot(&mut self, name: &str, compress: bool) -> Result<()> {
+        let data = self.to_snapshot()?;
+        self.write_snapshot_file(name, data, compress)?;
+        self.snapshot_base = Some(SnapshotBase::new(name, self));
+        Ok(())
+    }
+
+    /// Saves a delta snapshot storing only the changes relative to the base
+    /// snapshot with the given name.
+    ///
+    /// State of the most recently saved or loaded snapshot is kept in
+    /// memory, other base snapshots are read from disk. Saved delta becomes
+    /// the base for the next delta.
+    pub fn save_snapshot_delta(&mut self, name: &str, base: &str, compress: bool) -> Result<()> {
+        let data = match &self.snapshot_base {
+            Some(snapshot_base) if snapshot_base.name == base => {
+                SnapshotDelta::between(snapshot_base, self)
+            }
+            _ => {
+                let base_sim = Sim::load_snapshot_from_dir(&self.snapshots_dir()?, base)?;
+                SnapshotDelta::between(&SnapshotBase::new(base, &base_sim), self)
+            }
+        }
+        .to_bytes()?;
+        self.write_snapshot_file(name, data, compress)?;
+        self.snapshot_base = Some(SnapshotBase::new(name, self));
+        Ok(())
+    }
+
+    fn snapshots_dir(&self) -> Result<PathBuf> {
         // TODO store project path on Sim struct?
         let project_path = crate::util::find_project_root(self.model.scenario.path.clone(), 3)?;
-        let snapshot_path = project_path.join(crate::SNAPSHOTS_DIR_NAME).join(name);
+        Ok(project_path.join(crate::SNAPSHOTS_DIR_NAME))
+    }
+
+    fn write_snapshot_file(&self, name: &str, mut data: Vec<u8>, compress: bool) -> Result<()> {
+        let snapshot_path = self.snapshots_dir()?.join(name);
 
         #[cfg(feature = "lz4")]
         {
             if compress {
-                data = lz4::block::compress(&data, None, true)?;
+                data = snapshot::compress_snapshot(data)?;
             }
         }
 
         let mut file = File::create(snapshot_path)?;
-        file.write_all(&data);
+        file.write_all(&data)?;
 
         Ok(())
     }
 
-    /// Creates new `Sim` from snapshot, using
+    /// Directory where automatic checkpoints for the current project are
+    /// stored.
+    pub fn checkpoints_dir(&self) -> Result<PathBuf> {
+        Ok(self.snapshots_dir()?.join(CHECKPOINTS_DIR_NAME))
+    }
+
+    /// Enables automatic checkpointing using the provided settings, or
+    /// disables it if `None` is passed.
+    ///
+    /// Pending checkpoint write is finished before the settings are
+    /// replaced.
+    pub fn set_checkpointing(&mut self, settings: Option<CheckpointSettings>) -> Result<()> {
+        if let Some(mut checkpointer) = self.checkpointer.take() {
+            checkpointer.finish()?;
+        }
+        if let Some(settings) = settings {
+            if settings.is_enabled() {
+                let checkpointer = Checkpointer::new(settings, self.checkpoints_dir()?, self.clock)?;
+                self.checkpointer = Some(checkpointer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the grid var at the given address to a PNG file.
+    #[cfg(feature = "save_img")]
+    pub fn export_grid_png(
+        &self,
+        address: &Address,
+        path: &Path,
+        options: &GridImageOptions,
+    ) -> Result<()> {
+        img::save_grid_png(self.get_var(address)?, path, options)
+    }
+
+    /// Exports grid images that are due at the current clock.
+    #[cfg(feature = "save_img")]
+    pub(crate) fn export_grids_if_due(&self) -> Result<()> {
+        for export in &self.grid_exports {
+            if export.is_due(self.clock) {
+                self.export_grid_png(
+                    &export.address,
+                    &export.file_path(self.clock),
+                    &export.options,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a checkpoint if one is due at the current clock.
+    pub(crate) fn checkpoint_if_due(&mut self) -> Result<()> {
+        let due = match &self.checkpointer {
+            Some(checkpointer) => checkpointer.is_due(self.clock),
+            None => false,
+        };
+        if due {
+            let data = self.to_snapshot()?;
+            if let Some(checkpointer) = &mut self.checkpointer {
+                checkpointer.write(self.clock, data)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates new `Sim` from snapshot, using the snapshot name to find
+    /// it within the current project's snapshots directory.
+    ///
+    /// Snapshots created with incompatible engine features or a newer
+    /// format version are rejected, while older format versions are
+    /// migrated on load. Delta snapshots are restored by loading the whole
+    /// chain of base snapshots.
     pub fn load_snapshot(name: &str, compressed: Option<bool>) -> Result<Self> {
         let project_path = crate::util::find_project_root(std::env::current_dir()?, 3)?;
-        let snapshot_path = project_path.join(crate::SNAPSHOTS_DIR_NAME).join(name);
-        let mut file = File::open(snapshot_path)?;
-        let mut bytes = Vec::new();
-        file.read_to_end(&mut bytes);
-        if let Some(compressed) = compressed {
-            if compressed {
-                #[cfg(feature = "lz4")]
-                {
-                    bytes = lz4::block::decompress(&bytes, None)?;
-                }
-            };
-            let sim = Sim::from_snapshot(&mut bytes)?;
-            Ok(sim)
-        } else {
-            // first try reading compressed
+        let snapshots_dir = project_path.join(crate::SNAPSHOTS_DIR_NAME);
+        let mut bytes = snapshot::read_snapshot_file(&snapshots_dir.join(name))?;
+        // compression of versioned snapshots is marked in the preamble,
+        // legacy snapshots can be explicitly marked as compressed
+        if let Some(true) = compressed {
             #[cfg(feature = "lz4")]
             {
-                // bytes = lz4::block::decompress(&bytes, None)?;
-                match lz4::block::decompress(&bytes, None) {
-                    Ok(mut bytes) => {
-                        let sim = Sim::from_snapshot(&mut bytes)?;
-                        return Ok(sim);
-                    }
-                    Err(_) => {
-                        let sim = Sim::from_snapshot(&mut bytes)?;
-                        return Ok(sim);
-                    }
+                if !snapshot::is_versioned(&bytes) {
+                    bytes = lz4::block::decompress(&bytes, None)?;
                 }
             }
-            #[cfg(not(feature = "lz4"))]
-            {
-                let sim = Sim::from_snapshot(&mut bytes)?;
-                return Ok(sim);
-            }
         }
+        Sim::from_snapshot_chain(&snapshots_dir, name, bytes)
+    }
+
+    /// Creates new `Sim` from snapshot with the given name, located in the
+    /// provided snapshots directory.
+    pub fn load_snapshot_from_dir(snapshots_dir: &Path, name: &str) -> Result<Self> {
+        let bytes = snapshot::read_snapshot_file(&snapshots_dir.join(name))?;
+        Sim::from_snapshot_chain(snapshots_dir, name, bytes)
+    }
+
+    /// Creates new `Sim` from snapshot bytes, applying any deltas on top of
+    /// their base snapshots found in the snapshots directory.
+    fn from_snapshot_chain(snapshots_dir: &Path, name: &str, bytes: Vec<u8>) -> Result<Self> {
+        let (mut base_bytes, deltas) = snapshot::resolve_delta_chain(snapshots_dir, name, bytes)?;
+        let mut sim = Sim::from_snapshot(&mut base_bytes)?;
+        for (delta_name, delta) in deltas {
+            trace!("applying delta snapshot: {}", delta_name);
+            delta.apply_to(&mut sim)?;
+        }
+        sim.snapshot_base = Some(SnapshotBase::new(name, &sim));
+        Ok(sim)
     }
 
     // /// Create simulation instance from a vector of bytes representing a snapshot.
@@ -177,28 +316,38 @@ impl Sim {
     // }
 
     /// Create simulation instance using a path to snapshot file.
+    ///
+    /// Base snapshots of delta snapshots are looked up in the same
+    /// directory.
     pub fn from_snapshot_at(path: &str) -> Result<Self> {
         println!("sim from_snapshot_at: {}", path);
-        let pathbuf = PathBuf::from(path);
-        let path = pathbuf.canonicalize().unwrap();
-        let mut file = match File::open(path) {
-            Ok(f) => f,
-            Err(e) => {
-                // error!("{}", e);
-                return Err(Error::FailedReadingSnapshot(format!("{}", e)));
-            }
-        };
-        let mut buf: Vec<u8> = Vec::new();
-        file.read_to_end(&mut buf);
+        let path = PathBuf::from(path)
+            .canonicalize()
+            .map_err(|e| Error::FailedReadingSnapshot(format!("{}: {}", path, e)))?;
+        let buf = snapshot::read_snapshot_file(&path)?;
+        let snapshots_dir = path.parent().unwrap_or(Path::new("."));
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // compression and format version are detected based on the preamble
+        Self::from_snapshot_chain(snapshots_dir, &name, buf)
+    }
 
-        // first try deserializing as compressed, otherwise it must be uncompressed
-        if let Ok(s) = Self::from_snapshot(&mut buf) {
-            // if let Ok(s) = Self::from_snapshot(&buf, true) {
-            return Ok(s);
-        } else {
-            return Self::from_snapshot(&mut buf);
-            // return Self::from_snapshot(&buf, false);
-        }
+    /// Re-initializes the parts of simulation state that can't be
+    /// serialized directly, used when restoring from a snapshot.
+    ///
+    /// Lua states are recreated using the persisted globals. Dynamic
+    /// libraries are not loaded here, they're only loaded once the
+    /// simulation gets stepped, see [`Sim::ensure_libraries`].
+    pub(crate) fn restore_runtime_state(
+        &mut self,
+        #[cfg(feature = "machine_lua")] lua_globals: FnvHashMap<EntityId, LuaGlobals>,
+    ) -> Result<()> {
+        #[cfg(feature = "machine_lua")]
+        self.restore_lua_globals(lua_globals)?;
+        Ok(())
     }
 }
 
@@ -221,6 +370,14 @@ impl Sim {
             entity_lua_state: Default::default(),
             #[cfg(feature = "machine_dynlib")]
             libs: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs_loaded: false,
+            checkpointer: None,
+            snapshot_base: None,
+            #[cfg(feature = "save_img")]
+            grid_exports: Vec::new(),
+            #[cfg(feature = "machine")]
+            debugger: None,
         }
     }
 
@@ -271,102 +428,16 @@ impl Sim {
             entity_lua_state: Default::default(),
             #[cfg(feature = "machine_dynlib")]
             libs: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs_loaded: false,
+            checkpointer: None,
+            snapshot_base: None,
+            #[cfg(feature = "save_img")]
+            grid_exports: Vec::new(),
+            #[cfg(feature = "machine")]
+            debugger: None,
         };
 
-        #[cfg(feature = "machine_dynlib")]
-        {
-            for module in &sim.model.scenario.modules {
-                for module_lib in &module.manifest.libraries {
-                    // use paths to existing shared library files
-                    if let Some(lib_path) = &module_lib.path {
-                        let mut full_path = module.path.join(lib_path);
-                        // set extension based on detected system
-                        if full_path.extension().is_none() {
-                            #[cfg(target_os = "windows")]
-                            full_path.set_extension("dll");
-                            #[cfg(target_os = "linux")]
-                            full_path.set_extension("so");
-                        }
-                        let lib = Library::new(full_path).unwrap();
-                        sim.libs.insert(module_lib.name.clone(), lib);
-                    }
-                    // build rust projects as library using cargo
-                    else if let Some(lib_project_path) = &module_lib.project_path {
-                        let lib_project_path = PathBuf::from(lib_project_path);
-                        let lib_project_path_full = module.path.join(lib_project_path.clone());
-
-                        let mut cmd = std::process::Command::new("cargo");
-                        cmd.current_dir(lib_project_path_full.clone()).arg("build");
-
-                        if let Some(mode) = &module_lib.project_mode {
-                            if mode.as_str() == "release" {
-                                cmd.arg("--release");
-                            }
-                        } else {
-                            cmd.arg("--release");
-                        }
-
-                        // pass relevant features to the command
-                        let mut features = vec![];
-
-                        // add explicitly selected features
-                        if let Some(project_features) = &module_lib.project_features {
-                            let features_str = project_features.split(",").collect::<Vec<&str>>();
-                            for feature_str in features_str {
-                                features.push(feature_str.to_string());
-                            }
-                        }
-
-                        // inherit features from the current program
-                        if module_lib.project_inherit_features {
-                            if FEATURE_STACK_STRINGID {
-                                features
-                                    .push(format!("outcome-core/{}", FEATURE_NAME_STACK_STRINGID));
-                            }
-                            if FEATURE_SHORT_STRINGID {
-                                features
-                                    .push(format!("outcome-core/{}", FEATURE_NAME_SHORT_STRINGID));
-                            }
-                            // TODO add the rest of the features
-                        }
-
-                        cmd.arg(format!(
-                            "--features={}",
-                            features.iter().as_slice().join(",")
-                        ));
-
-                        info!(
-                            "building library from rust project: {}, mode: {:?} (cmd: {:?})",
-                            lib_project_path_full.to_str().unwrap(),
-                            module_lib.project_mode,
-                            cmd
-                        );
-
-                        // execute the command, building the project
-                        let status = cmd.status()?;
-
-                        let mut lib_path_full = lib_project_path_full.join(format!(
-                            // TODO does DLL output also include 'lib{}' prefix by default?
-                            "target/{}/lib{}",
-                            module_lib
-                                .project_mode
-                                .as_ref()
-                                .unwrap_or(&"debug".to_string()),
-                            lib_project_path.file_name().unwrap().to_str().unwrap()
-                        ));
-                        // set extension based on detected system
-                        if lib_path_full.extension().is_none() {
-                            #[cfg(target_os = "windows")]
-                            lib_path_full.set_extension("dll");
-                            #[cfg(target_os = "linux")]
-                            lib_path_full.set_extension("so");
-                        }
-                        let lib = Library::new(lib_path_full).unwrap();
-                        sim.libs.insert(module_lib.name.clone(), lib);
-                    }
-                }
-            }
-        }
         // let mut arc_libs = Arc::new(Mutex::new(libs));
         // TODO setup lua state
 
@@ -396,18 +467,95 @@ impl Sim {
 
         // apply single step to setup the model
         #[cfg(feature = "machine_script")]
-        sim.step();
+        {
+            #[cfg(feature = "machine_dynlib")]
+            sim.ensure_libraries()?;
+            sim.process_step()?;
+        }
+
+        // spawn entities from tables before the initial step is finished,
+        // prefabs declared in scripts are only available once it's been
+        // processed
+        for path in sim.model.entity_tables.clone() {
+            let ids = sim.spawn_from_table(&path)?;
+            info!(
+                "spawned {} entities from table: {}",
+                ids.len(),
+                path.to_string_lossy()
+            );
+        }
+        #[cfg(feature = "machine_script")]
+        sim.finish_step()?;
+
+        // start checkpointing if it's enabled in the scenario manifest
+        if let Some(settings) = sim.model.scenario.manifest.checkpoint.clone() {
+            sim.set_checkpointing(Some(settings))?;
+        }
 
         Ok(sim)
     }
 
-    /// Applies the model on already existing sim instance, spawning objects
-    /// and applying data based on what has been registered.
-    pub fn apply_model(&mut self) -> Result<()> {
-        unimplemented!()
-        // println!("applying model");
-        // self.apply_model_entities()
-        // self.apply_model_entities_par();
+    /// Applies a new version of the model to the running simulation.
+    ///
+    /// Existing entities are updated to match the new model, keeping var
+    /// values wherever the var types didn't change. Returned update holds
+    /// the difference between the models, as well as the list of changes
+    /// that couldn't be applied without losing data.
+    pub fn apply_model(&mut self, model: SimModel) -> Result<ModelUpdate> {
+        let diff = ModelDiff::new(&self.model, &model);
+        self.model = model;
+
+        let mut incompatible = Vec::new();
+        for (id, entity) in &mut self.entities {
+            for issue in entity.update_to_model(&self.model) {
+                incompatible.push(format!("entity {}: {}", id, issue));
+            }
+        }
+        for issue in &incompatible {
+            warn!("{}", issue);
+        }
+
+        Ok(ModelUpdate { diff, incompatible })
+    }
+
+    /// Reloads the scenario from disk and applies the resulting model to
+    /// the running simulation.
+    ///
+    /// Current values of scenario settings are preserved.
+    pub fn reload_model(&mut self) -> Result<ModelUpdate> {
+        let scenario = self.model.scenario.reload()?;
+        let model = SimModel::from_scenario(scenario)?;
+        self.apply_model(model)
+    }
+
+    /// Spawns entities defined in the entity table at the given path.
+    ///
+    /// Values from the table are applied on top of the prefab defaults.
+    /// Returns ids of the spawned entities. See [`model::table`] for
+    /// details on the table format.
+    pub fn spawn_from_table(&mut self, path: &Path) -> Result<Vec<EntityId>> {
+        let table = EntityTable::from_path(path)?;
+        let mut ids = Vec::with_capacity(table.rows.len());
+        for row in table.rows {
+            let id = self.spawn_entity(Some(&row.prefab), row.name)?;
+            let entity = self.get_entity_mut(&id)?;
+            for (column, value) in table.columns.iter().zip(row.values) {
+                if let Some(value) = value {
+                    let var = entity.storage.get_var_mut(&column.storage_index()).map_err(|_| {
+                        Error::InvalidEntityTable(
+                            path.to_string_lossy().to_string(),
+                            format!(
+                                "prefab \"{}\" has no var \"{}:{}\"",
+                                row.prefab, column.comp, column.var_name
+                            ),
+                        )
+                    })?;
+                    *var = value;
+                }
+            }
+            ids.push(id);
+        }
+        Ok(ids)
     }
 
     /// Spawns a new entity based on the given prefab.
@@ -417,6 +565,19 @@ impl Sim {
         &mut self,
         prefab: Option<&StringId>,
         name: Option<StringId>,
+    ) -> Result<EntityId> {
+        self.spawn_entity_with(prefab, name, &[])
+    }
+
+    /// Spawns a new entity based on the given prefab, overriding initial
+    /// values of the selected vars.
+    ///
+    /// Overrides are applied on top of the values defined by the prefab.
+    pub fn spawn_entity_with(
+        &mut self,
+        prefab: Option<&StringId>,
+        name: Option<StringId>,
+        vars: &[VarOverride],
     ) -> Result<EntityId> {
         trace!("starting spawn_entity");
 
@@ -426,6 +587,7 @@ impl Sim {
             Some(p) => Entity::from_prefab_name(p, &self.model)?,
             None => Entity::empty(),
         };
+        ent.set_vars(vars)?;
         // trace!(
         //     "creating ent from prefab took: {}ns",
         //     now.elapsed().as_nanos()
@@ -436,6 +598,15 @@ impl Sim {
         let new_uid = self.entity_pool.request_id().unwrap();
         trace!("done");
 
+        // only entities that actually use lua get their own state
+        #[cfg(feature = "machine_lua")]
+        {
+            if self.needs_lua_state(&ent) {
+                self.entity_lua_state
+                    .insert(new_uid, new_entity_lua_state());
+            }
+        }
+
         trace!("inserting entity");
         if let Some(n) = &name {
             if !self.entity_idx.contains_key(n) {
@@ -462,6 +633,134 @@ impl Sim {
     }
 }
 
+/// Functionality related to handling dynamic libraries.
+#[cfg(feature = "machine_dynlib")]
+impl Sim {
+    /// Loads dynamic libraries declared by the modules of the current
+    /// model.
+    ///
+    /// Libraries can't be serialized, so this is also used to
+    /// re-initialize them when restoring from a snapshot.
+    pub fn load_libraries(&mut self) -> Result<()> {
+        for module in &self.model.scenario.modules {
+            for module_lib in &module.manifest.libraries {
+                // use paths to existing shared library files
+                if let Some(lib_path) = &module_lib.path {
+                    let mut full_path = module.path.join(lib_path);
+                    // set extension based on detected system
+                    if full_path.extension().is_none() {
+                        #[cfg(target_os = "windows")]
+                        full_path.set_extension("dll");
+                        #[cfg(target_os = "linux")]
+                        full_path.set_extension("so");
+                    }
+                    let lib = Library::new(&full_path).map_err(|e| {
+                        Error::FailedLoadingLibrary(
+                            full_path.to_string_lossy().to_string(),
+                            e.to_string(),
+                        )
+                    })?;
+                    self.libs.insert(module_lib.name.clone(), lib);
+                }
+                // build rust projects as library using cargo
+                else if let Some(lib_project_path) = &module_lib.project_path {
+                    let lib_project_path = PathBuf::from(lib_project_path);
+                    let lib_project_path_full = module.path.join(lib_project_path.clone());
+
+                    let mut cmd = std::process::Command::new("cargo");
+                    cmd.current_dir(lib_project_path_full.clone()).arg("build");
+
+                    if let Some(mode) = &module_lib.project_mode {
+                        if mode.as_str() == "release" {
+                            cmd.arg("--release");
+                        }
+                    } else {
+                        cmd.arg("--release");
+                    }
+
+                    // pass relevant features to the command
+                    let mut features = vec![];
+
+                    // add explicitly selected features
+                    if let Some(project_features) = &module_lib.project_features {
+                        let features_str = project_features.split(",").collect::<Vec<&str>>();
+                        for feature_str in features_str {
+                            features.push(feature_str.to_string());
+                        }
+                    }
+
+                    // inherit features from the current program
+                    if module_lib.project_inherit_features {
+                        if FEATURE_STACK_STRINGID {
+                            features
+                                .push(format!("outcome-core/{}", FEATURE_NAME_STACK_STRINGID));
+                        }
+                        if FEATURE_SHORT_STRINGID {
+                            features
+                                .push(format!("outcome-core/{}", FEATURE_NAME_SHORT_STRINGID));
+                        }
+                        // TODO add the rest of the features
+                    }
+
+                    cmd.arg(format!(
+                        "--features={}",
+                        features.iter().as_slice().join(",")
+                    ));
+
+                    info!(
+                        "building library from rust project: {}, mode: {:?} (cmd: {:?})",
+                        lib_project_path_full.to_str().unwrap(),
+                        module_lib.project_mode,
+                        cmd
+                    );
+
+                    // execute the command, building the project
+                    let status = cmd.status()?;
+
+                    let mut lib_path_full = lib_project_path_full.join(format!(
+                        // TODO does DLL output also include 'lib{}' prefix by default?
+                        "target/{}/lib{}",
+                        module_lib
+                            .project_mode
+                            .as_ref()
+                            .unwrap_or(&"debug".to_string()),
+                        lib_project_path.file_name().unwrap().to_str().unwrap()
+                    ));
+                    // set extension based on detected system
+                    if lib_path_full.extension().is_none() {
+                        #[cfg(target_os = "windows")]
+                        lib_path_full.set_extension("dll");
+                        #[cfg(target_os = "linux")]
+                        lib_path_full.set_extension("so");
+                    }
+                    let lib = Library::new(&lib_path_full).map_err(|e| {
+                        Error::FailedLoadingLibrary(
+                            lib_path_full.to_string_lossy().to_string(),
+                            e.to_string(),
+                        )
+                    })?;
+                    self.libs.insert(module_lib.name.clone(), lib);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads dynamic libraries, unless loading was already attempted.
+    ///
+    /// Called before processing each step, so that simulations restored
+    /// from snapshots only load libraries once they actually get stepped.
+    /// Loading is only attempted once, a failure is not retried on
+    /// subsequent steps.
+    pub fn ensure_libraries(&mut self) -> Result<()> {
+        if !self.libs_loaded {
+            self.libs_loaded = true;
+            self.load_libraries()?;
+        }
+        Ok(())
+    }
+}
+
 /// Functionality related to handling lua.
 #[cfg(feature = "machine_lua")]
 impl Sim {
@@ -526,6 +825,81 @@ impl Sim {
             }
         }
     }
+
+    /// Checks whether the entity needs its own Lua state, that is if any
+    /// of its components uses Lua, or if the model declares Lua globals
+    /// to persist.
+    fn needs_lua_state(&self, entity: &Entity) -> bool {
+        #[cfg(feature = "machine")]
+        let uses_lua = entity.components.iter().any(|comp| {
+            self.model.get_component(comp).map_or(false, |comp| {
+                comp.logic.commands.iter().any(|cmd| {
+                    matches!(
+                        cmd,
+                        crate::machine::cmd::Command::LuaScript(_)
+                            | crate::machine::cmd::Command::LuaCall(_)
+                    )
+                })
+            })
+        });
+        #[cfg(not(feature = "machine"))]
+        let uses_lua = false;
+        uses_lua || !self.model.lua_globals().is_empty()
+    }
+
+    /// Captures persisted Lua globals of all entities that have a Lua
+    /// state.
+    ///
+    /// Globals to persist are declared by the modules of the current model.
+    pub fn lua_globals(&self) -> FnvHashMap<EntityId, LuaGlobals> {
+        let names = self.model.lua_globals();
+        if names.is_empty() {
+            return FnvHashMap::default();
+        }
+        self.entity_lua_state
+            .iter()
+            .map(|(id, lua)| (*id, lua::capture_globals(&lua.lock().unwrap(), &names)))
+            .collect()
+    }
+
+    /// Restores persisted Lua globals.
+    ///
+    /// Existing Lua states are reused. Entities without one, for example
+    /// those read from a snapshot, get a new state set up the same way as
+    /// for freshly spawned entities, see [`Sim::needs_lua_state`].
+    pub fn restore_lua_globals(
+        &mut self,
+        mut globals: FnvHashMap<EntityId, LuaGlobals>,
+    ) -> Result<()> {
+        let ids = self
+            .entities
+            .iter()
+            .filter(|(id, entity)| globals.contains_key(id) || self.needs_lua_state(entity))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in ids {
+            let lua = self
+                .entity_lua_state
+                .entry(id)
+                .or_insert_with(new_entity_lua_state);
+            if let Some(globals) = globals.remove(&id) {
+                lua::restore_globals(&lua.lock().unwrap(), &globals)?;
+            }
+        }
+        if !globals.is_empty() {
+            warn!(
+                "dropping persisted lua globals of {} entities that don't exist",
+                globals.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Creates the Lua state of a single entity.
+#[cfg(feature = "machine_lua")]
+fn new_entity_lua_state() -> Arc<Mutex<Lua>> {
+    Arc::new(Mutex::new(Lua::new()))
 }
 
 /// Data access helpers.
@@ -714,98 +1088,84 @@ impl Sim {
         Ok(())
     }
 
-    // TODO support more image types
     /// Apply image data as found in the model.
+    ///
+    /// Single channel variants use the luma value of each pixel. `U8U8U8`
+    /// variants pack the RGB channels into a single integer, `Concat`
+    /// concatenates their decimal representations, and `Combine` variants
+    /// pack all four RGBA channels, with the alpha channel taking the least
+    /// significant byte. Without `big_nums` the packed value is masked to
+    /// fit a positive integer, dropping the most significant bit of the red
+    /// channel.
     #[cfg(feature = "load_img")]
     fn apply_data_img(&mut self) -> Result<()> {
-        use self::image::GenericImageView;
         for die in &self.model.data_imgs.clone() {
-            //            debug!("loading image: {:?}", die);
-            match die {
-                DataImageEntry::BmpU8(addr, path) => {
-                    let img = image::open(path).unwrap();
-                    debug!(
-                        "loading image <BmpU8> ({},{}) from path: {}, destination: {}",
-                        img.width(),
-                        img.height(),
-                        path,
-                        addr
-                    );
-                    //                    println!("{}", img.);
-                    let img = img.to_luma8();
-                    let mut out_grid: Vec<Vec<crate::Var>> = Vec::new();
-                    let mut row: Vec<Var> = Vec::new();
-                    for (w, h, luma) in img.enumerate_pixels() {
-                        row.push(Var::Int(luma[0] as crate::Int));
-                        if (w + 1) % img.width() == 0 {
-                            out_grid.push(row);
-                            row = Vec::new();
-                        }
-                    }
-                    let ig = self.get_var_mut(&addr.parse()?)?.as_grid_mut()?;
-                    *ig = out_grid;
+            let (addr, path, grid) = match die {
+                DataImageEntry::BmpU8(addr, path) | DataImageEntry::PngU8(addr, path) => (
+                    addr,
+                    path,
+                    read_image_grid(path, |px| Var::Int(px.to_luma()[0] as crate::Int))?,
+                ),
+                DataImageEntry::BmpU8U8U8(addr, path) | DataImageEntry::PngU8U8U8(addr, path) => {
+                    let grid = read_image_grid(path, |px| {
+                        let rgb = px.to_rgb();
+                        let c = 65536 * rgb[0] as u32 + 256 * rgb[1] as u32 + rgb[2] as u32;
+                        Var::Int(c as crate::Int)
+                    })?;
+                    (addr, path, grid)
                 }
                 DataImageEntry::PngU8U8U8Concat(addr, path) => {
-                    let img = image::open(path).unwrap();
-                    debug!(
-                        "loading image <PngU8U8U8Concat> ({},{}) from path: {}, destination: {}",
-                        img.width(),
-                        img.height(),
-                        path,
-                        addr
-                    );
-                    let width = img.width();
-                    let img = img.to_rgb8();
-
-                    let mut out_grid: Vec<Vec<Var>> = Vec::new();
-                    let mut row: Vec<Var> = Vec::new();
-                    for (w, h, rgb) in img.enumerate_pixels() {
-                        let combined = (rgb.0[0] as u32 * 10_u32.pow(3) + rgb.0[1] as u32)
+                    let grid = read_image_grid(path, |px| {
+                        let rgb = px.to_rgb();
+                        let combined = (rgb[0] as u32 * 10_u32.pow(3) + rgb[1] as u32)
                             * 10_u32.pow(3)
-                            + rgb.0[2] as u32;
-                        row.push(Var::Int(combined as crate::Int));
-
-                        if (w + 1) % img.width() == 0 {
-                            out_grid.push(row);
-                            row = Vec::new();
-                        }
-                    }
-                    let deal = addr.parse().expect("failed creating addr from str");
-                    let ig = self.get_var_mut(&deal)?.as_grid_mut()?;
-                    *ig = out_grid;
+                            + rgb[2] as u32;
+                        Var::Int(combined as crate::Int)
+                    })?;
+                    (addr, path, grid)
                 }
-                DataImageEntry::PngU8U8U8(addr, path) => {
-                    let img = image::open(path).unwrap();
-                    debug!(
-                        "loading image ({},{}) from path: {}, destination: {}",
-                        img.width(),
-                        img.height(),
-                        path,
-                        addr
-                    );
-                    let width = img.width();
-                    let img = img.to_rgb8();
-
-                    let mut out_grid: Vec<Vec<Var>> = Vec::new();
-                    let mut row: Vec<Var> = Vec::new();
-                    for (w, h, rgb) in img.enumerate_pixels() {
-                        let c = 65536 * rgb.0[0] as u32 + 256 * rgb.0[1] as u32 + rgb.0[2] as u32;
-                        row.push(Var::Int(c as crate::Int));
-                        if (w + 1) % img.width() == 0 {
-                            out_grid.push(row);
-                            row = Vec::new();
-                        }
-                    }
-                    let ig = self.get_var_mut(&Address::from_str(addr)?)?.as_grid_mut()?;
-                    *ig = out_grid;
+                DataImageEntry::BmpCombineU8U8U8U8(addr, path)
+                | DataImageEntry::PngCombineU8U8U8U8(addr, path) => {
+                    let grid = read_image_grid(path, |px| {
+                        let c = u32::from_be_bytes(px.0) & crate::Int::MAX as u32;
+                        Var::Int(c as crate::Int)
+                    })?;
+                    (addr, path, grid)
                 }
-                _ => (),
-            }
+            };
+            debug!(
+                "loaded image <{:?}> from path: {}, destination: {}",
+                die, path, addr
+            );
+            *self.get_var_mut(&Address::from_str(addr)?)?.as_grid_mut()? = grid;
         }
         Ok(())
     }
 }
 
+/// Reads the image at the given path into a grid, converting each pixel
+/// using the provided function.
+#[cfg(feature = "load_img")]
+fn read_image_grid<F>(path: &str, pixel_to_var: F) -> Result<Vec<Vec<Var>>>
+where
+    F: Fn(&image::Rgba<u8>) -> Var,
+{
+    use self::image::{GenericImageView, Pixel};
+    let img = image::open(path)
+        .map_err(|e| Error::Other(format!("failed loading image {}: {}", path, e)))?;
+    debug!(
+        "loading image ({},{}) from path: {}",
+        img.width(),
+        img.height(),
+        path
+    );
+    let img = img.to_rgba8();
+    Ok(img
+        .rows()
+        .map(|row| row.map(&pixel_to_var).collect())
+        .collect())
+}
+
 // TODO revise data applying
 /// Data applying functions.
 impl Sim {
@@ -841,59 +1201,35 @@ impl Sim {
 
     /// Apply settings as found in scenario manifest.
     fn apply_settings(&mut self) {
-        for (addr, val) in &self.model.scenario.manifest.settings.clone() {
-            let addr = match Address::from_str(&addr) {
-                Ok(a) => a,
-                Err(_) => continue,
-            };
-            use crate::util::coerce_toml_val_to_string;
-            match addr.var_type {
-                VarType::String | VarType::Int | VarType::Float | VarType::Bool | VarType::Byte => {
-                    //                    println!("{}", &addr.to_string());
-                    self.set_from_string(&addr, &val);
-                }
-                _ => unimplemented!(), //                    self.set_from_string(&addr, &val.as_str().to_string()),
-                                       // VarType::StringList
-                                       // | VarType::IntList
-                                       // | VarType::FloatList
-                                       // | VarType::BoolList
-                                       // | VarType::ByteList => {
-                                       //     unimplemented!()
-                                       // self.set_from_string_list(
-                                       //     &addr,
-                                       //     &val.as_array()
-                                       //         .unwrap()
-                                       //         .iter()
-                                       //         .map(|v| coerce_toml_val_to_string(&v))
-                                       //         .collect(),
-                                       // );
-                                       // }
-                                       // #[cfg(feature = "grids")]
-                                       // VarType::StringGrid
-                                       // | VarType::IntGrid
-                                       // | VarType::FloatGrid
-                                       // | VarType::BoolGrid
-                                       // | VarType::ByteGrid => {
-                                       //     unimplemented!()
-
-                                       // self.set_from_string_grid(
-                                       //     &addr,
-                                       //     &val.as_array()
-                                       //         .unwrap()
-                                       //         .iter()
-                                       //         .map(|v| {
-                                       //             v.as_array()
-                                       //                 .unwrap()
-                                       //                 .iter()
-                                       //                 .map(|vv| coerce_toml_val_to_string(&vv))
-                                       //                 .collect()
-                                       //         })
-                                       //         .collect(),
-                                       // );
-                                       // }
-            };
+        for setting in &self.model.scenario.manifest.settings.clone() {
+            match self.get_var_mut(&setting.target) {
+                Ok(var) => *var = setting.value.clone(),
+                Err(e) => warn!("failed applying setting \"{}\": {}", setting.name, e),
+            }
         }
     }
+
+    /// Gets the current value of a scenario setting.
+    ///
+    /// Value is read from the target var, as it might have changed since
+    /// initialization.
+    pub fn get_setting(&self, name: &str) -> Result<&Var> {
+        let setting = self.model.scenario.manifest.get_setting(name).ok_or(
+            Error::InvalidScenarioSetting(name.to_string(), "not declared".to_string()),
+        )?;
+        self.get_var(&setting.target)
+    }
+
+    /// Sets the value of a scenario setting, parsing the provided string
+    /// based on the setting type. Value is also applied to the target var.
+    pub fn set_setting(&mut self, name: &str, value: &str) -> Result<()> {
+        self.model.scenario.manifest.set_setting(name, value)?;
+        let setting = self.model.scenario.manifest.get_setting(name).cloned();
+        if let Some(setting) = setting {
+            *self.get_var_mut(&setting.target)? = setting.value;
+        }
+        Ok(())
+    }
 }
 
 /// Entity and component handling functions.
@@ -6,6 +6,8 @@ use crate::entity::Entity;
 use crate::error::Error;
 use crate::{string, EntityId, EntityName, SimModel, StringId};
 
+#[cfg(feature = "machine")]
+use crate::machine::debug::DebugStatus;
 #[cfg(feature = "machine")]
 use crate::machine::{cmd::CentralRemoteCommand, cmd::ExtCommand, exec, ExecutionContext};
 #[cfg(feature = "machine")]
@@ -20,6 +22,15 @@ use std::collections::BTreeMap;
 
 use super::Sim;
 
+/// Outcome of a single call to [`Sim::step`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StepStatus {
+    /// Step was finished and the clock was advanced
+    Finished,
+    /// Step was paused by the attached debugger, the clock wasn't advanced
+    Paused,
+}
+
 /// Single step processing functions.
 impl Sim {
     /// Performs single simulation step, utilizing multi-threading.
@@ -36,15 +47,37 @@ impl Sim {
     /// commands. Once parallel iteration over entities is done, last thing
     /// to do is executing external and central-external commands that have
     /// been accumulated during parallel iteration stage.
-    pub fn step(&mut self) -> Result<(), Error> {
-        // clone event queue into a local variable
-        let mut event_queue = self.event_queue.clone();
+    ///
+    /// # Debugging
+    ///
+    /// If a debugger is attached, entities are processed one at a time and
+    /// the step can get paused at a breakpoint, in which case this function
+    /// returns [`StepStatus::Paused`] without finishing the step. Calling it
+    /// again continues processing the paused step. See
+    /// [`Sim::attach_debugger`].
+    pub fn step(&mut self) -> Result<StepStatus, Error> {
+        #[cfg(feature = "machine_dynlib")]
+        self.ensure_libraries()?;
 
-        let arrstr_step = string::new_truncate("step");
-        if !event_queue.contains(&arrstr_step) {
-            event_queue.push(arrstr_step.clone());
+        #[cfg(feature = "machine")]
+        {
+            if self.debugger.is_some() {
+                return match self.debug_continue()? {
+                    DebugStatus::Finished => Ok(StepStatus::Finished),
+                    DebugStatus::Paused(_) => Ok(StepStatus::Paused),
+                };
+            }
         }
-        self.event_queue.clear();
+
+        self.process_step()?;
+        self.finish_step()?;
+        Ok(StepStatus::Finished)
+    }
+
+    /// Processes the events queued for the step, without advancing the
+    /// clock.
+    pub(crate) fn process_step(&mut self) -> Result<(), Error> {
+        let event_queue = self.take_event_queue();
 
         #[cfg(feature = "machine")]
         {
@@ -76,7 +109,8 @@ impl Sim {
             );
 
             // post phase
-            exec::execute_ext(&ext_cmds.lock().unwrap(), self)?;
+            self.clear_inboxes();
+            exec::execute_ext(&mut ext_cmds.lock().unwrap(), self)?;
             exec::execute_central_ext(&central_ext_cmds.lock().unwrap(), self)?;
         }
 
@@ -87,12 +121,46 @@ impl Sim {
         // self.event_queue.clear();
         // self.event_queue = event_queue;
 
+        Ok(())
+    }
+
+    /// Takes events to be processed during the step, leaving the global
+    /// event queue empty. The `step` event is always included.
+    pub(crate) fn take_event_queue(&mut self) -> Vec<StringId> {
+        // clone event queue into a local variable
+        let mut event_queue = self.event_queue.clone();
+
+        let arrstr_step = string::new_truncate("step");
+        if !event_queue.contains(&arrstr_step) {
+            event_queue.push(arrstr_step);
+        }
+        self.event_queue.clear();
+        event_queue
+    }
+
+    /// Removes messages received during the previous step, making room for
+    /// the ones sent during the current step.
+    #[cfg(feature = "machine")]
+    pub(crate) fn clear_inboxes(&mut self) {
+        self.entities
+            .par_iter_mut()
+            .for_each(|(_, entity)| entity.storage.inbox.clear());
+    }
+
+    /// Advances the clock and performs tasks scheduled to run after each
+    /// step.
+    pub(crate) fn finish_step(&mut self) -> Result<(), Error> {
         self.clock += 1;
 
+        let arrstr_step = string::new_truncate("step");
         if !self.event_queue.contains(&arrstr_step) {
             self.event_queue.push(arrstr_step);
         }
 
+        self.checkpoint_if_due()?;
+        #[cfg(feature = "save_img")]
+        self.export_grids_if_due()?;
+
         Ok(())
     }
 }
@@ -0,0 +1,1338 @@
+//! Reading snapshots created with older format versions.
+//!
+//! Each older format version is described by a marker type implementing
+//! [`Layout`]. Layout selects the legacy variant of each piece of the
+//! serialized state that has changed since that version. Snapshot data is
+//! deserialized using the legacy variants and converted directly into the
+//! current types.
+//!
+//! Changing the layout of any serialized type requires adding a piece type
+//! mirroring the previous layout, along with a new marker type describing
+//! the previous format version, and registering it in
+//! [`super::SNAPSHOT_MIGRATIONS`].
+
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::path::PathBuf;
+
+use fnv::FnvHashMap;
+use id_pool::IdPool;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::entity::{Entity, EntityNonSer, Storage, StorageIndex};
+use crate::error::Error;
+#[cfg(feature = "machine")]
+use crate::machine::cmd::{self, Command};
+#[cfg(feature = "machine")]
+use crate::model::LogicModel;
+use crate::model::{
+    ComponentModel, DataEntry, DataFileEntry, DataImageEntry, EntityPrefab, EventModel, Module,
+    ModuleDep, ModuleLib, ModuleManifest, Scenario, ScenarioManifest, ScenarioModuleDep,
+    ScenarioSetting, ServiceModel, VarModel,
+};
+#[cfg(feature = "machine_lua")]
+use crate::sim::lua::LuaGlobals;
+#[cfg(feature = "machine")]
+use crate::{address::Address, address::ShortLocalAddress, ShortString};
+use crate::{CompName, EntityId, EntityName, EventName, Result, SimModel, StringId, Var};
+
+use super::{EntityDelta, SnapshotDelta, SnapshotHeader, SnapshotMetadata, SnapshotPart};
+
+/// Legacy variant of a piece of the serialized state, convertible into the
+/// current type `T`. Pieces that didn't change use `T` itself.
+pub(crate) trait Piece<T>: Serialize + DeserializeOwned + Into<T> {}
+
+impl<T, P: Serialize + DeserializeOwned + Into<T>> Piece<T> for P {}
+
+/// Layout of the serialized state used by a single snapshot format
+/// version.
+pub(crate) trait Layout {
+    type ScenarioManifest: Piece<ScenarioManifest>;
+    type ModuleManifest: Piece<ModuleManifest>;
+    type EntityTables: Piece<Vec<PathBuf>>;
+    type EntityPrefab: Piece<EntityPrefab>;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab: Piece<cmd::register::RegisterEntityPrefab>;
+    #[cfg(feature = "machine")]
+    type Spawn: Piece<cmd::Spawn>;
+    #[cfg(feature = "machine")]
+    type Eval: Piece<cmd::eval::Eval>;
+    #[cfg(feature = "machine")]
+    type Set: Piece<cmd::set::Set>;
+    #[cfg(feature = "machine")]
+    type ForIn: Piece<cmd::flow::forin::ForIn>;
+    #[cfg(feature = "machine")]
+    type Procedure: Piece<cmd::flow::procedure::Procedure>;
+    #[cfg(feature = "machine")]
+    type Call: Piece<cmd::flow::call::Call>;
+    type Entity: Piece<Entity>;
+    type EntityDelta: Piece<EntityDelta>;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals: Piece<FnvHashMap<EntityId, LuaGlobals>>;
+}
+
+/// Layout of format versions 0 and 1.
+pub(crate) struct V0;
+
+impl Layout for V0 {
+    type ScenarioManifest = ScenarioManifestV0;
+    type ModuleManifest = ModuleManifestV0;
+    type EntityTables = Absent;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = Absent;
+}
+
+/// Layout of format version 2, which added persisted Lua globals.
+pub(crate) struct V2;
+
+impl Layout for V2 {
+    type ScenarioManifest = ScenarioManifestV0;
+    type ModuleManifest = ModuleManifestV2;
+    type EntityTables = Absent;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 3, which replaced untyped scenario settings
+/// with typed ones.
+pub(crate) struct V3;
+
+impl Layout for V3 {
+    type ScenarioManifest = ScenarioManifestV3;
+    type ModuleManifest = ModuleManifestV2;
+    type EntityTables = Absent;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 4, which added entity tables.
+pub(crate) struct V4;
+
+impl Layout for V4 {
+    type ScenarioManifest = ScenarioManifestV4;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 5, which added image export commands.
+pub(crate) struct V5;
+
+impl Layout for V5 {
+    type ScenarioManifest = ScenarioManifestV4;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 6, which added scenario inheritance and data
+/// entries.
+pub(crate) struct V6;
+
+impl Layout for V6 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = RegisterEntityPrefabV0;
+    #[cfg(feature = "machine")]
+    type Spawn = SpawnV0;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 7, which added prefab inheritance and var
+/// overrides.
+pub(crate) struct V7;
+
+impl Layout for V7 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefab;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = cmd::register::RegisterEntityPrefab;
+    #[cfg(feature = "machine")]
+    type Spawn = cmd::Spawn;
+    #[cfg(feature = "machine")]
+    type Eval = EvalV0;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 8, which added vector and list support to
+/// eval.
+pub(crate) struct V8;
+
+impl Layout for V8 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefab;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = cmd::register::RegisterEntityPrefab;
+    #[cfg(feature = "machine")]
+    type Spawn = cmd::Spawn;
+    #[cfg(feature = "machine")]
+    type Eval = cmd::eval::Eval;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 9, which added list, map and grid commands.
+pub(crate) struct V9;
+
+impl Layout for V9 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefab;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = cmd::register::RegisterEntityPrefab;
+    #[cfg(feature = "machine")]
+    type Spawn = cmd::Spawn;
+    #[cfg(feature = "machine")]
+    type Eval = cmd::eval::Eval;
+    #[cfg(feature = "machine")]
+    type Set = SetV0;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 10, which added merge policies for writes to
+/// other entities.
+pub(crate) struct V10;
+
+impl Layout for V10 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefab;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = cmd::register::RegisterEntityPrefab;
+    #[cfg(feature = "machine")]
+    type Spawn = cmd::Spawn;
+    #[cfg(feature = "machine")]
+    type Eval = cmd::eval::Eval;
+    #[cfg(feature = "machine")]
+    type Set = cmd::set::Set;
+    #[cfg(feature = "machine")]
+    type ForIn = ForInV0;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = EntityV0;
+    type EntityDelta = EntityDeltaV1;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Layout of format version 11, which added entity message inboxes.
+pub(crate) struct V11;
+
+impl Layout for V11 {
+    type ScenarioManifest = ScenarioManifest;
+    type ModuleManifest = ModuleManifest;
+    type EntityTables = Vec<PathBuf>;
+    type EntityPrefab = EntityPrefab;
+    #[cfg(feature = "machine")]
+    type RegisterEntityPrefab = cmd::register::RegisterEntityPrefab;
+    #[cfg(feature = "machine")]
+    type Spawn = cmd::Spawn;
+    #[cfg(feature = "machine")]
+    type Eval = cmd::eval::Eval;
+    #[cfg(feature = "machine")]
+    type Set = cmd::set::Set;
+    #[cfg(feature = "machine")]
+    type ForIn = cmd::flow::forin::ForIn;
+    #[cfg(feature = "machine")]
+    type Procedure = ProcedureV0;
+    #[cfg(feature = "machine")]
+    type Call = CallV0;
+    type Entity = Entity;
+    type EntityDelta = EntityDelta;
+    #[cfg(feature = "machine_lua")]
+    type LuaGlobals = FnvHashMap<EntityId, LuaGlobals>;
+}
+
+/// Upgrades snapshot body bytes using the given legacy layout to the
+/// current format version.
+///
+/// Bytes of full snapshots consist of the header followed by one or more
+/// parts, depending on the number of nodes that took part in creating the
+/// snapshot.
+pub(crate) fn upgrade<L: Layout>(bytes: Vec<u8>, delta: bool) -> Result<Vec<u8>> {
+    let err = |e: bincode::Error| Error::FailedReadingSnapshot(e.to_string());
+    let ser_err = |e: bincode::Error| Error::FailedCreatingSnapshot(e.to_string());
+    if delta {
+        let legacy: DeltaL<L> = bincode::deserialize(&bytes).map_err(err)?;
+        return bincode::serialize(&SnapshotDelta::from(legacy)).map_err(ser_err);
+    }
+    let mut cursor = &bytes[..];
+    let header: HeaderL<L> = bincode::deserialize_from(&mut cursor).map_err(err)?;
+    let mut out = bincode::serialize(&SnapshotHeader::from(header)).map_err(ser_err)?;
+    while !cursor.is_empty() {
+        let part: PartL<L> = bincode::deserialize_from(&mut cursor).map_err(err)?;
+        out.extend(bincode::serialize(&SnapshotPart::from(part)).map_err(ser_err)?);
+    }
+    Ok(out)
+}
+
+/// Placeholder for data that didn't exist yet in a legacy layout, takes up
+/// no space when serialized.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Absent;
+
+impl<T> From<Absent> for Vec<T> {
+    fn from(_: Absent) -> Self {
+        Vec::new()
+    }
+}
+
+impl<K, V, S: BuildHasher + Default> From<Absent> for HashMap<K, V, S> {
+    fn from(_: Absent) -> Self {
+        HashMap::default()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct HeaderL<L: Layout> {
+    metadata: SnapshotMetadata,
+    clock: usize,
+    model: SimModelL<L>,
+    entities_idx: FnvHashMap<EntityName, EntityId>,
+    event_queue: Vec<EventName>,
+    entity_pool: IdPool,
+}
+
+impl<L: Layout> From<HeaderL<L>> for SnapshotHeader {
+    fn from(legacy: HeaderL<L>) -> Self {
+        SnapshotHeader {
+            metadata: legacy.metadata,
+            clock: legacy.clock,
+            model: legacy.model.into(),
+            entities_idx: legacy.entities_idx,
+            event_queue: legacy.event_queue,
+            entity_pool: legacy.entity_pool,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct PartL<L: Layout> {
+    entities: FnvHashMap<EntityId, L::Entity>,
+    #[cfg(feature = "machine_lua")]
+    lua_globals: L::LuaGlobals,
+}
+
+impl<L: Layout> From<PartL<L>> for SnapshotPart {
+    fn from(legacy: PartL<L>) -> Self {
+        SnapshotPart {
+            entities: convert_map(legacy.entities),
+            #[cfg(feature = "machine_lua")]
+            lua_globals: legacy.lua_globals.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct DeltaL<L: Layout> {
+    base: String,
+    base_clock: usize,
+    header: HeaderL<L>,
+    added: FnvHashMap<EntityId, L::Entity>,
+    changed: FnvHashMap<EntityId, L::EntityDelta>,
+    removed: Vec<EntityId>,
+    #[cfg(feature = "machine_lua")]
+    lua_globals: L::LuaGlobals,
+}
+
+impl<L: Layout> From<DeltaL<L>> for SnapshotDelta {
+    fn from(legacy: DeltaL<L>) -> Self {
+        SnapshotDelta {
+            base: legacy.base,
+            base_clock: legacy.base_clock,
+            header: legacy.header.into(),
+            added: convert_map(legacy.added),
+            changed: convert_map(legacy.changed),
+            removed: legacy.removed,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: legacy.lua_globals.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct SimModelL<L: Layout> {
+    scenario: ScenarioL<L>,
+    events: Vec<EventModel>,
+    scripts: Vec<String>,
+    entities: Vec<L::EntityPrefab>,
+    #[cfg(feature = "machine")]
+    components: Vec<ComponentModelL<L>>,
+    #[cfg(not(feature = "machine"))]
+    components: Vec<ComponentModel>,
+    data: Vec<DataEntry>,
+    data_files: Vec<DataFileEntry>,
+    data_imgs: Vec<DataImageEntry>,
+    entity_tables: L::EntityTables,
+    services: Vec<ServiceModel>,
+}
+
+impl<L: Layout> From<SimModelL<L>> for SimModel {
+    fn from(legacy: SimModelL<L>) -> Self {
+        SimModel {
+            scenario: legacy.scenario.into(),
+            events: legacy.events,
+            scripts: legacy.scripts,
+            entities: convert_vec(legacy.entities),
+            components: convert_vec(legacy.components),
+            data: legacy.data,
+            data_files: legacy.data_files,
+            data_imgs: legacy.data_imgs,
+            entity_tables: legacy.entity_tables.into(),
+            services: legacy.services,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct ScenarioL<L: Layout> {
+    path: PathBuf,
+    manifest: L::ScenarioManifest,
+    modules: Vec<ModuleL<L>>,
+}
+
+impl<L: Layout> From<ScenarioL<L>> for Scenario {
+    fn from(legacy: ScenarioL<L>) -> Self {
+        Scenario {
+            path: legacy.path,
+            manifest: legacy.manifest.into(),
+            modules: convert_vec(legacy.modules),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct ModuleL<L: Layout> {
+    manifest: L::ModuleManifest,
+    path: PathBuf,
+}
+
+impl<L: Layout> From<ModuleL<L>> for Module {
+    fn from(legacy: ModuleL<L>) -> Self {
+        Module {
+            manifest: legacy.manifest.into(),
+            path: legacy.path,
+        }
+    }
+}
+
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct ComponentModelL<L: Layout> {
+    name: CompName,
+    vars: Vec<VarModel>,
+    triggers: Vec<StringId>,
+    logic: LogicModelL<L>,
+}
+
+#[cfg(feature = "machine")]
+impl<L: Layout> From<ComponentModelL<L>> for ComponentModel {
+    fn from(legacy: ComponentModelL<L>) -> Self {
+        ComponentModel {
+            name: legacy.name,
+            vars: legacy.vars,
+            triggers: legacy.triggers,
+            logic: legacy.logic.into(),
+        }
+    }
+}
+
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) struct LogicModelL<L: Layout> {
+    start_state: StringId,
+    commands: Vec<CommandL<L>>,
+    pre_commands: FnvHashMap<ShortString, Vec<cmd::ExtCommand>>,
+    states: FnvHashMap<StringId, (usize, usize)>,
+    procedures: FnvHashMap<ShortString, (usize, usize)>,
+    cmd_location_map: Vec<crate::machine::LocationInfo>,
+}
+
+#[cfg(feature = "machine")]
+impl<L: Layout> From<LogicModelL<L>> for LogicModel {
+    fn from(legacy: LogicModelL<L>) -> Self {
+        LogicModel {
+            start_state: legacy.start_state,
+            commands: convert_vec(legacy.commands),
+            pre_commands: legacy.pre_commands,
+            states: legacy.states,
+            procedures: legacy.procedures,
+            cmd_location_map: legacy.cmd_location_map,
+        }
+    }
+}
+
+/// Commands as found in legacy layouts, in their original order. Commands
+/// added after the newest legacy layout are left out.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub(crate) enum CommandL<L: Layout> {
+    Sim(cmd::sim::SimControl),
+    Print(cmd::print::Print),
+    PrintFmt(cmd::print::PrintFmt),
+    Set(L::Set),
+    SetIntIntAddr(cmd::set::SetIntIntAddr),
+    Eval(L::Eval),
+    #[cfg(feature = "machine_lua")]
+    LuaScript(cmd::lua::LuaScript),
+    #[cfg(feature = "machine_lua")]
+    LuaCall(cmd::lua::LuaCall),
+    #[cfg(feature = "machine_dynlib")]
+    LibCall(cmd::lib::LibCall),
+    Attach(cmd::Attach),
+    Detach(cmd::Detach),
+    Goto(cmd::Goto),
+    Jump(cmd::Jump),
+    Get(cmd::get_set::Get),
+    Invoke(cmd::Invoke),
+    Spawn(L::Spawn),
+    RegisterEvent(cmd::register::RegisterEvent),
+    RegisterEntityPrefab(L::RegisterEntityPrefab),
+    RegisterComponent(cmd::register::RegisterComponent),
+    RegisterTrigger(cmd::register::RegisterTrigger),
+    RegisterVar(cmd::register::RegisterVar),
+    Extend(cmd::register::Extend),
+    State(cmd::flow::state::State),
+    Component(cmd::flow::component::ComponentBlock),
+    If(cmd::flow::ifelse::If),
+    Else(cmd::flow::ifelse::Else),
+    End(cmd::flow::end::End),
+    Call(L::Call),
+    ForIn(L::ForIn),
+    Loop(cmd::flow::_loop::Loop),
+    Break(cmd::flow::_loop::Break),
+    Procedure(L::Procedure),
+    Range(cmd::range::Range),
+    SpawnFrom(cmd::SpawnFrom),
+    #[cfg(feature = "save_img")]
+    ExportPng(cmd::img::ExportPng),
+    List(cmd::collection::ListCommand),
+    Map(cmd::collection::MapCommand),
+    Grid(cmd::collection::GridCommand),
+    Add(cmd::set::Add),
+    Send(cmd::send::SendMessage),
+}
+
+#[cfg(feature = "machine")]
+impl<L: Layout> From<CommandL<L>> for Command {
+    fn from(legacy: CommandL<L>) -> Self {
+        match legacy {
+            CommandL::Sim(c) => Command::Sim(c),
+            CommandL::Print(c) => Command::Print(c),
+            CommandL::PrintFmt(c) => Command::PrintFmt(c),
+            CommandL::Set(c) => Command::Set(c.into()),
+            CommandL::SetIntIntAddr(c) => Command::SetIntIntAddr(c),
+            CommandL::Eval(c) => Command::Eval(c.into()),
+            #[cfg(feature = "machine_lua")]
+            CommandL::LuaScript(c) => Command::LuaScript(c),
+            #[cfg(feature = "machine_lua")]
+            CommandL::LuaCall(c) => Command::LuaCall(c),
+            #[cfg(feature = "machine_dynlib")]
+            CommandL::LibCall(c) => Command::LibCall(c),
+            CommandL::Attach(c) => Command::Attach(c),
+            CommandL::Detach(c) => Command::Detach(c),
+            CommandL::Goto(c) => Command::Goto(c),
+            CommandL::Jump(c) => Command::Jump(c),
+            CommandL::Get(c) => Command::Get(c),
+            CommandL::Invoke(c) => Command::Invoke(c),
+            CommandL::Spawn(c) => Command::Spawn(c.into()),
+            CommandL::RegisterEvent(c) => Command::RegisterEvent(c),
+            CommandL::RegisterEntityPrefab(c) => Command::RegisterEntityPrefab(c.into()),
+            CommandL::RegisterComponent(c) => Command::RegisterComponent(c),
+            CommandL::RegisterTrigger(c) => Command::RegisterTrigger(c),
+            CommandL::RegisterVar(c) => Command::RegisterVar(c),
+            CommandL::Extend(c) => Command::Extend(c),
+            CommandL::State(c) => Command::State(c),
+            CommandL::Component(c) => Command::Component(c),
+            CommandL::If(c) => Command::If(c),
+            CommandL::Else(c) => Command::Else(c),
+            CommandL::End(c) => Command::End(c),
+            CommandL::Call(c) => Command::Call(c.into()),
+            CommandL::ForIn(c) => Command::ForIn(c.into()),
+            CommandL::Loop(c) => Command::Loop(c),
+            CommandL::Break(c) => Command::Break(c),
+            CommandL::Procedure(c) => Command::Procedure(c.into()),
+            CommandL::Range(c) => Command::Range(c),
+            CommandL::SpawnFrom(c) => Command::SpawnFrom(c),
+            #[cfg(feature = "save_img")]
+            CommandL::ExportPng(c) => Command::ExportPng(c),
+            CommandL::List(c) => Command::List(c),
+            CommandL::Map(c) => Command::Map(c),
+            CommandL::Grid(c) => Command::Grid(c),
+            CommandL::Add(c) => Command::Add(c),
+            CommandL::Send(c) => Command::Send(c),
+        }
+    }
+}
+
+/// Scenario manifest with untyped settings, used before format version 3.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ScenarioManifestV0 {
+    name: String,
+    version: String,
+    engine: String,
+    mods: Vec<ScenarioModuleDep>,
+    settings: HashMap<String, String>,
+    title: Option<String>,
+    desc: Option<String>,
+    desc_long: Option<String>,
+    author: Option<String>,
+    website: Option<String>,
+}
+
+impl From<ScenarioManifestV0> for ScenarioManifest {
+    fn from(legacy: ScenarioManifestV0) -> Self {
+        let mut settings = Vec::new();
+        for (name, value) in &legacy.settings {
+            match ScenarioSetting::from_legacy(name, value) {
+                Some(setting) => settings.push(setting),
+                None => warn!("dropping invalid scenario setting: {} = {}", name, value),
+            }
+        }
+        ScenarioManifest {
+            name: legacy.name,
+            version: legacy.version,
+            engine: legacy.engine,
+            mods: legacy.mods,
+            settings,
+            title: legacy.title,
+            desc: legacy.desc,
+            desc_long: legacy.desc_long,
+            author: legacy.author,
+            website: legacy.website,
+            ..ScenarioManifest::default()
+        }
+    }
+}
+
+/// Scenario manifest without entity tables, used in format version 3.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ScenarioManifestV3 {
+    name: String,
+    version: String,
+    engine: String,
+    mods: Vec<ScenarioModuleDep>,
+    settings: Vec<ScenarioSetting>,
+    title: Option<String>,
+    desc: Option<String>,
+    desc_long: Option<String>,
+    author: Option<String>,
+    website: Option<String>,
+}
+
+impl From<ScenarioManifestV3> for ScenarioManifest {
+    fn from(legacy: ScenarioManifestV3) -> Self {
+        ScenarioManifest {
+            name: legacy.name,
+            version: legacy.version,
+            engine: legacy.engine,
+            mods: legacy.mods,
+            settings: legacy.settings,
+            title: legacy.title,
+            desc: legacy.desc,
+            desc_long: legacy.desc_long,
+            author: legacy.author,
+            website: legacy.website,
+            ..ScenarioManifest::default()
+        }
+    }
+}
+
+/// Scenario manifest without inheritance and data entries, used in
+/// format versions 4 and 5.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ScenarioManifestV4 {
+    name: String,
+    version: String,
+    engine: String,
+    mods: Vec<ScenarioModuleDep>,
+    settings: Vec<ScenarioSetting>,
+    entity_tables: Vec<String>,
+    title: Option<String>,
+    desc: Option<String>,
+    desc_long: Option<String>,
+    author: Option<String>,
+    website: Option<String>,
+}
+
+impl From<ScenarioManifestV4> for ScenarioManifest {
+    fn from(legacy: ScenarioManifestV4) -> Self {
+        ScenarioManifest {
+            name: legacy.name,
+            version: legacy.version,
+            engine: legacy.engine,
+            mods: legacy.mods,
+            settings: legacy.settings,
+            entity_tables: legacy.entity_tables,
+            title: legacy.title,
+            desc: legacy.desc,
+            desc_long: legacy.desc_long,
+            author: legacy.author,
+            website: legacy.website,
+            ..ScenarioManifest::default()
+        }
+    }
+}
+
+/// Module manifest used before format version 2.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ModuleManifestV0 {
+    name: String,
+    version: String,
+    engine_version_req: String,
+    engine_features: Vec<String>,
+    dependencies: HashMap<String, ModuleDep>,
+    reqs: Vec<String>,
+    libraries: Vec<ModuleLib>,
+    services: Vec<ServiceModel>,
+    title: Option<String>,
+    desc: Option<String>,
+    desc_long: Option<String>,
+    author: Option<String>,
+    website: Option<String>,
+}
+
+impl From<ModuleManifestV0> for ModuleManifest {
+    fn from(legacy: ModuleManifestV0) -> Self {
+        ModuleManifest {
+            name: legacy.name,
+            version: legacy.version,
+            engine_version_req: legacy.engine_version_req,
+            engine_features: legacy.engine_features,
+            dependencies: legacy.dependencies,
+            reqs: legacy.reqs,
+            libraries: legacy.libraries,
+            services: legacy.services,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: Vec::new(),
+            entity_tables: Vec::new(),
+            title: legacy.title,
+            desc: legacy.desc,
+            desc_long: legacy.desc_long,
+            author: legacy.author,
+            website: legacy.website,
+        }
+    }
+}
+
+/// Module manifest without entity tables, used in format versions 2
+/// and 3.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ModuleManifestV2 {
+    name: String,
+    version: String,
+    engine_version_req: String,
+    engine_features: Vec<String>,
+    dependencies: HashMap<String, ModuleDep>,
+    reqs: Vec<String>,
+    libraries: Vec<ModuleLib>,
+    services: Vec<ServiceModel>,
+    #[cfg(feature = "machine_lua")]
+    lua_globals: Vec<String>,
+    title: Option<String>,
+    desc: Option<String>,
+    desc_long: Option<String>,
+    author: Option<String>,
+    website: Option<String>,
+}
+
+impl From<ModuleManifestV2> for ModuleManifest {
+    fn from(legacy: ModuleManifestV2) -> Self {
+        ModuleManifest {
+            name: legacy.name,
+            version: legacy.version,
+            engine_version_req: legacy.engine_version_req,
+            engine_features: legacy.engine_features,
+            dependencies: legacy.dependencies,
+            reqs: legacy.reqs,
+            libraries: legacy.libraries,
+            services: legacy.services,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: legacy.lua_globals,
+            entity_tables: Vec::new(),
+            title: legacy.title,
+            desc: legacy.desc,
+            desc_long: legacy.desc_long,
+            author: legacy.author,
+            website: legacy.website,
+        }
+    }
+}
+
+/// Entity prefab without inheritance and var overrides.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntityPrefabV0 {
+    name: EntityName,
+    components: Vec<CompName>,
+}
+
+impl From<EntityPrefabV0> for EntityPrefab {
+    fn from(legacy: EntityPrefabV0) -> Self {
+        EntityPrefab {
+            name: legacy.name,
+            components: legacy.components,
+            ..EntityPrefab::default()
+        }
+    }
+}
+
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RegisterEntityPrefabV0 {
+    name: StringId,
+    components: Vec<StringId>,
+}
+
+#[cfg(feature = "machine")]
+impl From<RegisterEntityPrefabV0> for cmd::register::RegisterEntityPrefab {
+    fn from(legacy: RegisterEntityPrefabV0) -> Self {
+        cmd::register::RegisterEntityPrefab {
+            name: legacy.name,
+            components: legacy.components,
+            extends: None,
+            vars: Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SpawnV0 {
+    prefab: Option<StringId>,
+    spawn_id: Option<StringId>,
+    out: Option<ShortLocalAddress>,
+}
+
+#[cfg(feature = "machine")]
+impl From<SpawnV0> for cmd::Spawn {
+    fn from(legacy: SpawnV0) -> Self {
+        cmd::Spawn {
+            prefab: legacy.prefab,
+            spawn_id: legacy.spawn_id,
+            out: legacy.out,
+            vars: Vec::new(),
+        }
+    }
+}
+
+/// Eval command limited to a single scalar expression.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EvalV0 {
+    expr: fasteval::Instruction,
+    slab: fasteval::Slab,
+    args: Vec<(StringId, ShortLocalAddress)>,
+    out: Option<ShortLocalAddress>,
+}
+
+#[cfg(feature = "machine")]
+impl From<EvalV0> for cmd::eval::Eval {
+    fn from(legacy: EvalV0) -> Self {
+        cmd::eval::Eval {
+            expr: cmd::eval::Expression::Scalar {
+                instr: legacy.expr,
+                slab: legacy.slab,
+            },
+            args: legacy.args,
+            out: legacy.out,
+            vars: Vec::new(),
+            seed: None,
+        }
+    }
+}
+
+/// Set command without a merge policy.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SetV0 {
+    target: cmd::set::Target,
+    source: cmd::set::Source,
+    out: Option<ShortLocalAddress>,
+}
+
+#[cfg(feature = "machine")]
+impl From<SetV0> for cmd::set::Set {
+    fn from(legacy: SetV0) -> Self {
+        cmd::set::Set {
+            target: legacy.target,
+            source: legacy.source,
+            out: legacy.out,
+            policy: Default::default(),
+        }
+    }
+}
+
+/// For-in loop that can only iterate over vars.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ForInV0 {
+    start: usize,
+    end: usize,
+    target: ShortLocalAddress,
+    variable: ShortLocalAddress,
+}
+
+#[cfg(feature = "machine")]
+impl From<ForInV0> for cmd::flow::forin::ForIn {
+    fn from(legacy: ForInV0) -> Self {
+        cmd::flow::forin::ForIn {
+            start: legacy.start,
+            end: legacy.end,
+            target: cmd::flow::forin::ForInTarget::Var(legacy.target),
+            variable: legacy.variable,
+        }
+    }
+}
+
+/// Procedure without parameters and return values.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ProcedureV0 {
+    name: ShortString,
+    start_line: usize,
+    end_line: usize,
+    output_variable: Option<Address>,
+}
+
+#[cfg(feature = "machine")]
+impl From<ProcedureV0> for cmd::flow::procedure::Procedure {
+    fn from(legacy: ProcedureV0) -> Self {
+        cmd::flow::procedure::Procedure {
+            name: legacy.name,
+            start_line: legacy.start_line,
+            end_line: legacy.end_line,
+            params: Vec::new(),
+            returns: None,
+        }
+    }
+}
+
+/// Procedure call without arguments and return values.
+#[cfg(feature = "machine")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CallV0 {
+    proc_name: ShortString,
+}
+
+#[cfg(feature = "machine")]
+impl From<CallV0> for cmd::flow::call::Call {
+    fn from(legacy: CallV0) -> Self {
+        cmd::flow::call::Call {
+            proc_name: legacy.proc_name,
+            args: Vec::new(),
+            output: None,
+        }
+    }
+}
+
+/// Entity with storage consisting only of the var map.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntityV0 {
+    storage: FnvHashMap<StorageIndex, Var>,
+    components: Vec<CompName>,
+    #[cfg(feature = "machine")]
+    comp_state: FnvHashMap<CompName, StringId>,
+    #[cfg(feature = "machine")]
+    comp_queue: FnvHashMap<EventName, Vec<CompName>>,
+    insta: EntityNonSer,
+}
+
+impl From<EntityV0> for Entity {
+    fn from(legacy: EntityV0) -> Self {
+        Entity {
+            storage: Storage {
+                map: legacy.storage,
+                ..Storage::default()
+            },
+            components: legacy.components,
+            #[cfg(feature = "machine")]
+            comp_state: legacy.comp_state,
+            #[cfg(feature = "machine")]
+            comp_queue: legacy.comp_queue,
+            insta: legacy.insta,
+        }
+    }
+}
+
+/// Entity delta without the inbox, delta snapshots were introduced in
+/// format version 1.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct EntityDeltaV1 {
+    vars: FnvHashMap<StorageIndex, Var>,
+    removed_vars: Vec<StorageIndex>,
+    components: Option<Vec<CompName>>,
+    #[cfg(feature = "machine")]
+    comp_state: Option<FnvHashMap<CompName, StringId>>,
+    #[cfg(feature = "machine")]
+    comp_queue: Option<FnvHashMap<EventName, Vec<CompName>>>,
+}
+
+impl From<EntityDeltaV1> for EntityDelta {
+    fn from(legacy: EntityDeltaV1) -> Self {
+        EntityDelta {
+            vars: legacy.vars,
+            removed_vars: legacy.removed_vars,
+            components: legacy.components,
+            inbox: None,
+            #[cfg(feature = "machine")]
+            comp_state: legacy.comp_state,
+            #[cfg(feature = "machine")]
+            comp_queue: legacy.comp_queue,
+        }
+    }
+}
+
+fn convert_vec<P: Into<T>, T>(legacy: Vec<P>) -> Vec<T> {
+    legacy.into_iter().map(Into::into).collect()
+}
+
+fn convert_map<P: Into<T>, T>(legacy: FnvHashMap<EntityId, P>) -> FnvHashMap<EntityId, T> {
+    legacy.into_iter().map(|(id, p)| (id, p.into())).collect()
+}
+
+#[cfg(test)]
+fn legacy_header<L: Layout>(
+    manifest: L::ScenarioManifest,
+    module: L::ModuleManifest,
+    entity_tables: L::EntityTables,
+) -> HeaderL<L> {
+    HeaderL {
+        metadata: SnapshotMetadata {
+            created: chrono::Utc::now(),
+            starter: crate::SimStarter::Scenario("".to_string()),
+        },
+        clock: 7,
+        model: SimModelL {
+            scenario: ScenarioL {
+                path: PathBuf::new(),
+                manifest,
+                modules: vec![ModuleL {
+                    manifest: module,
+                    path: PathBuf::new(),
+                }],
+            },
+            events: Vec::new(),
+            scripts: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+            data: Vec::new(),
+            data_files: Vec::new(),
+            data_imgs: Vec::new(),
+            entity_tables,
+            services: Vec::new(),
+        },
+        entities_idx: FnvHashMap::default(),
+        event_queue: Vec::new(),
+        entity_pool: IdPool::new(),
+    }
+}
+
+#[cfg(test)]
+fn legacy_entity(map: &FnvHashMap<StorageIndex, Var>) -> EntityV0 {
+    let entity = Entity::empty();
+    EntityV0 {
+        storage: map.clone(),
+        components: entity.components,
+        #[cfg(feature = "machine")]
+        comp_state: entity.comp_state,
+        #[cfg(feature = "machine")]
+        comp_queue: entity.comp_queue,
+        insta: entity.insta,
+    }
+}
+
+#[cfg(test)]
+fn scenario_manifest_v0(settings: HashMap<String, String>) -> ScenarioManifestV0 {
+    ScenarioManifestV0 {
+        name: "name".to_string(),
+        version: "0.1.0".to_string(),
+        engine: "*".to_string(),
+        mods: Vec::new(),
+        settings,
+        title: Some("title".to_string()),
+        desc: None,
+        desc_long: None,
+        author: None,
+        website: None,
+    }
+}
+
+#[cfg(test)]
+fn module_manifest_v0() -> ModuleManifestV0 {
+    ModuleManifestV0 {
+        name: "module".to_string(),
+        version: "0.1.0".to_string(),
+        engine_version_req: "*".to_string(),
+        engine_features: Vec::new(),
+        dependencies: HashMap::new(),
+        reqs: Vec::new(),
+        libraries: Vec::new(),
+        services: Vec::new(),
+        title: None,
+        desc: None,
+        desc_long: None,
+        author: None,
+        website: None,
+    }
+}
+
+#[test]
+fn snapshot_legacy_untyped_settings() {
+    let mut settings = HashMap::new();
+    settings.insert("ent:comp:int:count".to_string(), "3".to_string());
+    settings.insert("not_an_address".to_string(), "true".to_string());
+    let manifest = scenario_manifest_v0(settings);
+    let header = legacy_header::<V0>(manifest, module_manifest_v0(), Absent);
+    let bytes = bincode::serialize(&header).unwrap();
+
+    let upgraded = upgrade::<V0>(bytes, false).unwrap();
+    let header: SnapshotHeader = bincode::deserialize(&upgraded).unwrap();
+    assert_eq!(header.clock, 7);
+    let manifest = &header.model.scenario.manifest;
+    assert_eq!(manifest.name, "name");
+    assert_eq!(manifest.title, Some("title".to_string()));
+    assert_eq!(manifest.settings.len(), 1);
+    assert_eq!(manifest.settings[0].value, Var::Int(3));
+    assert_eq!(header.model.scenario.modules[0].manifest.name, "module");
+}
+
+#[test]
+fn snapshot_legacy_entity_inbox() {
+    let index = (
+        crate::string::new_truncate("comp"),
+        crate::string::new_truncate("x"),
+    );
+    let mut map = FnvHashMap::default();
+    map.insert(index.clone(), Var::Int(5));
+    let manifest = scenario_manifest_v0(HashMap::new());
+    let header = legacy_header::<V2>(manifest, module_manifest_v2(), Absent);
+
+    // union snapshot with two parts
+    let mut bytes = bincode::serialize(&header).unwrap();
+    for id in 0..2 {
+        let mut entities = FnvHashMap::default();
+        entities.insert(id as EntityId, legacy_entity(&map));
+        let part = PartL::<V2> {
+            entities,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: FnvHashMap::default(),
+        };
+        bytes.extend(bincode::serialize(&part).unwrap());
+    }
+    let upgraded = upgrade::<V2>(bytes, false).unwrap();
+    let mut cursor = &upgraded[..];
+    let _: SnapshotHeader = bincode::deserialize_from(&mut cursor).unwrap();
+    for id in 0..2 {
+        let part: SnapshotPart = bincode::deserialize_from(&mut cursor).unwrap();
+        let entity = &part.entities[&(id as EntityId)];
+        assert_eq!(entity.storage.map, map);
+        assert!(entity.storage.inbox.is_empty());
+    }
+    assert!(cursor.is_empty());
+
+    // delta snapshot
+    let mut changed = FnvHashMap::default();
+    changed.insert(
+        0,
+        EntityDeltaV1 {
+            vars: map.clone(),
+            removed_vars: Vec::new(),
+            components: None,
+            #[cfg(feature = "machine")]
+            comp_state: None,
+            #[cfg(feature = "machine")]
+            comp_queue: None,
+        },
+    );
+    let delta = DeltaL::<V2> {
+        base: "base".to_string(),
+        base_clock: 3,
+        header,
+        added: FnvHashMap::default(),
+        changed,
+        removed: vec![1],
+        #[cfg(feature = "machine_lua")]
+        lua_globals: FnvHashMap::default(),
+    };
+    let bytes = bincode::serialize(&delta).unwrap();
+    let upgraded = upgrade::<V2>(bytes, true).unwrap();
+    let delta: SnapshotDelta = bincode::deserialize(&upgraded).unwrap();
+    assert_eq!(delta.base, "base");
+    assert_eq!(delta.base_clock, 3);
+    assert_eq!(delta.changed[&0].vars, map);
+    assert!(delta.changed[&0].inbox.is_none());
+    assert_eq!(delta.removed, vec![1]);
+}
+
+#[cfg(test)]
+fn module_manifest_v2() -> ModuleManifestV2 {
+    let legacy = module_manifest_v0();
+    ModuleManifestV2 {
+        name: legacy.name,
+        version: legacy.version,
+        engine_version_req: legacy.engine_version_req,
+        engine_features: legacy.engine_features,
+        dependencies: legacy.dependencies,
+        reqs: legacy.reqs,
+        libraries: legacy.libraries,
+        services: legacy.services,
+        #[cfg(feature = "machine_lua")]
+        lua_globals: vec!["counter".to_string()],
+        title: legacy.title,
+        desc: legacy.desc,
+        desc_long: legacy.desc_long,
+        author: legacy.author,
+        website: legacy.website,
+    }
+}
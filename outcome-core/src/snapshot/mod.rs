@@ -0,0 +1,1058 @@
+mod legacy;
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use fnv::FnvHashMap;
+use id_pool::IdPool;
+
+use crate::distr::SimNode;
+use crate::entity::{Entity, Message, StorageIndex};
+use crate::error::Error;
+#[cfg(feature = "machine_lua")]
+use crate::sim::lua::LuaGlobals;
+use crate::{
+    CompName, EntityId, EntityName, EventName, Result, Sim, SimModel, SimStarter, StringId, Var,
+};
+use std::io::Read;
+
+/// Magic bytes found at the very beginning of every versioned snapshot.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"OSNP";
+
+/// Magic bytes found at the beginning of delta snapshots, which only store
+/// changes relative to a base snapshot.
+pub const SNAPSHOT_DELTA_MAGIC: [u8; 4] = *b"OSND";
+
+/// Maximum number of delta snapshots that can be chained on top of a single
+/// full snapshot.
+pub const MAX_DELTA_CHAIN_LENGTH: usize = 1024;
+
+/// Placeholder recorded in place of the layout features of legacy snapshots,
+/// which didn't store them.
+pub const UNKNOWN_FEATURES: &str = "unknown";
+
+/// Current version of the snapshot binary format.
+///
+/// Needs to be bumped each time the layout of the serialized data changes,
+/// together with registering an appropriate migration in
+/// [`SNAPSHOT_MIGRATIONS`].
+pub const SNAPSHOT_FORMAT_VERSION: u16 = 12;
+
+/// Function upgrading snapshot body bytes to the current format version.
+///
+/// Second argument tells whether the bytes belong to a delta snapshot.
+pub type SnapshotMigration = fn(Vec<u8>, bool) -> Result<Vec<u8>>;
+
+/// Migrations from older snapshot format versions. Migration at index `n`
+/// upgrades body bytes from version `n` directly to the current version.
+///
+/// Version 1 introduced the preamble, version 2 added persisted Lua
+/// globals, version 3 replaced untyped scenario settings with typed ones,
+/// version 4 added entity tables, version 5 added image export commands,
+/// version 6 added scenario inheritance and data entries, version 7 added
+/// prefab inheritance and var overrides, version 8 added vector and list
+/// support to eval, version 9 added list, map and grid commands, version 10
+/// added merge policies for writes to other entities, version 11 added
+/// entity message inboxes and version 12 added procedure parameters,
+/// return values and local vars.
+pub const SNAPSHOT_MIGRATIONS: [SnapshotMigration; SNAPSHOT_FORMAT_VERSION as usize] = [
+    legacy::upgrade::<legacy::V0>,
+    legacy::upgrade::<legacy::V0>,
+    legacy::upgrade::<legacy::V2>,
+    legacy::upgrade::<legacy::V3>,
+    legacy::upgrade::<legacy::V4>,
+    legacy::upgrade::<legacy::V5>,
+    legacy::upgrade::<legacy::V6>,
+    legacy::upgrade::<legacy::V7>,
+    legacy::upgrade::<legacy::V8>,
+    legacy::upgrade::<legacy::V9>,
+    legacy::upgrade::<legacy::V10>,
+    legacy::upgrade::<legacy::V11>,
+];
+
+/// Names of the enabled engine features that affect the binary layout of
+/// the serialized simulation state.
+pub fn layout_features() -> Vec<String> {
+    let mut features = Vec::new();
+    if crate::FEATURE_BIG_NUMS {
+        features.push(crate::FEATURE_NAME_BIG_NUMS.to_string());
+    }
+    if crate::FEATURE_STACK_STRINGID {
+        features.push(crate::FEATURE_NAME_STACK_STRINGID.to_string());
+    }
+    if crate::FEATURE_SHORT_STRINGID {
+        features.push(crate::FEATURE_NAME_SHORT_STRINGID.to_string());
+    }
+    if crate::FEATURE_MACHINE {
+        features.push(crate::FEATURE_NAME_MACHINE.to_string());
+    }
+    if crate::FEATURE_MACHINE_LUA {
+        features.push(crate::FEATURE_NAME_MACHINE_LUA.to_string());
+    }
+    if crate::FEATURE_SAVE_IMG {
+        features.push(crate::FEATURE_NAME_SAVE_IMG.to_string());
+    }
+    features
+}
+
+/// Leading section of each snapshot, describing the format and the engine
+/// that created it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotPreamble {
+    pub magic: [u8; 4],
+    pub format_version: u16,
+    /// Version of the engine that created the snapshot
+    pub engine_version: String,
+    /// Layout-relevant features enabled in the engine that created the
+    /// snapshot, see [`layout_features`]
+    pub features: Vec<String>,
+    /// Whether the snapshot body following the preamble is compressed
+    pub compressed: bool,
+}
+
+impl SnapshotPreamble {
+    /// Creates a preamble describing the currently running engine.
+    pub fn current() -> Self {
+        Self {
+            magic: SNAPSHOT_MAGIC,
+            format_version: SNAPSHOT_FORMAT_VERSION,
+            engine_version: crate::VERSION.to_string(),
+            features: layout_features(),
+            compressed: false,
+        }
+    }
+
+    /// Creates a preamble representing a legacy snapshot created before
+    /// snapshot versioning was introduced.
+    ///
+    /// Legacy snapshots don't record the engine features they were created
+    /// with, so the features are marked as unknown.
+    pub fn legacy() -> Self {
+        Self {
+            magic: SNAPSHOT_MAGIC,
+            format_version: 0,
+            engine_version: "unknown".to_string(),
+            features: vec![UNKNOWN_FEATURES.to_string()],
+            compressed: false,
+        }
+    }
+
+    /// Checks whether the preamble represents a legacy snapshot, see
+    /// [`SnapshotPreamble::legacy`].
+    pub fn is_legacy(&self) -> bool {
+        self.format_version == 0
+    }
+
+    /// Creates a preamble for a delta snapshot created by the currently
+    /// running engine.
+    pub fn current_delta() -> Self {
+        Self {
+            magic: SNAPSHOT_DELTA_MAGIC,
+            ..Self::current()
+        }
+    }
+
+    /// Checks whether the preamble belongs to a delta snapshot.
+    pub fn is_delta(&self) -> bool {
+        self.magic == SNAPSHOT_DELTA_MAGIC
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))
+    }
+
+    /// Checks whether a snapshot described by the preamble can be loaded
+    /// by the current engine.
+    ///
+    /// Features of legacy snapshots are unknown and can't be verified.
+    pub fn check_compatible(&self) -> Result<()> {
+        if self.format_version > SNAPSHOT_FORMAT_VERSION {
+            return Err(Error::SnapshotUnsupportedFormatVersion(
+                self.format_version,
+                SNAPSHOT_FORMAT_VERSION,
+            ));
+        }
+        if self.is_legacy() {
+            warn!(
+                "legacy snapshot doesn't record engine features, \
+                 assuming they match the current engine"
+            );
+            return Ok(());
+        }
+        let mut current = layout_features();
+        // image export commands only affect the layout since version 5
+        if self.format_version < 5 {
+            current.retain(|f| f != crate::FEATURE_NAME_SAVE_IMG);
+        }
+        let missing = self
+            .features
+            .iter()
+            .filter(|f| !current.contains(f))
+            .cloned()
+            .collect::<Vec<_>>();
+        let extra = current
+            .iter()
+            .filter(|f| !self.features.contains(f))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing.is_empty() || !extra.is_empty() {
+            return Err(Error::SnapshotFeatureMismatch(format!(
+                "snapshot requires features: [{}], engine is missing: [{}], engine has extra: [{}]",
+                self.features.join(", "),
+                missing.join(", "),
+                extra.join(", "),
+            )));
+        }
+        if self.engine_version != crate::VERSION {
+            warn!(
+                "snapshot was created with engine version {}, current version is {}",
+                self.engine_version,
+                crate::VERSION
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Checks if the provided bytes start with one of the snapshot magic
+/// numbers.
+pub fn is_versioned(bytes: &[u8]) -> bool {
+    bytes.starts_with(&SNAPSHOT_MAGIC) || bytes.starts_with(&SNAPSHOT_DELTA_MAGIC)
+}
+
+/// Checks if the provided (decompressed) bytes represent a delta snapshot.
+pub fn is_delta(bytes: &[u8]) -> bool {
+    bytes.starts_with(&SNAPSHOT_DELTA_MAGIC)
+}
+
+/// Checks whether the snapshot body is compressed.
+///
+/// Versioned snapshots store the information in the preamble. Legacy
+/// snapshots were compressed as a whole, so they are considered compressed
+/// if they can be decompressed.
+pub fn is_compressed(bytes: &[u8]) -> Result<bool> {
+    if is_versioned(bytes) {
+        let preamble: SnapshotPreamble = bincode::deserialize(bytes)
+            .map_err(|e| Error::FailedReadingSnapshotHeader(e.to_string()))?;
+        return Ok(preamble.compressed);
+    }
+    #[cfg(feature = "lz4")]
+    {
+        return Ok(lz4::block::decompress(bytes, None).is_ok());
+    }
+    #[cfg(not(feature = "lz4"))]
+    Ok(false)
+}
+
+/// Compresses the body of the snapshot, leaving the preamble uncompressed
+/// and marking the body as compressed.
+#[cfg(feature = "lz4")]
+pub fn compress_snapshot(mut bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let mut preamble = extract_preamble(&mut bytes)?;
+    if preamble.compressed {
+        return Ok(bytes);
+    }
+    preamble.compressed = true;
+    let mut out = preamble.to_bytes()?;
+    out.extend(lz4::block::compress(&bytes, None, true)?);
+    Ok(out)
+}
+
+/// Decompresses the snapshot body if the preamble marks it as compressed.
+/// Returned bytes include the preamble.
+///
+/// Legacy snapshots don't have a preamble and were compressed as a whole,
+/// those that fail decompression are returned unchanged.
+pub fn decompress_if_needed(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    if !is_versioned(&bytes) {
+        #[cfg(feature = "lz4")]
+        {
+            if let Ok(data) = lz4::block::decompress(&bytes, None) {
+                return Ok(data);
+            }
+        }
+        return Ok(bytes);
+    }
+    let mut body = &bytes[..];
+    let mut preamble: SnapshotPreamble = bincode::deserialize_from(&mut body)
+        .map_err(|e| Error::FailedReadingSnapshotHeader(e.to_string()))?;
+    if !preamble.compressed {
+        return Ok(bytes);
+    }
+    #[cfg(feature = "lz4")]
+    {
+        preamble.compressed = false;
+        let mut out = preamble.to_bytes()?;
+        out.extend(
+            lz4::block::decompress(body, None)
+                .map_err(|e| Error::SnapshotDecompressionError(e.to_string()))?,
+        );
+        Ok(out)
+    }
+    #[cfg(not(feature = "lz4"))]
+    Err(Error::SnapshotDecompressionError(
+        "snapshot is compressed, decompression requires the lz4 feature".to_string(),
+    ))
+}
+
+/// Extracts the snapshot preamble from the provided bytes, leaving the
+/// snapshot body in place.
+///
+/// Bytes without the magic number are treated as a legacy snapshot with
+/// format version 0.
+pub fn extract_preamble(bytes: &mut Vec<u8>) -> Result<SnapshotPreamble> {
+    if !is_versioned(bytes) {
+        return Ok(SnapshotPreamble::legacy());
+    }
+    let mut cursor = &bytes[..];
+    let preamble: SnapshotPreamble = bincode::deserialize_from(&mut cursor)
+        .map_err(|e| Error::FailedReadingSnapshotHeader(e.to_string()))?;
+    *bytes = cursor.to_owned();
+    Ok(preamble)
+}
+
+/// Upgrades snapshot body bytes from the given format version to the
+/// current one, using the registered migration.
+pub fn migrate(bytes: Vec<u8>, from_version: u16, delta: bool) -> Result<Vec<u8>> {
+    let migration = SNAPSHOT_MIGRATIONS[from_version as usize];
+    migration(bytes, delta).map_err(|e| Error::SnapshotMigrationFailed(from_version, e.to_string()))
+}
+
+/// Prepares raw snapshot bytes for reading the header and parts.
+///
+/// Handles decompression, reads and verifies the preamble, and migrates
+/// the body to the current format version if needed.
+pub fn read_preamble(bytes: &mut Vec<u8>) -> Result<SnapshotPreamble> {
+    *bytes = decompress_if_needed(std::mem::take(bytes))?;
+    let preamble = extract_preamble(bytes)?;
+    preamble.check_compatible()?;
+    if preamble.format_version < SNAPSHOT_FORMAT_VERSION {
+        debug!(
+            "migrating snapshot from format version {} to {}",
+            preamble.format_version, SNAPSHOT_FORMAT_VERSION
+        );
+        *bytes = migrate(
+            std::mem::take(bytes),
+            preamble.format_version,
+            preamble.is_delta(),
+        )?;
+    }
+    Ok(preamble)
+}
+
+pub trait Snap {
+    fn to_snapshot(&self) -> Result<Vec<u8>>;
+    fn from_snapshot(bytes: &mut Vec<u8>) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+pub trait SnapPart {
+    fn to_snapshot_part(&self) -> Result<Vec<u8>>;
+    fn from_snapshot_part(bytes: &[u8], header: SnapshotHeader) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+impl Snap for Sim {
+    fn to_snapshot(&self) -> Result<Vec<u8>> {
+        let header = SnapshotHeader {
+            metadata: SnapshotMetadata {
+                created: Utc::now(),
+                // TODO
+                starter: SimStarter::Scenario("".to_string()),
+            },
+            clock: self.clock,
+            model: self.model.clone(),
+            entities_idx: self.entity_idx.clone(),
+            event_queue: self.event_queue.clone(),
+            entity_pool: self.entity_pool.clone(),
+        };
+        let part = SnapshotPart {
+            entities: self.entities.clone(),
+            #[cfg(feature = "machine_lua")]
+            lua_globals: self.lua_globals(),
+        };
+        let mut bytes = SnapshotPreamble::current().to_bytes()?;
+        bytes.extend(
+            bincode::serialize(&header).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?,
+        );
+        bytes.extend(
+            bincode::serialize(&part).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?,
+        );
+        Ok(bytes)
+    }
+
+    fn from_snapshot(mut bytes: &mut Vec<u8>) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        if read_preamble(&mut bytes)?.is_delta() {
+            return Err(Error::FailedReadingSnapshot(
+                "delta snapshot can't be loaded without its base, use `Sim::load_snapshot`"
+                    .to_string(),
+            ));
+        }
+        let header = extract_header(&mut bytes)?;
+        // union snapshots consist of multiple parts, one for each node
+        let mut part = extract_part(&mut bytes)?;
+        while !bytes.is_empty() {
+            let next = extract_part(&mut bytes)?;
+            part.entities.extend(next.entities);
+            #[cfg(feature = "machine_lua")]
+            part.lua_globals.extend(next.lua_globals);
+        }
+        let mut sim = Self {
+            model: header.model,
+            clock: header.clock,
+            event_queue: header.event_queue,
+            entities: part.entities,
+            entity_idx: header.entities_idx,
+            entity_pool: header.entity_pool,
+            #[cfg(feature = "machine_lua")]
+            entity_lua_state: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs_loaded: false,
+            checkpointer: None,
+            snapshot_base: None,
+            #[cfg(feature = "save_img")]
+            grid_exports: Vec::new(),
+            #[cfg(feature = "machine")]
+            debugger: None,
+        };
+        sim.restore_runtime_state(
+            #[cfg(feature = "machine_lua")]
+            part.lua_globals,
+        )?;
+        Ok(sim)
+    }
+}
+
+impl SnapPart for Sim {
+    fn to_snapshot_part(&self) -> Result<Vec<u8>> {
+        let part = SnapshotPart {
+            entities: self.entities.clone(),
+            #[cfg(feature = "machine_lua")]
+            lua_globals: self.lua_globals(),
+        };
+        let out = bincode::serialize(&part)
+            .map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?;
+
+        // let vec = vec![&self.entities];
+        // let out = bincode::serialize(&vec).unwrap();
+
+        Ok(out)
+    }
+
+    fn from_snapshot_part(bytes: &[u8], header: SnapshotHeader) -> Result<Self> {
+        let part: SnapshotPart = bincode::deserialize(bytes)
+            .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+        let mut sim = Sim {
+            model: header.model,
+            clock: header.clock,
+            event_queue: header.event_queue,
+            entities: part.entities,
+            entity_idx: header.entities_idx,
+            entity_pool: header.entity_pool,
+            #[cfg(feature = "machine_lua")]
+            entity_lua_state: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs: Default::default(),
+            #[cfg(feature = "machine_dynlib")]
+            libs_loaded: false,
+            checkpointer: None,
+            snapshot_base: None,
+            #[cfg(feature = "save_img")]
+            grid_exports: Vec::new(),
+            #[cfg(feature = "machine")]
+            debugger: None,
+        };
+        sim.restore_runtime_state(
+            #[cfg(feature = "machine_lua")]
+            part.lua_globals,
+        )?;
+        Ok(sim)
+    }
+}
+
+impl SnapPart for SimNode {
+    fn to_snapshot_part(&self) -> Result<Vec<u8>> {
+        let part = SnapshotPart {
+            entities: self.entities.clone(),
+            #[cfg(feature = "machine_lua")]
+            lua_globals: Default::default(),
+        };
+        bincode::serialize(&part).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))
+    }
+
+    fn from_snapshot_part(bytes: &[u8], header: SnapshotHeader) -> Result<Self> {
+        let part: SnapshotPart = bincode::deserialize(bytes)
+            .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+        let entities_idx = header
+            .entities_idx
+            .into_iter()
+            .filter(|(_, id)| part.entities.contains_key(id))
+            .collect();
+        Ok(SimNode {
+            clock: header.clock,
+            model: header.model,
+            event_queue: header.event_queue,
+            entities: part.entities,
+            entities_idx,
+        })
+    }
+}
+
+/// Assembles a union snapshot out of the central header and serialized
+/// parts collected from each of the nodes.
+///
+/// Resulting snapshot can be restored using any number of nodes, including
+/// a single local `Sim`.
+pub fn assemble_union_snapshot(header: &SnapshotHeader, parts: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut bytes = SnapshotPreamble::current().to_bytes()?;
+    bytes.extend(
+        bincode::serialize(header).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?,
+    );
+    for part in parts {
+        bytes.extend(part);
+    }
+    Ok(bytes)
+}
+
+/// Extracts snapshot header from the provided bytes.
+pub fn extract_header(mut bytes: &mut Vec<u8>) -> Result<SnapshotHeader> {
+    let mut cursor = &bytes[..];
+    let mut header: SnapshotHeader = bincode::deserialize_from(&mut cursor)
+        .map_err(|e| Error::FailedReadingSnapshotHeader(e.to_string()))?;
+    *bytes = cursor.to_owned();
+    Ok(header)
+}
+
+/// Extracts a single snapshot part from the provided bytes.
+pub fn extract_part(mut bytes: &mut Vec<u8>) -> Result<SnapshotPart> {
+    let mut cursor = &bytes[..];
+    let mut part: SnapshotPart = bincode::deserialize_from(&mut cursor)
+        .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+    *bytes = cursor.to_owned();
+    Ok(part)
+}
+
+/// Extracts delta snapshot data from the provided bytes, expects the
+/// preamble to be already read.
+pub fn extract_delta(bytes: &mut Vec<u8>) -> Result<SnapshotDelta> {
+    let mut cursor = &bytes[..];
+    let delta: SnapshotDelta = bincode::deserialize_from(&mut cursor)
+        .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+    *bytes = cursor.to_owned();
+    Ok(delta)
+}
+
+/// Reads snapshot file at the given path into a vector of bytes.
+pub fn read_snapshot_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path).map_err(|e| {
+        Error::FailedReadingSnapshot(format!("{}: {}", path.to_string_lossy(), e))
+    })?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Walks the chain of delta snapshots, starting with the provided bytes of
+/// the snapshot with the given name.
+///
+/// Returns decompressed bytes of the full base snapshot, along with the
+/// named deltas in the order they need to be applied. Deltas are returned
+/// with their preamble already verified.
+pub fn resolve_delta_chain(
+    snapshots_dir: &Path,
+    name: &str,
+    bytes: Vec<u8>,
+) -> Result<(Vec<u8>, Vec<(String, SnapshotDelta)>)> {
+    let mut names = vec![name.to_string()];
+    let mut deltas = Vec::new();
+    let mut bytes = decompress_if_needed(bytes)?;
+    while is_delta(&bytes) {
+        read_preamble(&mut bytes)?;
+        let delta = extract_delta(&mut bytes)?;
+        if names.contains(&delta.base) {
+            return Err(Error::FailedReadingSnapshot(format!(
+                "cycle detected in delta snapshot chain: {} -> {}",
+                names.join(" -> "),
+                delta.base
+            )));
+        }
+        if deltas.len() >= MAX_DELTA_CHAIN_LENGTH {
+            return Err(Error::FailedReadingSnapshot(format!(
+                "delta snapshot chain too long (max {})",
+                MAX_DELTA_CHAIN_LENGTH
+            )));
+        }
+        bytes = decompress_if_needed(read_snapshot_file(&snapshots_dir.join(&delta.base))?)?;
+        names.push(delta.base.clone());
+        deltas.push((names[names.len() - 2].clone(), delta));
+    }
+    deltas.reverse();
+    Ok((bytes, deltas))
+}
+
+/// Resolves the chain of snapshots needed to restore the snapshot with the
+/// given name, starting with the full base snapshot and ending with the
+/// named snapshot itself.
+pub fn snapshot_chain(snapshots_dir: &Path, name: &str) -> Result<Vec<String>> {
+    let bytes = read_snapshot_file(&snapshots_dir.join(name))?;
+    let (_, deltas) = resolve_delta_chain(snapshots_dir, name, bytes)?;
+    let mut chain = match deltas.first() {
+        Some((_, delta)) => vec![delta.base.clone()],
+        None => vec![name.to_string()],
+    };
+    chain.extend(deltas.into_iter().map(|(name, _)| name));
+    Ok(chain)
+}
+
+/// Compacts a chain of delta snapshots into a single full snapshot.
+///
+/// Resulting snapshot is written to `target`, which can also be the name of
+/// the delta snapshot itself. Base snapshots are left untouched.
+pub fn compact_snapshot(
+    snapshots_dir: &Path,
+    name: &str,
+    target: &str,
+    compress: bool,
+) -> Result<()> {
+    let sim = Sim::load_snapshot_from_dir(snapshots_dir, name)?;
+    let mut data = sim.to_snapshot()?;
+    #[cfg(feature = "lz4")]
+    {
+        if compress {
+            data = compress_snapshot(data)?;
+        }
+    }
+    let mut file = File::create(snapshots_dir.join(target))?;
+    file.write_all(&data)?;
+    Ok(())
+}
+
+pub fn prepend_header(mut buf: &mut Vec<u8>, header: SnapshotHeader) -> Result<()> {
+    unimplemented!()
+}
+
+/// Representation of the simulation state at a certain point in time.
+///
+/// This representation is not fully self-sufficient, and will require the
+/// project file structure for proper initialization.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnapshotHeader {
+    /// Data about the snapshot itself
+    pub metadata: SnapshotMetadata,
+
+    pub clock: usize,
+    pub model: SimModel,
+    pub entities_idx: FnvHashMap<EntityName, EntityId>,
+    pub event_queue: Vec<EventName>,
+    pub entity_pool: IdPool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    /// Time when the snapshot was created
+    pub created: DateTime<Utc>,
+    /// Starter that was used to initialize the simulation
+    pub starter: SimStarter,
+}
+
+/// Delta snapshot, storing only the changes relative to a base snapshot.
+///
+/// Base snapshot can itself be a delta snapshot, forming a chain that
+/// eventually leads to a full snapshot.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnapshotDelta {
+    /// Name of the base snapshot, relative to the snapshots directory
+    pub base: String,
+    /// Clock value of the base snapshot, used for verification on load
+    pub base_clock: usize,
+    /// Full header, model and indexes are comparatively small
+    pub header: SnapshotHeader,
+    /// Entities not present in the base snapshot
+    pub added: FnvHashMap<EntityId, Entity>,
+    /// Changes to entities present in the base snapshot
+    pub changed: FnvHashMap<EntityId, EntityDelta>,
+    /// Entities present in the base snapshot that were removed since
+    pub removed: Vec<EntityId>,
+    /// Full set of persisted Lua globals
+    #[cfg(feature = "machine_lua")]
+    pub lua_globals: FnvHashMap<EntityId, LuaGlobals>,
+}
+
+/// Entity state of a saved snapshot, used for computing delta snapshots
+/// against it.
+#[derive(Clone)]
+pub struct SnapshotBase {
+    /// Name of the snapshot, relative to the snapshots directory
+    pub name: String,
+    pub clock: usize,
+    pub entities: FnvHashMap<EntityId, Entity>,
+}
+
+impl SnapshotBase {
+    pub fn new(name: &str, sim: &Sim) -> Self {
+        Self {
+            name: name.to_string(),
+            clock: sim.clock,
+            entities: sim.entities.clone(),
+        }
+    }
+}
+
+impl SnapshotDelta {
+    /// Computes delta between the base and the current simulation state.
+    pub fn between(base: &SnapshotBase, current: &Sim) -> Self {
+        let mut added = FnvHashMap::default();
+        let mut changed = FnvHashMap::default();
+        for (id, entity) in &current.entities {
+            match base.entities.get(id) {
+                Some(base_entity) => {
+                    if let Some(delta) = EntityDelta::between(base_entity, entity) {
+                        changed.insert(*id, delta);
+                    }
+                }
+                None => {
+                    added.insert(*id, entity.clone());
+                }
+            }
+        }
+        let removed = base
+            .entities
+            .keys()
+            .filter(|id| !current.entities.contains_key(id))
+            .cloned()
+            .collect();
+        Self {
+            base: base.name.clone(),
+            base_clock: base.clock,
+            header: SnapshotHeader {
+                metadata: SnapshotMetadata {
+                    created: Utc::now(),
+                    // TODO
+                    starter: SimStarter::Snapshot(base.name.clone()),
+                },
+                clock: current.clock,
+                model: current.model.clone(),
+                entities_idx: current.entity_idx.clone(),
+                event_queue: current.event_queue.clone(),
+                entity_pool: current.entity_pool.clone(),
+            },
+            added,
+            changed,
+            removed,
+            #[cfg(feature = "machine_lua")]
+            lua_globals: current.lua_globals(),
+        }
+    }
+
+    /// Serializes the delta, including the preamble.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = SnapshotPreamble::current_delta().to_bytes()?;
+        bytes.extend(
+            bincode::serialize(self).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?,
+        );
+        Ok(bytes)
+    }
+
+    /// Applies the delta on top of the base simulation state.
+    pub fn apply_to(self, sim: &mut Sim) -> Result<()> {
+        if sim.clock != self.base_clock {
+            return Err(Error::FailedReadingSnapshot(format!(
+                "delta snapshot base clock mismatch: expected {}, base snapshot has {}",
+                self.base_clock, sim.clock
+            )));
+        }
+        sim.clock = self.header.clock;
+        sim.model = self.header.model;
+        sim.entity_idx = self.header.entities_idx;
+        sim.event_queue = self.header.event_queue;
+        sim.entity_pool = self.header.entity_pool;
+        for id in &self.removed {
+            sim.entities.remove(id);
+            #[cfg(feature = "machine_lua")]
+            sim.entity_lua_state.remove(id);
+        }
+        for (id, delta) in self.changed {
+            let entity = sim
+                .entities
+                .get_mut(&id)
+                .ok_or(Error::FailedGettingEntityById(id))?;
+            delta.apply_to(entity);
+        }
+        sim.entities.extend(self.added);
+        #[cfg(feature = "machine_lua")]
+        sim.restore_lua_globals(self.lua_globals)?;
+        Ok(())
+    }
+}
+
+/// Changes to a single entity relative to its base snapshot state.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct EntityDelta {
+    /// Vars that were added or changed
+    pub vars: FnvHashMap<StorageIndex, Var>,
+    /// Vars that were removed
+    pub removed_vars: Vec<StorageIndex>,
+    /// Full list of components, if changed
+    pub components: Option<Vec<CompName>>,
+    /// Full list of received messages, if changed
+    pub inbox: Option<Vec<Message>>,
+    /// Full component state map, if changed
+    #[cfg(feature = "machine")]
+    pub comp_state: Option<FnvHashMap<CompName, StringId>>,
+    /// Full component queue map, if changed
+    #[cfg(feature = "machine")]
+    pub comp_queue: Option<FnvHashMap<EventName, Vec<CompName>>>,
+}
+
+impl EntityDelta {
+    /// Computes delta between two versions of the same entity. Returns
+    /// `None` if there are no changes.
+    pub fn between(base: &Entity, current: &Entity) -> Option<Self> {
+        let mut delta = EntityDelta::default();
+        for (idx, var) in &current.storage.map {
+            if base.storage.map.get(idx) != Some(var) {
+                delta.vars.insert(idx.clone(), var.clone());
+            }
+        }
+        for idx in base.storage.map.keys() {
+            if !current.storage.map.contains_key(idx) {
+                delta.removed_vars.push(idx.clone());
+            }
+        }
+        if base.components != current.components {
+            delta.components = Some(current.components.clone());
+        }
+        if base.storage.inbox != current.storage.inbox {
+            delta.inbox = Some(current.storage.inbox.clone());
+        }
+        #[cfg(feature = "machine")]
+        {
+            if base.comp_state != current.comp_state {
+                delta.comp_state = Some(current.comp_state.clone());
+            }
+            if base.comp_queue != current.comp_queue {
+                delta.comp_queue = Some(current.comp_queue.clone());
+            }
+        }
+        if delta.is_empty() {
+            None
+        } else {
+            Some(delta)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "machine")]
+        {
+            if self.comp_state.is_some() || self.comp_queue.is_some() {
+                return false;
+            }
+        }
+        self.vars.is_empty()
+            && self.removed_vars.is_empty()
+            && self.components.is_none()
+            && self.inbox.is_none()
+    }
+
+    /// Applies the changes to the entity.
+    pub fn apply_to(self, entity: &mut Entity) {
+        for idx in &self.removed_vars {
+            entity.storage.map.remove(idx);
+        }
+        entity.storage.map.extend(self.vars);
+        if let Some(components) = self.components {
+            entity.components = components;
+        }
+        if let Some(inbox) = self.inbox {
+            entity.storage.inbox = inbox;
+        }
+        #[cfg(feature = "machine")]
+        {
+            if let Some(comp_state) = self.comp_state {
+                entity.comp_state = comp_state;
+            }
+            if let Some(comp_queue) = self.comp_queue {
+                entity.comp_queue = comp_queue;
+            }
+        }
+    }
+}
+
+/// Partial snapshot, used when partitioning large snapshots.
+// TODO support snapshot partitioning
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotPart {
+    pub entities: FnvHashMap<EntityId, Entity>,
+    /// Persisted Lua globals for entities with a Lua state
+    #[cfg(feature = "machine_lua")]
+    pub lua_globals: FnvHashMap<EntityId, LuaGlobals>,
+}
+
+impl From<Sim> for Snapshot {
+    fn from(sim: Sim) -> Self {
+        unimplemented!()
+        // Self {
+        //     metadata: SnapshotMetadata {
+        //         created: Utc::now(),
+        //     },
+        //     clock: sim.clock,
+        //     event_queue: sim.event_queue,
+        //     entity_pool: sim.entity_pool,
+        //     model: sim.model,
+        //     entities: sim.entities,
+        // }
+    }
+}
+
+impl TryFrom<&Vec<u8>> for Snapshot {
+    type Error = Error;
+    fn try_from(bytes: &Vec<u8>) -> Result<Self> {
+        #[cfg(feature = "lz4")]
+        {
+            match lz4::block::decompress(&bytes, None) {
+                Ok(data) => {
+                    let snapshot: Snapshot = bincode::deserialize(&data)
+                        .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+                    Ok(snapshot)
+                }
+                Err(e) => Err(Error::SnapshotDecompressionError(e.to_string())),
+            }
+        }
+        #[cfg(not(feature = "lz4"))]
+        {
+            let snapshot: Snapshot = bincode::deserialize(&bytes)
+                .map_err(|e| Error::FailedReadingSnapshot(e.to_string()))?;
+            Ok(snapshot)
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self, compress: bool) -> Result<Vec<u8>> {
+        let mut data: Vec<u8> =
+            bincode::serialize(&self).map_err(|e| Error::FailedCreatingSnapshot(e.to_string()))?;
+        #[cfg(feature = "lz4")]
+        {
+            if compress {
+                data = lz4::block::compress(&data, None, true)?;
+            }
+        }
+        Ok(data)
+    }
+}
+
+/// Self-sufficient representation of a simulation state that includes
+/// serialized project files.
+pub struct Package {
+    pub project: FnvHashMap<PathBuf, Vec<u8>>,
+    pub snapshot: Snapshot,
+}
+
+#[test]
+fn snapshot_preamble_legacy_detection() {
+    let mut bytes = SnapshotPreamble::current().to_bytes().unwrap();
+    bytes.extend(&[1, 2, 3]);
+    assert_eq!(extract_preamble(&mut bytes).unwrap(), SnapshotPreamble::current());
+    assert_eq!(bytes, vec![1, 2, 3]);
+
+    let mut legacy = vec![1, 2, 3];
+    let preamble = extract_preamble(&mut legacy).unwrap();
+    assert_eq!(preamble.format_version, 0);
+    assert_eq!(preamble.features, vec![UNKNOWN_FEATURES.to_string()]);
+    assert!(preamble.check_compatible().is_ok());
+    assert_eq!(legacy, vec![1, 2, 3]);
+}
+
+#[cfg(feature = "lz4")]
+#[test]
+fn snapshot_compression_flag() {
+    let mut bytes = SnapshotPreamble::current().to_bytes().unwrap();
+    bytes.extend(&[1, 2, 3, 4]);
+    assert!(!is_compressed(&bytes).unwrap());
+
+    let compressed = compress_snapshot(bytes.clone()).unwrap();
+    assert!(is_versioned(&compressed));
+    assert!(is_compressed(&compressed).unwrap());
+    assert_eq!(decompress_if_needed(compressed).unwrap(), bytes);
+}
+
+#[test]
+fn snapshot_entity_delta_roundtrip() {
+    use crate::entity::Storage;
+
+    let var = |name: &str| {
+        (
+            crate::string::new_truncate("comp"),
+            crate::string::new_truncate(name),
+        )
+    };
+    let mut base = Entity::empty();
+    base.storage.insert(var("kept"), Var::Int(1));
+    base.storage.insert(var("changed"), Var::Int(2));
+    base.storage.insert(var("removed"), Var::Int(3));
+
+    let mut current = base.clone();
+    current.storage.insert(var("changed"), Var::Int(20));
+    current.storage.insert(var("added"), Var::Float(4.));
+    current.storage.map.remove(&var("removed"));
+    current.storage.inbox.push(Message {
+        sender: 1,
+        kind: crate::string::new_truncate("hello"),
+        args: vec![Var::Bool(true)],
+    });
+    current.components.push(crate::string::new_truncate("comp"));
+    #[cfg(feature = "machine")]
+    {
+        current.comp_state.insert(
+            crate::string::new_truncate("comp"),
+            crate::string::new_truncate("idle"),
+        );
+        current.comp_queue.insert(
+            crate::string::new_truncate("step"),
+            vec![crate::string::new_truncate("comp")],
+        );
+    }
+
+    let delta = EntityDelta::between(&base, &current).unwrap();
+    let bytes = bincode::serialize(&delta).unwrap();
+    let delta: EntityDelta = bincode::deserialize(&bytes).unwrap();
+    let mut restored = base.clone();
+    delta.apply_to(&mut restored);
+
+    // destructured so that fields added to the entity need to be covered
+    // by the delta before the test compiles again
+    let Entity {
+        storage: Storage { map, inbox },
+        components,
+        #[cfg(feature = "machine")]
+        comp_state,
+        #[cfg(feature = "machine")]
+        comp_queue,
+        insta: _,
+    } = restored;
+    assert_eq!(map, current.storage.map);
+    assert_eq!(inbox, current.storage.inbox);
+    assert_eq!(components, current.components);
+    #[cfg(feature = "machine")]
+    {
+        assert_eq!(comp_state, current.comp_state);
+        assert_eq!(comp_queue, current.comp_queue);
+    }
+    assert!(EntityDelta::between(&current, &current).is_none());
+}
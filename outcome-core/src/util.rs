@@ -82,7 +82,7 @@ pub fn get_snapshot_paths(path: PathBuf) -> Result<Vec<PathBuf>> {
                     let entry_path = entry.path();
                     if entry_path.is_file() {
                         if let Some(entry_ext) = entry_path.extension() {
-                            if entry_ext == ".snapshot" {
+                            if entry_ext == "snapshot" {
                                 snapshot_paths.push(entry_path);
                             }
                         } else {
@@ -181,6 +181,12 @@ pub fn find_files_with_extension(
                 Ok(p) => p.path(),
                 _ => continue,
             };
+            if let Some(excludes) = &exclude {
+                let file_name = path.file_name().unwrap_or(OsStr::new(""));
+                if excludes.iter().any(|e| file_name == OsStr::new(e)) {
+                    continue;
+                }
+            }
             if path.is_dir() && recursive {
                 paths.extend(find_files_with_extension(
                     path,
@@ -196,14 +202,6 @@ pub fn find_files_with_extension(
                     .unwrap_or("");
                 for extension in &extensions {
                     if &ext == extension {
-                        // TODO excludes
-                        //if let Some(excludes) = exclude {
-                        //for exclude in excludes {
-                        //if path.file_name().unwrap_or(OsStr::new("")) == exclude {
-                        ////
-                        //}
-                        //}
-                        //}
                         paths.push(path.clone());
                         break;
                     }
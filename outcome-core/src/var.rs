@@ -85,6 +85,7 @@ impl VarType {
             BYTE_VAR_TYPE_NAME => VarType::Byte,
             VEC2_VAR_TYPE_NAME => VarType::Vec2,
             VEC3_VAR_TYPE_NAME => VarType::Vec3,
+            MAP_VAR_TYPE_NAME => VarType::Map,
             _ => {
                 let split = s.split(VAR_TYPE_NAME_SEPARATOR).collect::<Vec<&str>>();
                 if split.len() != 2 {
@@ -168,6 +169,23 @@ impl VarType {
         }
     }
 
+    /// Gets the type of elements stored within a list or grid of this type.
+    ///
+    /// Returns `None` for types that are not lists or grids, and for lists
+    /// and grids that can hold elements of any type.
+    pub fn element_type(&self) -> Option<VarType> {
+        match self {
+            VarType::StringList | VarType::StringGrid => Some(VarType::String),
+            VarType::IntList | VarType::IntGrid => Some(VarType::Int),
+            VarType::FloatList | VarType::FloatGrid => Some(VarType::Float),
+            VarType::BoolList | VarType::BoolGrid => Some(VarType::Bool),
+            VarType::ByteList | VarType::ByteGrid => Some(VarType::Byte),
+            VarType::Vec2List | VarType::Vec2Grid => Some(VarType::Vec2),
+            VarType::Vec3List | VarType::Vec3Grid => Some(VarType::Vec3),
+            _ => None,
+        }
+    }
+
     /// Get default value of the `VarType`.
     pub fn default_value(&self) -> Var {
         match self {
@@ -6,9 +6,10 @@ use std::time::Duration;
 
 use crate::msg::{
     DataTransferRequest, DataTransferResponse, ExportSnapshotRequest, ExportSnapshotResponse,
-    Message, PingRequest, RegisterClientRequest, RegisterClientResponse,
+    Message, PingRequest, QueryId, RegisterClientRequest, RegisterClientResponse,
+    RegisterQueryRequest, RegisterQueryResponse, ReloadModelRequest, ReloadModelResponse,
     ScheduledDataTransferRequest, StatusRequest, StatusResponse, TransferResponseData,
-    TurnAdvanceRequest, TypedSimDataPack,
+    TurnAdvanceRequest, TypedSimDataPack, UnregisterQueryRequest, UnregisterQueryResponse,
 };
 use crate::socket::{
     CompositeSocketAddress, Encoding, Socket, SocketAddress, SocketConfig, SocketType, Transport,
@@ -271,6 +272,61 @@ impl Client {
         )
     }
 
+    /// Registers a query on the server, returning the assigned query id.
+    ///
+    /// Products of the registered query are sent by the server each time
+    /// the query's event trigger fires, as `RegisteredQueryProduct`
+    /// messages carrying the query id. With `diff` enabled, products are
+    /// `AddressedVarDiff`s that can be applied on top of the previously
+    /// received state.
+    pub fn register_query(
+        &mut self,
+        query: crate::msg::query::Query,
+        diff: bool,
+    ) -> Result<QueryId> {
+        self.connection
+            .send_payload(RegisterQueryRequest { query, diff }, None)?;
+        let resp: RegisterQueryResponse = self
+            .connection
+            .recv_msg()?
+            .1
+            .unpack_payload(self.connection.encoding())?;
+        if !resp.error.is_empty() {
+            return Err(Error::Other(resp.error));
+        }
+        Ok(resp.query_id)
+    }
+
+    /// Removes a previously registered query from the server.
+    pub fn unregister_query(&mut self, query_id: QueryId) -> Result<()> {
+        self.connection
+            .send_payload(UnregisterQueryRequest { query_id }, None)?;
+        let resp: UnregisterQueryResponse = self
+            .connection
+            .recv_msg()?
+            .1
+            .unpack_payload(self.connection.encoding())?;
+        if !resp.error.is_empty() {
+            return Err(Error::Other(resp.error));
+        }
+        Ok(())
+    }
+
+    /// Requests the server to reload the scenario from disk and apply the
+    /// resulting model to the running simulation.
+    pub fn reload_model(&mut self) -> Result<outcome::model::diff::ModelUpdate> {
+        self.connection.send_payload(ReloadModelRequest {}, None)?;
+        let resp: ReloadModelResponse = self
+            .connection
+            .recv_msg()?
+            .1
+            .unpack_payload(self.connection.encoding())?;
+        match resp.update {
+            Some(update) if resp.error.is_empty() => Ok(update),
+            _ => Err(Error::Other(resp.error)),
+        }
+    }
+
     // blocking
     pub fn snapshot_request(&mut self, name: String, save_to_disk: bool) -> Result<Vec<u8>> {
         let req = ExportSnapshotRequest {
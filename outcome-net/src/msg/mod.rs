@@ -12,10 +12,9 @@ use serde::{Deserialize, Serialize};
 use serde_repr::*;
 
 pub mod coord_worker;
+pub mod query;
 pub mod server_client;
 
-mod query;
-
 pub use server_client::*;
 
 use crate::socket::{pack, unpack, Encoding};
@@ -73,6 +72,16 @@ pub enum MessageType {
 
     SpawnEntitiesRequest,
     SpawnEntitiesResponse,
+
+    RegisterQueryRequest,
+    RegisterQueryResponse,
+    UnregisterQueryRequest,
+    UnregisterQueryResponse,
+
+    ReloadModelRequest,
+    ReloadModelResponse,
+
+    RegisteredQueryProduct,
 }
 
 /// Self-described message structure wrapping a byte payload.
@@ -66,7 +66,19 @@ pub struct StatusResponse {
     pub scenario_version: String,
     pub scenario_engine: String,
     pub scenario_mods: Vec<String>,
-    pub scenario_settings: Vec<String>,
+    pub scenario_settings: Vec<ScenarioSettingStatus>,
+}
+
+/// Scenario setting along with its current value.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ScenarioSettingStatus {
+    pub name: String,
+    pub type_: String,
+    pub value: String,
+    pub default: String,
+    pub desc: String,
+    /// Address the setting is applied to
+    pub target: String,
 }
 pub(crate) const STATUS_RESPONSE: &str = "StatusResponse";
 impl Payload for StatusResponse {
@@ -128,6 +140,88 @@ impl Payload for QueryRequest {
     }
 }
 
+/// Integer identifier of a query registered on the server.
+pub type QueryId = u32;
+
+/// Requests registration of a query on the server.
+///
+/// Registered query is stored on the server and processed each time
+/// it's trigger is fulfilled, with products sent back to the client as
+/// `RegisteredQueryProduct` messages. Only queries using the event trigger
+/// and the addressed description can be registered.
+///
+/// `diff` specifies whether products should be sent as diffs against
+/// the previous product sent for the same query. First product is always
+/// sent in full, as the `added` part of the diff.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RegisterQueryRequest {
+    pub query: crate::msg::query::Query,
+    pub diff: bool,
+}
+pub(crate) const REGISTER_QUERY_REQUEST: &str = "RegisterQueryRequest";
+impl Payload for RegisterQueryRequest {
+    fn type_(&self) -> MessageType {
+        MessageType::RegisterQueryRequest
+    }
+}
+
+/// Response to `RegisterQueryRequest`.
+///
+/// `query_id` is the id under which the query was registered, it's used
+/// to tag all the products sent for that query.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RegisterQueryResponse {
+    pub query_id: QueryId,
+    pub error: String,
+}
+pub(crate) const REGISTER_QUERY_RESPONSE: &str = "RegisterQueryResponse";
+impl Payload for RegisterQueryResponse {
+    fn type_(&self) -> MessageType {
+        MessageType::RegisterQueryResponse
+    }
+}
+
+/// Requests removal of a previously registered query.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct UnregisterQueryRequest {
+    pub query_id: QueryId,
+}
+pub(crate) const UNREGISTER_QUERY_REQUEST: &str = "UnregisterQueryRequest";
+impl Payload for UnregisterQueryRequest {
+    fn type_(&self) -> MessageType {
+        MessageType::UnregisterQueryRequest
+    }
+}
+
+/// Response to `UnregisterQueryRequest`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct UnregisterQueryResponse {
+    pub error: String,
+}
+pub(crate) const UNREGISTER_QUERY_RESPONSE: &str = "UnregisterQueryResponse";
+impl Payload for UnregisterQueryResponse {
+    fn type_(&self) -> MessageType {
+        MessageType::UnregisterQueryResponse
+    }
+}
+
+/// Product of a registered query, sent by the server each time the query's
+/// trigger is fulfilled.
+///
+/// Products are pushed using their own message type, so they can't be
+/// mistaken for responses to requests tagged with client task ids.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegisteredQueryProduct {
+    pub query_id: QueryId,
+    pub data: TransferResponseData,
+}
+pub(crate) const REGISTERED_QUERY_PRODUCT: &str = "RegisteredQueryProduct";
+impl Payload for RegisteredQueryProduct {
+    fn type_(&self) -> MessageType {
+        MessageType::RegisteredQueryProduct
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct NativeQueryRequest {
     pub query: outcome::Query,
@@ -178,6 +272,8 @@ pub enum TransferResponseData {
     Var(VarSimDataPack),
     AddressedVar(FnvHashMap<Address, Var>),
     VarOrdered(u32, VarSimDataPackOrdered),
+    /// Changes since the last product of a registered query
+    AddressedVarDiff(outcome::query::QueryProductDiff),
 }
 
 /// Response to `DataTransferRequest`.
@@ -458,6 +554,11 @@ pub struct SpawnEntitiesRequest {
     /// List of names for the new entities to be spawned, has to be the same
     /// length as `entity_prefabs`
     pub entity_names: Vec<String>,
+    /// Initial var values for each of the new entities, in the
+    /// `comp:type:var=value` format, overriding the prefab values. If not
+    /// empty, has to be the same length as `entity_prefabs`
+    #[serde(default)]
+    pub entity_vars: Vec<Vec<String>>,
 }
 pub(crate) const SPAWN_ENTITIES_REQUEST: &str = "SpawnEntitiesRequest";
 impl Payload for SpawnEntitiesRequest {
@@ -480,6 +581,34 @@ impl Payload for SpawnEntitiesResponse {
     }
 }
 
+/// Requests the server to reload the scenario from disk and apply the
+/// resulting model to the running simulation.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ReloadModelRequest {}
+pub(crate) const RELOAD_MODEL_REQUEST: &str = "ReloadModelRequest";
+impl Payload for ReloadModelRequest {
+    fn type_(&self) -> MessageType {
+        MessageType::ReloadModelRequest
+    }
+}
+
+/// Response to `ReloadModelRequest`.
+///
+/// `update` describes the changes applied to the model, along with any
+/// changes that couldn't be applied to existing entities without loss
+/// of data.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ReloadModelResponse {
+    pub update: Option<outcome::model::diff::ModelUpdate>,
+    pub error: String,
+}
+pub(crate) const RELOAD_MODEL_RESPONSE: &str = "ReloadModelResponse";
+impl Payload for ReloadModelResponse {
+    fn type_(&self) -> MessageType {
+        MessageType::ReloadModelResponse
+    }
+}
+
 /// Requests the server to export a snapshot.
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct ExportSnapshotRequest {
@@ -68,7 +68,10 @@ pub enum OrganizerTask {
     },
     WaitForSnapshotResponses {
         remaining: u32,
-        snapshots: Vec<outcome::snapshot::SnapshotPart>,
+        /// Serialized snapshot parts collected from workers
+        snapshots: Vec<Vec<u8>>,
+        /// Set if any of the workers responded with an inconsistent state
+        error: Option<String>,
     },
 }
 
@@ -152,6 +155,7 @@ impl Organizer {
             organ.add_worker(worker_addr)?;
         }
         organ.reach_initialize();
+        organ.distribute_restored_entities()?;
         Ok(organ)
     }
 
@@ -273,8 +277,9 @@ impl Organizer {
                         }
                     }
                     SimStarter::Snapshot(snapshot) => {
-                        info!("initializing ");
-                        // self.central.snap
+                        // restored entities are distributed once all the
+                        // known workers are initialized
+                        info!("initializing from snapshot: {}", snapshot);
                     }
                     SimStarter::Experiment(_) => unimplemented!(),
                 }
@@ -378,6 +383,23 @@ impl Organizer {
                             None,
                         )?;
                     }
+                    Signal::SnapshotResponse(clock, part) => {
+                        if let Some(OrganizerTask::WaitForSnapshotResponses {
+                            remaining,
+                            snapshots,
+                            error,
+                        }) = self.tasks.get_mut(&task_id)
+                        {
+                            *remaining -= 1;
+                            if clock != self.central.clock {
+                                *error = Some(format!(
+                                    "worker {} snapshot taken at clock {}, expected {}",
+                                    worker_id, clock, self.central.clock
+                                ));
+                            }
+                            snapshots.push(part);
+                        }
+                    }
                     Signal::QueryResponse(product) => {
                         if let Some(OrganizerTask::WaitForQueryResponses {
                             remaining,
@@ -395,6 +417,9 @@ impl Organizer {
         for worker_id in to_initialize_node {
             self.initialize_worker_node(&worker_id)?;
         }
+        if !self.central.restore_queue.is_empty() {
+            self.distribute_restored_entities()?;
+        }
         for task_id in to_unregister {
             self.unregister_task(task_id)?;
         }
@@ -402,6 +427,7 @@ impl Organizer {
         if do_step
             && !self.net.workers.iter().any(|(_, w)| w.is_blocking_step)
             && !self.is_blocking_step
+            && !self.is_snapshot_pending()
         {
             info!("stepping");
             let mut event_queue = self.central.event_queue.clone();
@@ -410,7 +436,7 @@ impl Organizer {
                 event_queue.push(step_event_name);
             }
             self.central.event_queue.clear();
-            self.central.step_network(&mut self.net, event_queue);
+            self.central.step_network(&mut self.net, event_queue)?;
             self.central.clock += 1;
         }
         Ok(())
@@ -424,17 +450,28 @@ impl Organizer {
     ) -> Result<Self> {
         let scenario_path = PathBuf::from(scenario_path);
         let scenario = Scenario::from_path(scenario_path.clone())?;
-        let model = SimModel::from_scenario(scenario)?;
         let starter = SimStarter::Scenario(
             scenario_path
-                .clone()
                 .file_name()
                 .unwrap()
                 .to_str()
                 .unwrap()
                 .to_string(),
         );
-        let sim_central = SimCentral::from_model(model, Some(starter))?;
+        Organizer::new_with_scenario(scenario, Some(starter), addr, worker_addrs)
+    }
+
+    /// Creates a new organizer using an already loaded scenario, which
+    /// allows for modifying the scenario, e.g. overriding its settings,
+    /// before the model is created.
+    pub fn new_with_scenario(
+        scenario: Scenario,
+        starter: Option<SimStarter>,
+        addr: &str,
+        worker_addrs: Vec<String>,
+    ) -> Result<Self> {
+        let model = SimModel::from_scenario(scenario)?;
+        let sim_central = SimCentral::from_model(model, starter)?;
         let mut coord = Organizer::new(sim_central, addr, worker_addrs)?;
         debug!("created new cluster coordinator");
         Ok(coord)
@@ -454,14 +491,51 @@ impl Organizer {
 }
 
 impl Organizer {
+    /// Requests snapshot parts from all the workers.
+    ///
+    /// # Barrier
+    ///
+    /// Snapshot request acts as a barrier: no further steps are processed
+    /// until all the workers send back their parts. This way all the parts
+    /// represent the same simulation step.
     pub fn download_snapshots(&mut self) -> Result<TaskId> {
         let task_id = self.register_task(OrganizerTask::WaitForSnapshotResponses {
             remaining: self.net.workers.len() as u32,
             snapshots: vec![],
+            error: None,
         })?;
         self.net.broadcast_sig(task_id, Signal::SnapshotRequest)?;
         Ok(task_id)
     }
+
+    /// Checks whether there is a union snapshot currently being collected.
+    pub fn is_snapshot_pending(&self) -> bool {
+        self.tasks.values().any(|task| match task {
+            OrganizerTask::WaitForSnapshotResponses { remaining, .. } => *remaining > 0,
+            _ => false,
+        })
+    }
+
+    /// Distributes entities restored from a snapshot among the currently
+    /// connected workers, based on the current distribution policy.
+    ///
+    /// Number of workers doesn't have to match the number of workers used
+    /// when the snapshot was created.
+    pub fn distribute_restored_entities(&mut self) -> Result<()> {
+        if self.central.restore_queue.is_empty() || self.net.workers.is_empty() {
+            return Ok(());
+        }
+        let assignment = self.central.flush_restore_queue(&mut self.net)?;
+        for (worker_id, entity_ids) in assignment {
+            for entity_id in &entity_ids {
+                self.net.routing_table.insert(*entity_id, worker_id);
+            }
+            if let Some(worker) = self.net.workers.get_mut(&worker_id) {
+                worker.entities.extend(entity_ids);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl outcome::distr::CentralCommunication for OrganizerNet {
@@ -10,6 +10,8 @@ use std::time::{Duration, Instant};
 
 use fnv::FnvHashMap;
 use id_pool::IdPool;
+use outcome::model::VarOverride;
+use outcome::query::QueryProductDiff;
 use outcome::{string, Address, EventName, Sim, SimModel, StringId, VarType};
 
 use crate::msg::*;
@@ -77,6 +79,8 @@ pub struct Client {
     pub scheduled_transfers: FnvHashMap<EventName, Vec<DataTransferRequest>>,
     /// List of scheduled queries
     pub scheduled_queries: FnvHashMap<EventName, Vec<(TaskId, outcome::Query)>>,
+    /// Queries registered by the client, processed on their triggers
+    pub registered_queries: RegisteredQueries,
     /// Clock step on which client needs to be notified of step advance success
     pub scheduled_advance_response: Option<usize>,
 
@@ -101,6 +105,151 @@ impl Client {
 
         Ok(())
     }
+
+    /// Processes a registered query and sends the product to the client,
+    /// see [`RegisteredQueries::process`].
+    pub fn process_registered_query(
+        &mut self,
+        query_id: QueryId,
+        entities: &FnvHashMap<outcome::EntityId, outcome::entity::Entity>,
+        entity_idx: &FnvHashMap<outcome::EntityName, outcome::EntityId>,
+    ) -> Result<()> {
+        match self
+            .registered_queries
+            .process(query_id, entities, entity_idx)?
+        {
+            Some(product) => self.connection.send_payload(product, None),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Query registered by the client for repeated processing.
+pub struct PreparedQuery {
+    /// The query itself
+    pub query: outcome::Query,
+    /// Whether products are sent as diffs against the previous product
+    pub diff: bool,
+    /// Last product sent to the client, serves as base for the next diff
+    pub last_product: Option<FnvHashMap<Address, outcome::Var>>,
+}
+
+/// Collection of queries registered by a single client.
+pub struct RegisteredQueries {
+    /// Registered queries by id
+    pub queries: FnvHashMap<QueryId, PreparedQuery>,
+    /// Registered queries scheduled for processing on specific events
+    pub scheduled: FnvHashMap<EventName, Vec<QueryId>>,
+    /// Pool of ids for registered queries
+    pub id_pool: IdPool,
+}
+
+impl Default for RegisteredQueries {
+    fn default() -> Self {
+        RegisteredQueries {
+            queries: Default::default(),
+            scheduled: Default::default(),
+            id_pool: IdPool::new(),
+        }
+    }
+}
+
+impl RegisteredQueries {
+    /// Registers a query for repeated processing, returning the new query id.
+    ///
+    /// Only event-triggered queries with addressed products can be
+    /// registered, other queries are rejected right away instead of
+    /// failing each time they're triggered.
+    pub fn register(&mut self, query: outcome::Query, diff: bool) -> Result<QueryId> {
+        let event = match &query.trigger {
+            outcome::query::Trigger::Event(event) => event.clone(),
+            _ => {
+                return Err(Error::Other(
+                    "only event-triggered queries can be registered".to_string(),
+                ))
+            }
+        };
+        if query.description != outcome::query::Description::Addressed {
+            return Err(Error::Other(
+                "only queries with addressed description can be registered".to_string(),
+            ));
+        }
+        let query_id = self
+            .id_pool
+            .request_id()
+            .ok_or(Error::Other("failed getting new query id".to_string()))?;
+        self.scheduled.entry(event).or_default().push(query_id);
+        self.queries.insert(
+            query_id,
+            PreparedQuery {
+                query,
+                diff,
+                last_product: None,
+            },
+        );
+        Ok(query_id)
+    }
+
+    /// Removes a registered query, along with any scheduling entries.
+    pub fn unregister(&mut self, query_id: QueryId) -> Result<()> {
+        if self.queries.remove(&query_id).is_none() {
+            return Err(Error::Other(format!(
+                "no registered query with id: {}",
+                query_id
+            )));
+        }
+        for (_, ids) in &mut self.scheduled {
+            ids.retain(|id| id != &query_id);
+        }
+        self.id_pool
+            .return_id(query_id)
+            .map_err(|_| Error::Other(format!("failed returning query id: {}", query_id)))?;
+        Ok(())
+    }
+
+    /// Gets ids of registered queries triggered by any of the given events.
+    pub fn triggered(&self, events: &[EventName]) -> Vec<QueryId> {
+        let mut ids = self
+            .scheduled
+            .iter()
+            .filter(|(event, _)| events.contains(event))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    /// Processes a registered query, returning the product to be sent to
+    /// the client.
+    ///
+    /// For queries registered with `diff` only the changes since the
+    /// previous product are returned. Empty diffs are not returned at all.
+    pub fn process(
+        &mut self,
+        query_id: QueryId,
+        entities: &FnvHashMap<outcome::EntityId, outcome::entity::Entity>,
+        entity_idx: &FnvHashMap<outcome::EntityName, outcome::EntityId>,
+    ) -> Result<Option<RegisteredQueryProduct>> {
+        let prepared = self.queries.get_mut(&query_id).ok_or(Error::Other(format!(
+            "no registered query with id: {}",
+            query_id
+        )))?;
+        let product = prepared.query.process(entities, entity_idx)?;
+
+        let data = if prepared.diff {
+            let current = product.to_addressed_vars()?;
+            let diff = QueryProductDiff::between(prepared.last_product.as_ref(), &current);
+            let is_first = prepared.last_product.is_none();
+            prepared.last_product = Some(current);
+            if diff.is_empty() && !is_first {
+                return Ok(None);
+            }
+            TransferResponseData::AddressedVarDiff(diff)
+        } else {
+            TransferResponseData::AddressedVar(product.to_addressed_vars()?)
+        };
+        Ok(Some(RegisteredQueryProduct { query_id, data }))
+    }
 }
 
 /// Configuration settings for server.
@@ -211,6 +360,10 @@ pub struct Server {
     pub services: Vec<Service>,
 
     pub tasks: HashMap<TaskId, ServerTask>,
+
+    /// External trigger for reloading the simulation model, e.g. following
+    /// changes to project files
+    pub reload_trigger: Option<Arc<AtomicBool>>,
 }
 
 impl Server {
@@ -346,6 +499,7 @@ impl Server {
             last_accept_time: Instant::now(),
             services: vec![],
             tasks: Default::default(),
+            reload_trigger: None,
         })
     }
 
@@ -545,6 +699,15 @@ impl Server {
             self.uptime += self.config.poll_wait;
             self.last_accept_time += self.config.poll_wait;
 
+            if let Some(trigger) = &self.reload_trigger {
+                if trigger.swap(false, Ordering::SeqCst) {
+                    match self.reload_model() {
+                        Ok(update) => info!("model reloaded:\n{}", update.diff),
+                        Err(e) => error!("failed reloading model: {}", e),
+                    }
+                }
+            }
+
             // perform manual poll, match for loop-breaking errors
             if let Err(err) = self.manual_poll() {
                 match err {
@@ -664,6 +827,7 @@ impl Server {
                 },
                 scheduled_transfers: Default::default(),
                 scheduled_queries: Default::default(),
+                registered_queries: Default::default(),
                 scheduled_advance_response: None,
                 order_store: Default::default(),
                 order_id_pool: IdPool::new(),
@@ -775,6 +939,13 @@ impl Server {
             MessageType::ExportSnapshotRequest => {
                 self.handle_export_snapshot_request(msg, client_id)?
             }
+            MessageType::RegisterQueryRequest => {
+                self.handle_register_query_request(msg, client_id)?
+            }
+            MessageType::UnregisterQueryRequest => {
+                self.handle_unregister_query_request(msg, client_id)?
+            }
+            MessageType::ReloadModelRequest => self.handle_reload_model_request(msg, client_id)?,
             _ => println!("unknown message type: {:?}", msg.type_),
         }
         Ok(())
@@ -835,20 +1006,42 @@ impl Server {
                 "" => None,
                 _ => Some(string::new_truncate(&req.entity_names[i])),
             };
+            let entity_vars = req
+                .entity_vars
+                .get(i)
+                .map(|vars| vars.as_slice())
+                .unwrap_or_default();
             match &mut self.sim {
                 SimConnection::Local(sim) => {
-                    match sim
-                        .spawn_entity(Some(&outcome::string::new_truncate(&prefab)), entity_name)
-                    {
+                    let spawned = entity_vars
+                        .iter()
+                        .map(|v| VarOverride::from_str(v))
+                        .collect::<outcome::Result<Vec<_>>>()
+                        .and_then(|vars| {
+                            sim.spawn_entity_with(
+                                Some(&outcome::string::new_truncate(&prefab)),
+                                entity_name,
+                                &vars,
+                            )
+                        });
+                    match spawned {
                         Ok(entity_id) => out_names.push(entity_id.to_string()),
                         Err(e) => error = e.to_string(),
                     }
                 }
-                SimConnection::UnionOrganizer(organizer) => organizer.central.spawn_entity(
-                    Some(prefab.clone()),
-                    entity_name,
-                    outcome::distr::DistributionPolicy::Random,
-                )?,
+                SimConnection::UnionOrganizer(organizer) => {
+                    if !entity_vars.is_empty() {
+                        error = "setting var values on spawn is not supported in \
+                            distributed simulations"
+                            .to_string();
+                        continue;
+                    }
+                    organizer.central.spawn_entity(
+                        Some(prefab.clone()),
+                        entity_name,
+                        outcome::distr::DistributionPolicy::Random,
+                    )?
+                }
                 _ => unimplemented!(),
             }
         }
@@ -860,6 +1053,43 @@ impl Server {
         client.connection.send_payload(resp, None)
     }
 
+    /// Reloads the scenario from disk and applies the resulting model to
+    /// the simulation.
+    ///
+    /// Only supported for local simulations.
+    pub fn reload_model(&mut self) -> Result<outcome::model::diff::ModelUpdate> {
+        match &mut self.sim {
+            SimConnection::Local(sim) => Ok(sim.reload_model()?),
+            _ => Err(Error::Other(
+                "model reload is only supported for local simulations".to_string(),
+            )),
+        }
+    }
+
+    pub fn handle_reload_model_request(
+        &mut self,
+        msg: Message,
+        client_id: &ClientId,
+    ) -> Result<()> {
+        let resp = match self.reload_model() {
+            Ok(update) => ReloadModelResponse {
+                update: Some(update),
+                error: String::new(),
+            },
+            Err(e) => ReloadModelResponse {
+                update: None,
+                error: e.to_string(),
+            },
+        };
+        let client = self
+            .clients
+            .get(client_id)
+            .ok_or(Error::FailedGettingClientById(client_id.clone()))?;
+        client
+            .connection
+            .send_payload_with_task(resp, msg.task_id, None)
+    }
+
     pub fn handle_ping_request(&mut self, msg: Message, client_id: &ClientId) -> Result<()> {
         let client = self.clients.get_mut(client_id).unwrap();
         let req: PingRequest = msg.unpack_payload(client.connection.encoding())?;
@@ -932,9 +1162,25 @@ impl Server {
             scenario_settings: model_scenario
                 .manifest
                 .settings
-                .clone()
                 .iter()
-                .map(|(k, v)| format!("{} = {:?}", k, v))
+                .map(|setting| {
+                    // read the current value of the target var if possible
+                    let value = match &self.sim {
+                        SimConnection::Local(sim) => sim
+                            .get_setting(&setting.name)
+                            .map(|v| v.to_string())
+                            .unwrap_or(setting.value.to_string()),
+                        _ => setting.value.to_string(),
+                    };
+                    ScenarioSettingStatus {
+                        name: setting.name.clone(),
+                        type_: setting.type_.to_string(),
+                        value,
+                        default: setting.default.to_string(),
+                        desc: setting.desc.clone().unwrap_or("".to_string()),
+                        target: setting.target.to_string(),
+                    }
+                })
                 .collect(),
         };
         trace!("sending status response");
@@ -1373,46 +1619,46 @@ impl Server {
                                     .get(client_id)
                                     .ok_or(Error::FailedGettingClientById(*client_id))?;
                                 if let OrganizerTask::WaitForSnapshotResponses {
-                                    snapshots, ..
+                                    snapshots,
+                                    error,
+                                    ..
                                 } = organ_task
                                 {
-                                    let mut bytes = Vec::new();
-                                    // consolidate the snapshot
-                                    // TODO implement Snap on Organizer
-                                    let header = outcome::snapshot::SnapshotHeader {
-                                        metadata: outcome::snapshot::SnapshotMetadata {
-                                            created: chrono::Utc::now(),
-                                            // TODO
-                                            starter: outcome::SimStarter::Scenario("".to_string()),
-                                        },
-                                        clock: organ.central.clock.clone(),
-                                        model: organ.central.model.clone(),
-                                        entities_idx: organ.central.entities_idx.clone(),
-                                        event_queue: organ.central.event_queue.clone(),
-                                        entity_pool: organ.central.entity_idpool.clone(),
-                                    };
-                                    bytes.extend(bincode::serialize(&header)?);
-                                    for part in &snapshots {
-                                        bytes.extend(bincode::serialize(part)?);
-                                    }
-
-                                    if req.save_to_disk {
-                                        let project_path = outcome::util::find_project_root(
-                                            organ.central.model.scenario.path.clone(),
-                                            3,
+                                    // union snapshot is only valid if all the parts
+                                    // represent the same step
+                                    if let Some(error) = error {
+                                        client.connection.send_payload(
+                                            ExportSnapshotResponse {
+                                                error,
+                                                snapshot: vec![],
+                                            },
+                                            None,
                                         )?;
-                                        let snapshot_path = project_path
-                                            .join(outcome::SNAPSHOTS_DIR_NAME)
-                                            .join(req.name.clone());
-                                        let mut file = File::create(snapshot_path)?;
-                                        file.write_all(&bytes);
-                                    }
-                                    if req.send_back {
-                                        let payload = ExportSnapshotResponse {
-                                            error: "".to_string(),
-                                            snapshot: bytes,
-                                        };
-                                        client.connection.send_payload(payload, None);
+                                    } else {
+                                        // consolidate the snapshot
+                                        let bytes = outcome::snapshot::assemble_union_snapshot(
+                                            &organ.central.snapshot_header(),
+                                            &snapshots,
+                                        )?;
+
+                                        if req.save_to_disk {
+                                            let project_path = outcome::util::find_project_root(
+                                                organ.central.model.scenario.path.clone(),
+                                                3,
+                                            )?;
+                                            let snapshot_path = project_path
+                                                .join(outcome::SNAPSHOTS_DIR_NAME)
+                                                .join(req.name.clone());
+                                            let mut file = File::create(snapshot_path)?;
+                                            file.write_all(&bytes);
+                                        }
+                                        if req.send_back {
+                                            let payload = ExportSnapshotResponse {
+                                                error: "".to_string(),
+                                                snapshot: bytes,
+                                            };
+                                            client.connection.send_payload(payload, None);
+                                        }
                                     }
                                 }
                             }
@@ -1425,3 +1671,89 @@ impl Server {
         Ok(())
     }
 }
+
+#[test]
+fn registered_query_lifecycle() {
+    use outcome::query::{Description, Layout, Map, Trigger};
+    use outcome::Var;
+
+    let step = string::new_truncate("step");
+    let query = |trigger: Trigger, description: Description| outcome::Query {
+        trigger,
+        description,
+        layout: Layout::Var,
+        filters: vec![],
+        mappings: vec![Map::All],
+    };
+    let mut registered = RegisteredQueries::default();
+
+    // queries that can't produce addressed products are rejected right away
+    assert!(registered
+        .register(query(Trigger::Immediate, Description::Addressed), true)
+        .is_err());
+    assert!(registered
+        .register(
+            query(Trigger::Event(step.clone()), Description::NativeDescribed),
+            true
+        )
+        .is_err());
+    assert!(registered.queries.is_empty());
+
+    let query_id = registered
+        .register(
+            query(Trigger::Event(step.clone()), Description::Addressed),
+            true,
+        )
+        .unwrap();
+    assert!(registered
+        .triggered(&[string::new_truncate("other")])
+        .is_empty());
+    assert_eq!(registered.triggered(&[step.clone()]), vec![query_id]);
+
+    let var = (string::new_truncate("position"), string::new_truncate("x"));
+    let addr: Address = "0:position:float:x".parse().unwrap();
+    let mut entity = outcome::entity::Entity::empty();
+    entity.storage.insert(var.clone(), Var::Float(1.));
+    let mut entities = FnvHashMap::default();
+    entities.insert(0, entity);
+    let entity_idx = FnvHashMap::default();
+    let expect_diff = |product: Option<RegisteredQueryProduct>| match product {
+        Some(RegisteredQueryProduct {
+            query_id: id,
+            data: TransferResponseData::AddressedVarDiff(diff),
+        }) if id == query_id => diff,
+        product => panic!("unexpected product: {:?}", product),
+    };
+
+    // first product is sent in full
+    let diff = expect_diff(
+        registered
+            .process(query_id, &entities, &entity_idx)
+            .unwrap(),
+    );
+    assert_eq!(diff.added.get(&addr), Some(&Var::Float(1.)));
+    // nothing is sent if nothing changed
+    assert!(registered
+        .process(query_id, &entities, &entity_idx)
+        .unwrap()
+        .is_none());
+    entities
+        .get_mut(&0)
+        .unwrap()
+        .storage
+        .insert(var, Var::Float(2.));
+    let diff = expect_diff(
+        registered
+            .process(query_id, &entities, &entity_idx)
+            .unwrap(),
+    );
+    assert!(diff.added.is_empty());
+    assert_eq!(diff.changed.get(&addr), Some(&Var::Float(2.)));
+
+    registered.unregister(query_id).unwrap();
+    assert!(registered.triggered(&[step]).is_empty());
+    assert!(registered
+        .process(query_id, &entities, &entity_idx)
+        .is_err());
+    assert!(registered.unregister(query_id).is_err());
+}
@@ -4,7 +4,8 @@ use outcome::distr::{CentralCommunication, Signal};
 
 use crate::msg::{
     DataTransferResponse, Message, NativeQueryRequest, NativeQueryResponse, QueryRequest,
-    TransferResponseData,
+    RegisterQueryRequest, RegisterQueryResponse, TransferResponseData, UnregisterQueryRequest,
+    UnregisterQueryResponse,
 };
 use crate::organizer::OrganizerTask;
 use crate::server::{ClientId, ServerTask};
@@ -116,4 +117,66 @@ impl Server {
         }
         Ok(())
     }
+
+    /// Registers a query for the client, responding with the new query id.
+    ///
+    /// Queries that can't be registered are rejected with an error, see
+    /// [`crate::server::RegisteredQueries::register`]. Registered queries are currently
+    /// only processed on servers backed by a local sim.
+    pub fn handle_register_query_request(
+        &mut self,
+        msg: Message,
+        client_id: &ClientId,
+    ) -> Result<()> {
+        let client = self
+            .clients
+            .get_mut(client_id)
+            .ok_or(Error::FailedGettingClientById(*client_id))?;
+        let req: RegisterQueryRequest = msg.unpack_payload(client.connection.encoding())?;
+
+        let mut resp = RegisterQueryResponse {
+            query_id: 0,
+            error: String::new(),
+        };
+        match &self.sim {
+            SimConnection::Local(_) => {
+                let query: Result<outcome::query::Query> = req.query.try_into();
+                match query.and_then(|query| client.registered_queries.register(query, req.diff)) {
+                    Ok(query_id) => resp.query_id = query_id,
+                    Err(e) => resp.error = e.to_string(),
+                }
+            }
+            _ => {
+                resp.error =
+                    "registered queries are only supported on local sim servers".to_string();
+            }
+        }
+
+        client
+            .connection
+            .send_payload_with_task(resp, msg.task_id, None)
+    }
+
+    /// Removes a query previously registered by the client.
+    pub fn handle_unregister_query_request(
+        &mut self,
+        msg: Message,
+        client_id: &ClientId,
+    ) -> Result<()> {
+        let client = self
+            .clients
+            .get_mut(client_id)
+            .ok_or(Error::FailedGettingClientById(*client_id))?;
+        let req: UnregisterQueryRequest = msg.unpack_payload(client.connection.encoding())?;
+
+        let error = match client.registered_queries.unregister(req.query_id) {
+            Ok(()) => String::new(),
+            Err(e) => e.to_string(),
+        };
+        client.connection.send_payload_with_task(
+            UnregisterQueryResponse { error },
+            msg.task_id,
+            None,
+        )
+    }
 }
@@ -7,6 +7,7 @@ use crate::{Server, SimConnection};
 use crate::msg::TransferResponseData::AddressedVar;
 use crate::{Error, Result};
 use outcome::distr::NodeCommunication;
+use outcome::sim::StepStatus;
 
 impl Server {
     // fn advance_turn(&mut self, tick_num: u32) -> Result<()> {}
@@ -93,7 +94,11 @@ impl Server {
                     // for local sim instance simply step until common
                     // furthest step is achieved
                     for _ in 0..common_furthest_step - step_before_advance {
-                        sim_instance.step();
+                        // clock doesn't advance while paused by the debugger
+                        if sim_instance.step()? == StepStatus::Paused {
+                            warn!("simulation paused by the debugger");
+                            break;
+                        }
                         clock_after_advance += 1;
                         // let events = sim_instance.event_queue.clone();
                         trace!("processed single tick");
@@ -147,6 +152,20 @@ impl Server {
                                 }
                             }
 
+                            for query_id in client
+                                .registered_queries
+                                .triggered(&sim_instance.event_queue)
+                            {
+                                trace!("handling registered query: {}", query_id);
+                                if let Err(e) = client.process_registered_query(
+                                    query_id,
+                                    &sim_instance.entities,
+                                    &sim_instance.entity_idx,
+                                ) {
+                                    error!("{}", e);
+                                }
+                            }
+
                             if &client.id == client_id {
                                 continue;
                             }
@@ -170,6 +189,17 @@ impl Server {
                     trace!("clock step after advance: {}", clock_after_advance);
                 }
                 SimConnection::UnionOrganizer(coord) => {
+                    // union snapshot acts as a barrier, no steps are processed
+                    // until all the snapshot parts are collected
+                    if coord.is_snapshot_pending() {
+                        let resp = TurnAdvanceResponse {
+                            error: "SnapshotPending".to_string(),
+                        };
+                        if let Some(client) = self.clients.get_mut(client_id) {
+                            client.connection.send_payload(resp, None)?;
+                        }
+                        return Ok(());
+                    }
                     let mut event_queue = coord.central.event_queue.clone();
 
                     let step_event_name = outcome::string::new_truncate("step");
@@ -191,7 +221,7 @@ impl Server {
                     //         return Ok(());
                     //     }
                     // }
-                    coord.central.step_network(&mut coord.net, event_queue);
+                    coord.central.step_network(&mut coord.net, event_queue)?;
                     // coord_lock
                     //     .central
                     //     .step_network(&mut coord_lock.network, event_queue)?;
@@ -21,6 +21,8 @@ use fnv::FnvHashMap;
 use id_pool::IdPool;
 use outcome::Sim;
 use outcome_core::distr::{NodeCommunication, Signal, SimNode};
+use outcome_core::entity::Entity;
+use outcome_core::snapshot::SnapPart;
 use outcome_core::query::{Query, QueryProduct};
 use outcome_core::{
     string, Address, CompName, EntityId, EntityName, SimModel, StringId, Var, VarType,
@@ -303,6 +305,10 @@ impl Worker {
             Signal::DataPullRequest(pull_data) => {
                 self.handle_sig_pull_data_request(task_id, pull_data)?
             }
+            Signal::SnapshotRequest => self.handle_sig_snapshot_request(task_id)?,
+            Signal::RestoreEntities(clock, entities) => {
+                self.handle_sig_restore_entities(clock, entities)?
+            }
             _ => warn!("unhandled signal: {:?}", sig),
         }
 
@@ -341,6 +347,30 @@ impl Worker {
         Ok(())
     }
 
+    fn handle_sig_snapshot_request(&mut self, task_id: TaskId) -> Result<()> {
+        let node = self
+            .sim_node
+            .as_ref()
+            .ok_or(Error::Other("sim node not initialized".to_string()))?;
+        let part = node.to_snapshot_part()?;
+        self.network
+            .sig_send_central(task_id, Signal::SnapshotResponse(node.clock, part))?;
+        Ok(())
+    }
+
+    fn handle_sig_restore_entities(
+        &mut self,
+        clock: usize,
+        entities: Vec<(EntityId, Option<EntityName>, Entity)>,
+    ) -> Result<()> {
+        info!("restoring {} entities from snapshot", entities.len());
+        self.sim_node
+            .as_mut()
+            .ok_or(Error::Other("sim node not initialized".to_string()))?
+            .restore_entities(clock, entities);
+        Ok(())
+    }
+
     fn handle_sig_pull_data_request(
         &mut self,
         task_id: TaskId,
@@ -607,7 +637,9 @@ impl outcome::distr::NodeCommunication for WorkerNetwork {
         task_id: u32,
         signal: Signal,
     ) -> outcome::Result<()> {
-        unimplemented!()
+        // workers don't keep track of where entities are stored, instead
+        // the signal is passed on by the organizer
+        self.sig_send_central(task_id, Signal::RouteToEntity(entity_uid, Box::new(signal)))
     }
 
     fn sig_broadcast(&mut self, task_id: u32, signal: Signal) -> outcome::Result<()> {